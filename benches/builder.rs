@@ -0,0 +1,42 @@
+mod common;
+
+use common::{synthetic_hgrid, synthetic_vqs};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use schismrs_vgrid::vqs::VQS;
+use std::fs;
+use std::io::Write as _;
+use std::path::PathBuf;
+
+/// Sides of the synthetic meshes, giving about 10k, 100k and 1M nodes.
+const SIDES: [usize; 3] = [100, 317, 1000];
+
+fn bench_builder(c: &mut Criterion) {
+    let mut group = c.benchmark_group("vgrid builder");
+    group.sample_size(10);
+    for side in SIDES {
+        let hgrid = synthetic_hgrid(side);
+        let np = side * side;
+        group.bench_with_input(BenchmarkId::new("build", np), &hgrid, |b, hgrid| {
+            b.iter(|| synthetic_vqs(hgrid))
+        });
+        let vqs = synthetic_vqs(&hgrid);
+        let mut sink: Vec<u8> = Vec::new();
+        group.bench_with_input(BenchmarkId::new("Display", np), &vqs, |b, vqs| {
+            b.iter(|| {
+                sink.clear();
+                write!(sink, "{}", vqs).unwrap();
+            })
+        });
+        let path: PathBuf =
+            std::env::temp_dir().join(format!("schismrs-vgrid-bench-{}.vgrid.in", side));
+        vqs.write_to_file(&path).unwrap();
+        group.bench_with_input(BenchmarkId::new("try_from_file", np), &path, |b, path| {
+            b.iter(|| VQS::try_from_file(&hgrid, path).unwrap())
+        });
+        fs::remove_file(&path).unwrap();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_builder);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+use schismrs_hgrid::hgrid::Hgrid;
+use schismrs_vgrid::transforms::quadratic::QuadraticTransformOpts;
+use schismrs_vgrid::transforms::StretchingFunction;
+use schismrs_vgrid::vqs::{VQSBuilder, VQS};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+/// Writes a structured, triangulated gr3 of `side` * `side` nodes with depths
+/// growing from 1 m to 1000 m along x and loads it back.
+pub fn synthetic_hgrid(side: usize) -> Hgrid {
+    let mut gr3 = String::new();
+    let np = side * side;
+    let ne = 2 * (side - 1) * (side - 1);
+    write!(gr3, "synthetic\n{} {}\n", ne, np).unwrap();
+    for j in 0..side {
+        for i in 0..side {
+            let depth = 1. + 999. * (i as f64) / ((side - 1) as f64);
+            write!(gr3, "{} {} {} {}\n", j * side + i + 1, i, j, depth).unwrap();
+        }
+    }
+    let mut element = 0;
+    for j in 0..side - 1 {
+        for i in 0..side - 1 {
+            let n = j * side + i + 1;
+            element += 1;
+            write!(gr3, "{} 3 {} {} {}\n", element, n, n + 1, n + side + 1).unwrap();
+            element += 1;
+            write!(gr3, "{} 3 {} {} {}\n", element, n, n + side + 1, n + side).unwrap();
+        }
+    }
+    let path: PathBuf = std::env::temp_dir().join(format!("schismrs-vgrid-bench-{}.gr3", side));
+    fs::write(&path, gr3).unwrap();
+    let hgrid = Hgrid::try_from(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    hgrid
+}
+
+pub fn synthetic_vqs(hgrid: &Hgrid) -> VQS {
+    let depths = vec![20., 200., 1000.];
+    let nlevels = vec![10, 20, 40];
+    let opts = QuadraticTransformOpts {
+        a_vqs0: &0.,
+        etal: &0.,
+        skew_decay_rate: &0.3,
+    };
+    let stretching = StretchingFunction::Quadratic(opts);
+    VQSBuilder::default()
+        .hgrid(hgrid)
+        .depths(&depths)
+        .nlevels(&nlevels)
+        .stretching(&stretching)
+        .dz_bottom_min(&0.1)
+        .build()
+        .unwrap()
+}
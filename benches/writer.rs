@@ -0,0 +1,114 @@
+mod common;
+
+use common::{synthetic_hgrid, synthetic_vqs};
+use criterion::{criterion_group, criterion_main, Criterion};
+use schismrs_vgrid::vqs::VQS;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::Write as _;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts heap allocations so the writers can be compared on allocator pressure
+/// and not only on wall time.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Side of the synthetic structured mesh, giving SIDE * SIDE nodes.
+const SIDE: usize = 150;
+
+/// The writer as it was before VQS::write, formatting every value into its own
+/// String. Kept as the reference point of the benchmark.
+fn legacy_write(vqs: &VQS, sink: &mut Vec<u8>) {
+    write!(sink, "{:>12}\n", vqs.ivcor()).unwrap();
+    write!(sink, "{:>12}\n", vqs.nvrt()).unwrap();
+    write!(
+        sink,
+        " {}\n",
+        vqs.bottom_level_indices()
+            .iter()
+            .map(|&index| format!("{:>10}", index))
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+    .unwrap();
+    for (row, values) in vqs.sigma().outer_iter().enumerate() {
+        let formatted_values: Vec<String> = values
+            .to_vec()
+            .iter()
+            .map(|value| {
+                if value.is_nan() {
+                    format!("{:15.6}", -9.0)
+                } else {
+                    format!("{:15.6}", value)
+                }
+            })
+            .collect();
+        write!(sink, "{:>10}{}\n", row + 1, formatted_values.join("")).unwrap();
+    }
+}
+
+fn count_allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn bench_writer(c: &mut Criterion) {
+    let hgrid = synthetic_hgrid(SIDE);
+    let vqs = synthetic_vqs(&hgrid);
+    let mut sink: Vec<u8> = Vec::new();
+    vqs.write(&mut sink).unwrap();
+    let capacity = sink.len();
+    let mut sink: Vec<u8> = Vec::with_capacity(capacity);
+    let legacy = count_allocations(|| legacy_write(&vqs, &mut sink));
+    sink.clear();
+    let display = count_allocations(|| write!(sink, "{}", vqs).unwrap());
+    sink.clear();
+    let write = count_allocations(|| vqs.write(&mut sink).unwrap());
+    println!(
+        "allocations per write of {} nodes x {} levels: legacy {}, Display {}, VQS::write {}",
+        vqs.sigma().ncols(),
+        vqs.nvrt(),
+        legacy,
+        display,
+        write
+    );
+
+    let mut group = c.benchmark_group("vgrid.in writer");
+    group.sample_size(10);
+    group.bench_function("legacy", |b| {
+        b.iter(|| {
+            sink.clear();
+            legacy_write(&vqs, &mut sink);
+        })
+    });
+    group.bench_function("Display", |b| {
+        b.iter(|| {
+            sink.clear();
+            write!(sink, "{}", vqs).unwrap();
+        })
+    });
+    group.bench_function("VQS::write", |b| {
+        b.iter(|| {
+            sink.clear();
+            vqs.write(&mut sink).unwrap();
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_writer);
+criterion_main!(benches);
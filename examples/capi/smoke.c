@@ -0,0 +1,40 @@
+/*
+ * Smoke test of the C API: builds an auto mode vgrid.in for the hgrid given
+ * on the command line. From the repository root:
+ *
+ *   cargo rustc --release --lib --features capi --crate-type cdylib
+ *   cc examples/capi/smoke.c -Iinclude -Ltarget/release -lschismrs_vgrid \
+ *       -Wl,-rpath,target/release -o target/release/capi_smoke
+ *   target/release/capi_smoke /path/to/hgrid.gr3 vgrid.in
+ *
+ * For the static library, build with --crate-type staticlib and link
+ * target/release/libschismrs_vgrid.a -lpthread -ldl -lm in place of
+ * -lschismrs_vgrid.
+ */
+#include <stdio.h>
+#include "schismrs_vgrid.h"
+
+static const char *CONFIG =
+    "{\"dz_bottom_min\": 1.0,"
+    " \"transform\": {\"kind\": \"s\", \"theta_b\": 0.7, \"theta_f\": 5.0},"
+    " \"mode\": {\"kind\": \"auto\", \"ngrids\": 10}}";
+
+int main(int argc, char **argv) {
+    if (argc != 3) {
+        fprintf(stderr, "usage: %s hgrid.gr3 vgrid.in\n", argv[0]);
+        return 2;
+    }
+    int status = vgrid_generate(argv[1], CONFIG, argv[2]);
+    if (status != VGRID_OK) {
+        fprintf(stderr, "vgrid_generate failed with status %d\n", status);
+        return 1;
+    }
+    FILE *vgrid = fopen(argv[2], "r");
+    if (vgrid == NULL) {
+        fprintf(stderr, "%s was not written\n", argv[2]);
+        return 1;
+    }
+    fclose(vgrid);
+    printf("wrote %s\n", argv[2]);
+    return 0;
+}
@@ -0,0 +1,55 @@
+#ifndef SCHISMRS_VGRID_H
+#define SCHISMRS_VGRID_H
+
+/* Generated with cbindgen from src/capi.rs, see cbindgen.toml. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Returned by [`vgrid_generate`] on success.
+ */
+#define VGRID_OK 0
+
+/**
+ * A path or the config is null or not valid UTF-8.
+ */
+#define VGRID_INVALID_ARGUMENT 1
+
+/**
+ * The config JSON does not match the expected layout.
+ */
+#define VGRID_INVALID_CONFIG 2
+
+/**
+ * The hgrid could not be read.
+ */
+#define VGRID_HGRID_ERROR 3
+
+/**
+ * The builder rejected the config.
+ */
+#define VGRID_BUILD_ERROR 4
+
+/**
+ * The vgrid.in could not be written.
+ */
+#define VGRID_WRITE_ERROR 5
+
+/**
+ * Builds the vertical grid described by `config_json` on the hgrid at
+ * `hgrid_path` and writes it as a vgrid.in to `out_path`.
+ *
+ * Returns [`VGRID_OK`] on success or one of the other `VGRID_*` codes. The
+ * cause of a failure is logged at error level.
+ *
+ * # Safety
+ *
+ * The three arguments must be null or point to NUL terminated strings that
+ * stay valid for the duration of the call.
+ */
+int vgrid_generate(const char *hgrid_path, const char *config_json, const char *out_path);
+
+#endif /* SCHISMRS_VGRID_H */
@@ -0,0 +1,113 @@
+#!/usr/bin/env python3
+"""Compares the LSC2 grid generated by gen_vqs against pyschism's LSC2.
+
+Both tools are driven with the same master grids and S-transform parameters.
+The script reports the nodes whose bottom level index differs and the maximum
+sigma deviation per level. It exits with status 0 and a notice when pyschism
+is not installed, so it can run unconditionally in CI.
+
+pyschism's LSC2 takes its own a_vqs0 and dz_bottom_min. Pass the values your
+pyschism version uses through --a-vqs0 and --dz-bottom-min so the comparison
+is like for like.
+
+Example:
+
+    python3 scripts/compare_pyschism.py /path/to/hgrid.gr3 \
+        --depths 50 60 80 110 150 200 8426 --nlevels 21 22 23 24 25 26 27 \
+        --theta-b 0. --theta-f 3.
+"""
+import argparse
+import pathlib
+import subprocess
+import sys
+import tempfile
+
+import numpy as np
+
+
+def read_vgrid(path):
+    with open(path) as f:
+        tokens = f.read().split()
+    ivcor, nvrt = int(tokens[0]), int(tokens[1])
+    if ivcor != 1:
+        raise ValueError(f"{path}: only ivcor=1 is supported, got {ivcor}")
+    rest = tokens[2:]
+    # The bottom indices line holds one entry per node, followed by nvrt
+    # lines of 1 + np entries.
+    np_ = (len(rest) - nvrt) // (nvrt + 1)
+    kbp = np.array(rest[:np_], dtype=int)
+    levels = np.array(rest[np_:], dtype=float).reshape(nvrt, np_ + 1)[:, 1:]
+    sigma = np.where(levels == -9.0, np.nan, levels)
+    return kbp, sigma
+
+
+def build_pyschism(lsc2_cls, hgrid, args, output):
+    lsc2 = lsc2_cls(args.depths, args.nlevels, args.depths[0], args.theta_b, args.theta_f)
+    lsc2.calc_m_grid()
+    lsc2.calc_lsc2_att(hgrid)
+    lsc2.write(output)
+
+
+def build_rust(args, output):
+    command = [
+        "cargo", "run", "--release", "--quiet", "--bin", "gen_vqs", "--",
+        str(args.hgrid), "-o", str(output), "--transform", "s",
+        f"--a-vqs0={args.a_vqs0}", f"--theta-b={args.theta_b}",
+        f"--theta-f={args.theta_f}", f"--dz-bottom-min={args.dz_bottom_min}",
+        "hsm", "--depths", *map(str, args.depths),
+        "--nlevels", *map(str, args.nlevels),
+    ]
+    subprocess.run(command, check=True)
+
+
+def report(kbp_py, sigma_py, kbp_rs, sigma_rs, tolerance):
+    if sigma_py.shape != sigma_rs.shape:
+        print(f"shape mismatch: pyschism {sigma_py.shape}, gen_vqs {sigma_rs.shape}")
+        return False
+    changed = np.flatnonzero(kbp_py != kbp_rs)
+    print(f"nodes with different bottom level index: {len(changed)}")
+    for node in changed[:20]:
+        print(f"  node {node + 1}: pyschism {kbp_py[node]}, gen_vqs {kbp_rs[node]}")
+    print(f"{'level':>6} {'max |dsigma|':>14}")
+    deviation = np.abs(sigma_py - sigma_rs)
+    worst = 0.0
+    for level, row in enumerate(deviation, start=1):
+        both = ~np.isnan(row)
+        value = float(row[both].max()) if both.any() else 0.0
+        worst = max(worst, value)
+        print(f"{level:>6} {value:>14.6e}")
+    print(f"max |dsigma|: {worst:.6e}")
+    return len(changed) == 0 and worst <= tolerance
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("hgrid", type=pathlib.Path)
+    parser.add_argument("--depths", type=float, nargs="+", required=True)
+    parser.add_argument("--nlevels", type=int, nargs="+", required=True)
+    parser.add_argument("--theta-b", type=float, default=0.0)
+    parser.add_argument("--theta-f", type=float, default=3.0)
+    parser.add_argument("--a-vqs0", type=float, default=-0.3)
+    parser.add_argument("--dz-bottom-min", type=float, default=1.0)
+    parser.add_argument("--tolerance", type=float, default=1e-5)
+    args = parser.parse_args()
+    try:
+        from pyschism.mesh import Hgrid
+        from pyschism.mesh.vgrid import LSC2
+    except ImportError:
+        print("pyschism is not installed, skipping comparison")
+        return 0
+    with tempfile.TemporaryDirectory() as tmpdir:
+        pyschism_output = pathlib.Path(tmpdir) / "vgrid.pyschism.in"
+        rust_output = pathlib.Path(tmpdir) / "vgrid.gen_vqs.in"
+        build_pyschism(LSC2, Hgrid.open(args.hgrid, crs="epsg:4326"), args, pyschism_output)
+        build_rust(args, rust_output)
+        kbp_py, sigma_py = read_vgrid(pyschism_output)
+        kbp_rs, sigma_rs = read_vgrid(rust_output)
+    passed = report(kbp_py, sigma_py, kbp_rs, sigma_rs, args.tolerance)
+    print("PASS" if passed else "FAIL")
+    return 0 if passed else 1
+
+
+if __name__ == "__main__":
+    sys.exit(main())
@@ -0,0 +1,181 @@
+use crate::csv::CsvFormatter;
+use crate::vqs::VQS;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use thiserror::Error;
+
+const HEADER: [&str; 5] = ["depth", "nlevels", "min_dz", "mean_dz", "max_dz"];
+
+/// A master grid of a built vertical grid, with the layer thickness statistics
+/// of its zone: the nodes deeper than the previous master grid and no deeper
+/// than this one.
+pub struct Anchor {
+    depth: f64,
+    nlevels: usize,
+    min_dz: f64,
+    mean_dz: f64,
+    max_dz: f64,
+}
+
+impl Anchor {
+    pub fn depth(&self) -> &f64 {
+        &self.depth
+    }
+
+    pub fn nlevels(&self) -> usize {
+        self.nlevels
+    }
+
+    /// Thinnest layer of the zone, NaN when the zone has no wet node.
+    pub fn min_dz(&self) -> &f64 {
+        &self.min_dz
+    }
+
+    pub fn mean_dz(&self) -> &f64 {
+        &self.mean_dz
+    }
+
+    pub fn max_dz(&self) -> &f64 {
+        &self.max_dz
+    }
+}
+
+/// Anchor table of `vqs`, None when it was loaded rather than built.
+pub fn anchors(vqs: &VQS) -> Option<Vec<Anchor>> {
+    let (depths, nlevels) = vqs.master_grids()?;
+    let mut anchors: Vec<Anchor> = depths
+        .iter()
+        .zip(nlevels.iter())
+        .map(|(&depth, &nlevels)| Anchor {
+            depth,
+            nlevels,
+            min_dz: f64::INFINITY,
+            mean_dz: 0.,
+            max_dz: 0.,
+        })
+        .collect();
+    let mut counts = vec![0usize; anchors.len()];
+    let z = vqs.z();
+    let nvrt = vqs.nvrt();
+    for (i, (&kbp, &depth)) in vqs
+        .bottom_level_indices()
+        .iter()
+        .zip(vqs.depths().iter())
+        .enumerate()
+    {
+        if depth <= 0. {
+            continue;
+        }
+        let zone = depths
+            .partition_point(|&anchor| anchor < depth)
+            .min(anchors.len() - 1);
+        let anchor = &mut anchors[zone];
+        for row in kbp - 1..nvrt - 1 {
+            let dz = z[[row + 1, i]] - z[[row, i]];
+            anchor.min_dz = anchor.min_dz.min(dz);
+            anchor.max_dz = anchor.max_dz.max(dz);
+            anchor.mean_dz += dz;
+            counts[zone] += 1;
+        }
+    }
+    for (anchor, count) in anchors.iter_mut().zip(counts) {
+        if count == 0 {
+            anchor.min_dz = f64::NAN;
+            anchor.mean_dz = f64::NAN;
+            anchor.max_dz = f64::NAN;
+        } else {
+            anchor.mean_dz /= count as f64;
+        }
+    }
+    Some(anchors)
+}
+
+/// Writes `anchors` as a CSV table with a `depth,nlevels,min_dz,mean_dz,max_dz`
+/// header.
+pub fn write_anchors_csv(
+    anchors: &Vec<Anchor>,
+    path: &PathBuf,
+    csv: &CsvFormatter,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    csv.write_header(&mut writer, &HEADER)?;
+    for anchor in anchors.iter() {
+        csv.write_record(
+            &mut writer,
+            &[
+                csv.float(anchor.depth),
+                anchor.nlevels.to_string(),
+                csv.float(anchor.min_dz),
+                csv.float(anchor.mean_dz),
+                csv.float(anchor.max_dz),
+            ],
+        )?;
+    }
+    writer.flush()
+}
+
+/// Reads the master grid depths and nlevels from the depth and nlevels columns
+/// of a CSV table, e.g. one written by [`write_anchors_csv`] and edited in a
+/// spreadsheet. Other columns are ignored.
+pub fn read_anchors_csv(
+    path: &PathBuf,
+    csv: &CsvFormatter,
+) -> Result<(Vec<f64>, Vec<usize>), AnchorsCsvError> {
+    let content = fs::read_to_string(path)?;
+    let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+    let header = csv.split_record(lines.next().ok_or(AnchorsCsvError::Empty)?);
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|field| field.trim().eq_ignore_ascii_case(name))
+            .ok_or_else(|| AnchorsCsvError::MissingColumn(name.to_string()))
+    };
+    let (depth_column, nlevels_column) = (column("depth")?, column("nlevels")?);
+    let mut depths = Vec::new();
+    let mut nlevels = Vec::new();
+    for (row, line) in lines.enumerate() {
+        let fields = csv.split_record(line);
+        let field = |column: usize| {
+            fields
+                .get(column)
+                .map(|field| field.trim())
+                .ok_or(AnchorsCsvError::MissingField(row + 2, column + 1))
+        };
+        let depth = field(depth_column)?;
+        depths.push(depth.parse::<f64>().map_err(|_| {
+            AnchorsCsvError::ParseError(row + 2, "depth".to_string(), depth.to_string())
+        })?);
+        let levels = field(nlevels_column)?;
+        nlevels.push(levels.parse::<usize>().map_err(|_| {
+            AnchorsCsvError::ParseError(row + 2, "nlevels".to_string(), levels.to_string())
+        })?);
+    }
+    if depths.is_empty() {
+        return Err(AnchorsCsvError::Empty);
+    }
+    if let Some(row) = depths.windows(2).position(|pair| pair[1] <= pair[0]) {
+        return Err(AnchorsCsvError::NonIncreasingDepths(
+            row + 3,
+            depths[row + 1],
+            depths[row],
+        ));
+    }
+    Ok((depths, nlevels))
+}
+
+#[derive(Error, Debug)]
+pub enum AnchorsCsvError {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error("The anchors CSV has no header or no rows")]
+    Empty,
+    #[error("The anchors CSV has no {0} column")]
+    MissingColumn(String),
+    #[error("Row {0} of the anchors CSV has no column {1}")]
+    MissingField(usize, usize),
+    #[error("Could not parse {1} from {2:?} on row {0} of the anchors CSV")]
+    ParseError(usize, String, String),
+    #[error("Anchor depths must be strictly increasing but row {0} has {1} after {2}")]
+    NonIncreasingDepths(usize, f64, f64),
+}
@@ -0,0 +1,448 @@
+//! Search of the stretching parameters of an auto mode grid that maximize the
+//! quality score within a level budget, behind `vgrid autotune`.
+//!
+//! Unstable: this module is not in [`crate::prelude`] and may change in any
+//! release.
+
+use crate::quality::{quality_report, QualityScore, QualityScoreOptions};
+use crate::transforms::quadratic::QuadraticTransformOpts;
+use crate::transforms::s::STransformOpts;
+use crate::transforms::song_haidvogel::{HcPolicy, SongHaidvogel1994TransformOpts};
+use crate::transforms::StretchingFunction;
+use crate::vqs::{VQSAutoBuilder, VQS};
+use schismrs_hgrid::Hgrid;
+use std::fmt;
+use thiserror::Error;
+
+/// Maximum number of candidates listed when displaying a report.
+const MAX_LISTED_CANDIDATES: usize = 10;
+
+/// Skew decay rate used for quadratic candidates, same as
+/// [`QuadraticTransformOpts::new`].
+const QUADRATIC_SKEW_DECAY_RATE: f64 = 0.03;
+
+/// Stretching family searched by [`VQSAutotuneBuilder`].
+#[derive(Clone, Debug)]
+pub enum AutotuneTransform {
+    /// Searches a_vqs0 in [-1, 0].
+    Quadratic,
+    /// Searches theta_f in (0, 20] and theta_b in [0, 1].
+    S,
+    /// Searches theta_s in (0, 20] and theta_b in [0, 1] with a fixed critical
+    /// depth.
+    SongHaidvogel1994 { hc: f64 },
+}
+
+/// A single evaluated configuration of the search.
+#[derive(Clone, Debug)]
+pub struct AutotuneCandidate {
+    transform: AutotuneTransform,
+    etal: f64,
+    a_vqs0: f64,
+    theta: f64,
+    theta_b: f64,
+    max_levels: usize,
+    nvrt: usize,
+    score: QualityScore,
+}
+
+impl AutotuneCandidate {
+    /// Stretching function the candidate was built with.
+    pub fn stretching(&self) -> StretchingFunction<'_> {
+        stretching_function(
+            &self.transform,
+            &self.etal,
+            &self.a_vqs0,
+            &self.theta,
+            &self.theta_b,
+        )
+    }
+
+    pub fn max_levels(&self) -> usize {
+        self.max_levels
+    }
+
+    pub fn nvrt(&self) -> usize {
+        self.nvrt
+    }
+
+    pub fn score(&self) -> &QualityScore {
+        &self.score
+    }
+}
+
+impl fmt::Display for AutotuneCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.transform {
+            AutotuneTransform::Quadratic => write!(f, "quadratic a_vqs0={:.3}", self.a_vqs0)?,
+            AutotuneTransform::S => {
+                write!(f, "s theta_f={:.3} theta_b={:.3}", self.theta, self.theta_b)?
+            }
+            AutotuneTransform::SongHaidvogel1994 { hc } => write!(
+                f,
+                "song-haidvogel1994 theta_s={:.3} theta_b={:.3} hc={}",
+                self.theta, self.theta_b, hc
+            )?,
+        }
+        write!(
+            f,
+            " max_levels={} nvrt={} score={:.4}",
+            self.max_levels,
+            self.nvrt,
+            self.score.total()
+        )
+    }
+}
+
+fn stretching_function<'b>(
+    transform: &'b AutotuneTransform,
+    etal: &'b f64,
+    a_vqs0: &'b f64,
+    theta: &'b f64,
+    theta_b: &'b f64,
+) -> StretchingFunction<'b> {
+    match transform {
+        AutotuneTransform::Quadratic => StretchingFunction::Quadratic(QuadraticTransformOpts {
+            etal,
+            a_vqs0,
+            skew_decay_rate: &QUADRATIC_SKEW_DECAY_RATE,
+        }),
+        AutotuneTransform::S => StretchingFunction::S(STransformOpts {
+            etal,
+            a_vqs0,
+            theta_b,
+            theta_f: theta,
+        }),
+        AutotuneTransform::SongHaidvogel1994 { hc } => {
+            StretchingFunction::SongHaidvogel1994(SongHaidvogel1994TransformOpts {
+                etal,
+                a_vqs0,
+                theta_s: theta,
+                theta_b,
+                hc,
+                hc_policy: &HcPolicy::Error,
+            })
+        }
+    }
+}
+
+/// Outcome of a [`VQSAutotuneBuilder`] search.
+pub struct AutotuneReport {
+    candidates: Vec<AutotuneCandidate>,
+    best: VQS,
+}
+
+impl AutotuneReport {
+    /// Evaluated candidates within the level budget, best first.
+    pub fn candidates(&self) -> &Vec<AutotuneCandidate> {
+        &self.candidates
+    }
+
+    pub fn best_candidate(&self) -> &AutotuneCandidate {
+        &self.candidates[0]
+    }
+
+    /// Vertical grid of the best candidate.
+    pub fn best(&self) -> &VQS {
+        &self.best
+    }
+}
+
+impl fmt::Display for AutotuneReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "evaluated candidates: {}\n", self.candidates.len())?;
+        for (rank, candidate) in self
+            .candidates
+            .iter()
+            .take(MAX_LISTED_CANDIDATES)
+            .enumerate()
+        {
+            write!(f, "{:>4} {}\n", rank + 1, candidate)?;
+        }
+        write!(f, "best: {}\n", self.best_candidate())?;
+        write!(f, "{}", self.best_candidate().score())
+    }
+}
+
+/// Searches the stretching parameters, and optionally the level count of the
+/// deepest master grid, that maximize the [`QualityScore`] of an auto mode
+/// grid without exceeding a level budget on nvrt.
+///
+/// Each parameter is sampled at `steps` evenly spaced values and every
+/// combination is built with [`VQSAutoBuilder`], so the search costs
+/// `steps^2` builds (times `steps` when tuning levels) on the full mesh.
+#[derive(Default)]
+pub struct VQSAutotuneBuilder<'a> {
+    hgrid: Option<&'a Hgrid>,
+    transform: Option<&'a AutotuneTransform>,
+    etal: Option<&'a f64>,
+    a_vqs0: Option<&'a f64>,
+    dz_bottom_min: Option<&'a f64>,
+    ngrids: Option<&'a usize>,
+    initial_depth: Option<&'a f64>,
+    shallow_levels: Option<&'a usize>,
+    level_budget: Option<&'a usize>,
+    tune_levels: Option<&'a bool>,
+    steps: Option<&'a usize>,
+    score_options: Option<&'a QualityScoreOptions>,
+}
+
+impl<'a> VQSAutotuneBuilder<'a> {
+    pub fn build(&self) -> Result<AutotuneReport, VQSAutotuneBuilderError> {
+        let hgrid = self
+            .hgrid
+            .ok_or_else(|| VQSAutotuneBuilderError::UninitializedFieldError("hgrid".to_string()))?;
+        let transform = self.transform.ok_or_else(|| {
+            VQSAutotuneBuilderError::UninitializedFieldError("transform".to_string())
+        })?;
+        let dz_bottom_min = self.dz_bottom_min.ok_or_else(|| {
+            VQSAutotuneBuilderError::UninitializedFieldError("dz_bottom_min".to_string())
+        })?;
+        let ngrids = self.ngrids.ok_or_else(|| {
+            VQSAutotuneBuilderError::UninitializedFieldError("ngrids".to_string())
+        })?;
+        let level_budget = self.level_budget.ok_or_else(|| {
+            VQSAutotuneBuilderError::UninitializedFieldError("level_budget".to_string())
+        })?;
+        let shallow_levels = self.shallow_levels.cloned().unwrap_or(2);
+        Self::validate_level_budget(level_budget, &shallow_levels)?;
+        let steps = self.steps.cloned().unwrap_or(5);
+        Self::validate_steps(&steps)?;
+        let etal = self.etal.cloned().unwrap_or(0.);
+        let a_vqs0 = self.a_vqs0.cloned().unwrap_or(0.);
+        let initial_depth = self.initial_depth.cloned().unwrap_or(1.);
+        let default_score_options = QualityScoreOptions::default();
+        let score_options = self.score_options.unwrap_or(&default_score_options);
+        let level_candidates = if self.tune_levels.cloned().unwrap_or(false) {
+            Self::level_candidates(shallow_levels, *level_budget, steps)
+        } else {
+            vec![*level_budget]
+        };
+        let mut candidates = Vec::new();
+        let mut best: Option<(f64, usize, VQS)> = None;
+        for (a_vqs0, theta, theta_b) in Self::parameter_candidates(transform, a_vqs0, steps) {
+            for &max_levels in level_candidates.iter() {
+                let stretching = stretching_function(transform, &etal, &a_vqs0, &theta, &theta_b);
+                let mut builder = VQSAutoBuilder::default();
+                builder
+                    .hgrid(hgrid)
+                    .stretching(&stretching)
+                    .ngrids(ngrids)
+                    .dz_bottom_min(dz_bottom_min)
+                    .initial_depth(&initial_depth)
+                    .shallow_levels(&shallow_levels)
+                    .max_levels(&max_levels);
+                let vqs = match builder.build() {
+                    Ok(vqs) => vqs,
+                    Err(err) => {
+                        log::debug!("Skipping autotune candidate: {}", err);
+                        continue;
+                    }
+                };
+                if vqs.nvrt() > *level_budget {
+                    continue;
+                }
+                let score = quality_report(&vqs, hgrid).score(score_options);
+                let total = *score.total();
+                let nvrt = vqs.nvrt();
+                candidates.push(AutotuneCandidate {
+                    transform: transform.clone(),
+                    etal,
+                    a_vqs0,
+                    theta,
+                    theta_b,
+                    max_levels,
+                    nvrt,
+                    score,
+                });
+                if best.as_ref().map_or(true, |(score, best_nvrt, _)| {
+                    total > *score || (total == *score && nvrt < *best_nvrt)
+                }) {
+                    best = Some((total, nvrt, vqs));
+                }
+            }
+        }
+        let (_, _, best) = best.ok_or(VQSAutotuneBuilderError::NoValidCandidate)?;
+        // best first; among equal scores, prefer the cheaper grid
+        candidates.sort_by(|a, b| {
+            b.score
+                .total()
+                .total_cmp(a.score.total())
+                .then(a.nvrt.cmp(&b.nvrt))
+        });
+        Ok(AutotuneReport { candidates, best })
+    }
+
+    fn parameter_candidates(
+        transform: &AutotuneTransform,
+        a_vqs0: f64,
+        steps: usize,
+    ) -> Vec<(f64, f64, f64)> {
+        let fractions: Vec<f64> = (0..steps).map(|i| i as f64 / (steps - 1) as f64).collect();
+        match transform {
+            AutotuneTransform::Quadratic => fractions
+                .iter()
+                .map(|fraction| (fraction - 1., 0., 0.))
+                .collect(),
+            AutotuneTransform::S | AutotuneTransform::SongHaidvogel1994 { .. } => {
+                let mut candidates = Vec::with_capacity(steps * steps);
+                for i in 1..=steps {
+                    let theta = 20. * i as f64 / steps as f64;
+                    for &theta_b in fractions.iter() {
+                        candidates.push((a_vqs0, theta, theta_b));
+                    }
+                }
+                candidates
+            }
+        }
+    }
+
+    fn level_candidates(shallow_levels: usize, level_budget: usize, steps: usize) -> Vec<usize> {
+        let mut levels: Vec<usize> = (0..steps)
+            .map(|i| {
+                let fraction = i as f64 / (steps - 1) as f64;
+                shallow_levels
+                    + (fraction * (level_budget - shallow_levels) as f64).round() as usize
+            })
+            .collect();
+        levels.dedup();
+        levels
+    }
+
+    fn validate_level_budget(
+        level_budget: &usize,
+        shallow_levels: &usize,
+    ) -> Result<(), VQSAutotuneBuilderError> {
+        if *level_budget < *shallow_levels {
+            return Err(VQSAutotuneBuilderError::InvalidLevelBudget(
+                *shallow_levels,
+                *level_budget,
+            ));
+        }
+        Ok(())
+    }
+
+    fn validate_steps(steps: &usize) -> Result<(), VQSAutotuneBuilderError> {
+        if *steps < 2 {
+            return Err(VQSAutotuneBuilderError::InvalidSteps(*steps));
+        }
+        Ok(())
+    }
+
+    pub fn hgrid(&mut self, hgrid: &'a Hgrid) -> &mut Self {
+        self.hgrid = Some(hgrid);
+        self
+    }
+    pub fn transform(&mut self, transform: &'a AutotuneTransform) -> &mut Self {
+        self.transform = Some(transform);
+        self
+    }
+    pub fn etal(&mut self, etal: &'a f64) -> &mut Self {
+        self.etal = Some(etal);
+        self
+    }
+    /// a_vqs0 of the s and song-haidvogel1994 candidates, which only affects
+    /// shallow nodes. Ignored by quadratic candidates, which search it.
+    pub fn a_vqs0(&mut self, a_vqs0: &'a f64) -> &mut Self {
+        self.a_vqs0 = Some(a_vqs0);
+        self
+    }
+    pub fn dz_bottom_min(&mut self, dz_bottom_min: &'a f64) -> &mut Self {
+        self.dz_bottom_min = Some(dz_bottom_min);
+        self
+    }
+    pub fn ngrids(&mut self, ngrids: &'a usize) -> &mut Self {
+        self.ngrids = Some(ngrids);
+        self
+    }
+    pub fn initial_depth(&mut self, initial_depth: &'a f64) -> &mut Self {
+        self.initial_depth = Some(initial_depth);
+        self
+    }
+    pub fn shallow_levels(&mut self, shallow_levels: &'a usize) -> &mut Self {
+        self.shallow_levels = Some(shallow_levels);
+        self
+    }
+    /// Largest nvrt a candidate may have.
+    pub fn level_budget(&mut self, level_budget: &'a usize) -> &mut Self {
+        self.level_budget = Some(level_budget);
+        self
+    }
+    /// Also search the levels of the deepest master grid, between
+    /// shallow_levels and the level budget.
+    pub fn tune_levels(&mut self, tune_levels: &'a bool) -> &mut Self {
+        self.tune_levels = Some(tune_levels);
+        self
+    }
+    pub fn steps(&mut self, steps: &'a usize) -> &mut Self {
+        self.steps = Some(steps);
+        self
+    }
+    pub fn score_options(&mut self, score_options: &'a QualityScoreOptions) -> &mut Self {
+        self.score_options = Some(score_options);
+        self
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum VQSAutotuneBuilderError {
+    #[error("Unitialized field on VQSAutotuneBuilder: {0}")]
+    UninitializedFieldError(String),
+    #[error(
+        "level_budget must be >= shallow_levels but got shallow_levels={0} and level_budget={1}"
+    )]
+    InvalidLevelBudget(usize, usize),
+    #[error("steps must be >= 2 but got {0}")]
+    InvalidSteps(usize),
+    #[error("No candidate could be built within the level budget")]
+    NoValidCandidate,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::channel_hgrid;
+
+    #[test]
+    fn stays_within_the_level_budget_and_beats_the_start() {
+        let hgrid = channel_hgrid(5, 9).unwrap();
+        let transform = AutotuneTransform::S;
+        let score_options = QualityScoreOptions::default();
+        let report = VQSAutotuneBuilder::default()
+            .hgrid(&hgrid)
+            .transform(&transform)
+            .dz_bottom_min(&0.1)
+            .ngrids(&3)
+            .level_budget(&12)
+            .steps(&3)
+            .score_options(&score_options)
+            .build()
+            .unwrap();
+        assert!(!report.candidates().is_empty());
+        for candidate in report.candidates() {
+            assert!(candidate.nvrt() <= 12, "{}", candidate);
+        }
+        assert!(report.best().nvrt() <= 12);
+        // the first candidate searched
+        let stretching = stretching_function(&transform, &0., &0., &(20. / 3.), &0.);
+        let start = VQSAutoBuilder::default()
+            .hgrid(&hgrid)
+            .stretching(&stretching)
+            .ngrids(&3)
+            .dz_bottom_min(&0.1)
+            .initial_depth(&1.)
+            .shallow_levels(&2)
+            .max_levels(&12)
+            .build()
+            .unwrap();
+        let start_score = quality_report(&start, &hgrid).score(&score_options);
+        let best = report.best_candidate();
+        assert!(
+            best.score().total() >= start_score.total(),
+            "{} < {}",
+            best.score().total(),
+            start_score.total()
+        );
+        assert_eq!(best.nvrt(), report.best().nvrt());
+    }
+}
@@ -0,0 +1,169 @@
+use crate::csv::CsvFormatter;
+use crate::vqs::VQS;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Band edges (positive down) used when none are given. The last band is open
+/// ended.
+pub const DEFAULT_DEPTH_BAND_EDGES: [f64; 7] = [0., 5., 20., 100., 500., 1000., 5000.];
+
+/// Level statistics of the wet nodes whose depth falls in a band.
+pub struct DepthBand {
+    top: f64,
+    bottom: Option<f64>,
+    nodes: usize,
+    mean_levels: f64,
+    mean_dz: f64,
+}
+
+impl DepthBand {
+    pub fn top(&self) -> &f64 {
+        &self.top
+    }
+
+    /// Bottom of the band, None for the open ended deepest band.
+    pub fn bottom(&self) -> Option<&f64> {
+        self.bottom.as_ref()
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.nodes
+    }
+
+    /// Mean number of wet levels of the nodes, NaN when the band is empty.
+    pub fn mean_levels(&self) -> &f64 {
+        &self.mean_levels
+    }
+
+    /// Mean layer thickness over all layers of the nodes, NaN when the band is
+    /// empty.
+    pub fn mean_dz(&self) -> &f64 {
+        &self.mean_dz
+    }
+
+    fn label(&self) -> String {
+        match self.bottom {
+            Some(bottom) => format!("{}-{} m", self.top, bottom),
+            None => format!(">{} m", self.top),
+        }
+    }
+}
+
+/// Mean levels and layer thickness per depth band, since reviewers reason in
+/// depth ranges rather than in master grids.
+pub struct DepthBandReport {
+    bands: Vec<DepthBand>,
+}
+
+impl DepthBandReport {
+    pub fn bands(&self) -> &Vec<DepthBand> {
+        &self.bands
+    }
+
+    /// Writes the report as a CSV table, leaving the bottom of the open ended
+    /// band and the means of empty bands blank.
+    pub fn write_to_csv(&self, path: &PathBuf, csv: &CsvFormatter) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        csv.write_header(
+            &mut writer,
+            &["top", "bottom", "nodes", "mean_levels", "mean_dz"],
+        )?;
+        for band in self.bands.iter() {
+            csv.write_record(
+                &mut writer,
+                &[
+                    csv.float(band.top),
+                    band.bottom
+                        .map_or(String::new(), |bottom| csv.float(bottom)),
+                    band.nodes.to_string(),
+                    csv.float(band.mean_levels),
+                    csv.float(band.mean_dz),
+                ],
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+impl fmt::Display for DepthBandReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<18}{:>10}{:>14}{:>14}\n",
+            "depth band", "nodes", "mean levels", "mean dz"
+        )?;
+        for band in self.bands.iter() {
+            write!(
+                f,
+                "{:<18}{:>10}{:>14.2}{:>14.4}\n",
+                band.label(),
+                band.nodes,
+                band.mean_levels,
+                band.mean_dz
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Groups the wet nodes of `vqs` in the bands delimited by the strictly
+/// increasing `edges` (positive down). Nodes shallower than the first edge are
+/// left out and the last band holds every node deeper than the last edge.
+pub fn depth_band_report(vqs: &VQS, edges: &[f64]) -> Result<DepthBandReport, DepthBandError> {
+    if edges.is_empty() {
+        return Err(DepthBandError::NoEdges);
+    }
+    if edges.windows(2).any(|pair| pair[1] <= pair[0]) {
+        return Err(DepthBandError::NonIncreasingEdges(edges.to_vec()));
+    }
+    let mut bands: Vec<DepthBand> = edges
+        .iter()
+        .enumerate()
+        .map(|(i, &top)| DepthBand {
+            top,
+            bottom: edges.get(i + 1).cloned(),
+            nodes: 0,
+            mean_levels: 0.,
+            mean_dz: 0.,
+        })
+        .collect();
+    let mut layers = vec![0usize; bands.len()];
+    let nvrt = vqs.nvrt();
+    for (i, (&kbp, &depth)) in vqs
+        .bottom_level_indices()
+        .iter()
+        .zip(vqs.depths().iter())
+        .enumerate()
+    {
+        if depth <= 0. || depth < edges[0] {
+            continue;
+        }
+        let band = edges.partition_point(|&edge| edge <= depth) - 1;
+        let levels = nvrt + 1 - kbp;
+        bands[band].nodes += 1;
+        bands[band].mean_levels += levels as f64;
+        bands[band].mean_dz += vqs.etal_at(i) + depth;
+        layers[band] += levels - 1;
+    }
+    for (band, layers) in bands.iter_mut().zip(layers) {
+        if band.nodes == 0 {
+            band.mean_levels = f64::NAN;
+            band.mean_dz = f64::NAN;
+            continue;
+        }
+        band.mean_levels /= band.nodes as f64;
+        band.mean_dz /= layers as f64;
+    }
+    Ok(DepthBandReport { bands })
+}
+
+#[derive(Error, Debug)]
+pub enum DepthBandError {
+    #[error("At least one depth band edge is required")]
+    NoEdges,
+    #[error("Depth band edges must be strictly increasing but got {0:?}")]
+    NonIncreasingEdges(Vec<f64>),
+}
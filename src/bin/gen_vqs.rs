@@ -1,11 +1,32 @@
 use clap::{Args, Parser, Subcommand, ValueEnum};
+use indicatif::{ProgressBar, ProgressStyle};
 use pretty_env_logger;
 use schismrs_hgrid::hgrid::Hgrid;
+use schismrs_vgrid::anchors::{anchors, read_anchors_csv, write_anchors_csv};
+use schismrs_vgrid::bands::{depth_band_report, DEFAULT_DEPTH_BAND_EDGES};
+use schismrs_vgrid::cells::DEFAULT_TIME_STEP;
+use schismrs_vgrid::csv::CsvFormatter;
+use schismrs_vgrid::fvcom::{self, fit_fvcom_sigma};
+use schismrs_vgrid::lock::VgridLock;
+#[cfg(feature = "plotters")]
+use schismrs_vgrid::plot::PlotFormat;
+use schismrs_vgrid::progress::{LogProgress, ProgressReporter};
+use schismrs_vgrid::regions::RegionOverride;
+use schismrs_vgrid::transforms::geometric::GeometricTransformOpts;
 use schismrs_vgrid::transforms::quadratic::QuadraticTransformOpts;
 use schismrs_vgrid::transforms::s::STransformOpts;
+use schismrs_vgrid::transforms::song_haidvogel::{HcPolicy, SongHaidvogel1994TransformOpts};
 use schismrs_vgrid::transforms::StretchingFunction;
-use schismrs_vgrid::vqs::{VQSAutoBuilder, VQSBuilder, VQSKMeansBuilder};
+use schismrs_vgrid::vqs::{
+    DzBottomMinProfile, LevelLimits, ShallowFormula, VQSAutoBuilder, VQSBuildOptions, VQSBuilder,
+    VQSKMeansBuilder, VQSLogLinearBuilder, DEFAULT_H0,
+};
+use schismrs_vgrid::writer::{is_gzip_path, VgridFormat, VgridWriterOptions};
+use schismrs_vgrid::ClusteringMethod;
+use std::fmt;
+use std::io::IsTerminal;
 use std::process::ExitCode;
+use std::time::{Duration, Instant};
 use std::{error::Error, path::PathBuf};
 
 const VERSION: &'static str = concat! {
@@ -21,6 +42,18 @@ struct Cli {
     hgrid_path: PathBuf,
     #[clap(short, long)]
     output_filepath: Option<PathBuf>,
+    #[cfg(feature = "netcdf")]
+    #[clap(long, help = "Also write the vertical grid as a netCDF file.")]
+    netcdf_output: Option<PathBuf>,
+    #[cfg(feature = "netcdf")]
+    #[clap(
+        long,
+        action,
+        requires = "netcdf_output",
+        help = "Also write the z of each level interpolated from the master grids \
+                as 'znd' to --netcdf-output, for debugging the interpolation."
+    )]
+    retain_znd: bool,
     #[clap(short, long)]
     transform: StretchingFunctionKind,
     #[clap(
@@ -36,6 +69,7 @@ struct Cli {
     skew_decay_rate: Option<f64>,
     #[clap(
         long,
+        required_if_eq("transform", "s"),
         help = "Range is (0., 20.]. Values closer to 0. make the transformation \
                 more similar to traditional sigma. Larger values will increase \
                 resolution at the top and bottom."
@@ -43,17 +77,266 @@ struct Cli {
     theta_f: Option<f64>,
     #[clap(
         long,
+        required_if_eq_any([("transform", "s"), ("transform", "song-haidvogel1994")]),
         help = "Range is [0., 1.]. For values closer to 0. the surface is \
                 resolved. For values closer to 1., but the surface and bottom \
                 are resolved."
     )]
     theta_b: Option<f64>,
+    #[clap(
+        long,
+        required_if_eq("transform", "song-haidvogel1994"),
+        help = "Surface control parameter of the song-haidvogel1994 transform. \
+                Range is (0., 20.]."
+    )]
+    theta_s: Option<f64>,
+    #[clap(
+        long,
+        required_if_eq("transform", "song-haidvogel1994"),
+        help = "Critical depth of the song-haidvogel1994 transform. Must be > 0. \
+                and no deeper than the first master grid."
+    )]
+    hc: Option<f64>,
+    #[clap(
+        long,
+        value_enum,
+        default_value = "error",
+        help = "What to do when --hc is deeper than the first master grid: fail, or \
+                clamp it to that depth with a warning."
+    )]
+    hc_policy: HcPolicyKind,
+    #[clap(
+        long,
+        required_if_eq("transform", "geometric"),
+        help = "Thickness of the top layer of the geometric transform. Must be > 0."
+    )]
+    surface_dz: Option<f64>,
+    #[clap(
+        long,
+        conflicts_with = "a_vqs0",
+        help = "Solve the a_vqs0 of the quadratic transform for this thickness of the \
+                top layer at the first master grid. Only in hsm mode."
+    )]
+    quadratic_surface_dz: Option<f64>,
+    #[clap(
+        long,
+        required_if_eq("transform", "geometric"),
+        help = "Ratio between the thicknesses of consecutive layers of the geometric \
+                transform, e.g. 1.15 for 15% growth. Must be >= 1."
+    )]
+    growth: Option<f64>,
     #[clap(long)]
     dz_bottom_min: f64,
+    #[clap(
+        long,
+        help = "Scale the bottom layer floor with depth: each node uses \
+                max(--dz-bottom-min, this fraction * depth)."
+    )]
+    dz_bottom_min_fraction: Option<f64>,
+    #[clap(
+        long,
+        default_value_t = DEFAULT_TIME_STEP,
+        help = "SCHISM time step in seconds used to estimate the core hours per \
+                simulated day in the printed cell count summary."
+    )]
+    time_step: f64,
+    #[clap(
+        long,
+        help = "Nodes shallower than this depth (positive down) get a flat, uniform \
+                column of --intertidal-levels levels. Must be shallower than the \
+                first master grid."
+    )]
+    intertidal_depth: Option<f64>,
+    #[clap(
+        long,
+        requires = "intertidal_depth",
+        help = "Number of levels used on intertidal nodes. Defaults to 2."
+    )]
+    intertidal_levels: Option<usize>,
+    #[clap(
+        long,
+        help = "Minimum total water depth (etal + h) of the z levels of shallow columns, \
+                for nodes dry at the design etal. Does not change sigma. Defaults to 0.01"
+    )]
+    h0: Option<f64>,
+    #[clap(
+        long,
+        value_enum,
+        default_value = "quadratic",
+        help = "Layer distribution of nodes shallower than the first master grid."
+    )]
+    shallow_formula: ShallowFormulaKind,
+    #[clap(
+        long,
+        help = "File with one 'nlevels POLYGON ((x y, ...))' line per region. Nodes \
+                inside a region get that many levels regardless of their depth."
+    )]
+    region_overrides: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "gr3 on the same nodes as the hgrid whose depths are used to build \
+                the columns, e.g. a smoothed or dredged bathymetry."
+    )]
+    bathy: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "gr3 on the same nodes as the hgrid holding the mean free surface \
+                elevation (positive up) of each node, e.g. a mean sea surface. \
+                Columns follow the local surface instead of --etal."
+    )]
+    etal_gr3: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "Design the deepest master grid of the kmeans and auto modes for this \
+                depth (positive down) instead of the deepest node, so the grid stays \
+                valid after the mesh is deepened."
+    )]
+    max_depth_override: Option<f64>,
+    #[clap(
+        long,
+        help = "Pull the interior levels of each master grid towards the level surfaces \
+                of the neighbouring master grids by this strength in (0, 1]. Reduces \
+                kinks between independently designed master grids."
+    )]
+    smooth_zmas: Option<f64>,
+    #[clap(
+        long,
+        default_value = "2",
+        help = "Minimum number of levels of every column. Columns with fewer levels are \
+                rebuilt with this many, and their count is reported after the build."
+    )]
+    min_levels: usize,
+    #[clap(
+        long,
+        help = "Fail the build, listing the nodes and levels, when two adjacent layers \
+                differ in thickness by more than this ratio (>= 1)."
+    )]
+    max_layer_ratio: Option<f64>,
+    #[clap(
+        long,
+        help = "Raise the bottom level of nodes with more than this many levels over a \
+                node sharing an element, like the neighbour check of SCHISM's gen_vqs."
+    )]
+    max_level_jump: Option<usize>,
+    #[clap(
+        long,
+        requires = "max_level_jump",
+        help = "Write the nodes adjusted by --max-level-jump to this CSV file."
+    )]
+    level_jump_csv: Option<PathBuf>,
+    #[clap(
+        long,
+        value_enum,
+        default_value = "legacy",
+        help = "Write one record per level ('legacy') or one record per node \
+                ('node-major', read by newer SCHISM versions)."
+    )]
+    vgrid_format: VgridFormatKind,
+    #[clap(
+        long,
+        action,
+        help = "Gzip the vgrid.in output, appending .gz to its path unless present. \
+                Paths ending in .gz are always compressed."
+    )]
+    compress: bool,
+    #[clap(
+        long,
+        default_value = "200",
+        help = "Warn when any column would get more levels than this."
+    )]
+    soft_level_limit: usize,
+    #[clap(
+        long,
+        default_value = "1000",
+        help = "Refuse to build when any column would get more levels than this."
+    )]
+    hard_level_limit: usize,
     #[clap(long, action)]
     show_zmas_plot: bool,
-    #[clap(long)]
+    #[clap(
+        long,
+        help = "Save the master grids plot. A .png or .svg path is rendered headless \
+                when built with the plotters feature."
+    )]
     save_zmas_plot: Option<PathBuf>,
+    #[clap(
+        long,
+        default_value = "vgrid.lock",
+        help = "Where to write the resolved build parameters."
+    )]
+    lock_file: PathBuf,
+    #[clap(
+        long,
+        help = "Write the metadata of the vertical grid to a .json or .yaml file."
+    )]
+    emit_metadata: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "Write the depths, level counts and level z of the master grids to a .json \
+                or .yaml file."
+    )]
+    master_grid_output: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "Write the master grids with the min, mean and max dz of the nodes \
+                each one covers to this CSV file."
+    )]
+    anchors_csv_output: Option<PathBuf>,
+    #[clap(
+        long,
+        action,
+        help = "Print the mean levels and mean dz per depth band after the build."
+    )]
+    depth_band_report: bool,
+    #[clap(
+        long,
+        value_delimiter = ' ',
+        num_args = 1..,
+        default_values_t = DEFAULT_DEPTH_BAND_EDGES,
+        help = "Strictly increasing depth band edges (positive down) of the depth band \
+                report. The last band holds every node deeper than the last edge."
+    )]
+    depth_band_edges: Vec<f64>,
+    #[clap(long, help = "Write the depth band report to this CSV file.")]
+    depth_band_csv: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "Write an FVCOM sigma.dat approximating the column of the deepest node \
+                to this file, for paired SCHISM/FVCOM experiments."
+    )]
+    fvcom_sigma_output: Option<PathBuf>,
+    #[clap(
+        long,
+        value_enum,
+        default_value = "auto",
+        requires = "fvcom_sigma_output",
+        help = "FVCOM sigma definition of --fvcom-sigma-output. auto picks the closest."
+    )]
+    fvcom_sigma_kind: FvcomSigmaKind,
+    #[clap(
+        long,
+        help = "Write a gr3 holding the bottom level index (kbp) of each node to this file."
+    )]
+    nlevels_gr3_output: Option<PathBuf>,
+    #[clap(
+        long,
+        default_value = ",",
+        help = "Field separator of CSV inputs and outputs."
+    )]
+    csv_delimiter: char,
+    #[clap(
+        long,
+        action,
+        help = "Refuse to write outputs if the build resolves differently from --lock-file."
+    )]
+    locked: bool,
+    #[clap(
+        long,
+        action,
+        help = "Print the wall time of each stage of the run: loading, build, lock \
+                and outputs."
+    )]
+    bench: bool,
     #[clap(subcommand)]
     mode: Modes,
 }
@@ -62,6 +345,8 @@ struct Cli {
 enum StretchingFunctionKind {
     Quadratic,
     S,
+    SongHaidvogel1994,
+    Geometric,
     // Shchepetkin2005,
     // Geyer,
     // Shchepetkin2010,
@@ -74,11 +359,12 @@ enum Modes {
     Kmeans(KmeansCliOpts),
     Hsm(HsmCliOpts),
     Auto(AutoCliOpts),
+    LogLinear(LogLinearCliOpts),
 }
 
 #[derive(Args, Debug)]
 struct KmeansCliOpts {
-    #[clap(short, long, help = "Number of clusters. Must be an interger >= 1")]
+    #[clap(short, long, help = "Number of clusters. Must be an integer >= 1")]
     clusters: usize,
     #[clap(
         short,
@@ -93,14 +379,78 @@ struct KmeansCliOpts {
                 Defaults to shallow_levels + clusters - 1"
     )]
     max_levels: Option<usize>,
+    #[clap(long, value_enum, default_value = "kmeans")]
+    method: ClusteringMethodKind,
+    #[clap(
+        long,
+        action,
+        help = "Weight each node by its median-dual area, so that densely refined \
+                regions do not dominate the clusters."
+    )]
+    area_weighted: bool,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum VgridFormatKind {
+    Legacy,
+    NodeMajor,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum HcPolicyKind {
+    Error,
+    Clamp,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum ShallowFormulaKind {
+    Quadratic,
+    UniformZ,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum FvcomSigmaKind {
+    Auto,
+    Uniform,
+    Geometric,
+    Tanh,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum ClusteringMethodKind {
+    Kmeans,
+    LogKmeans,
+    Quantile,
+    Jenks,
 }
 
 #[derive(Args, Debug)]
 struct HsmCliOpts {
-    #[clap(short, long, value_delimiter = ' ', num_args = 1..)]
+    #[clap(
+        short,
+        long,
+        value_delimiter = ' ',
+        num_args = 1..,
+        help = "Master grid depths, strictly increasing. Accepts separate values or \
+                a single quoted list, e.g. --depths \"50 100 200\"."
+    )]
     depths: Vec<f64>,
-    #[clap(short, long, value_delimiter = ' ', num_args = 1..)]
+    #[clap(
+        short,
+        long,
+        value_delimiter = ' ',
+        num_args = 1..,
+        help = "Levels of each master grid, one per depth and non-decreasing, \
+                e.g. --nlevels \"21 30 39\"."
+    )]
     nlevels: Vec<usize>,
+    #[clap(
+        long,
+        conflicts_with_all = ["depths", "nlevels"],
+        help = "Read the master grids from the depth and nlevels columns of a CSV \
+                file, e.g. one written by --anchors-csv-output."
+    )]
+    anchors_csv: Option<PathBuf>,
 }
 
 #[derive(Args, Debug)]
@@ -126,16 +476,231 @@ struct AutoCliOpts {
                 Defaults to shallow_levels + clusters - 1"
     )]
     max_levels: Option<usize>,
+    #[clap(
+        long,
+        conflicts_with = "max_levels",
+        help = "Search max_levels for the grid whose total number of wet 3D nodes is \
+                closest to this budget."
+    )]
+    target_cells: Option<usize>,
+    #[clap(
+        long,
+        requires = "target_cells",
+        help = "Largest accepted relative difference from --target-cells. Defaults to 0.02."
+    )]
+    target_cells_tolerance: Option<f64>,
+}
+
+#[derive(Args, Debug)]
+struct LogLinearCliOpts {
+    #[clap(short, long, value_delimiter = ' ', num_args = 1..)]
+    depths: Vec<f64>,
+    #[clap(long, help = "Slope a of nlevels = a * ln(depth) + b")]
+    slope: Option<f64>,
+    #[clap(long, help = "Intercept b of nlevels = a * ln(depth) + b")]
+    intercept: Option<f64>,
+    #[clap(
+        long,
+        value_delimiter = ' ',
+        num_args = 1..,
+        help = "Depths of the anchor points used to fit a and b when not given explicitly."
+    )]
+    anchor_depths: Option<Vec<f64>>,
+    #[clap(long, value_delimiter = ' ', num_args = 1..)]
+    anchor_levels: Option<Vec<usize>>,
+    #[clap(
+        short,
+        long,
+        default_value = "2",
+        help = "Minimum number of levels of any master grid. Must be an integer >= 2."
+    )]
+    shallow_levels: Option<usize>,
+}
+
+/// Name of `value` on the command line, which unlike its Debug output is
+/// stable across releases and so safe to record in a lock.
+fn value_name<T: ValueEnum>(value: &T) -> String {
+    value.to_possible_value().unwrap().get_name().to_string()
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+    values.join(" ")
+}
+
+/// Records the stretching and its resolved parameters, e.g. the a_vqs0 solved
+/// from --quadratic-surface-dz.
+fn insert_stretching(lock: &mut VgridLock, cli: &Cli, transform: &StretchingFunction) {
+    lock.insert("stretching", value_name(&cli.transform));
+    lock.insert("etal", transform.etal());
+    match transform {
+        StretchingFunction::Quadratic(opts) => {
+            lock.insert("a_vqs0", opts.a_vqs0);
+            lock.insert("skew_decay_rate", opts.skew_decay_rate);
+        }
+        StretchingFunction::S(opts) => {
+            lock.insert("a_vqs0", opts.a_vqs0);
+            lock.insert("theta_b", opts.theta_b);
+            lock.insert("theta_f", opts.theta_f);
+        }
+        StretchingFunction::SongHaidvogel1994(opts) => {
+            lock.insert("a_vqs0", opts.a_vqs0);
+            lock.insert("theta_s", opts.theta_s);
+            lock.insert("theta_b", opts.theta_b);
+            lock.insert("hc", opts.hc);
+            lock.insert("hc_policy", value_name(&cli.hc_policy));
+        }
+        StretchingFunction::Geometric(opts) => {
+            lock.insert("a_vqs0", opts.a_vqs0);
+            lock.insert("surface_dz", opts.surface_dz);
+            lock.insert("growth", opts.growth);
+        }
+    }
+}
+
+/// Records the mode under its subcommand name and its options under their
+/// flag names. Unset options are left out, as the lock has no empty values.
+fn insert_mode(lock: &mut VgridLock, mode: &Modes) {
+    match mode {
+        Modes::Kmeans(opts) => {
+            lock.insert("mode", "kmeans");
+            lock.insert("clusters", opts.clusters);
+            if let Some(shallow_levels) = opts.shallow_levels {
+                lock.insert("shallow_levels", shallow_levels);
+            }
+            if let Some(max_levels) = opts.max_levels {
+                lock.insert("max_levels", max_levels);
+            }
+            lock.insert("method", value_name(&opts.method));
+            lock.insert("area_weighted", opts.area_weighted);
+        }
+        Modes::Hsm(opts) => {
+            lock.insert("mode", "hsm");
+            // the master grids of --anchors-csv are covered by its hash
+            if opts.anchors_csv.is_none() {
+                lock.insert("depths", join(&opts.depths));
+                lock.insert("nlevels", join(&opts.nlevels));
+            }
+        }
+        Modes::Auto(opts) => {
+            lock.insert("mode", "auto");
+            lock.insert("ngrids", opts.ngrids);
+            if let Some(initial_depth) = opts.initial_depth {
+                lock.insert("initial_depth", initial_depth);
+            }
+            if let Some(shallow_levels) = opts.shallow_levels {
+                lock.insert("shallow_levels", shallow_levels);
+            }
+            if let Some(max_levels) = opts.max_levels {
+                lock.insert("max_levels", max_levels);
+            }
+            if let Some(target_cells) = opts.target_cells {
+                lock.insert("target_cells", target_cells);
+            }
+            if let Some(tolerance) = opts.target_cells_tolerance {
+                lock.insert("target_cells_tolerance", tolerance);
+            }
+        }
+        Modes::LogLinear(opts) => {
+            lock.insert("mode", "log-linear");
+            lock.insert("depths", join(&opts.depths));
+            if let Some(slope) = opts.slope {
+                lock.insert("slope", slope);
+            }
+            if let Some(intercept) = opts.intercept {
+                lock.insert("intercept", intercept);
+            }
+            if let Some(anchor_depths) = &opts.anchor_depths {
+                lock.insert("anchor_depths", join(anchor_depths));
+            }
+            if let Some(anchor_levels) = &opts.anchor_levels {
+                lock.insert("anchor_levels", join(anchor_levels));
+            }
+            if let Some(shallow_levels) = opts.shallow_levels {
+                lock.insert("shallow_levels", shallow_levels);
+            }
+        }
+    }
+}
+
+/// Solves the quadratic a_vqs0 for `surface_dz` at the first master grid,
+/// which is only known up front in hsm mode.
+fn quadratic_a_vqs0(cli: &Cli, csv: &CsvFormatter, surface_dz: f64) -> Result<f64, Box<dyn Error>> {
+    let opts = match &cli.mode {
+        Modes::Hsm(opts) => opts,
+        _ => return Err("--quadratic-surface-dz needs the hsm mode".into()),
+    };
+    let (depths, nlevels) = match &opts.anchors_csv {
+        Some(path) => read_anchors_csv(path, csv)?,
+        None => (opts.depths.clone(), opts.nlevels.clone()),
+    };
+    let (depth, nlevels) = match (depths.first(), nlevels.first()) {
+        (Some(depth), Some(nlevels)) => (*depth, *nlevels),
+        _ => return Err("hsm mode needs at least one master grid".into()),
+    };
+    let a_vqs0 =
+        QuadraticTransformOpts::from_surface_dz(surface_dz, depth + cli.etal.unwrap(), nlevels)?;
+    log::info!(
+        "a_vqs0 = {} gives a {} thick surface layer at the first master grid",
+        a_vqs0,
+        surface_dz
+    );
+    Ok(a_vqs0)
+}
+
+/// Sets the build options every mode takes straight from the command line.
+fn apply_common_options<'a>(cli: &'a Cli, options: &mut VQSBuildOptions<'a>) {
+    if let Some(intertidal_depth) = &cli.intertidal_depth {
+        options.intertidal_depth(intertidal_depth);
+    }
+    if let Some(intertidal_levels) = &cli.intertidal_levels {
+        options.intertidal_levels(intertidal_levels);
+    }
+    if let Some(h0) = &cli.h0 {
+        options.h0(h0);
+    }
+    if let Some(smooth_zmas) = &cli.smooth_zmas {
+        options.smooth_zmas(smooth_zmas);
+    }
+    options.min_levels(&cli.min_levels);
+    if let Some(max_layer_ratio) = &cli.max_layer_ratio {
+        options.max_layer_ratio(max_layer_ratio);
+    }
+    #[cfg(feature = "netcdf")]
+    options.retain_znd(&cli.retain_znd);
 }
 
 fn entrypoint() -> Result<(), Box<dyn Error>> {
     pretty_env_logger::init();
     let cli = Cli::parse();
+    let mut timer = StageTimer::new();
     let hgrid = Hgrid::try_from(&cli.hgrid_path)?;
+    timer.lap("load hgrid");
+    let mut csv = CsvFormatter::new();
+    csv.delimiter(cli.csv_delimiter);
+    let geometric = matches!(cli.transform, StretchingFunctionKind::Geometric);
+    if cli.surface_dz.is_some() && !geometric {
+        return Err("--surface-dz only applies to the geometric transform, \
+                    use --quadratic-surface-dz to solve the quadratic a_vqs0"
+            .into());
+    }
+    let a_vqs0 = match (&cli.transform, &cli.quadratic_surface_dz) {
+        (StretchingFunctionKind::Quadratic, Some(surface_dz)) => {
+            quadratic_a_vqs0(&cli, &csv, *surface_dz)?
+        }
+        (_, Some(_)) => {
+            return Err("--quadratic-surface-dz only applies to the quadratic transform".into())
+        }
+        _ => cli.a_vqs0.unwrap(),
+    };
+    let hc_policy = match cli.hc_policy {
+        HcPolicyKind::Error => HcPolicy::Error,
+        HcPolicyKind::Clamp => HcPolicy::Clamp,
+    };
     let transform = match cli.transform {
         StretchingFunctionKind::Quadratic => {
             let quadratic_opts = QuadraticTransformOpts {
-                a_vqs0: cli.a_vqs0.as_ref().unwrap(),
+                a_vqs0: &a_vqs0,
                 etal: cli.etal.as_ref().unwrap(),
                 skew_decay_rate: cli.skew_decay_rate.as_ref().unwrap(),
             };
@@ -150,21 +715,182 @@ fn entrypoint() -> Result<(), Box<dyn Error>> {
             };
             StretchingFunction::S(s_opts)
         }
+        StretchingFunctionKind::SongHaidvogel1994 => {
+            let sh_opts = SongHaidvogel1994TransformOpts {
+                a_vqs0: cli.a_vqs0.as_ref().unwrap(),
+                etal: cli.etal.as_ref().unwrap(),
+                theta_s: cli.theta_s.as_ref().unwrap(),
+                theta_b: cli.theta_b.as_ref().unwrap(),
+                hc: cli.hc.as_ref().unwrap(),
+                hc_policy: &hc_policy,
+            };
+            StretchingFunction::SongHaidvogel1994(sh_opts)
+        }
+        StretchingFunctionKind::Geometric => {
+            let geometric_opts = GeometricTransformOpts {
+                a_vqs0: cli.a_vqs0.as_ref().unwrap(),
+                etal: cli.etal.as_ref().unwrap(),
+                surface_dz: cli.surface_dz.as_ref().unwrap(),
+                growth: cli.growth.as_ref().unwrap(),
+            };
+            StretchingFunction::Geometric(geometric_opts)
+        }
     };
-    let vqs = match &cli.mode {
-        Modes::Hsm(opts) => VQSBuilder::default()
-            .hgrid(&hgrid)
-            .depths(&opts.depths)
-            .nlevels(&opts.nlevels)
-            .stretching(&transform)
-            .dz_bottom_min(&cli.dz_bottom_min)
-            .build()?,
+    #[cfg(not(feature = "plotters"))]
+    if let Some("png" | "svg") = cli
+        .save_zmas_plot
+        .as_ref()
+        .and_then(|path| path.extension())
+        .and_then(|e| e.to_str())
+    {
+        return Err("PNG and SVG plots need a build with --features plotters".into());
+    }
+    let derives_depths = matches!(cli.mode, Modes::Kmeans(_) | Modes::Auto(_));
+    if cli.max_depth_override.is_some() && !derives_depths {
+        return Err("--max-depth-override only applies to the kmeans and auto modes".into());
+    }
+    let shallow_formula = match cli.shallow_formula {
+        ShallowFormulaKind::Quadratic => ShallowFormula::Quadratic,
+        ShallowFormulaKind::UniformZ => ShallowFormula::UniformZ,
+    };
+    let bathymetry = match &cli.bathy {
+        Some(path) => Some(Hgrid::try_from(path)?.depths()),
+        None => None,
+    };
+    // gr3 values are read as depths, i.e. negated
+    let etal_field = match &cli.etal_gr3 {
+        Some(path) => Some(-Hgrid::try_from(path)?.depths()),
+        None => None,
+    };
+    let dz_bottom_min_profile =
+        cli.dz_bottom_min_fraction
+            .map(|fraction| DzBottomMinProfile::Proportional {
+                min: cli.dz_bottom_min,
+                fraction,
+            });
+    let level_limits = LevelLimits::new(cli.soft_level_limit, cli.hard_level_limit);
+    let region_overrides = match &cli.region_overrides {
+        Some(path) => Some(RegionOverride::try_from_file(path)?),
+        None => None,
+    };
+    let mut lock = VgridLock::new();
+    lock.insert("version", VERSION);
+    lock.insert_file_hash("hgrid_hash", &cli.hgrid_path)?;
+    insert_stretching(&mut lock, &cli, &transform);
+    lock.insert("dz_bottom_min", cli.dz_bottom_min);
+    lock.insert("min_levels", cli.min_levels);
+    if let Some(max_layer_ratio) = cli.max_layer_ratio {
+        lock.insert("max_layer_ratio", max_layer_ratio);
+    }
+    if let Some(fraction) = cli.dz_bottom_min_fraction {
+        lock.insert("dz_bottom_min_fraction", fraction);
+    }
+    if let Some(intertidal_depth) = cli.intertidal_depth {
+        lock.insert("intertidal_depth", intertidal_depth);
+    }
+    if let Some(intertidal_levels) = cli.intertidal_levels {
+        lock.insert("intertidal_levels", intertidal_levels);
+    }
+    lock.insert("h0", cli.h0.unwrap_or(DEFAULT_H0));
+    lock.insert("shallow_formula", value_name(&cli.shallow_formula));
+    lock.insert("vgrid_format", value_name(&cli.vgrid_format));
+    if let Some(max_depth) = cli.max_depth_override {
+        lock.insert("max_depth_override", max_depth);
+    }
+    if let Some(smooth_zmas) = cli.smooth_zmas {
+        lock.insert("smooth_zmas", smooth_zmas);
+    }
+    if let Some(max_level_jump) = cli.max_level_jump {
+        lock.insert("max_level_jump", max_level_jump);
+    }
+    if let Some(path) = &cli.region_overrides {
+        lock.insert_file_hash("region_overrides_hash", path)?;
+    }
+    if let Some(path) = &cli.bathy {
+        lock.insert_file_hash("bathy_hash", path)?;
+    }
+    if let Some(path) = &cli.etal_gr3 {
+        lock.insert_file_hash("etal_gr3_hash", path)?;
+    }
+    if let Modes::Hsm(HsmCliOpts {
+        anchors_csv: Some(path),
+        ..
+    }) = &cli.mode
+    {
+        lock.insert_file_hash("anchors_csv_hash", path)?;
+    }
+    insert_mode(&mut lock, &cli.mode);
+    // the parameters are checked before the build, which can take a while,
+    // and the master grids they resolve to after it
+    let locked = match cli.locked {
+        true => Some(VgridLock::try_from_file(&cli.lock_file)?),
+        false => None,
+    };
+    if let Some(locked) = &locked {
+        let differences = lock.parameter_differences(locked);
+        if !differences.is_empty() {
+            return Err(format!(
+                "parameters differ from {}: {}",
+                cli.lock_file.display(),
+                differences.join(", ")
+            )
+            .into());
+        }
+    }
+    timer.lap("load inputs");
+    let progress = ProgressBarReporter::new();
+    let mut options = VQSBuildOptions::new();
+    apply_common_options(&cli, &mut options);
+    options
+        .shallow_formula(&shallow_formula)
+        .level_limits(&level_limits)
+        .progress(&progress);
+    if let Some(dz_bottom_min_profile) = &dz_bottom_min_profile {
+        options.dz_bottom_min_profile(dz_bottom_min_profile);
+    }
+    if let Some(bathymetry) = &bathymetry {
+        options.bathymetry(bathymetry);
+    }
+    if let Some(etal_field) = &etal_field {
+        options.etal_field(etal_field);
+    }
+    if let Some(region_overrides) = &region_overrides {
+        options.region_overrides(region_overrides);
+    }
+    let mut vqs = match &cli.mode {
+        Modes::Hsm(opts) => {
+            let (depths, nlevels) = match &opts.anchors_csv {
+                Some(path) => read_anchors_csv(path, &csv)?,
+                None => (opts.depths.clone(), opts.nlevels.clone()),
+            };
+            let mut builder = VQSBuilder::default();
+            builder
+                .hgrid(&hgrid)
+                .depths(&depths)
+                .nlevels(&nlevels)
+                .stretching(&transform)
+                .dz_bottom_min(&cli.dz_bottom_min);
+            builder.options(options);
+            builder.build()?
+        }
         Modes::Kmeans(opts) => {
+            let clustering_method = match opts.method {
+                ClusteringMethodKind::Kmeans => ClusteringMethod::KMeans,
+                ClusteringMethodKind::LogKmeans => ClusteringMethod::LogKMeans,
+                ClusteringMethodKind::Quantile => ClusteringMethod::Quantile,
+                ClusteringMethodKind::Jenks => ClusteringMethod::Jenks,
+            };
             let mut builder = VQSKMeansBuilder::default();
             builder.hgrid(&hgrid);
+            builder.clustering_method(&clustering_method);
             builder.stretching(&transform);
             builder.nclusters(&opts.clusters);
+            builder.area_weighted(&opts.area_weighted);
             builder.dz_bottom_min(&cli.dz_bottom_min);
+            if let Some(max_depth_override) = &cli.max_depth_override {
+                builder.max_depth_override(max_depth_override);
+            }
+            builder.options(options);
             builder.etal(cli.etal.as_ref().unwrap());
             if let Some(shallow_levels) = &opts.shallow_levels {
                 builder.shallow_levels(shallow_levels);
@@ -180,18 +906,159 @@ fn entrypoint() -> Result<(), Box<dyn Error>> {
             builder.stretching(&transform);
             builder.ngrids(&opts.ngrids);
             builder.dz_bottom_min(&cli.dz_bottom_min);
+            if let Some(max_depth_override) = &cli.max_depth_override {
+                builder.max_depth_override(max_depth_override);
+            }
+            builder.options(options);
             builder.initial_depth(&opts.initial_depth.as_ref().unwrap());
             builder.shallow_levels(&opts.shallow_levels.as_ref().unwrap());
             if let Some(max_levels) = &opts.max_levels {
                 builder.max_levels(max_levels);
             }
+            if let Some(target_cells) = &opts.target_cells {
+                builder.target_cells(target_cells);
+            }
+            if let Some(target_cells_tolerance) = &opts.target_cells_tolerance {
+                builder.target_cells_tolerance(target_cells_tolerance);
+            }
+            builder.build()?
+        }
+        Modes::LogLinear(opts) => {
+            let mut builder = VQSLogLinearBuilder::default();
+            builder.hgrid(&hgrid);
+            builder.stretching(&transform);
+            builder.depths(&opts.depths);
+            builder.dz_bottom_min(&cli.dz_bottom_min);
+            builder.options(options);
+            builder.shallow_levels(opts.shallow_levels.as_ref().unwrap());
+            if let Some(slope) = &opts.slope {
+                builder.slope(slope);
+            }
+            if let Some(intercept) = &opts.intercept {
+                builder.intercept(intercept);
+            }
+            if let Some(anchor_depths) = &opts.anchor_depths {
+                builder.anchor_depths(anchor_depths);
+            }
+            if let Some(anchor_levels) = &opts.anchor_levels {
+                builder.anchor_levels(anchor_levels);
+            }
             builder.build()?
         }
     };
-    if cli.output_filepath.is_some() {
-        vqs.write_to_file(&cli.output_filepath.as_ref().unwrap())?;
+    let level_jumps = match cli.max_level_jump {
+        Some(max_level_jump) => {
+            let report = vqs.limit_level_jumps(&hgrid, max_level_jump);
+            print!("{}", report);
+            Some(report)
+        }
+        None => None,
+    };
+    timer.lap("build");
+    let mut cell_count_summary = vqs.cell_count_summary(&hgrid);
+    cell_count_summary.time_step(cli.time_step);
+    print!("{}", cell_count_summary);
+    if let Some(floored_nodes) = vqs.floored_nodes() {
+        print!(
+            "{:<28}{:>14}\n",
+            format!("nodes raised to {} levels", cli.min_levels),
+            floored_nodes
+        );
+    }
+    lock.insert_master_grids(&vqs);
+    if let Some(locked) = &locked {
+        let differences = lock.differences(locked);
+        if !differences.is_empty() {
+            return Err(format!(
+                "build resolves differently from {}: {}",
+                cli.lock_file.display(),
+                differences.join(", ")
+            )
+            .into());
+        }
+    } else {
+        lock.write_to_file(&cli.lock_file)?;
+    }
+    timer.lap("lock");
+    if let Some(output_filepath) = &cli.output_filepath {
+        let mut writer_options = VgridWriterOptions::new();
+        writer_options.format(match cli.vgrid_format {
+            VgridFormatKind::Legacy => VgridFormat::Legacy,
+            VgridFormatKind::NodeMajor => VgridFormat::NodeMajor,
+        });
+        let output_filepath = if cli.compress && !is_gzip_path(output_filepath) {
+            let mut path = output_filepath.clone().into_os_string();
+            path.push(".gz");
+            PathBuf::from(path)
+        } else {
+            output_filepath.clone()
+        };
+        vqs.write_to_file_with_options(&output_filepath, &writer_options)?;
     };
+    timer.lap("write vgrid.in");
+    #[cfg(feature = "netcdf")]
+    if let Some(netcdf_output) = &cli.netcdf_output {
+        vqs.write_to_netcdf(netcdf_output)?;
+    }
+    if let Some(emit_metadata) = &cli.emit_metadata {
+        vqs.metadata().write_to_file(emit_metadata)?;
+    }
+    if let Some(master_grid_output) = &cli.master_grid_output {
+        vqs.master_grid().write_to_file(master_grid_output)?;
+    }
+    if cli.depth_band_report || cli.depth_band_csv.is_some() {
+        let report = depth_band_report(&vqs, &cli.depth_band_edges)?;
+        if cli.depth_band_report {
+            print!("{}", report);
+        }
+        if let Some(depth_band_csv) = &cli.depth_band_csv {
+            report.write_to_csv(depth_band_csv, &csv)?;
+        }
+    }
+    if let Some(anchors_csv_output) = &cli.anchors_csv_output {
+        if let Some(anchors) = anchors(&vqs) {
+            write_anchors_csv(&anchors, anchors_csv_output, &csv)?;
+        }
+    }
+    if let (Some(report), Some(level_jump_csv)) = (&level_jumps, &cli.level_jump_csv) {
+        report.write_to_csv(level_jump_csv, &csv)?;
+    }
+    if let Some(fvcom_sigma_output) = &cli.fvcom_sigma_output {
+        let kind = match cli.fvcom_sigma_kind {
+            FvcomSigmaKind::Auto => fvcom::FvcomSigmaKind::Auto,
+            FvcomSigmaKind::Uniform => fvcom::FvcomSigmaKind::Uniform,
+            FvcomSigmaKind::Geometric => fvcom::FvcomSigmaKind::Geometric,
+            FvcomSigmaKind::Tanh => fvcom::FvcomSigmaKind::Tanh,
+        };
+        let deepest_node = vqs
+            .depths()
+            .iter()
+            .enumerate()
+            .fold(0, |deepest, (i, &depth)| {
+                if depth > vqs.depths()[deepest] {
+                    i
+                } else {
+                    deepest
+                }
+            });
+        let fit = fit_fvcom_sigma(&vqs.sigma_column(deepest_node), kind)?;
+        print!("{}", fit);
+        fit.write_sigma_dat(fvcom_sigma_output)?;
+    }
+    if let Some(nlevels_gr3_output) = &cli.nlevels_gr3_output {
+        vqs.write_nlevels_gr3(&hgrid, nlevels_gr3_output)?;
+    }
+    timer.lap("other outputs");
+    if cli.bench {
+        print!("{}", timer);
+    }
 
+    #[cfg(feature = "plotters")]
+    if let Some(save_zmas_plot) = &cli.save_zmas_plot {
+        if let Some(format) = PlotFormat::from_path(save_zmas_plot) {
+            vqs.plot_zmas_to_file(save_zmas_plot, format)?;
+        }
+    }
     if cli.show_zmas_plot || cli.save_zmas_plot.is_some() {
         let zmas_plot = vqs.make_z_mas_plot()?;
         if cli.show_zmas_plot {
@@ -201,6 +1068,97 @@ fn entrypoint() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Shows the build stages as a progress bar on a terminal, and logs them
+/// otherwise.
+struct ProgressBarReporter {
+    bar: Option<ProgressBar>,
+    log: LogProgress,
+}
+
+impl ProgressBarReporter {
+    fn new() -> Self {
+        let bar = if std::io::stderr().is_terminal() {
+            let bar = ProgressBar::new(0);
+            bar.set_style(
+                ProgressStyle::with_template("{msg:<16} [{bar:40}] {pos}/{len} ({eta})")
+                    .unwrap()
+                    .progress_chars("=> "),
+            );
+            Some(bar)
+        } else {
+            None
+        };
+        Self {
+            bar,
+            log: LogProgress::new(),
+        }
+    }
+}
+
+impl ProgressReporter for ProgressBarReporter {
+    fn stage_started(&self, stage: &str) {
+        match &self.bar {
+            Some(bar) => {
+                bar.reset();
+                bar.set_message(stage.to_string());
+            }
+            None => self.log.stage_started(stage),
+        }
+    }
+
+    fn progress(&self, done: usize, total: usize) {
+        match &self.bar {
+            Some(bar) => {
+                if total > 0 {
+                    bar.set_length(total as u64);
+                }
+                bar.set_position(done as u64);
+            }
+            None => self.log.progress(done, total),
+        }
+    }
+
+    fn stage_finished(&self, stage: &str, elapsed: Duration) {
+        match &self.bar {
+            Some(bar) => bar.finish_and_clear(),
+            None => self.log.stage_finished(stage, elapsed),
+        }
+    }
+}
+
+/// Wall time of each stage of a gen_vqs run, printed by --bench.
+struct StageTimer {
+    start: Instant,
+    last: Instant,
+    stages: Vec<(&'static str, Duration)>,
+}
+
+impl StageTimer {
+    fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            start: now,
+            last: now,
+            stages: Vec::new(),
+        }
+    }
+
+    fn lap(&mut self, stage: &'static str) {
+        let now = Instant::now();
+        self.stages.push((stage, now - self.last));
+        self.last = now;
+    }
+}
+
+impl fmt::Display for StageTimer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (stage, duration) in self.stages.iter() {
+            write!(f, "{:<20}{:>16.3?}\n", stage, duration)?;
+        }
+        write!(f, "{:<20}{:>16.3?}\n", "total", self.last - self.start)
+    }
+}
+
 fn main() -> ExitCode {
     let exit_code = match entrypoint() {
         Err(e) => {
@@ -0,0 +1,884 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use pretty_env_logger;
+use schismrs_hgrid::hgrid::Hgrid;
+use schismrs_vgrid::autotune::{AutotuneTransform, VQSAutotuneBuilder};
+use schismrs_vgrid::bands::{depth_band_report, DEFAULT_DEPTH_BAND_EDGES};
+use schismrs_vgrid::channels::{channel_truncation_report, ChannelPolygon};
+use schismrs_vgrid::config::VgridConfig;
+use schismrs_vgrid::convert::{read_vgrid, write_vgrid, VgridFileFormat};
+use schismrs_vgrid::csv::CsvFormatter;
+use schismrs_vgrid::fit::{fit_stretching, FitKind};
+use schismrs_vgrid::optimize::{
+    OptimizeConstraints, OptimizeCost, OptimizeTransform, VQSOptimizeBuilder,
+};
+#[cfg(feature = "plotters")]
+use schismrs_vgrid::plot::PlotFormat;
+use schismrs_vgrid::pressure_gradient::PressureGradientOptions;
+use schismrs_vgrid::probe::read_build_points;
+use schismrs_vgrid::quality::QualityScoreOptions;
+#[cfg(feature = "netcdf")]
+use schismrs_vgrid::ugrid::{read_ugrid_vertical, write_ugrid_vertical};
+use schismrs_vgrid::validation::{check_bottom_levels, etal_robustness, validate};
+use schismrs_vgrid::vqs::{VQSLoadError, VQS};
+use std::process::ExitCode;
+use std::{error::Error, path::PathBuf};
+
+const VERSION: &'static str = concat! {
+    env! {"CARGO_PKG_VERSION"},
+    "-",
+    env! {"VERGEN_GIT_DESCRIBE"}
+};
+
+#[derive(Parser, Debug)]
+#[command(author, about, long_about = None)]
+#[command(version = VERSION)]
+struct Cli {
+    #[clap(
+        long,
+        global = true,
+        help = "Write the metadata of the vertical grid to a .json or .yaml file."
+    )]
+    emit_metadata: Option<PathBuf>,
+    #[clap(
+        long,
+        global = true,
+        default_value = ",",
+        help = "Field separator of CSV outputs."
+    )]
+    csv_delimiter: char,
+    #[clap(
+        long,
+        global = true,
+        default_value = "6",
+        help = "Digits after the decimal point of the floats in CSV outputs."
+    )]
+    csv_precision: usize,
+    #[clap(
+        long,
+        global = true,
+        help = "Refuse vgrid files whose bottom level indices disagree with their sigma."
+    )]
+    strict: bool,
+    #[clap(
+        long,
+        global = true,
+        help = "Read vgrid files from a binary <vgrid>.cache next to them when it is up to date, and write it otherwise."
+    )]
+    cache: bool,
+    #[clap(subcommand)]
+    mode: Modes,
+}
+
+#[derive(Subcommand, Debug)]
+enum Modes {
+    Validate(ValidateCliOpts),
+    Inspect(InspectCliOpts),
+    Robustness(RobustnessCliOpts),
+    Quality(QualityCliOpts),
+    Bands(BandsCliOpts),
+    Compare(CompareCliOpts),
+    Channels(ChannelsCliOpts),
+    Plot(PlotCliOpts),
+    Convert(ConvertCliOpts),
+    PressureGradient(PressureGradientCliOpts),
+    LayerThickness(LayerThicknessCliOpts),
+    FromConfig(FromConfigCliOpts),
+    LevelJumps(LevelJumpsCliOpts),
+    Blend(BlendCliOpts),
+    Probe(ProbeCliOpts),
+    Autotune(AutotuneCliOpts),
+    Fit(FitCliOpts),
+    Optimize(OptimizeCliOpts),
+    #[cfg(feature = "netcdf")]
+    ExportUgrid(UgridCliOpts),
+    #[cfg(feature = "netcdf")]
+    ImportUgrid(UgridCliOpts),
+}
+
+#[derive(Args, Debug)]
+struct ValidateCliOpts {
+    hgrid_path: PathBuf,
+    vgrid_path: PathBuf,
+    #[clap(
+        long,
+        default_value = "0.",
+        help = "Minimum thickness expected for the bottom layer."
+    )]
+    dz_bottom_min: f64,
+}
+
+#[derive(Args, Debug)]
+struct RobustnessCliOpts {
+    hgrid_path: PathBuf,
+    vgrid_path: PathBuf,
+    #[clap(long, default_value = "-2.", allow_hyphen_values = true)]
+    etal_min: f64,
+    #[clap(long, default_value = "3.", allow_hyphen_values = true)]
+    etal_max: f64,
+    #[clap(
+        long,
+        default_value = "11",
+        help = "Number of elevations to evaluate. Must be >= 2."
+    )]
+    steps: usize,
+    #[clap(
+        long,
+        default_value = "0.1",
+        help = "Layers thinner than this are reported."
+    )]
+    min_dz: f64,
+}
+
+#[derive(Args, Debug)]
+struct QualityCliOpts {
+    hgrid_path: PathBuf,
+    vgrid_path: PathBuf,
+    #[clap(
+        long,
+        default_value = "7.",
+        help = "Largest acceptable Haney number (rx1) for the quality score."
+    )]
+    max_haney: f64,
+    #[clap(
+        long,
+        default_value = "1.5",
+        help = "Largest acceptable adjacent layer thickness ratio for the quality score."
+    )]
+    max_dz_ratio: f64,
+    #[clap(
+        long,
+        default_value = "2",
+        help = "Largest acceptable level count difference within an element for the \
+                quality score."
+    )]
+    max_level_jump: usize,
+    #[clap(
+        long,
+        default_value = "3",
+        help = "Smallest acceptable number of wet levels for the quality score."
+    )]
+    min_levels: usize,
+    #[clap(long, help = "Write the metrics of each node to this CSV file.")]
+    nodes_csv: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct BandsCliOpts {
+    hgrid_path: PathBuf,
+    vgrid_path: PathBuf,
+    #[clap(
+        long,
+        value_delimiter = ' ',
+        num_args = 1..,
+        default_values_t = DEFAULT_DEPTH_BAND_EDGES,
+        help = "Strictly increasing depth band edges (positive down). The last band \
+                holds every node deeper than the last edge."
+    )]
+    edges: Vec<f64>,
+    #[clap(long, help = "Also write the table to this CSV file.")]
+    csv: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct AutotuneCliOpts {
+    hgrid_path: PathBuf,
+    #[clap(short, long, help = "Write the vgrid.in of the best candidate here.")]
+    output_filepath: Option<PathBuf>,
+    #[clap(short, long)]
+    transform: AutotuneTransformKind,
+    #[clap(
+        long,
+        help = "Critical depth of the song-haidvogel1994 transform. Must be > 0. \
+                and no deeper than the first master grid."
+    )]
+    hc: Option<f64>,
+    #[clap(long, help = "Largest nvrt a candidate may have.")]
+    level_budget: usize,
+    #[clap(
+        long,
+        action,
+        help = "Also search the levels of the deepest master grid, up to --level-budget."
+    )]
+    tune_levels: bool,
+    #[clap(long, default_value = "5", help = "Samples per searched parameter.")]
+    steps: usize,
+    #[clap(long, help = "Number of master grids to generate. Must be an int >= 2")]
+    ngrids: usize,
+    #[clap(long)]
+    dz_bottom_min: f64,
+    #[clap(
+        long,
+        default_value = "1.",
+        help = "This is the first depth below etal. This input is positive down."
+    )]
+    initial_depth: f64,
+    #[clap(short, long, default_value = "2")]
+    shallow_levels: usize,
+    #[clap(short, long, default_value = "0.", help = "defined as positive down")]
+    etal: f64,
+    #[clap(
+        short,
+        long,
+        default_value = "0.",
+        help = "a_vqs0 of the s and song-haidvogel1994 candidates. Searched for quadratic."
+    )]
+    a_vqs0: f64,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum AutotuneTransformKind {
+    Quadratic,
+    S,
+    SongHaidvogel1994,
+}
+
+#[derive(Args, Debug)]
+struct FitCliOpts {
+    hgrid_path: PathBuf,
+    vgrid_path: PathBuf,
+    #[clap(short, long)]
+    transform: FitTransformKind,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum FitTransformKind {
+    Quadratic,
+    S,
+}
+
+#[derive(Args, Debug)]
+struct OptimizeCliOpts {
+    hgrid_path: PathBuf,
+    #[clap(short, long, help = "Write the vgrid.in of the best candidate here.")]
+    output_filepath: Option<PathBuf>,
+    #[clap(short, long)]
+    transform: OptimizeTransformKind,
+    #[clap(
+        short,
+        long,
+        value_delimiter = ' ',
+        num_args = 1..,
+        help = "Master grid depths, strictly increasing."
+    )]
+    depths: Vec<f64>,
+    #[clap(
+        short,
+        long,
+        value_delimiter = ' ',
+        num_args = 1..,
+        help = "Levels of each master grid, one per depth and non-decreasing."
+    )]
+    nlevels: Vec<usize>,
+    #[clap(
+        long,
+        default_value = "0.",
+        help = "Fixed theta_b of the song-haidvogel1994 transform."
+    )]
+    theta_b: f64,
+    #[clap(long)]
+    dz_bottom_min: f64,
+    #[clap(short, long, default_value = "0.", help = "defined as positive down")]
+    etal: f64,
+    #[clap(short, long, default_value = "0.")]
+    a_vqs0: f64,
+    #[clap(long, value_enum, default_value = "mean-layer-ratio")]
+    cost: OptimizeCostKind,
+    #[clap(long, help = "Reject candidates with a larger adjacent layer ratio.")]
+    max_layer_ratio: Option<f64>,
+    #[clap(long, help = "Reject candidates with a thinner surface layer.")]
+    min_surface_dz: Option<f64>,
+    #[clap(long, help = "Reject candidates with a larger Haney number.")]
+    max_haney: Option<f64>,
+    #[clap(long, default_value = "50")]
+    max_iterations: usize,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum OptimizeTransformKind {
+    S,
+    SongHaidvogel1994,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum OptimizeCostKind {
+    MeanLayerRatio,
+    MaxLayerRatio,
+    MeanHaney,
+}
+
+#[cfg(feature = "netcdf")]
+#[derive(Args, Debug)]
+struct UgridCliOpts {
+    hgrid_path: PathBuf,
+    #[clap(help = "vgrid.in or UGRID netCDF file to read.")]
+    input_path: PathBuf,
+    #[clap(help = "UGRID netCDF or vgrid.in file to write.")]
+    output_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct InspectCliOpts {
+    hgrid_path: PathBuf,
+    vgrid_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct CompareCliOpts {
+    hgrid_path: PathBuf,
+    vgrid_path: PathBuf,
+    other_vgrid_path: PathBuf,
+    #[clap(
+        long,
+        default_value = "1e-6",
+        help = "Largest sigma deviation considered equal."
+    )]
+    tolerance: f64,
+}
+
+#[derive(Args, Debug)]
+struct ChannelsCliOpts {
+    hgrid_path: PathBuf,
+    vgrid_path: PathBuf,
+    #[clap(help = "Text file with a 'design_depth nvertices' line followed by \
+                   nvertices 'x y' lines per channel.")]
+    channels_path: PathBuf,
+    #[clap(
+        long,
+        default_value = "3",
+        help = "Minimum number of levels required below the design draft."
+    )]
+    min_levels: usize,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum ConvertFormatKind {
+    Legacy,
+    NodeMajor,
+    Netcdf,
+    Json,
+}
+
+#[derive(Args, Debug)]
+struct ConvertCliOpts {
+    #[clap(help = "vgrid.in (either layout), .nc or .json file to read.")]
+    input_path: PathBuf,
+    output_path: PathBuf,
+    #[clap(
+        long,
+        value_enum,
+        help = "Output format. Guessed from the output extension when omitted: .nc is \
+                netcdf, .json is json and anything else legacy."
+    )]
+    format: Option<ConvertFormatKind>,
+}
+
+#[derive(Args, Debug)]
+struct PressureGradientCliOpts {
+    hgrid_path: PathBuf,
+    vgrid_path: PathBuf,
+    #[clap(
+        long,
+        default_value = "25.",
+        allow_hyphen_values = true,
+        help = "Temperature at the surface of the idealized exponential profile, degrees C."
+    )]
+    surface_temperature: f64,
+    #[clap(
+        long,
+        default_value = "5.",
+        allow_hyphen_values = true,
+        help = "Temperature approached at depth, degrees C."
+    )]
+    bottom_temperature: f64,
+    #[clap(
+        long,
+        default_value = "500.",
+        help = "e-folding depth of the temperature profile in meters."
+    )]
+    scale_depth: f64,
+    #[clap(
+        long,
+        default_value = "1e-4",
+        help = "Coriolis parameter converting the error to a geostrophic current."
+    )]
+    coriolis: f64,
+    #[clap(long, default_value = "10", help = "Number of worst elements to list.")]
+    worst_elements: usize,
+    #[clap(
+        long,
+        help = "Exit with an error when the largest spurious current exceeds this, in m/s."
+    )]
+    max_velocity: Option<f64>,
+    #[clap(
+        long,
+        help = "Write the spurious current of every element to this CSV file."
+    )]
+    csv: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct LayerThicknessCliOpts {
+    hgrid_path: PathBuf,
+    vgrid_path: PathBuf,
+    output_path: PathBuf,
+    #[clap(
+        long,
+        help = "Map the layer below this vgrid level (2 to nvrt) instead of the bottom layer."
+    )]
+    level: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+struct FromConfigCliOpts {
+    #[clap(help = "YAML model configuration with a vgrid: block.")]
+    config_path: PathBuf,
+    hgrid_path: PathBuf,
+    #[clap(help = "Directory the vgrid.in is written to, created if missing.")]
+    output_dir: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct LevelJumpsCliOpts {
+    hgrid_path: PathBuf,
+    vgrid_path: PathBuf,
+    #[clap(
+        long,
+        default_value = "2",
+        help = "Largest acceptable level count difference within an element."
+    )]
+    max_level_jump: usize,
+    #[clap(long, help = "Write every steep element to this CSV file.")]
+    csv: Option<PathBuf>,
+    #[clap(
+        long,
+        help = "Raise the bottom level of the nodes over the limit and write the fixed \
+                grid to this vgrid.in."
+    )]
+    fix_output: Option<PathBuf>,
+    #[clap(
+        long,
+        requires = "fix_output",
+        help = "Write the nodes changed by --fix-output to this CSV file."
+    )]
+    adjusted_csv: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct BlendCliOpts {
+    hgrid_path: PathBuf,
+    vgrid_path: PathBuf,
+    other_vgrid_path: PathBuf,
+    #[clap(help = "gr3 of the weight of the second grid at each node, in [0, 1].")]
+    weight_gr3_path: PathBuf,
+    output_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct ProbeCliOpts {
+    hgrid_path: PathBuf,
+    vgrid_path: PathBuf,
+    #[clap(help = "Build point (.bp) file of the stations, in the coordinates of the hgrid.")]
+    points_path: PathBuf,
+    #[clap(
+        long,
+        help = "Write the level interfaces of every point to this CSV file."
+    )]
+    csv: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum PlotKind {
+    Zmas,
+    LevelsMap,
+    ThicknessHist,
+}
+
+#[derive(Args, Debug)]
+struct PlotCliOpts {
+    hgrid_path: PathBuf,
+    vgrid_path: PathBuf,
+    #[clap(
+        long,
+        value_enum,
+        help = "Master grid z curves, map of levels per node or histogram of layer thicknesses."
+    )]
+    what: PlotKind,
+    #[clap(
+        long,
+        help = "Output file. A .png or .svg path is rendered headless when built with the \
+                plotters feature, any other is written as plotly HTML."
+    )]
+    output: PathBuf,
+}
+
+fn plot(vqs: &VQS, hgrid: &Hgrid, opts: &PlotCliOpts) -> Result<(), Box<dyn Error>> {
+    #[cfg(feature = "plotters")]
+    if let Some(format) = PlotFormat::from_path(&opts.output) {
+        match opts.what {
+            PlotKind::Zmas => vqs.plot_zmas_to_file(&opts.output, format)?,
+            PlotKind::LevelsMap => vqs.plot_levels_map_to_file(hgrid, &opts.output, format)?,
+            PlotKind::ThicknessHist => {
+                vqs.plot_thickness_histogram_to_file(&opts.output, format)?
+            }
+        }
+        return Ok(());
+    }
+    #[cfg(not(feature = "plotters"))]
+    if let Some("png" | "svg") = opts.output.extension().and_then(|e| e.to_str()) {
+        return Err("PNG and SVG plots need a build with --features plotters".into());
+    }
+    let plot = match opts.what {
+        PlotKind::Zmas => vqs.make_z_mas_plot()?,
+        PlotKind::LevelsMap => vqs.make_levels_map_plot(hgrid),
+        PlotKind::ThicknessHist => vqs.make_thickness_histogram_plot(),
+    };
+    plot.write_html(&opts.output);
+    Ok(())
+}
+
+fn emit_metadata(vqs: &VQS, path: &Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = path {
+        vqs.metadata().write_to_file(path)?;
+    }
+    Ok(())
+}
+
+fn load_vqs(hgrid: &Hgrid, path: &PathBuf, cli: &Cli) -> Result<VQS, Box<dyn Error>> {
+    let cache_path = path.with_extension("cache");
+    // --strict checks the text file itself, so it always parses it
+    if cli.cache && !cli.strict && is_newer(&cache_path, path) {
+        if let Ok(vqs) = VQS::read_cache(hgrid, &cache_path) {
+            return Ok(vqs);
+        }
+    }
+    let vqs = if cli.strict {
+        VQS::try_from_file_strict(hgrid, path)?
+    } else {
+        VQS::try_from_file(hgrid, path)?
+    };
+    if cli.cache {
+        vqs.write_cache(hgrid, &cache_path)?;
+    }
+    Ok(vqs)
+}
+
+fn is_newer(path: &PathBuf, than: &PathBuf) -> bool {
+    let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|meta| meta.modified());
+    match (modified(path), modified(than)) {
+        (Ok(a), Ok(b)) => a >= b,
+        _ => false,
+    }
+}
+
+fn csv_formatter(cli: &Cli) -> CsvFormatter {
+    let mut csv = CsvFormatter::new();
+    csv.delimiter(cli.csv_delimiter)
+        .precision(cli.csv_precision);
+    csv
+}
+
+fn entrypoint() -> Result<(), Box<dyn Error>> {
+    pretty_env_logger::init();
+    let cli = Cli::parse();
+    match &cli.mode {
+        Modes::Validate(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = load_vqs(&hgrid, &opts.vgrid_path, &cli)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            let report = validate(&vqs, &opts.dz_bottom_min);
+            print!("{}", report);
+            if !report.passed() {
+                return Err(format!("{} failed validation", opts.vgrid_path.display()).into());
+            }
+        }
+        Modes::Inspect(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = load_vqs(&hgrid, &opts.vgrid_path, &cli)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            print!("{}", vqs.inspect());
+        }
+        Modes::Robustness(opts) => {
+            if opts.steps < 2 {
+                return Err(format!("steps must be >= 2 but got {}", opts.steps).into());
+            }
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = load_vqs(&hgrid, &opts.vgrid_path, &cli)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            let step = (opts.etal_max - opts.etal_min) / (opts.steps - 1) as f64;
+            let etals: Vec<f64> = (0..opts.steps)
+                .map(|i| opts.etal_min + i as f64 * step)
+                .collect();
+            print!("{}", etal_robustness(&vqs, &etals, &opts.min_dz));
+        }
+        Modes::Quality(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = load_vqs(&hgrid, &opts.vgrid_path, &cli)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            let report = vqs.quality_report(&hgrid);
+            print!("{}", report);
+            let mut options = QualityScoreOptions::new();
+            options
+                .max_haney(opts.max_haney)
+                .max_dz_ratio(opts.max_dz_ratio)
+                .max_level_jump(opts.max_level_jump)
+                .min_levels(opts.min_levels);
+            print!("{}", report.score(&options));
+            if let Some(path) = &opts.nodes_csv {
+                report.write_nodes_csv(path, &csv_formatter(&cli))?;
+            }
+        }
+        Modes::Bands(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = load_vqs(&hgrid, &opts.vgrid_path, &cli)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            let report = depth_band_report(&vqs, &opts.edges)?;
+            print!("{}", report);
+            if let Some(path) = &opts.csv {
+                report.write_to_csv(path, &csv_formatter(&cli))?;
+            }
+        }
+        Modes::Channels(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = load_vqs(&hgrid, &opts.vgrid_path, &cli)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            let channels = ChannelPolygon::try_from_file(&opts.channels_path)?;
+            let report = channel_truncation_report(&vqs, &hgrid, &channels, &opts.min_levels);
+            print!("{}", report);
+            if !report.passed() {
+                return Err(format!(
+                    "{} does not resolve the channels in {}",
+                    opts.vgrid_path.display(),
+                    opts.channels_path.display()
+                )
+                .into());
+            }
+        }
+        Modes::FromConfig(opts) => {
+            let config = VgridConfig::try_from_model_config(&opts.config_path)?;
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = config.build(&hgrid)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            std::fs::create_dir_all(&opts.output_dir)?;
+            vqs.write_to_file(&opts.output_dir.join("vgrid.in"))?;
+        }
+        Modes::Blend(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = load_vqs(&hgrid, &opts.vgrid_path, &cli)?;
+            let other = load_vqs(&hgrid, &opts.other_vgrid_path, &cli)?;
+            // gr3 values are read as depths, i.e. negated
+            let weights = -Hgrid::try_from(&opts.weight_gr3_path)?.depths();
+            let blended = vqs.blend(&other, &weights)?;
+            emit_metadata(&blended, &cli.emit_metadata)?;
+            blended.write_to_file(&opts.output_path)?;
+        }
+        Modes::Probe(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = load_vqs(&hgrid, &opts.vgrid_path, &cli)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            let report = vqs.probe(&hgrid, &read_build_points(&opts.points_path)?);
+            print!("{}", report);
+            if let Some(path) = &opts.csv {
+                report.write_to_csv(path, &csv_formatter(&cli))?;
+            }
+        }
+        Modes::LevelJumps(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let mut vqs = load_vqs(&hgrid, &opts.vgrid_path, &cli)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            let report = vqs.steep_elements(&hgrid, opts.max_level_jump);
+            print!("{}", report);
+            if let Some(path) = &opts.csv {
+                report.write_to_csv(path, &csv_formatter(&cli))?;
+            }
+            if let Some(fix_output) = &opts.fix_output {
+                let adjusted = vqs.limit_level_jumps(&hgrid, opts.max_level_jump);
+                print!("\n{}", adjusted);
+                if let Some(path) = &opts.adjusted_csv {
+                    adjusted.write_to_csv(path, &csv_formatter(&cli))?;
+                }
+                vqs.write_to_file(fix_output)?;
+            }
+        }
+        Modes::LayerThickness(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = load_vqs(&hgrid, &opts.vgrid_path, &cli)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            vqs.write_layer_thickness_gr3(&hgrid, opts.level, &opts.output_path)?;
+        }
+        Modes::PressureGradient(opts) => {
+            if !(opts.scale_depth > 0.) || opts.coriolis == 0. {
+                return Err("scale-depth must be > 0 and coriolis must not be 0".into());
+            }
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = load_vqs(&hgrid, &opts.vgrid_path, &cli)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            let mut options = PressureGradientOptions::new();
+            options
+                .surface_temperature(opts.surface_temperature)
+                .bottom_temperature(opts.bottom_temperature)
+                .scale_depth(opts.scale_depth)
+                .coriolis(opts.coriolis)
+                .worst_elements(opts.worst_elements);
+            let report = vqs.pressure_gradient_report(&hgrid, &options);
+            print!("{}", report);
+            if let Some(path) = &opts.csv {
+                report.write_to_csv(path, &csv_formatter(&cli))?;
+            }
+            if let Some(max_velocity) = opts.max_velocity {
+                if report.max_velocity() > max_velocity {
+                    return Err(format!(
+                        "{} drives spurious currents up to {:.4} m/s, above {} m/s",
+                        opts.vgrid_path.display(),
+                        report.max_velocity(),
+                        max_velocity
+                    )
+                    .into());
+                }
+            }
+        }
+        Modes::Convert(opts) => {
+            let file = read_vgrid(&opts.input_path)?;
+            if cli.strict {
+                let report = check_bottom_levels(&file);
+                if !report.passed() {
+                    return Err(VQSLoadError::InconsistentBottomLevels(report).into());
+                }
+            }
+            let format = match opts.format {
+                Some(ConvertFormatKind::Legacy) => VgridFileFormat::Legacy,
+                Some(ConvertFormatKind::NodeMajor) => VgridFileFormat::NodeMajor,
+                Some(ConvertFormatKind::Netcdf) => VgridFileFormat::Netcdf,
+                Some(ConvertFormatKind::Json) => VgridFileFormat::Json,
+                None => VgridFileFormat::from_path(&opts.output_path),
+            };
+            write_vgrid(&file, &opts.output_path, format)?;
+            if let Some(path) = &cli.emit_metadata {
+                file.metadata().write_to_file(path)?;
+            }
+        }
+        Modes::Plot(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = load_vqs(&hgrid, &opts.vgrid_path, &cli)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            plot(&vqs, &hgrid, opts)?;
+        }
+        Modes::Autotune(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let transform = match opts.transform {
+                AutotuneTransformKind::Quadratic => AutotuneTransform::Quadratic,
+                AutotuneTransformKind::S => AutotuneTransform::S,
+                AutotuneTransformKind::SongHaidvogel1994 => {
+                    let hc = opts
+                        .hc
+                        .ok_or("--hc is required by the song-haidvogel1994 transform")?;
+                    AutotuneTransform::SongHaidvogel1994 { hc }
+                }
+            };
+            let report = VQSAutotuneBuilder::default()
+                .hgrid(&hgrid)
+                .transform(&transform)
+                .level_budget(&opts.level_budget)
+                .tune_levels(&opts.tune_levels)
+                .steps(&opts.steps)
+                .ngrids(&opts.ngrids)
+                .dz_bottom_min(&opts.dz_bottom_min)
+                .initial_depth(&opts.initial_depth)
+                .shallow_levels(&opts.shallow_levels)
+                .etal(&opts.etal)
+                .a_vqs0(&opts.a_vqs0)
+                .build()?;
+            emit_metadata(report.best(), &cli.emit_metadata)?;
+            print!("{}", report);
+            if let Some(output_filepath) = &opts.output_filepath {
+                report.best().write_to_file(output_filepath)?;
+            }
+        }
+        Modes::Fit(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = load_vqs(&hgrid, &opts.vgrid_path, &cli)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            let kind = match opts.transform {
+                FitTransformKind::Quadratic => FitKind::Quadratic,
+                FitTransformKind::S => FitKind::S,
+            };
+            print!("{}", fit_stretching(&vqs, &hgrid, &kind)?);
+        }
+        Modes::Optimize(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let transform = match opts.transform {
+                OptimizeTransformKind::S => OptimizeTransform::S,
+                OptimizeTransformKind::SongHaidvogel1994 => OptimizeTransform::SongHaidvogel1994 {
+                    theta_b: opts.theta_b,
+                },
+            };
+            let cost = match opts.cost {
+                OptimizeCostKind::MeanLayerRatio => OptimizeCost::MeanLayerRatio,
+                OptimizeCostKind::MaxLayerRatio => OptimizeCost::MaxLayerRatio,
+                OptimizeCostKind::MeanHaney => OptimizeCost::MeanHaney,
+            };
+            let constraints = OptimizeConstraints {
+                max_layer_ratio: opts.max_layer_ratio,
+                min_surface_dz: opts.min_surface_dz,
+                max_haney: opts.max_haney,
+            };
+            let report = VQSOptimizeBuilder::default()
+                .hgrid(&hgrid)
+                .depths(&opts.depths)
+                .nlevels(&opts.nlevels)
+                .transform(&transform)
+                .etal(&opts.etal)
+                .a_vqs0(&opts.a_vqs0)
+                .dz_bottom_min(&opts.dz_bottom_min)
+                .cost(&cost)
+                .constraints(&constraints)
+                .max_iterations(&opts.max_iterations)
+                .build()?;
+            emit_metadata(report.best(), &cli.emit_metadata)?;
+            print!("{}", report);
+            if let Some(output_filepath) = &opts.output_filepath {
+                report.best().write_to_file(output_filepath)?;
+            }
+        }
+        #[cfg(feature = "netcdf")]
+        Modes::ExportUgrid(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = load_vqs(&hgrid, &opts.input_path, &cli)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            write_ugrid_vertical(&vqs, &hgrid, &opts.output_path)?;
+        }
+        #[cfg(feature = "netcdf")]
+        Modes::ImportUgrid(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = read_ugrid_vertical(&hgrid, &opts.input_path)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            vqs.write_to_file(&opts.output_path)?;
+        }
+        Modes::Compare(opts) => {
+            let hgrid = Hgrid::try_from(&opts.hgrid_path)?;
+            let vqs = load_vqs(&hgrid, &opts.vgrid_path, &cli)?;
+            emit_metadata(&vqs, &cli.emit_metadata)?;
+            let other = load_vqs(&hgrid, &opts.other_vgrid_path, &cli)?;
+            let diff = vqs.diff(&other)?;
+            print!("{}", diff);
+            if !diff.is_identical(&opts.tolerance) {
+                return Err(format!(
+                    "{} and {} differ",
+                    opts.vgrid_path.display(),
+                    opts.other_vgrid_path.display()
+                )
+                .into());
+            }
+        }
+    };
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match entrypoint() {
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+        Ok(_) => ExitCode::SUCCESS,
+    }
+}
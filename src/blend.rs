@@ -0,0 +1,150 @@
+//! Blending of two grids built on the same hgrid with a spatial weight, e.g.
+//! to move from a high resolution estuary design to a coarse open ocean one
+//! within a single domain.
+
+use crate::vqs::VQS;
+use ndarray::{Array1, Array2};
+use std::f64::NAN;
+use thiserror::Error;
+
+/// Sigma of a column with `sigma.len()` levels, bottom to surface, at the
+/// fraction `t` in [0, 1] of its level index range.
+fn resample(sigma: &[f64], t: f64) -> f64 {
+    let position = t * (sigma.len() - 1) as f64;
+    let below = (position.floor() as usize).min(sigma.len() - 2);
+    let fraction = position - below as f64;
+    sigma[below] + (sigma[below + 1] - sigma[below]) * fraction
+}
+
+/// Implements [`VQS::blend`].
+pub(crate) fn blend(vqs: &VQS, other: &VQS, weights: &Array1<f64>) -> Result<VQS, BlendError> {
+    let np = vqs.depths().len();
+    if other.depths().len() != np {
+        return Err(BlendError::NodeCountMismatch(np, other.depths().len()));
+    }
+    if weights.len() != np {
+        return Err(BlendError::WeightCountMismatch(weights.len(), np));
+    }
+    if let Some(i) = weights.iter().position(|w| !(0. ..=1.).contains(w)) {
+        return Err(BlendError::InvalidWeight(i + 1, weights[i]));
+    }
+    let columns: Vec<Vec<f64>> = (0..np)
+        .map(|i| {
+            let a = vqs.sigma_column(i).into_bottom_up();
+            let b = other.sigma_column(i).into_bottom_up();
+            if a.len() < 2 || b.len() < 2 {
+                return Err(BlendError::TooFewLevels(i + 1));
+            }
+            let w = weights[i];
+            let nlev = ((1. - w) * a.len() as f64 + w * b.len() as f64).round() as usize;
+            let column = (0..nlev)
+                .map(|k| {
+                    let t = k as f64 / (nlev - 1) as f64;
+                    (1. - w) * resample(&a, t) + w * resample(&b, t)
+                })
+                .collect::<Vec<f64>>();
+            Ok(column)
+        })
+        .collect::<Result<_, BlendError>>()?;
+    let nvrt = columns.iter().map(|column| column.len()).max().unwrap_or(0);
+    let mut sigma = Array2::from_elem((nvrt, np), NAN);
+    for (i, column) in columns.iter().enumerate() {
+        let kbp = nvrt - column.len();
+        for (k, &value) in column.iter().enumerate() {
+            sigma[[kbp + k, i]] = value;
+        }
+    }
+    Ok(VQS::from_sigma(sigma, vqs.depths().clone(), *vqs.etal())
+        .with_etal_field(vqs.etal_field().cloned()))
+}
+
+#[derive(Error, Debug)]
+pub enum BlendError {
+    #[error("The first grid has {0} nodes but the second has {1}")]
+    NodeCountMismatch(usize, usize),
+    #[error("Got {0} weights for {1} nodes")]
+    WeightCountMismatch(usize, usize),
+    #[error("Weight of node {0} must be in [0, 1] but got {1}")]
+    InvalidWeight(usize, f64),
+    #[error("Node {0} has fewer than 2 levels in one of the grids")]
+    TooFewLevels(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    /// Grid with the given bottom-up columns, padded with NaN below the bottom.
+    fn grid(columns: &[&[f64]]) -> VQS {
+        let nvrt = columns.iter().map(|column| column.len()).max().unwrap();
+        let mut sigma = Array2::from_elem((nvrt, columns.len()), NAN);
+        for (i, column) in columns.iter().enumerate() {
+            for (k, &value) in column.iter().enumerate() {
+                sigma[[nvrt - column.len() + k, i]] = value;
+            }
+        }
+        VQS::from_sigma(sigma, Array1::from_elem(columns.len(), 50.), 0.)
+    }
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(
+            actual.len(),
+            expected.len(),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+        for (a, b) in actual.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-12, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    const COARSE: [f64; 3] = [-1., -0.5, 0.];
+    const FINE: [f64; 5] = [-1., -0.6, -0.3, -0.1, 0.];
+
+    #[test]
+    fn extreme_weights_return_either_grid() {
+        let (coarse, fine) = (grid(&[&COARSE, &COARSE]), grid(&[&FINE, &FINE]));
+        let blended = blend(&coarse, &fine, &array![0., 1.]).unwrap();
+        assert_close(&blended.sigma_column(0).into_bottom_up(), &COARSE);
+        assert_close(&blended.sigma_column(1).into_bottom_up(), &FINE);
+        assert_eq!(blended.nvrt(), FINE.len());
+    }
+
+    #[test]
+    fn intermediate_weight_interpolates_level_count_and_sigma() {
+        let blended = blend(&grid(&[&COARSE]), &grid(&[&FINE]), &array![0.5]).unwrap();
+        let column = blended.sigma_column(0);
+        assert_eq!(column.len(), 4);
+        assert_eq!(column.bottom(), Some(-1.));
+        assert_eq!(column.surface(), Some(0.));
+        let values = column.into_bottom_up();
+        assert!(
+            values.windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            values
+        );
+    }
+
+    #[test]
+    fn rejects_mismatched_inputs() {
+        let (one, two) = (grid(&[&COARSE]), grid(&[&COARSE, &COARSE]));
+        assert!(matches!(
+            blend(&one, &two, &array![0.]),
+            Err(BlendError::NodeCountMismatch(1, 2))
+        ));
+        assert!(matches!(
+            blend(&one, &one, &array![0., 1.]),
+            Err(BlendError::WeightCountMismatch(2, 1))
+        ));
+        assert!(matches!(
+            blend(&one, &one, &array![1.5]),
+            Err(BlendError::InvalidWeight(1, _))
+        ));
+        assert!(matches!(
+            blend(&grid(&[&[0.]]), &one, &array![0.5]),
+            Err(BlendError::TooFewLevels(1))
+        ));
+    }
+}
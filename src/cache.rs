@@ -0,0 +1,250 @@
+//! Binary cache of a parsed vgrid.in, so repeated analyses of a large grid
+//! skip the ASCII parsing.
+//!
+//! The layout is little-endian: the magic bytes, a format version, the
+//! checksum of the hgrid the grid was loaded on, nvrt, np and etal, a flag
+//! byte followed by np values when the grid has an etal field, then for each
+//! node its 1-based bottom level index followed by the sigma of its levels
+//! from the bottom up.
+
+use crate::hash::{fnv1a, FNV_OFFSET_BASIS};
+use crate::vqs::VQS;
+use ndarray::{Array1, Array2};
+use schismrs_hgrid::hgrid::Hgrid;
+use std::f64::NAN;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::path::PathBuf;
+use thiserror::Error;
+
+const MAGIC: &[u8; 8] = b"VQSCACHE";
+const VERSION: u32 = 2;
+
+/// Checksum of the node coordinates and depths of `hgrid`, which a cache
+/// must match to be read back on it.
+pub(crate) fn hgrid_checksum(hgrid: &Hgrid) -> u64 {
+    hgrid
+        .x()
+        .iter()
+        .chain(hgrid.y().iter())
+        .chain(hgrid.depths().iter())
+        .fold(FNV_OFFSET_BASIS, |hash, value| {
+            fnv1a(hash, &value.to_le_bytes())
+        })
+}
+
+/// Implements [`VQS::write_cache`].
+pub(crate) fn write_cache(vqs: &VQS, hgrid: &Hgrid, path: &PathBuf) -> Result<(), CacheError> {
+    let sigma = vqs.sigma();
+    let (nvrt, np) = sigma.dim();
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&hgrid_checksum(hgrid).to_le_bytes())?;
+    writer.write_all(&(nvrt as u64).to_le_bytes())?;
+    writer.write_all(&(np as u64).to_le_bytes())?;
+    writer.write_all(&vqs.etal().to_le_bytes())?;
+    match vqs.etal_field() {
+        Some(etal_field) => {
+            writer.write_all(&[1])?;
+            for value in etal_field.iter() {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        None => writer.write_all(&[0])?,
+    }
+    for (i, &kbp) in vqs.bottom_level_indices().iter().enumerate() {
+        writer.write_all(&(kbp as u32).to_le_bytes())?;
+        for row in kbp - 1..nvrt {
+            writer.write_all(&sigma[[row, i]].to_le_bytes())?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Implements [`VQS::read_cache`].
+pub(crate) fn read_cache(hgrid: &Hgrid, path: &PathBuf) -> Result<VQS, CacheError> {
+    let file = File::open(path)?;
+    let length = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(CacheError::NotACache(path.clone()));
+    }
+    let version = u32::from_le_bytes(read_array(&mut reader)?);
+    if version != VERSION {
+        return Err(CacheError::UnsupportedVersion(version));
+    }
+    if u64::from_le_bytes(read_array(&mut reader)?) != hgrid_checksum(hgrid) {
+        return Err(CacheError::HgridMismatch);
+    }
+    let nvrt = u64::from_le_bytes(read_array(&mut reader)?) as usize;
+    let np = u64::from_le_bytes(read_array(&mut reader)?) as usize;
+    if np != hgrid.depths().len() {
+        return Err(CacheError::NodeCountMismatch(np, hgrid.depths().len()));
+    }
+    let etal = f64::from_le_bytes(read_array(&mut reader)?);
+    let etal_field = match read_array::<1>(&mut reader)? {
+        [0] => None,
+        [1] => Some(
+            (0..np)
+                .map(|_| Ok(f64::from_le_bytes(read_array(&mut reader)?)))
+                .collect::<std::io::Result<Array1<f64>>>()?,
+        ),
+        [flag] => return Err(CacheError::InvalidEtalFieldFlag(flag)),
+    };
+    // each node stores its bottom level index and 1 to nvrt sigma values,
+    // checked before allocating the (nvrt, np) sigma a corrupt header asks for
+    let levels_bytes = length.saturating_sub(reader.stream_position()?);
+    let (min_bytes, max_bytes) = (np as u128 * 12, np as u128 * (4 + 8 * nvrt as u128));
+    if (levels_bytes as u128) < min_bytes || levels_bytes as u128 > max_bytes {
+        return Err(CacheError::SizeMismatch(levels_bytes, nvrt, np));
+    }
+    let size = nvrt.checked_mul(np).ok_or(CacheError::TooLarge(nvrt, np))?;
+    let mut values = Vec::new();
+    values
+        .try_reserve_exact(size)
+        .map_err(|_| CacheError::TooLarge(nvrt, np))?;
+    values.resize(size, NAN);
+    let mut sigma = Array2::from_shape_vec((nvrt, np), values).expect("nvrt * np values");
+    for i in 0..np {
+        let kbp = u32::from_le_bytes(read_array(&mut reader)?) as usize;
+        if kbp < 1 || kbp > nvrt {
+            return Err(CacheError::InvalidBottomLevel(i + 1, kbp, nvrt));
+        }
+        for row in kbp - 1..nvrt {
+            sigma[[row, i]] = f64::from_le_bytes(read_array(&mut reader)?);
+        }
+    }
+    Ok(VQS::from_sigma(sigma, -hgrid.depths(), etal).with_etal_field(etal_field))
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[derive(Error, Debug)]
+pub enum CacheError {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error("{0:?} is not a vgrid cache")]
+    NotACache(PathBuf),
+    #[error("Cache format version {0} is not supported")]
+    UnsupportedVersion(u32),
+    #[error("The cache was written for a different hgrid")]
+    HgridMismatch,
+    #[error("The cache has {0} nodes but the hgrid has {1}")]
+    NodeCountMismatch(usize, usize),
+    #[error("{0} bytes of levels do not fit a grid of nvrt={1} and np={2}")]
+    SizeMismatch(u64, usize, usize),
+    #[error("Not enough memory for the sigma of nvrt={0} and np={1}")]
+    TooLarge(usize, usize),
+    #[error("Invalid etal field flag {0}")]
+    InvalidEtalFieldFlag(u8),
+    #[error("Node {0} has bottom level {1} which is outside of [1, nvrt={2}]")]
+    InvalidBottomLevel(usize, usize, usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::structured_hgrid;
+    use std::fs;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "schismrs-vgrid-cache-{}-{}.bin",
+            std::process::id(),
+            name
+        ))
+    }
+
+    fn hgrid(depth: f64) -> Hgrid {
+        structured_hgrid(2, 2, 100., |x, _| depth + x / 100.).unwrap()
+    }
+
+    /// Grid on the 4 nodes of [`hgrid`], with a node one level shallower.
+    fn vqs() -> VQS {
+        let mut sigma = Array2::from_elem((3, 4), NAN);
+        for i in 0..4 {
+            let column: &[f64] = if i == 2 { &[-1., 0.] } else { &[-1., -0.4, 0.] };
+            for (k, &value) in column.iter().enumerate() {
+                sigma[[3 - column.len() + k, i]] = value;
+            }
+        }
+        VQS::from_sigma(sigma, Array1::from_elem(4, 10.), 0.5)
+            .with_etal_field(Some(Array1::from_vec(vec![0.5, 0.6, 0.7, 0.8])))
+    }
+
+    #[test]
+    fn round_trip_keeps_sigma_and_etal() {
+        let (hgrid, vqs, path) = (hgrid(10.), vqs(), temp_path("round-trip"));
+        write_cache(&vqs, &hgrid, &path).unwrap();
+        let read = read_cache(&hgrid, &path);
+        fs::remove_file(&path).unwrap();
+        let read = read.unwrap();
+        assert_eq!(read.bottom_level_indices(), vqs.bottom_level_indices());
+        for (a, b) in read.sigma().iter().zip(vqs.sigma().iter()) {
+            assert!(a == b || (a.is_nan() && b.is_nan()));
+        }
+        assert_eq!(read.etal(), vqs.etal());
+        assert_eq!(read.etal_field(), vqs.etal_field());
+    }
+
+    #[test]
+    fn rejects_another_hgrid() {
+        let path = temp_path("other-hgrid");
+        write_cache(&vqs(), &hgrid(10.), &path).unwrap();
+        let read = read_cache(&hgrid(11.), &path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(read, Err(CacheError::HgridMismatch)));
+    }
+
+    #[test]
+    fn rejects_other_files_and_versions() {
+        let (hgrid, path) = (hgrid(10.), temp_path("invalid"));
+        fs::write(&path, "vgrid.in is text").unwrap();
+        let not_a_cache = read_cache(&hgrid, &path);
+        write_cache(&vqs(), &hgrid, &path).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+        let newer = read_cache(&hgrid, &path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(not_a_cache, Err(CacheError::NotACache(_))));
+        assert!(matches!(newer, Err(CacheError::UnsupportedVersion(v)) if v == VERSION + 1));
+    }
+
+    #[test]
+    fn rejects_corrupt_sizes_before_allocating() {
+        let (hgrid, path) = (hgrid(10.), temp_path("corrupt"));
+        write_cache(&vqs(), &hgrid, &path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let nvrt_at = MAGIC.len() + 4 + 8;
+        let with_u64 = |at: usize, value: u64| {
+            let mut bytes = bytes.clone();
+            bytes[at..at + 8].copy_from_slice(&value.to_le_bytes());
+            bytes
+        };
+        fs::write(&path, with_u64(nvrt_at + 8, 5)).unwrap();
+        let other_np = read_cache(&hgrid, &path);
+        fs::write(&path, with_u64(nvrt_at, 1)).unwrap();
+        let smaller_nvrt = read_cache(&hgrid, &path);
+        fs::write(&path, with_u64(nvrt_at, u64::MAX / 2)).unwrap();
+        let huge_nvrt = read_cache(&hgrid, &path);
+        fs::write(&path, &bytes[..bytes.len() - 60]).unwrap();
+        let truncated = read_cache(&hgrid, &path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(other_np, Err(CacheError::NodeCountMismatch(5, 4))));
+        assert!(matches!(
+            smaller_nvrt,
+            Err(CacheError::SizeMismatch(_, 1, 4))
+        ));
+        assert!(matches!(huge_nvrt, Err(CacheError::TooLarge(_, 4))));
+        assert!(matches!(truncated, Err(CacheError::SizeMismatch(_, 3, 4))));
+    }
+}
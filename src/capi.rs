@@ -0,0 +1,193 @@
+//! C ABI for generating a vgrid.in without spawning gen_vqs.
+//!
+//! Built with `--features capi`. The C header is checked in as
+//! `include/schismrs_vgrid.h` and regenerated with cbindgen, see cbindgen.toml.
+
+use crate::config::StretchingConfig;
+use crate::vqs::{VQSAutoBuilder, VQSBuilder, VQSKMeansBuilder, VQS};
+use crate::ClusteringMethod;
+use schismrs_hgrid::hgrid::Hgrid;
+use serde::Deserialize;
+use std::error::Error;
+use std::ffi::{c_char, c_int, CStr};
+use std::path::PathBuf;
+
+/// Returned by [`vgrid_generate`] on success.
+pub const VGRID_OK: c_int = 0;
+/// A path or the config is null or not valid UTF-8.
+pub const VGRID_INVALID_ARGUMENT: c_int = 1;
+/// The config JSON does not match the expected layout.
+pub const VGRID_INVALID_CONFIG: c_int = 2;
+/// The hgrid could not be read.
+pub const VGRID_HGRID_ERROR: c_int = 3;
+/// The builder rejected the config.
+pub const VGRID_BUILD_ERROR: c_int = 4;
+/// The vgrid.in could not be written.
+pub const VGRID_WRITE_ERROR: c_int = 5;
+
+/// Layout of the `config_json` argument of [`vgrid_generate`], mirroring the
+/// gen_vqs options, e.g.
+///
+/// ```json
+/// {
+///   "dz_bottom_min": 1.0,
+///   "transform": {"kind": "s", "theta_b": 0.0, "theta_f": 3.0},
+///   "mode": {"kind": "auto", "ngrids": 40}
+/// }
+/// ```
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct GenerateConfig {
+    dz_bottom_min: f64,
+    transform: StretchingConfig,
+    mode: ModeConfig,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "kebab-case", deny_unknown_fields)]
+enum ModeConfig {
+    Hsm {
+        depths: Vec<f64>,
+        nlevels: Vec<usize>,
+    },
+    Kmeans {
+        clusters: usize,
+        shallow_levels: Option<usize>,
+        max_levels: Option<usize>,
+    },
+    Auto {
+        ngrids: usize,
+        #[serde(default = "default_initial_depth")]
+        initial_depth: f64,
+        #[serde(default = "default_shallow_levels")]
+        shallow_levels: usize,
+        max_levels: Option<usize>,
+    },
+}
+
+fn default_initial_depth() -> f64 {
+    1.
+}
+
+fn default_shallow_levels() -> usize {
+    2
+}
+
+fn build(hgrid: &Hgrid, config: &GenerateConfig) -> Result<VQS, Box<dyn Error>> {
+    let stretching = config.transform.stretching();
+    let vqs = match &config.mode {
+        ModeConfig::Hsm { depths, nlevels } => VQSBuilder::default()
+            .hgrid(hgrid)
+            .depths(depths)
+            .nlevels(nlevels)
+            .stretching(&stretching)
+            .dz_bottom_min(&config.dz_bottom_min)
+            .build()?,
+        ModeConfig::Kmeans {
+            clusters,
+            shallow_levels,
+            max_levels,
+        } => {
+            let mut builder = VQSKMeansBuilder::default();
+            builder
+                .hgrid(hgrid)
+                .clustering_method(&ClusteringMethod::KMeans)
+                .stretching(&stretching)
+                .nclusters(clusters)
+                .etal(config.transform.etal())
+                .dz_bottom_min(&config.dz_bottom_min);
+            if let Some(shallow_levels) = shallow_levels {
+                builder.shallow_levels(shallow_levels);
+            }
+            if let Some(max_levels) = max_levels {
+                builder.max_levels(max_levels);
+            }
+            builder.build()?
+        }
+        ModeConfig::Auto {
+            ngrids,
+            initial_depth,
+            shallow_levels,
+            max_levels,
+        } => {
+            let mut builder = VQSAutoBuilder::default();
+            builder
+                .hgrid(hgrid)
+                .stretching(&stretching)
+                .ngrids(ngrids)
+                .dz_bottom_min(&config.dz_bottom_min)
+                .initial_depth(initial_depth)
+                .shallow_levels(shallow_levels);
+            if let Some(max_levels) = max_levels {
+                builder.max_levels(max_levels);
+            }
+            builder.build()?
+        }
+    };
+    Ok(vqs)
+}
+
+unsafe fn to_str<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        return None;
+    }
+    CStr::from_ptr(ptr).to_str().ok()
+}
+
+/// Builds the vertical grid described by `config_json` on the hgrid at
+/// `hgrid_path` and writes it as a vgrid.in to `out_path`.
+///
+/// Returns [`VGRID_OK`] on success or one of the other `VGRID_*` codes. The
+/// cause of a failure is logged at error level.
+///
+/// # Safety
+///
+/// The three arguments must be null or point to NUL terminated strings that
+/// stay valid for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn vgrid_generate(
+    hgrid_path: *const c_char,
+    config_json: *const c_char,
+    out_path: *const c_char,
+) -> c_int {
+    let (hgrid_path, config_json, out_path) =
+        match (to_str(hgrid_path), to_str(config_json), to_str(out_path)) {
+            (Some(hgrid_path), Some(config_json), Some(out_path)) => (
+                PathBuf::from(hgrid_path),
+                config_json,
+                PathBuf::from(out_path),
+            ),
+            _ => {
+                log::error!("vgrid_generate expects three non-null UTF-8 strings");
+                return VGRID_INVALID_ARGUMENT;
+            }
+        };
+    let config: GenerateConfig = match serde_json::from_str(config_json) {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("Invalid vgrid_generate config: {}", e);
+            return VGRID_INVALID_CONFIG;
+        }
+    };
+    let hgrid = match Hgrid::try_from(&hgrid_path) {
+        Ok(hgrid) => hgrid,
+        Err(e) => {
+            log::error!("Could not read {}: {}", hgrid_path.display(), e);
+            return VGRID_HGRID_ERROR;
+        }
+    };
+    let vqs = match build(&hgrid, &config) {
+        Ok(vqs) => vqs,
+        Err(e) => {
+            log::error!("Could not build the vertical grid: {}", e);
+            return VGRID_BUILD_ERROR;
+        }
+    };
+    match vqs.write_to_file(&out_path) {
+        Ok(()) => VGRID_OK,
+        Err(e) => {
+            log::error!("Could not write {}: {}", out_path.display(), e);
+            VGRID_WRITE_ERROR
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::mesh::element_connectivity;
+use crate::vqs::VQS;
+use schismrs_hgrid::hgrid::Hgrid;
+use std::fmt;
+
+/// Rough SCHISM memory per wet node level, in bytes, over all ranks. Covers
+/// the 3D state, tracer and work arrays of a baroclinic run with a few
+/// tracers.
+pub const BYTES_PER_NODE_LEVEL: f64 = 800.;
+
+/// Rough SCHISM memory per prism, in bytes, including its share of the side
+/// levels (about 1.5 sides per element).
+pub const BYTES_PER_PRISM: f64 = 1200.;
+
+/// Rough prisms advanced by one time step per core second, transport and
+/// tracers included.
+pub const PRISM_UPDATES_PER_CORE_SECOND: f64 = 2e4;
+
+/// Time step in seconds used by [`CellCountSummary`] when none is given.
+pub const DEFAULT_TIME_STEP: f64 = 120.;
+
+/// Size of the 3D mesh a vertical grid makes on an hgrid, and the resulting
+/// order of magnitude of the cost of a SCHISM run.
+///
+/// The memory and core-hour figures scale the counts by the rules of thumb
+/// [`BYTES_PER_NODE_LEVEL`], [`BYTES_PER_PRISM`] and
+/// [`PRISM_UPDATES_PER_CORE_SECOND`]. They are meant to compare designs and
+/// catch an oversized grid before submitting a run, and can easily be off by
+/// a factor of 2 for a given setup.
+pub struct CellCountSummary {
+    np: usize,
+    ne: usize,
+    wet_node_levels: usize,
+    prisms: Vec<usize>,
+    time_step: f64,
+}
+
+impl CellCountSummary {
+    pub fn new(vqs: &VQS, hgrid: &Hgrid) -> Self {
+        let nvrt = vqs.nvrt();
+        let bottom_level_indices = vqs.bottom_level_indices();
+        let wet_node_levels = bottom_level_indices.iter().map(|kbp| nvrt + 1 - kbp).sum();
+        // an element has a prism per level above its shallowest bottom
+        let prisms: Vec<usize> = element_connectivity(hgrid)
+            .iter()
+            .map(|element| {
+                let kbe = element
+                    .iter()
+                    .map(|&node| bottom_level_indices[node])
+                    .max()
+                    .unwrap_or(nvrt);
+                nvrt - kbe
+            })
+            .collect();
+        Self {
+            np: bottom_level_indices.len(),
+            ne: prisms.len(),
+            wet_node_levels,
+            prisms,
+            time_step: DEFAULT_TIME_STEP,
+        }
+    }
+
+    /// Time step in seconds of the core-hour estimate.
+    pub fn time_step(&mut self, time_step: f64) -> &mut Self {
+        self.time_step = time_step;
+        self
+    }
+
+    /// Sum of the wet levels of every node.
+    pub fn wet_node_levels(&self) -> usize {
+        self.wet_node_levels
+    }
+
+    /// Number of prisms of each element, in hgrid element order.
+    pub fn prisms_per_element(&self) -> &Vec<usize> {
+        &self.prisms
+    }
+
+    /// Total number of prisms, the same as
+    /// [`crate::quality::QualityReport::cell_count`].
+    pub fn prism_count(&self) -> usize {
+        self.prisms.iter().sum()
+    }
+
+    /// Estimated SCHISM memory over all ranks, in bytes.
+    pub fn memory_bytes(&self) -> f64 {
+        self.wet_node_levels as f64 * BYTES_PER_NODE_LEVEL
+            + self.prism_count() as f64 * BYTES_PER_PRISM
+    }
+
+    /// Estimated core hours per simulated day at the time step.
+    pub fn core_hours_per_day(&self) -> f64 {
+        let steps = 86400. / self.time_step;
+        self.prism_count() as f64 * steps / PRISM_UPDATES_PER_CORE_SECOND / 3600.
+    }
+}
+
+impl fmt::Display for CellCountSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prisms = self.prism_count();
+        let (min, max) = self
+            .prisms
+            .iter()
+            .fold((usize::MAX, 0), |(min, max), &n| (min.min(n), max.max(n)));
+        write!(f, "{:<28}{:>14}\n", "nodes", self.np)?;
+        write!(f, "{:<28}{:>14}\n", "elements", self.ne)?;
+        write!(f, "{:<28}{:>14}\n", "wet node levels", self.wet_node_levels)?;
+        write!(f, "{:<28}{:>14}\n", "prisms", prisms)?;
+        if self.ne > 0 {
+            write!(f, "{:<28}{:>14}\n", "min prisms per element", min)?;
+            let mean = prisms as f64 / self.ne as f64;
+            write!(f, "{:<28}{:>14.2}\n", "mean prisms per element", mean)?;
+            write!(f, "{:<28}{:>14}\n", "max prisms per element", max)?;
+        }
+        write!(
+            f,
+            "{:<28}{:>14.2}\n",
+            "est. memory (GiB)",
+            self.memory_bytes() / 1024f64.powi(3)
+        )?;
+        write!(
+            f,
+            "{:<28}{:>14.1}\n",
+            format!("est. core-h/day (dt={}s)", self.time_step),
+            self.core_hours_per_day()
+        )
+    }
+}
@@ -0,0 +1,205 @@
+use crate::mesh::point_in_polygon;
+use crate::vqs::VQS;
+use schismrs_hgrid::hgrid::Hgrid;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Maximum number of violating nodes listed per channel when displaying a report.
+const MAX_LISTED_NODES: usize = 20;
+
+/// A dredged channel footprint with its design draft (positive down).
+pub struct ChannelPolygon {
+    vertices: Vec<(f64, f64)>,
+    design_depth: f64,
+}
+
+impl ChannelPolygon {
+    pub fn new(vertices: Vec<(f64, f64)>, design_depth: f64) -> Result<Self, ChannelsError> {
+        if vertices.len() < 3 {
+            return Err(ChannelsError::NotEnoughVertices(vertices.len()));
+        }
+        Ok(Self {
+            vertices,
+            design_depth,
+        })
+    }
+
+    /// Reads channel polygons from a text file holding, for each channel, a
+    /// `design_depth nvertices` line followed by `nvertices` lines of `x y`,
+    /// in the same coordinates as the hgrid.
+    pub fn try_from_file(path: &PathBuf) -> Result<Vec<Self>, ChannelsError> {
+        let content = fs::read_to_string(path)?;
+        let mut tokens = content.split_whitespace();
+        let mut channels = Vec::new();
+        while let Some(token) = tokens.next() {
+            let design_depth = parse_token::<f64>(token, "design_depth")?;
+            let nvertices = next_token::<usize>(&mut tokens, "nvertices")?;
+            let mut vertices = Vec::with_capacity(nvertices);
+            for _ in 0..nvertices {
+                let x = next_token::<f64>(&mut tokens, "x")?;
+                let y = next_token::<f64>(&mut tokens, "y")?;
+                vertices.push((x, y));
+            }
+            channels.push(Self::new(vertices, design_depth)?);
+        }
+        Ok(channels)
+    }
+
+    pub fn design_depth(&self) -> &f64 {
+        &self.design_depth
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        point_in_polygon(&self.vertices, x, y)
+    }
+}
+
+fn parse_token<T: FromStr>(token: &str, name: &str) -> Result<T, ChannelsError> {
+    token
+        .parse::<T>()
+        .map_err(|_| ChannelsError::ParseError(name.to_string(), token.to_string()))
+}
+
+fn next_token<T: FromStr>(
+    tokens: &mut std::str::SplitWhitespace,
+    name: &str,
+) -> Result<T, ChannelsError> {
+    let token = tokens
+        .next()
+        .ok_or_else(|| ChannelsError::UnexpectedEndOfFile(name.to_string()))?;
+    parse_token(token, name)
+}
+
+/// Nodes of a single channel that resolve too few levels below the design draft.
+pub struct ChannelTruncation {
+    channel: usize,
+    design_depth: f64,
+    nodes: usize,
+    violations: Vec<(usize, usize)>,
+}
+
+impl ChannelTruncation {
+    /// 1-based index of the channel in the input.
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    pub fn design_depth(&self) -> &f64 {
+        &self.design_depth
+    }
+
+    /// Number of hgrid nodes inside the channel.
+    pub fn nodes(&self) -> usize {
+        self.nodes
+    }
+
+    /// 1-based node ids and level counts below the draft of the violating nodes.
+    pub fn violations(&self) -> &Vec<(usize, usize)> {
+        &self.violations
+    }
+}
+
+pub struct ChannelTruncationReport {
+    min_levels: usize,
+    channels: Vec<ChannelTruncation>,
+}
+
+impl ChannelTruncationReport {
+    pub fn channels(&self) -> &Vec<ChannelTruncation> {
+        &self.channels
+    }
+
+    pub fn passed(&self) -> bool {
+        self.channels
+            .iter()
+            .all(|channel| channel.violations.is_empty())
+    }
+}
+
+impl fmt::Display for ChannelTruncationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for channel in self.channels.iter() {
+            let status = if channel.violations.is_empty() {
+                "PASS"
+            } else {
+                "FAIL"
+            };
+            write!(
+                f,
+                "[{}] channel {} (draft {}): {} of {} nodes have fewer than {} levels below the draft\n",
+                status,
+                channel.channel,
+                channel.design_depth,
+                channel.violations.len(),
+                channel.nodes,
+                self.min_levels
+            )?;
+            for (node, levels) in channel.violations.iter().take(MAX_LISTED_NODES) {
+                write!(f, "{:>12} {:>6}\n", node, levels)?;
+            }
+            if channel.violations.len() > MAX_LISTED_NODES {
+                write!(f, "{:>12}\n", "...")?;
+            }
+        }
+        if self.passed() {
+            write!(f, "PASS\n")
+        } else {
+            write!(f, "FAIL\n")
+        }
+    }
+}
+
+/// Checks that every node inside each channel has at least `min_levels` levels
+/// deeper than the channel design draft, measured from the etal of the node.
+pub fn channel_truncation_report(
+    vqs: &VQS,
+    hgrid: &Hgrid,
+    channels: &Vec<ChannelPolygon>,
+    min_levels: &usize,
+) -> ChannelTruncationReport {
+    let z = vqs.z();
+    let (x, y) = (hgrid.x(), hgrid.y());
+    let bottom_level_indices = vqs.bottom_level_indices();
+    let mut reports = Vec::with_capacity(channels.len());
+    for (c, channel) in channels.iter().enumerate() {
+        let mut report = ChannelTruncation {
+            channel: c + 1,
+            design_depth: channel.design_depth,
+            nodes: 0,
+            violations: Vec::new(),
+        };
+        for (i, &kbp) in bottom_level_indices.iter().enumerate() {
+            if !channel.contains(x[i], y[i]) {
+                continue;
+            }
+            report.nodes += 1;
+            let draft_z = vqs.etal_at(i) - channel.design_depth;
+            let levels = (kbp - 1..vqs.nvrt())
+                .filter(|&row| z[[row, i]] < draft_z)
+                .count();
+            if levels < *min_levels {
+                report.violations.push((i + 1, levels));
+            }
+        }
+        reports.push(report);
+    }
+    ChannelTruncationReport {
+        min_levels: *min_levels,
+        channels: reports,
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ChannelsError {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error("Unexpected end of file while reading {0}")]
+    UnexpectedEndOfFile(String),
+    #[error("Could not parse {0} from token {1:?}")]
+    ParseError(String, String),
+    #[error("A channel polygon needs at least 3 vertices but got {0}")]
+    NotEnoughVertices(usize),
+}
@@ -0,0 +1,60 @@
+//! Invariants every LSC2 grid must satisfy, for tests and for checking grids
+//! loaded from files.
+//!
+//! Each function returns the first violation found as a [`CheckError`] naming
+//! the node and level, so they can be used with `?` in tests or turned into
+//! a message for users. [`crate::validation::validate`] runs the same checks
+//! over every node and reports all offending nodes instead.
+
+pub use crate::validation::SIGMA_TOLERANCE;
+use crate::validation::{check_node_levels, check_node_monotone_sigma, check_node_sigma_bounds};
+use crate::vqs::VQS;
+use thiserror::Error;
+
+/// Every node has its bottom level in [1, nvrt - 1], i.e. at least two
+/// levels.
+pub fn assert_kbp_bounds(vqs: &VQS) -> Result<(), CheckError> {
+    let nvrt = vqs.nvrt();
+    for (i, &kbp) in vqs.bottom_level_indices().iter().enumerate() {
+        check_node_levels(nvrt, i, kbp)?;
+    }
+    Ok(())
+}
+
+/// Sigma is -1 at the bottom level and 0 at the surface of every node.
+pub fn assert_surface_zero_bottom_minus_one(vqs: &VQS) -> Result<(), CheckError> {
+    let sigma = vqs.sigma();
+    for (i, &kbp) in vqs.bottom_level_indices().iter().enumerate() {
+        check_node_sigma_bounds(sigma, i, kbp)?;
+    }
+    Ok(())
+}
+
+/// Sigma strictly increases from the bottom level to the surface of every
+/// node, with no NaN in between.
+pub fn assert_monotone_sigma(vqs: &VQS) -> Result<(), CheckError> {
+    let sigma = vqs.sigma();
+    for (i, &kbp) in vqs.bottom_level_indices().iter().enumerate() {
+        check_node_monotone_sigma(sigma, i, kbp)?;
+    }
+    Ok(())
+}
+
+/// Runs every check of this module.
+pub fn assert_invariants(vqs: &VQS) -> Result<(), CheckError> {
+    assert_kbp_bounds(vqs)?;
+    assert_surface_zero_bottom_minus_one(vqs)?;
+    assert_monotone_sigma(vqs)
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CheckError {
+    #[error("Node {0} has bottom level {1} which leaves fewer than 2 levels out of nvrt={2}")]
+    KbpOutOfBounds(usize, usize, usize),
+    #[error("Node {0} has sigma={1} at its bottom level instead of -1")]
+    BottomNotMinusOne(usize, f64),
+    #[error("Node {0} has sigma={1} at the surface instead of 0")]
+    SurfaceNotZero(usize, f64),
+    #[error("Node {0} has sigma={3} at level {1}, not above sigma={2} at the level below")]
+    NonMonotoneSigma(usize, usize, f64, f64),
+}
@@ -0,0 +1,184 @@
+//! Vertical grid designs read from configuration files.
+
+use crate::transforms::geometric::GeometricTransformOpts;
+use crate::transforms::quadratic::QuadraticTransformOpts;
+use crate::transforms::s::STransformOpts;
+use crate::transforms::song_haidvogel::{HcPolicy, SongHaidvogel1994TransformOpts};
+use crate::transforms::StretchingFunction;
+use crate::vqs::{VQSBuilder, VQSBuilderError, VQS};
+use schismrs_hgrid::hgrid::Hgrid;
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// The `vgrid:` block of a model configuration file, e.g.
+///
+/// ```yaml
+/// vgrid:
+///   depths: [5., 50., 500., 4000.]
+///   nlevels: [5, 20, 35, 50]
+///   dz_bottom_min: 1.0
+///   stretching:
+///     kind: s
+///     theta_b: 0.
+///     theta_f: 3.
+/// ```
+///
+/// The other top-level keys of the file are ignored.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct VgridConfig {
+    /// Depths of the master grids (positive down), shallowest first.
+    pub depths: Vec<f64>,
+    /// Levels of each master grid.
+    pub nlevels: Vec<usize>,
+    #[serde(default)]
+    pub dz_bottom_min: f64,
+    pub stretching: StretchingConfig,
+}
+
+#[derive(Deserialize)]
+struct ModelConfig {
+    vgrid: VgridConfig,
+}
+
+impl VgridConfig {
+    /// Reads the `vgrid:` block of the YAML file at `path`.
+    pub fn try_from_model_config(path: &PathBuf) -> Result<Self, VgridConfigError> {
+        let config: ModelConfig = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+        Ok(config.vgrid)
+    }
+
+    /// Builds the design on `hgrid` with the defaults of [`VQSBuilder`] for
+    /// every option the block does not set.
+    pub fn build(&self, hgrid: &Hgrid) -> Result<VQS, VQSBuilderError> {
+        let stretching = self.stretching.stretching();
+        VQSBuilder::default()
+            .hgrid(hgrid)
+            .depths(&self.depths)
+            .nlevels(&self.nlevels)
+            .stretching(&stretching)
+            .dz_bottom_min(&self.dz_bottom_min)
+            .build()
+    }
+}
+
+/// Stretching function and its parameters, tagged by `kind` and taking the
+/// gen_vqs option names.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum StretchingConfig {
+    Quadratic {
+        #[serde(default)]
+        a_vqs0: f64,
+        #[serde(default)]
+        etal: f64,
+        #[serde(default = "default_skew_decay_rate")]
+        skew_decay_rate: f64,
+    },
+    S {
+        #[serde(default)]
+        a_vqs0: f64,
+        #[serde(default)]
+        etal: f64,
+        theta_b: f64,
+        theta_f: f64,
+    },
+    #[serde(rename = "song-haidvogel1994")]
+    SongHaidvogel1994 {
+        #[serde(default)]
+        a_vqs0: f64,
+        #[serde(default)]
+        etal: f64,
+        theta_s: f64,
+        theta_b: f64,
+        hc: f64,
+        #[serde(default)]
+        hc_policy: HcPolicy,
+    },
+    Geometric {
+        #[serde(default)]
+        a_vqs0: f64,
+        #[serde(default)]
+        etal: f64,
+        surface_dz: f64,
+        growth: f64,
+    },
+}
+
+fn default_skew_decay_rate() -> f64 {
+    0.3
+}
+
+impl StretchingConfig {
+    /// The stretching function of these parameters.
+    pub fn stretching(&self) -> StretchingFunction<'_> {
+        match self {
+            StretchingConfig::Quadratic {
+                a_vqs0,
+                etal,
+                skew_decay_rate,
+            } => StretchingFunction::Quadratic(QuadraticTransformOpts {
+                a_vqs0,
+                etal,
+                skew_decay_rate,
+            }),
+            StretchingConfig::S {
+                a_vqs0,
+                etal,
+                theta_b,
+                theta_f,
+            } => StretchingFunction::S(STransformOpts {
+                a_vqs0,
+                etal,
+                theta_b,
+                theta_f,
+            }),
+            StretchingConfig::SongHaidvogel1994 {
+                a_vqs0,
+                etal,
+                theta_s,
+                theta_b,
+                hc,
+                hc_policy,
+            } => StretchingFunction::SongHaidvogel1994(SongHaidvogel1994TransformOpts {
+                a_vqs0,
+                etal,
+                theta_s,
+                theta_b,
+                hc,
+                hc_policy,
+            }),
+            StretchingConfig::Geometric {
+                a_vqs0,
+                etal,
+                surface_dz,
+                growth,
+            } => StretchingFunction::Geometric(GeometricTransformOpts {
+                a_vqs0,
+                etal,
+                surface_dz,
+                growth,
+            }),
+        }
+    }
+
+    /// Free surface elevation the master grids are designed at.
+    pub fn etal(&self) -> &f64 {
+        match self {
+            StretchingConfig::Quadratic { etal, .. }
+            | StretchingConfig::S { etal, .. }
+            | StretchingConfig::SongHaidvogel1994 { etal, .. }
+            | StretchingConfig::Geometric { etal, .. } => etal,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum VgridConfigError {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error(transparent)]
+    YamlError(#[from] serde_yaml::Error),
+}
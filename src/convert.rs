@@ -0,0 +1,217 @@
+//! Conversion of ivcor=1 vertical grids between file formats, without the
+//! matching hgrid.
+//!
+//! Every format holds the bottom level index and sigma of each node; the
+//! node depths are not needed to move a grid from one format to another.
+
+use crate::vgrid_file::VgridFile;
+use crate::vqs::{VQSLoadError, VQS};
+use crate::writer::{VgridFormat, VgridWriterOptions};
+use ndarray::{Array1, Array2};
+use serde::{Deserialize, Serialize};
+use std::f64::NAN;
+use std::fs;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// File formats [`read_vgrid`] and [`write_vgrid`] handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VgridFileFormat {
+    /// vgrid.in in the layout of SCHISM's gen_vqs.
+    Legacy,
+    /// vgrid.in with one record per node.
+    NodeMajor,
+    /// `kbp` and `sigma` variables, as in the netCDF output of gen_vqs.
+    Netcdf,
+    /// See [`VgridJson`].
+    Json,
+}
+
+impl VgridFileFormat {
+    /// Guesses the format from the extension of `path`: .nc is netCDF, .json
+    /// is JSON and anything else a vgrid.in in the legacy layout.
+    pub fn from_path(path: &PathBuf) -> Self {
+        match path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase())
+            .as_deref()
+        {
+            Some("nc") => VgridFileFormat::Netcdf,
+            Some("json") => VgridFileFormat::Json,
+            _ => VgridFileFormat::Legacy,
+        }
+    }
+}
+
+/// JSON layout of a vertical grid. Each node lists the sigma of its wet
+/// levels from the bottom up, so no NaN padding is needed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VgridJson {
+    pub ivcor: usize,
+    pub nvrt: usize,
+    /// 1-based bottom level index of each node.
+    pub kbp: Vec<usize>,
+    pub sigma: Vec<Vec<f64>>,
+}
+
+impl VgridJson {
+    fn new(file: &VgridFile) -> Self {
+        Self {
+            ivcor: 1,
+            nvrt: file.nvrt(),
+            kbp: file.kbp().clone(),
+            sigma: file
+                .sigma()
+                .columns()
+                .into_iter()
+                .map(|column| column.iter().filter(|s| !s.is_nan()).cloned().collect())
+                .collect(),
+        }
+    }
+
+    fn into_vgrid_file(self) -> Result<VgridFile, ConvertError> {
+        if self.ivcor != 1 {
+            return Err(VQSLoadError::UnsupportedIvcor(self.ivcor).into());
+        }
+        let mut sigma = Array2::from_elem((self.nvrt, self.kbp.len()), NAN);
+        for (i, (&kbp, levels)) in self.kbp.iter().zip(self.sigma.iter()).enumerate() {
+            if kbp < 1 || kbp > self.nvrt {
+                return Err(VQSLoadError::InvalidBottomLevel(i + 1, kbp, self.nvrt).into());
+            }
+            if levels.len() != self.nvrt + 1 - kbp {
+                return Err(ConvertError::LevelCountMismatch(
+                    i + 1,
+                    levels.len(),
+                    self.nvrt + 1 - kbp,
+                ));
+            }
+            for (k, &value) in levels.iter().enumerate() {
+                sigma[[kbp - 1 + k, i]] = value;
+            }
+        }
+        Ok(VgridFile::from_parts(self.kbp, sigma, VgridFormat::Legacy))
+    }
+}
+
+/// Reads a vertical grid in the format of [`VgridFileFormat::from_path`],
+/// either vgrid.in layout being detected from the contents.
+pub fn read_vgrid(path: &PathBuf) -> Result<VgridFile, ConvertError> {
+    match VgridFileFormat::from_path(path) {
+        VgridFileFormat::Legacy | VgridFileFormat::NodeMajor => Ok(VgridFile::parse(path)?),
+        VgridFileFormat::Json => {
+            let json: VgridJson = serde_json::from_str(&fs::read_to_string(path)?)?;
+            json.into_vgrid_file()
+        }
+        VgridFileFormat::Netcdf => read_netcdf(path),
+    }
+}
+
+/// Writes `file` to `path` in `format`.
+pub fn write_vgrid(
+    file: &VgridFile,
+    path: &PathBuf,
+    format: VgridFileFormat,
+) -> Result<(), ConvertError> {
+    match format {
+        VgridFileFormat::Legacy | VgridFileFormat::NodeMajor => {
+            let mut options = VgridWriterOptions::new();
+            options.format(match format {
+                VgridFileFormat::NodeMajor => VgridFormat::NodeMajor,
+                _ => VgridFormat::Legacy,
+            });
+            // the writer only reads sigma, so the depths are placeholders
+            let vqs = VQS::from_sigma(file.sigma().clone(), Array1::zeros(file.np()), 0.);
+            vqs.write_to_file_with_options(path, &options)?;
+        }
+        VgridFileFormat::Json => {
+            fs::write(path, serde_json::to_string(&VgridJson::new(file))?)?;
+        }
+        VgridFileFormat::Netcdf => write_netcdf(file, path)?,
+    }
+    Ok(())
+}
+
+#[cfg(feature = "netcdf")]
+fn read_netcdf(path: &PathBuf) -> Result<VgridFile, ConvertError> {
+    let nc = netcdf::open(path)?;
+    let variable = |name: &str| {
+        nc.variable(name)
+            .ok_or_else(|| ConvertError::MissingVariable(name.to_string()))
+    };
+    let sigma = variable("sigma")?;
+    let shape: Vec<usize> = sigma.dimensions().iter().map(|dim| dim.len()).collect();
+    let (nvrt, np) = match shape.as_slice() {
+        [nvrt, np] => (*nvrt, *np),
+        _ => return Err(ConvertError::InvalidShape(shape)),
+    };
+    let sigma = Array2::from_shape_vec((nvrt, np), sigma.get_values::<f64, _>(..)?)
+        .map_err(|_| ConvertError::InvalidShape(shape))?;
+    let kbp: Vec<usize> = variable("kbp")?
+        .get_values::<i32, _>(..)?
+        .into_iter()
+        .map(|kbp| kbp.max(0) as usize)
+        .collect();
+    for (i, &bottom_level) in kbp.iter().enumerate() {
+        if bottom_level < 1 || bottom_level > nvrt {
+            return Err(VQSLoadError::InvalidBottomLevel(i + 1, bottom_level, nvrt).into());
+        }
+    }
+    Ok(VgridFile::from_parts(kbp, sigma, VgridFormat::Legacy))
+}
+
+#[cfg(not(feature = "netcdf"))]
+fn read_netcdf(_path: &PathBuf) -> Result<VgridFile, ConvertError> {
+    Err(ConvertError::NetcdfDisabled)
+}
+
+#[cfg(feature = "netcdf")]
+fn write_netcdf(file: &VgridFile, path: &PathBuf) -> Result<(), ConvertError> {
+    let mut nc = netcdf::create(path)?;
+    nc.add_dimension("nSCHISM_vgrid_layers", file.nvrt())?;
+    nc.add_dimension("nSCHISM_hgrid_node", file.np())?;
+    nc.add_attribute("Conventions", "CF-1.8")?;
+    nc.add_attribute("title", "SCHISM LSC2 vertical grid")?;
+    nc.add_attribute("ivcor", 1)?;
+    let mut kbp = nc.add_variable::<i32>("kbp", &["nSCHISM_hgrid_node"])?;
+    kbp.put_attribute("long_name", "1-based index of the bottom level")?;
+    let values: Vec<i32> = file.kbp().iter().map(|&index| index as i32).collect();
+    kbp.put_values(&values, ..)?;
+    let mut sigma =
+        nc.add_variable::<f64>("sigma", &["nSCHISM_vgrid_layers", "nSCHISM_hgrid_node"])?;
+    sigma.put_attribute("standard_name", "ocean_sigma_coordinate")?;
+    sigma.put_attribute("positive", "up")?;
+    sigma.set_fill_value(NAN)?;
+    let values: Vec<f64> = file.sigma().iter().cloned().collect();
+    sigma.put_values(&values, ..)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "netcdf"))]
+fn write_netcdf(_file: &VgridFile, _path: &PathBuf) -> Result<(), ConvertError> {
+    Err(ConvertError::NetcdfDisabled)
+}
+
+#[derive(Error, Debug)]
+pub enum ConvertError {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error(transparent)]
+    VQSLoadError(#[from] VQSLoadError),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[cfg(feature = "netcdf")]
+    #[error(transparent)]
+    NetcdfError(#[from] netcdf::Error),
+    #[cfg(feature = "netcdf")]
+    #[error("Variable {0} not found")]
+    MissingVariable(String),
+    #[cfg(feature = "netcdf")]
+    #[error("sigma must be (nSCHISM_vgrid_layers, nSCHISM_hgrid_node) but got shape {0:?}")]
+    InvalidShape(Vec<usize>),
+    #[error("Node {0} lists {1} sigma values but its kbp gives {2} levels")]
+    LevelCountMismatch(usize, usize, usize),
+    #[cfg(not(feature = "netcdf"))]
+    #[error("netCDF files need a build with --features netcdf")]
+    NetcdfDisabled,
+}
@@ -0,0 +1,112 @@
+use std::io::Write;
+
+/// Formatting shared by every CSV output of the crate, so tables open the same
+/// way in any spreadsheet regardless of the platform locale.
+///
+/// Floats are written with a fixed number of decimals and a `.` separator,
+/// non-finite floats as empty fields, and fields are quoted per RFC 4180 when
+/// they hold the delimiter, a quote or a line break.
+#[derive(Clone, Debug)]
+pub struct CsvFormatter {
+    /// Field separator.
+    pub delimiter: char,
+    /// Digits after the decimal point of each float.
+    pub precision: usize,
+}
+
+impl Default for CsvFormatter {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            precision: 6,
+        }
+    }
+}
+
+impl CsvFormatter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn delimiter(&mut self, delimiter: char) -> &mut Self {
+        self.delimiter = delimiter;
+        self
+    }
+    pub fn precision(&mut self, precision: usize) -> &mut Self {
+        self.precision = precision;
+        self
+    }
+
+    pub fn float(&self, value: f64) -> String {
+        if value.is_finite() {
+            format!("{:.*}", self.precision, value)
+        } else {
+            String::new()
+        }
+    }
+
+    pub fn text(&self, value: &str) -> String {
+        let needs_quotes = value
+            .chars()
+            .any(|c| c == self.delimiter || c == '"' || c == '\r' || c == '\n');
+        if needs_quotes {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+
+    /// Writes `fields`, already formatted with [`Self::float`] or
+    /// [`Self::text`], as one CRLF terminated record.
+    pub fn write_record<W: Write, S: AsRef<str>>(
+        &self,
+        writer: &mut W,
+        fields: &[S],
+    ) -> std::io::Result<()> {
+        let mut delimiter = [0u8; 4];
+        let delimiter = self.delimiter.encode_utf8(&mut delimiter).as_bytes();
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                writer.write_all(delimiter)?;
+            }
+            writer.write_all(field.as_ref().as_bytes())?;
+        }
+        writer.write_all(b"\r\n")
+    }
+
+    /// Splits a single line record into its fields, unquoting RFC 4180 quoted
+    /// fields. Quoted line breaks are not supported.
+    pub fn split_record(&self, line: &str) -> Vec<String> {
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = line.trim_end_matches(&['\r', '\n'][..]).chars().peekable();
+        while let Some(c) = chars.next() {
+            if quoted {
+                if c == '"' {
+                    if chars.peek() == Some(&'"') {
+                        field.push('"');
+                        chars.next();
+                    } else {
+                        quoted = false;
+                    }
+                } else {
+                    field.push(c);
+                }
+            } else if c == '"' {
+                quoted = true;
+            } else if c == self.delimiter {
+                fields.push(std::mem::take(&mut field));
+            } else {
+                field.push(c);
+            }
+        }
+        fields.push(field);
+        fields
+    }
+
+    /// Writes the `header` record, quoting the names as needed.
+    pub fn write_header<W: Write>(&self, writer: &mut W, header: &[&str]) -> std::io::Result<()> {
+        let header: Vec<String> = header.iter().map(|name| self.text(name)).collect();
+        self.write_record(writer, &header)
+    }
+}
@@ -0,0 +1,141 @@
+use crate::vqs::VQS;
+use std::fmt;
+use thiserror::Error;
+
+/// Maximum number of nodes listed when displaying level count changes.
+const MAX_LISTED_NODES: usize = 20;
+
+/// A node whose number of wet levels differs between two grids.
+pub struct LevelCountChange {
+    node: usize,
+    levels: usize,
+    other_levels: usize,
+}
+
+impl LevelCountChange {
+    /// 1-based node id.
+    pub fn node(&self) -> usize {
+        self.node
+    }
+
+    pub fn levels(&self) -> usize {
+        self.levels
+    }
+
+    pub fn other_levels(&self) -> usize {
+        self.other_levels
+    }
+}
+
+/// Differences between two vertical grids defined on the same hgrid.
+///
+/// Levels are compared bottom-up as stored in vgrid.in, so level k of both
+/// grids is only compared on nodes where it exists in both.
+pub struct VQSDiff {
+    nvrt: usize,
+    other_nvrt: usize,
+    level_count_changes: Vec<LevelCountChange>,
+    max_sigma_deviation: Vec<f64>,
+}
+
+impl VQSDiff {
+    pub fn new(vqs: &VQS, other: &VQS) -> Result<Self, VQSDiffError> {
+        let np = vqs.sigma().ncols();
+        let other_np = other.sigma().ncols();
+        if np != other_np {
+            return Err(VQSDiffError::NodeCountMismatch(np, other_np));
+        }
+        let (nvrt, other_nvrt) = (vqs.nvrt(), other.nvrt());
+        let mut level_count_changes = Vec::new();
+        for (i, (kbp, other_kbp)) in vqs
+            .bottom_level_indices()
+            .iter()
+            .zip(other.bottom_level_indices().iter())
+            .enumerate()
+        {
+            let levels = nvrt + 1 - kbp;
+            let other_levels = other_nvrt + 1 - other_kbp;
+            if levels != other_levels {
+                level_count_changes.push(LevelCountChange {
+                    node: i + 1,
+                    levels,
+                    other_levels,
+                });
+            }
+        }
+        let (sigma, other_sigma) = (vqs.sigma(), other.sigma());
+        let max_sigma_deviation = (0..nvrt.min(other_nvrt))
+            .map(|row| {
+                sigma
+                    .row(row)
+                    .iter()
+                    .zip(other_sigma.row(row).iter())
+                    .filter(|(a, b)| !a.is_nan() && !b.is_nan())
+                    .map(|(a, b)| (a - b).abs())
+                    .fold(0., f64::max)
+            })
+            .collect();
+        Ok(Self {
+            nvrt,
+            other_nvrt,
+            level_count_changes,
+            max_sigma_deviation,
+        })
+    }
+
+    pub fn nvrt(&self) -> (usize, usize) {
+        (self.nvrt, self.other_nvrt)
+    }
+
+    pub fn level_count_changes(&self) -> &Vec<LevelCountChange> {
+        &self.level_count_changes
+    }
+
+    /// Maximum |sigma - other sigma| of each level shared by both grids.
+    pub fn max_sigma_deviation(&self) -> &Vec<f64> {
+        &self.max_sigma_deviation
+    }
+
+    /// Whether both grids match within `tolerance`.
+    pub fn is_identical(&self, tolerance: &f64) -> bool {
+        self.nvrt == self.other_nvrt
+            && self.level_count_changes.is_empty()
+            && self
+                .max_sigma_deviation
+                .iter()
+                .all(|deviation| deviation <= tolerance)
+    }
+}
+
+impl fmt::Display for VQSDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "nvrt: {} -> {}\n", self.nvrt, self.other_nvrt)?;
+        write!(
+            f,
+            "nodes with a different level count: {}\n",
+            self.level_count_changes.len()
+        )?;
+        for change in self.level_count_changes.iter().take(MAX_LISTED_NODES) {
+            write!(
+                f,
+                "{:>12} {:>6} -> {}\n",
+                change.node, change.levels, change.other_levels
+            )?;
+        }
+        if self.level_count_changes.len() > MAX_LISTED_NODES {
+            write!(f, "{:>12}\n", "...")?;
+        }
+        write!(f, "{:>10} {:>16}\n", "level", "max |dsigma|")?;
+        for (row, deviation) in self.max_sigma_deviation.iter().enumerate() {
+            write!(f, "{:>10} {:>16.6e}\n", row + 1, deviation)?;
+        }
+        let overall = self.max_sigma_deviation.iter().cloned().fold(0., f64::max);
+        write!(f, "max |dsigma| over all levels: {:.6e}\n", overall)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum VQSDiffError {
+    #[error("Grids have a different number of nodes: {0} and {1}")]
+    NodeCountMismatch(usize, usize),
+}
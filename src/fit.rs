@@ -0,0 +1,308 @@
+//! Least-squares recovery of the master grids and stretching parameters of an
+//! existing vgrid.in, behind `vgrid fit`.
+//!
+//! Unstable: this module is not in [`crate::prelude`] and may change in any
+//! release.
+
+use crate::transforms::quadratic::QuadraticTransformOpts;
+use crate::transforms::s::STransformOpts;
+use crate::transforms::transforms::StretchingFunctionError;
+use crate::transforms::StretchingFunction;
+use crate::vqs::VQS;
+use schismrs_hgrid::Hgrid;
+use std::collections::BTreeMap;
+use std::fmt;
+use thiserror::Error;
+
+/// Samples per parameter of the coarse search that seeds the refinement.
+const COARSE_STEPS: usize = 11;
+
+/// Number of refinement iterations, each halving the step when no
+/// neighbouring parameter set improves the fit.
+const REFINE_ITERATIONS: usize = 40;
+
+/// Skew decay rate assumed for quadratic fits, same as
+/// [`QuadraticTransformOpts::new`].
+const QUADRATIC_SKEW_DECAY_RATE: f64 = 0.03;
+
+/// Stretching family fitted by [`fit_stretching`].
+#[derive(Clone, Copy, Debug)]
+pub enum FitKind {
+    /// Fits a_vqs0 in [-1, 1].
+    Quadratic,
+    /// Fits theta_f in (0, 20] and theta_b in [0, 1].
+    S,
+}
+
+/// Stretching parameters recovered from a loaded vertical grid.
+///
+/// vgrid.in does not record the elevation it was designed at, so fits assume
+/// etal = 0.
+#[derive(Clone, Debug)]
+pub struct FittedTransform {
+    kind: FitKind,
+    etal: f64,
+    a_vqs0: f64,
+    theta_f: f64,
+    theta_b: f64,
+    depths: Vec<f64>,
+    nlevels: Vec<usize>,
+    rmse: f64,
+}
+
+impl FittedTransform {
+    /// Stretching function with the fitted parameters.
+    pub fn stretching(&self) -> StretchingFunction<'_> {
+        stretching_function(
+            self.kind,
+            &self.etal,
+            &self.a_vqs0,
+            &self.theta_f,
+            &self.theta_b,
+        )
+    }
+
+    /// Master depths recovered from the grid: the deepest node of each wet
+    /// level count, the last one extended to the deepest node of the hgrid.
+    pub fn depths(&self) -> &Vec<f64> {
+        &self.depths
+    }
+
+    pub fn nlevels(&self) -> &Vec<usize> {
+        &self.nlevels
+    }
+
+    pub fn a_vqs0(&self) -> &f64 {
+        &self.a_vqs0
+    }
+
+    pub fn theta_f(&self) -> &f64 {
+        &self.theta_f
+    }
+
+    pub fn theta_b(&self) -> &f64 {
+        &self.theta_b
+    }
+
+    /// Root mean square sigma error of the fit over the sampled nodes.
+    pub fn rmse(&self) -> &f64 {
+        &self.rmse
+    }
+}
+
+impl fmt::Display for FittedTransform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            FitKind::Quadratic => write!(f, "transform quadratic\na_vqs0 {:.6}\n", self.a_vqs0)?,
+            FitKind::S => write!(
+                f,
+                "transform s\ntheta_f {:.6}\ntheta_b {:.6}\n",
+                self.theta_f, self.theta_b
+            )?,
+        }
+        write!(f, "rmse {:.6e}\n", self.rmse)?;
+        let depths: Vec<String> = self.depths.iter().map(|depth| depth.to_string()).collect();
+        let nlevels: Vec<String> = self.nlevels.iter().map(|n| n.to_string()).collect();
+        write!(f, "depths {}\n", depths.join(" "))?;
+        write!(f, "nlevels {}\n", nlevels.join(" "))
+    }
+}
+
+fn stretching_function<'b>(
+    kind: FitKind,
+    etal: &'b f64,
+    a_vqs0: &'b f64,
+    theta_f: &'b f64,
+    theta_b: &'b f64,
+) -> StretchingFunction<'b> {
+    match kind {
+        FitKind::Quadratic => StretchingFunction::Quadratic(QuadraticTransformOpts {
+            etal,
+            a_vqs0,
+            skew_decay_rate: &QUADRATIC_SKEW_DECAY_RATE,
+        }),
+        FitKind::S => StretchingFunction::S(STransformOpts {
+            etal,
+            a_vqs0,
+            theta_b,
+            theta_f,
+        }),
+    }
+}
+
+/// Recovers the master grids and the stretching parameters of a loaded `vqs`
+/// so that it can be regenerated, e.g. after editing the hgrid.
+///
+/// The sigma profile of the deepest node of each wet level count is compared
+/// against [`crate::transforms::traits::Transform::sigma_at`], and the
+/// parameters minimizing the sum of squared sigma differences are found with a
+/// coarse search refined by a shrinking pattern search.
+pub fn fit_stretching(
+    vqs: &VQS,
+    hgrid: &Hgrid,
+    kind: &FitKind,
+) -> Result<FittedTransform, FitStretchingError> {
+    let samples = sample_profiles(vqs);
+    if samples.len() < 2 {
+        return Err(FitStretchingError::NotEnoughLevelCounts(samples.len()));
+    }
+    let mut depths: Vec<f64> = samples.iter().map(|(depth, _)| *depth).collect();
+    let nlevels: Vec<usize> = samples.iter().map(|(_, sigma)| sigma.len()).collect();
+    let max_depth = vqs.depths().iter().cloned().fold(f64::MIN, f64::max);
+    let last = depths.len() - 1;
+    depths[last] = depths[last].max(max_depth);
+    let etal = 0.;
+    let (lower, upper) = match kind {
+        FitKind::Quadratic => ([-1., 0., 0.], [1., 0., 0.]),
+        FitKind::S => ([0., 1e-3, 0.], [0., 20., 1.]),
+    };
+    let free: Vec<usize> = (0..3).filter(|&p| upper[p] > lower[p]).collect();
+    let cost = |params: &[f64; 3]| -> Result<f64, FitStretchingError> {
+        let stretching = stretching_function(*kind, &etal, &params[0], &params[1], &params[2]);
+        let transform = stretching.transform(hgrid, &depths, &nlevels)?;
+        let mut sum = 0.;
+        for (depth, sigma) in samples.iter() {
+            let fitted = transform.sigma_at(*depth, sigma.len());
+            for (a, b) in fitted.iter().zip(sigma.iter()) {
+                sum += (a - b) * (a - b);
+            }
+        }
+        Ok(sum)
+    };
+    let mut best = lower;
+    let mut best_cost = f64::INFINITY;
+    let mut coarse = vec![lower];
+    for &p in free.iter() {
+        coarse = coarse
+            .into_iter()
+            .flat_map(|params| {
+                (0..COARSE_STEPS).map(move |i| {
+                    let mut params = params;
+                    params[p] =
+                        lower[p] + (upper[p] - lower[p]) * i as f64 / (COARSE_STEPS - 1) as f64;
+                    params
+                })
+            })
+            .collect();
+    }
+    for params in coarse.iter() {
+        let params_cost = cost(params)?;
+        if params_cost < best_cost {
+            best = *params;
+            best_cost = params_cost;
+        }
+    }
+    let mut steps = [0.; 3];
+    for &p in free.iter() {
+        steps[p] = (upper[p] - lower[p]) / (COARSE_STEPS - 1) as f64;
+    }
+    for _ in 0..REFINE_ITERATIONS {
+        let mut improved = false;
+        for &p in free.iter() {
+            for direction in [-1., 1.] {
+                let mut params = best;
+                params[p] = (params[p] + direction * steps[p]).clamp(lower[p], upper[p]);
+                let params_cost = cost(&params)?;
+                if params_cost < best_cost {
+                    best = params;
+                    best_cost = params_cost;
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            for &p in free.iter() {
+                steps[p] /= 2.;
+            }
+        }
+    }
+    let count: usize = nlevels.iter().sum();
+    Ok(FittedTransform {
+        kind: *kind,
+        etal,
+        a_vqs0: best[0],
+        theta_f: best[1],
+        theta_b: best[2],
+        depths,
+        nlevels,
+        rmse: (best_cost / count as f64).sqrt(),
+    })
+}
+
+/// Depth and surface to bottom sigma of the deepest wet node of each level
+/// count, ordered by level count.
+fn sample_profiles(vqs: &VQS) -> Vec<(f64, Vec<f64>)> {
+    let mut deepest: BTreeMap<usize, usize> = BTreeMap::new();
+    let depths = vqs.depths();
+    for (i, &depth) in depths.iter().enumerate() {
+        if depth <= 0. {
+            continue;
+        }
+        let levels = vqs.sigma_column(i).len();
+        let node = deepest.entry(levels).or_insert(i);
+        if depth > depths[*node] {
+            *node = i;
+        }
+    }
+    let mut samples: Vec<(f64, Vec<f64>)> = Vec::with_capacity(deepest.len());
+    for (_, node) in deepest {
+        // master depths must be strictly increasing
+        if samples
+            .last()
+            .map_or(false, |(depth, _)| depths[node] <= *depth)
+        {
+            continue;
+        }
+        samples.push((depths[node], vqs.sigma_column(node).into_surface_down()));
+    }
+    samples
+}
+
+#[derive(Error, Debug)]
+pub enum FitStretchingError {
+    #[error("Fitting needs wet nodes with at least 2 distinct level counts but got {0}")]
+    NotEnoughLevelCounts(usize),
+    #[error(transparent)]
+    StretchingFunctionError(#[from] StretchingFunctionError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{structured_hgrid, SYNTHETIC_SPACING};
+    use crate::vqs::VQSBuilder;
+
+    const MASTER_DEPTHS: [f64; 4] = [5., 15., 30., 60.];
+    const MASTER_NLEVELS: [usize; 4] = [6, 10, 15, 22];
+
+    #[test]
+    fn recovers_s_parameters() {
+        // one column of nodes at each master depth, so that the sampled
+        // profiles are the master grids themselves
+        let hgrid = structured_hgrid(4, 2, SYNTHETIC_SPACING, |x, _| {
+            MASTER_DEPTHS[(x / SYNTHETIC_SPACING).round() as usize]
+        })
+        .unwrap();
+        let stretching = StretchingFunction::S(STransformOpts {
+            etal: &0.,
+            a_vqs0: &0.,
+            theta_b: &0.7,
+            theta_f: &5.,
+        });
+        let (depths, nlevels) = (MASTER_DEPTHS.to_vec(), MASTER_NLEVELS.to_vec());
+        let vqs = VQSBuilder::default()
+            .hgrid(&hgrid)
+            .depths(&depths)
+            .nlevels(&nlevels)
+            .stretching(&stretching)
+            .dz_bottom_min(&0.1)
+            .build()
+            .unwrap();
+        let fitted = fit_stretching(&vqs, &hgrid, &FitKind::S).unwrap();
+        assert_eq!(fitted.depths(), &depths);
+        assert_eq!(fitted.nlevels(), &nlevels);
+        assert!((fitted.theta_f() - 5.).abs() < 0.05, "{}", fitted);
+        assert!((fitted.theta_b() - 0.7).abs() < 1e-2, "{}", fitted);
+        assert!(*fitted.rmse() < 1e-3, "{}", fitted);
+    }
+}
@@ -0,0 +1,214 @@
+//! FVCOM `sigma.dat` exports, for paired SCHISM/FVCOM experiments.
+//!
+//! FVCOM uses a single sigma distribution everywhere, so one column of the
+//! LSC2 grid, typically the deepest, is approximated by the closest of the
+//! UNIFORM, GEOMETRIC and TANH definitions of FVCOM's `setup_sigma`.
+
+use crate::sigma::SigmaColumn;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Sigma coordinate definition of an FVCOM `sigma.dat`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FvcomSigma {
+    Uniform,
+    /// Levels symmetric about mid-depth, refined at the surface and bottom for
+    /// powers above 1. Needs an odd number of levels.
+    Geometric {
+        power: f64,
+    },
+    /// Hyperbolic tangent with surface (du) and bottom (dl) refinement.
+    Tanh {
+        du: f64,
+        dl: f64,
+    },
+}
+
+/// Which [`FvcomSigma`] definitions [`fit_fvcom_sigma`] may choose from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FvcomSigmaKind {
+    /// The definition with the smallest error.
+    #[default]
+    Auto,
+    Uniform,
+    Geometric,
+    Tanh,
+}
+
+/// Largest geometric power and tanh du and dl tried by [`fit_fvcom_sigma`].
+const MAX_PARAMETER: f64 = 10.;
+/// Step of the parameter search of [`fit_fvcom_sigma`].
+const PARAMETER_STEP: f64 = 0.05;
+
+impl FvcomSigma {
+    /// Sigma of `nlevels` levels from the surface (0) down to the bottom (-1),
+    /// following FVCOM's `setup_sigma`.
+    pub fn levels(&self, nlevels: usize) -> Vec<f64> {
+        let kbm1 = nlevels as f64 - 1.;
+        match *self {
+            FvcomSigma::Uniform => (0..nlevels).map(|k| -(k as f64) / kbm1).collect(),
+            FvcomSigma::Geometric { power } => {
+                let half = ((nlevels + 1) / 2) as f64 - 1.;
+                (0..nlevels)
+                    .map(|k| {
+                        if k < (nlevels + 1) / 2 {
+                            -(k as f64 / half).powf(power) / 2.
+                        } else {
+                            ((nlevels - 1 - k) as f64 / half).powf(power) / 2. - 1.
+                        }
+                    })
+                    .collect()
+            }
+            FvcomSigma::Tanh { du, dl } => {
+                let x2 = dl.tanh();
+                let x3 = x2 + du.tanh();
+                (0..nlevels)
+                    .map(|k| {
+                        if k == 0 {
+                            return 0.;
+                        }
+                        let x1 = ((dl + du) * (kbm1 - k as f64) / kbm1 - dl).tanh();
+                        (x1 + x2) / x3 - 1.
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Writes `sigma.dat` for `nlevels` levels.
+    pub fn write_sigma_dat(&self, path: &PathBuf, nlevels: usize) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write!(writer, "NUMBER OF SIGMA LEVELS = {}\n", nlevels)?;
+        match self {
+            FvcomSigma::Uniform => write!(writer, "SIGMA COORDINATE TYPE = UNIFORM\n")?,
+            FvcomSigma::Geometric { power } => {
+                write!(writer, "SIGMA COORDINATE TYPE = GEOMETRIC\n")?;
+                write!(writer, "SIGMA POWER = {:.2}\n", power)?;
+            }
+            FvcomSigma::Tanh { du, dl } => {
+                write!(writer, "SIGMA COORDINATE TYPE = TANH\n")?;
+                write!(writer, "DU = {:.2}\n", du)?;
+                write!(writer, "DL = {:.2}\n", dl)?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+/// An [`FvcomSigma`] approximating a sigma column.
+pub struct FvcomSigmaFit {
+    sigma: FvcomSigma,
+    nlevels: usize,
+    rms_error: f64,
+    max_error: f64,
+}
+
+impl FvcomSigmaFit {
+    pub fn sigma(&self) -> &FvcomSigma {
+        &self.sigma
+    }
+
+    pub fn nlevels(&self) -> usize {
+        self.nlevels
+    }
+
+    /// Root mean square sigma difference over the levels.
+    pub fn rms_error(&self) -> &f64 {
+        &self.rms_error
+    }
+
+    /// Largest sigma difference of a level.
+    pub fn max_error(&self) -> &f64 {
+        &self.max_error
+    }
+
+    pub fn write_sigma_dat(&self, path: &PathBuf) -> std::io::Result<()> {
+        self.sigma.write_sigma_dat(path, self.nlevels)
+    }
+}
+
+impl fmt::Display for FvcomSigmaFit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let definition = match self.sigma {
+            FvcomSigma::Uniform => "UNIFORM".to_string(),
+            FvcomSigma::Geometric { power } => format!("GEOMETRIC p={:.2}", power),
+            FvcomSigma::Tanh { du, dl } => format!("TANH du={:.2} dl={:.2}", du, dl),
+        };
+        write!(f, "{:<28}{:>14}\n", "FVCOM sigma", definition)?;
+        write!(f, "{:<28}{:>14}\n", "levels", self.nlevels)?;
+        write!(f, "{:<28}{:>14.6}\n", "rms sigma error", self.rms_error)?;
+        write!(f, "{:<28}{:>14.6}\n", "max sigma error", self.max_error)
+    }
+}
+
+/// Finds the FVCOM definition of `kind` closest to `column`, by a grid search
+/// over the geometric power and the tanh du and dl in steps of 0.05 up to 10.
+pub fn fit_fvcom_sigma(
+    column: &SigmaColumn,
+    kind: FvcomSigmaKind,
+) -> Result<FvcomSigmaFit, FvcomSigmaError> {
+    let target = column.clone().into_surface_down();
+    let nlevels = target.len();
+    if nlevels < 2 {
+        return Err(FvcomSigmaError::TooFewLevels(nlevels));
+    }
+    let geometric_allowed = nlevels % 2 == 1;
+    if kind == FvcomSigmaKind::Geometric && !geometric_allowed {
+        return Err(FvcomSigmaError::EvenLevelsForGeometric(nlevels));
+    }
+    let steps = (MAX_PARAMETER / PARAMETER_STEP).round() as usize;
+    let parameters = || (0..=steps).map(|i| i as f64 * PARAMETER_STEP);
+    let mut candidates = Vec::new();
+    if matches!(kind, FvcomSigmaKind::Auto | FvcomSigmaKind::Uniform) {
+        candidates.push(FvcomSigma::Uniform);
+    }
+    if matches!(kind, FvcomSigmaKind::Auto | FvcomSigmaKind::Geometric) && geometric_allowed {
+        // FVCOM treats powers below 1 as uniform
+        candidates.extend(
+            parameters()
+                .filter(|&power| power > 1.)
+                .map(|power| FvcomSigma::Geometric { power }),
+        );
+    }
+    if matches!(kind, FvcomSigmaKind::Auto | FvcomSigmaKind::Tanh) {
+        for du in parameters() {
+            candidates.extend(
+                parameters()
+                    .filter(|&dl| du + dl > 0.)
+                    .map(|dl| FvcomSigma::Tanh { du, dl }),
+            );
+        }
+    }
+    let mut best: Option<FvcomSigmaFit> = None;
+    for sigma in candidates {
+        let levels = sigma.levels(nlevels);
+        let (squares, max_error) = levels
+            .iter()
+            .zip(target.iter())
+            .map(|(a, b)| (a - b).abs())
+            .fold((0., 0f64), |(squares, max), error| {
+                (squares + error * error, max.max(error))
+            });
+        let rms_error = (squares / nlevels as f64).sqrt();
+        if best.as_ref().map_or(true, |fit| rms_error < fit.rms_error) {
+            best = Some(FvcomSigmaFit {
+                sigma,
+                nlevels,
+                rms_error,
+                max_error,
+            });
+        }
+    }
+    best.ok_or(FvcomSigmaError::TooFewLevels(nlevels))
+}
+
+#[derive(Error, Debug)]
+pub enum FvcomSigmaError {
+    #[error("An FVCOM sigma distribution needs at least 2 levels, but the column has {0}")]
+    TooFewLevels(usize),
+    #[error("The GEOMETRIC FVCOM sigma needs an odd number of levels, but the column has {0}")]
+    EvenLevelsForGeometric(usize),
+}
@@ -0,0 +1,201 @@
+use crate::vqs::VQS;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The thinnest layer of a grid and where it sits.
+pub struct ThinnestLayer {
+    dz: f64,
+    node: usize,
+    depth: f64,
+    level: usize,
+}
+
+impl ThinnestLayer {
+    pub fn dz(&self) -> &f64 {
+        &self.dz
+    }
+
+    /// 0-based node index.
+    pub fn node(&self) -> usize {
+        self.node
+    }
+
+    /// Depth of the node (positive down).
+    pub fn depth(&self) -> &f64 {
+        &self.depth
+    }
+
+    /// 1-based vgrid.in level at the bottom of the layer.
+    pub fn level(&self) -> usize {
+        self.level
+    }
+}
+
+/// Overview of a vertical grid, mostly useful on a vgrid.in of unknown design.
+pub struct VgridInspection {
+    nvrt: usize,
+    np: usize,
+    level_histogram: BTreeMap<usize, usize>,
+    min_bottom_dz: f64,
+    mean_bottom_dz: f64,
+    max_bottom_dz: f64,
+    thinnest_layer: Option<ThinnestLayer>,
+    master_grids: Vec<(f64, usize)>,
+    extracted: bool,
+}
+
+impl VgridInspection {
+    pub fn new(vqs: &VQS) -> Self {
+        let nvrt = vqs.nvrt();
+        let z = vqs.z();
+        let depths = vqs.depths();
+        let mut min_bottom_dz = f64::INFINITY;
+        let mut max_bottom_dz: f64 = 0.;
+        let mut bottom_dz_sum = 0.;
+        let mut wet_nodes = 0;
+        let mut thinnest_layer: Option<ThinnestLayer> = None;
+        for (i, &kbp) in vqs.bottom_level_indices().iter().enumerate() {
+            if depths[i] <= 0. || kbp >= nvrt {
+                continue;
+            }
+            let bottom_dz = z[[kbp, i]] - z[[kbp - 1, i]];
+            min_bottom_dz = min_bottom_dz.min(bottom_dz);
+            max_bottom_dz = max_bottom_dz.max(bottom_dz);
+            bottom_dz_sum += bottom_dz;
+            wet_nodes += 1;
+            for row in kbp - 1..nvrt - 1 {
+                let dz = z[[row + 1, i]] - z[[row, i]];
+                if thinnest_layer.as_ref().map_or(true, |layer| dz < layer.dz) {
+                    thinnest_layer = Some(ThinnestLayer {
+                        dz,
+                        node: i,
+                        depth: depths[i],
+                        level: row + 1,
+                    });
+                }
+            }
+        }
+        let (master_grids, extracted) = match vqs.master_grids() {
+            Some((depths, nlevels)) => (depths.into_iter().zip(nlevels).collect(), false),
+            None => (Self::extract_master_grids(vqs), true),
+        };
+        Self {
+            nvrt,
+            np: depths.len(),
+            level_histogram: vqs.level_histogram(),
+            min_bottom_dz: if wet_nodes > 0 {
+                min_bottom_dz
+            } else {
+                f64::NAN
+            },
+            mean_bottom_dz: if wet_nodes > 0 {
+                bottom_dz_sum / wet_nodes as f64
+            } else {
+                f64::NAN
+            },
+            max_bottom_dz: if wet_nodes > 0 {
+                max_bottom_dz
+            } else {
+                f64::NAN
+            },
+            thinnest_layer,
+            master_grids,
+            extracted,
+        }
+    }
+
+    /// Depth of the deepest wet node of each level count, which is where an
+    /// LSC2 grid places its master grids.
+    fn extract_master_grids(vqs: &VQS) -> Vec<(f64, usize)> {
+        let nvrt = vqs.nvrt();
+        let mut deepest: BTreeMap<usize, f64> = BTreeMap::new();
+        for (&kbp, &depth) in vqs.bottom_level_indices().iter().zip(vqs.depths().iter()) {
+            if depth <= 0. {
+                continue;
+            }
+            let entry = deepest.entry(nvrt + 1 - kbp).or_insert(depth);
+            *entry = entry.max(depth);
+        }
+        deepest
+            .into_iter()
+            .map(|(nlevels, depth)| (depth, nlevels))
+            .collect()
+    }
+
+    pub fn nvrt(&self) -> usize {
+        self.nvrt
+    }
+
+    pub fn np(&self) -> usize {
+        self.np
+    }
+
+    /// Number of nodes for each level count.
+    pub fn level_histogram(&self) -> &BTreeMap<usize, usize> {
+        &self.level_histogram
+    }
+
+    /// Thinnest bottom layer over the wet nodes, NaN without wet nodes.
+    pub fn min_bottom_dz(&self) -> &f64 {
+        &self.min_bottom_dz
+    }
+
+    pub fn mean_bottom_dz(&self) -> &f64 {
+        &self.mean_bottom_dz
+    }
+
+    pub fn max_bottom_dz(&self) -> &f64 {
+        &self.max_bottom_dz
+    }
+
+    /// Thinnest layer over the wet nodes, None without wet nodes.
+    pub fn thinnest_layer(&self) -> Option<&ThinnestLayer> {
+        self.thinnest_layer.as_ref()
+    }
+
+    /// Depth and levels of each master grid, from the build when available.
+    pub fn master_grids(&self) -> &Vec<(f64, usize)> {
+        &self.master_grids
+    }
+
+    /// Whether the master grids were extracted from the node columns rather
+    /// than taken from the build.
+    pub fn extracted(&self) -> bool {
+        self.extracted
+    }
+}
+
+impl fmt::Display for VgridInspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<28}{:>14}\n", "nvrt", self.nvrt)?;
+        write!(f, "{:<28}{:>14}\n", "nodes", self.np)?;
+        write!(f, "{:<28}{:>14.4}\n", "min bottom dz", self.min_bottom_dz)?;
+        write!(f, "{:<28}{:>14.4}\n", "mean bottom dz", self.mean_bottom_dz)?;
+        write!(f, "{:<28}{:>14.4}\n", "max bottom dz", self.max_bottom_dz)?;
+        if let Some(layer) = &self.thinnest_layer {
+            write!(
+                f,
+                "thinnest layer: {:.4} at node {} (depth {}) level {}\n",
+                layer.dz,
+                layer.node + 1,
+                layer.depth,
+                layer.level
+            )?;
+        }
+        write!(f, "\n{:>8}{:>10}\n", "levels", "nodes")?;
+        for (levels, count) in self.level_histogram.iter() {
+            write!(f, "{:>8}{:>10}\n", levels, count)?;
+        }
+        let source = if self.extracted {
+            "extracted from the node columns"
+        } else {
+            "from the build"
+        };
+        write!(f, "\nmaster grids ({})\n", source)?;
+        write!(f, "{:>14}{:>8}\n", "depth", "levels")?;
+        for (depth, nlevels) in self.master_grids.iter() {
+            write!(f, "{:>14.4}{:>8}\n", depth, nlevels)?;
+        }
+        Ok(())
+    }
+}
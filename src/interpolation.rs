@@ -0,0 +1,152 @@
+use crate::vqs::VQS;
+use ndarray::Array2;
+use std::f64::NAN;
+#[cfg(feature = "netcdf")]
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Per-node linear interpolation weights from the VQS levels to a set of
+/// standard depths.
+///
+/// Target depths are positive down and measured from the free surface, the
+/// etal of each node (see [`VQS::etal_at`]).
+/// For each target and node, `lower_levels` holds the 1-based vgrid.in level
+/// right below the target depth and `weights` the weight given to the level
+/// above it, so that `f(z) = (1 - w) * f[k] + w * f[k + 1]`. A level of 0
+/// (and a NaN weight) means the target depth falls outside the water column.
+pub struct VerticalInterpolationWeights {
+    target_depths: Vec<f64>,
+    lower_levels: Array2<usize>,
+    weights: Array2<f64>,
+}
+
+impl VerticalInterpolationWeights {
+    pub fn new(
+        vqs: &VQS,
+        target_depths: &Vec<f64>,
+    ) -> Result<Self, VerticalInterpolationWeightsError> {
+        Self::compute(vqs, target_depths, |node| vqs.etal_at(node))
+    }
+
+    /// Weights for a free surface elevation `eta` instead of the grid etal,
+    /// with the target depths measured from `eta`.
+    pub fn at_elevation(
+        vqs: &VQS,
+        target_depths: &Vec<f64>,
+        eta: &f64,
+    ) -> Result<Self, VerticalInterpolationWeightsError> {
+        Self::compute(vqs, target_depths, |_| *eta)
+    }
+
+    fn compute(
+        vqs: &VQS,
+        target_depths: &Vec<f64>,
+        eta_at: impl Fn(usize) -> f64,
+    ) -> Result<Self, VerticalInterpolationWeightsError> {
+        Self::validate_target_depths(target_depths)?;
+        let nvrt = vqs.nvrt();
+        let np = vqs.sigma().ncols();
+        let mut lower_levels = Array2::<usize>::zeros((target_depths.len(), np));
+        let mut weights = Array2::from_elem((target_depths.len(), np), NAN);
+        for (i, &bottom_level) in vqs.bottom_level_indices().iter().enumerate() {
+            let eta = eta_at(i);
+            let z = vqs.z_coordinates(i, eta);
+            let surface = z[z.len() - 1];
+            for (t, &target_depth) in target_depths.iter().enumerate() {
+                let zt = eta - target_depth;
+                if zt < z[0] || zt > surface {
+                    continue;
+                }
+                for k in 0..nvrt - bottom_level {
+                    let (z_lower, z_upper) = (z[k], z[k + 1]);
+                    if zt >= z_lower && zt <= z_upper {
+                        lower_levels[[t, i]] = bottom_level + k;
+                        weights[[t, i]] = if z_upper > z_lower {
+                            (zt - z_lower) / (z_upper - z_lower)
+                        } else {
+                            0.
+                        };
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            target_depths: target_depths.clone(),
+            lower_levels,
+            weights,
+        })
+    }
+
+    pub fn target_depths(&self) -> &Vec<f64> {
+        &self.target_depths
+    }
+
+    pub fn lower_levels(&self) -> &Array2<usize> {
+        &self.lower_levels
+    }
+
+    pub fn weights(&self) -> &Array2<f64> {
+        &self.weights
+    }
+
+    #[cfg(feature = "netcdf")]
+    pub fn write_to_netcdf(
+        &self,
+        filename: &PathBuf,
+    ) -> Result<(), VerticalInterpolationWeightsError> {
+        let mut file = netcdf::create(filename)?;
+        file.add_dimension("depth", self.target_depths.len())?;
+        file.add_dimension("nSCHISM_hgrid_node", self.weights.ncols())?;
+        file.add_attribute("Conventions", "CF-1.8")?;
+        file.add_attribute(
+            "title",
+            "Vertical interpolation weights from SCHISM levels to standard depths",
+        )?;
+        let mut depth = file.add_variable::<f64>("depth", &["depth"])?;
+        depth.put_attribute("standard_name", "depth")?;
+        depth.put_attribute("units", "m")?;
+        depth.put_attribute("positive", "down")?;
+        depth.put_values(&self.target_depths, ..)?;
+        let mut lower_level =
+            file.add_variable::<i32>("lower_level", &["depth", "nSCHISM_hgrid_node"])?;
+        lower_level.put_attribute(
+            "long_name",
+            "1-based vgrid level below the target depth, 0 if outside the water column",
+        )?;
+        let values: Vec<i32> = self.lower_levels.iter().map(|&k| k as i32).collect();
+        lower_level.put_values(&values, ..)?;
+        let mut weight = file.add_variable::<f64>("weight", &["depth", "nSCHISM_hgrid_node"])?;
+        weight.put_attribute("long_name", "weight of the level above lower_level")?;
+        weight.set_fill_value(NAN)?;
+        let values: Vec<f64> = self.weights.iter().cloned().collect();
+        weight.put_values(&values, ..)?;
+        Ok(())
+    }
+
+    fn validate_target_depths(
+        target_depths: &Vec<f64>,
+    ) -> Result<(), VerticalInterpolationWeightsError> {
+        if target_depths.is_empty() {
+            return Err(VerticalInterpolationWeightsError::EmptyTargetDepths);
+        }
+        if target_depths.iter().any(|&depth| depth < 0.) {
+            return Err(VerticalInterpolationWeightsError::InvalidTargetDepths);
+        }
+        if !target_depths.windows(2).all(|pair| pair[0] < pair[1]) {
+            return Err(VerticalInterpolationWeightsError::InvalidTargetDepths);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum VerticalInterpolationWeightsError {
+    #[error("target_depths must not be empty")]
+    EmptyTargetDepths,
+    #[error("target_depths must be positive down and strictly increasing")]
+    InvalidTargetDepths,
+    #[cfg(feature = "netcdf")]
+    #[error(transparent)]
+    NetCDFError(#[from] netcdf::Error),
+}
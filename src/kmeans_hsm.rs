@@ -9,31 +9,158 @@ use std::cmp::Ordering;
 use std::time::Instant;
 use thiserror::Error;
 
+/// Strategy used to derive master grid depths from the hgrid depth distribution.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClusteringMethod {
+    /// k-means on the raw depths.
+    #[default]
+    KMeans,
+    /// k-means on log-transformed depths, which spreads clusters over the
+    /// shallow range instead of the deep tail.
+    LogKMeans,
+    /// Bins holding the same number of wet nodes each.
+    Quantile,
+    /// Jenks natural breaks, minimizing the within-class depth variance.
+    Jenks,
+}
+
+/// Maximum number of distinct depths Jenks natural breaks is evaluated on. The
+/// algorithm is quadratic in the number of values, so larger sets are
+/// subsampled evenly in depth order.
+const JENKS_MAX_VALUES: usize = 1000;
+
 pub fn kmeans_hsm(
     hgrid: &Hgrid,
     nclusters: &usize,
     etal: &f64,
+) -> Result<Vec<f64>, KMeansHSMCreateError> {
+    cluster_hsm(hgrid, nclusters, etal, &ClusteringMethod::KMeans)
+}
+
+/// Same as [`kmeans_hsm`] over bare `depths` (negative down, like
+/// `Hgrid::depths`), e.g. a subset of the nodes or bathymetry samples.
+///
+/// `weights`, aligned with `depths`, scale the contribution of each sample to
+/// the clusters.
+pub fn kmeans_hsm_from_depths(
+    depths: &[f64],
+    weights: Option<&[f64]>,
+    nclusters: &usize,
+    etal: &f64,
+) -> Result<Vec<f64>, KMeansHSMCreateError> {
+    cluster_hsm_from_depths(depths, weights, nclusters, etal, &ClusteringMethod::KMeans)
+}
+
+/// Master grid depths (as hgrid values, negative down) derived with `method`.
+///
+/// Each cluster contributes its deepest value, and the result is sorted from
+/// the shallowest to the deepest cluster.
+pub fn cluster_hsm(
+    hgrid: &Hgrid,
+    nclusters: &usize,
+    etal: &f64,
+    method: &ClusteringMethod,
+) -> Result<Vec<f64>, KMeansHSMCreateError> {
+    let depths: Vec<f64> = hgrid.depths().into_iter().collect();
+    cluster_hsm_from_depths(&depths, None, nclusters, etal, method)
+}
+
+/// Same as [`cluster_hsm`] over bare `depths`, optionally weighted. Quantile
+/// bins then hold the same total weight rather than the same number of nodes.
+pub fn cluster_hsm_from_depths(
+    depths: &[f64],
+    weights: Option<&[f64]>,
+    nclusters: &usize,
+    etal: &f64,
+    method: &ClusteringMethod,
 ) -> Result<Vec<f64>, KMeansHSMCreateError> {
     log::info!(
-        "Begin computing vertical distribution with nclusters={}",
-        nclusters
+        "Begin computing vertical distribution with nclusters={} and method={:?}",
+        nclusters,
+        method
     );
     let now = Instant::now();
-    let mut depths: Vec<f64> = hgrid.depths().into_iter().collect();
-    depths.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    depths.dedup();
+    if let Some(weights) = weights {
+        validate_weights(depths, weights)?;
+    }
     // keep only the underwater numbers.
-    depths.retain(|&x| x <= *etal);
-    let depths = Array1::from(depths);
-    let depth_len = depths.len();
-    let observations = DatasetBase::from(depths.clone().into_shape((depth_len, 1))?);
+    let mut samples: Vec<(f64, f64)> = depths
+        .iter()
+        .enumerate()
+        .filter(|(_, &x)| x <= *etal)
+        .map(|(i, &x)| (x, weights.map_or(1., |weights| weights[i])))
+        .collect();
+    samples.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    let weighted = weights.is_some();
+    let mut hsm = match method {
+        ClusteringMethod::KMeans => {
+            dedup_samples(&mut samples, weighted);
+            let (depths, weights) = unzip_samples(&samples, weighted);
+            kmeans_minimums(&depths, &depths, weights.as_ref(), nclusters)?
+        }
+        ClusteringMethod::LogKMeans => {
+            dedup_samples(&mut samples, weighted);
+            let (depths, weights) = unzip_samples(&samples, weighted);
+            let features: Vec<f64> = depths.iter().map(|x| (*etal - x + 1.).ln()).collect();
+            kmeans_minimums(&features, &depths, weights.as_ref(), nclusters)?
+        }
+        ClusteringMethod::Quantile => quantile_minimums(&samples, nclusters)?,
+        ClusteringMethod::Jenks => {
+            dedup_samples(&mut samples, weighted);
+            jenks_minimums(&samples, nclusters)?
+        }
+    };
+    hsm.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    log::debug!(
+        "Took {} to compute vertical distribution.",
+        format_duration(now.elapsed())
+    );
+    Ok(hsm)
+}
+
+/// Removes repeated depths from the sorted `samples`. Weighted samples keep
+/// the total weight of their duplicates, unweighted ones count once.
+fn dedup_samples(samples: &mut Vec<(f64, f64)>, weighted: bool) {
+    let mut merged: Vec<(f64, f64)> = Vec::with_capacity(samples.len());
+    for &(depth, weight) in samples.iter() {
+        match merged.last_mut() {
+            Some(last) if last.0 == depth => {
+                if weighted {
+                    last.1 += weight;
+                }
+            }
+            _ => merged.push((depth, weight)),
+        }
+    }
+    *samples = merged;
+}
+
+fn unzip_samples(samples: &Vec<(f64, f64)>, weighted: bool) -> (Vec<f64>, Option<Vec<f64>>) {
+    let (depths, weights): (Vec<f64>, Vec<f64>) = samples.iter().cloned().unzip();
+    (depths, if weighted { Some(weights) } else { None })
+}
+
+/// Clusters `features` with k-means and returns the minimum of `depths` in
+/// each cluster. Both slices are aligned.
+fn kmeans_minimums(
+    features: &Vec<f64>,
+    depths: &Vec<f64>,
+    weights: Option<&Vec<f64>>,
+    nclusters: &usize,
+) -> Result<Vec<f64>, KMeansHSMCreateError> {
+    let features = Array1::from(features.clone());
+    let features_len = features.len();
+    let mut observations = DatasetBase::from(features.into_shape((features_len, 1))?);
+    if let Some(weights) = weights {
+        observations = observations.with_weights(weights.iter().map(|&w| w as f32).collect());
+    }
     let model = KMeans::params(*nclusters).fit(&observations)?;
     let predictions = model.predict(observations);
     let targets = predictions.targets();
     let centroids = model.centroids().to_owned();
-    let mut hsm = Vec::with_capacity(centroids.len());
+    let mut hsm = Vec::with_capacity(centroids.nrows());
     // find the minimum depth associated to each computed centroid
-    for (index, _) in centroids.iter().enumerate() {
+    for index in 0..centroids.nrows() {
         let mut min_depth = f64::INFINITY;
 
         for (&depth, &cluster) in depths.iter().zip(targets.iter()) {
@@ -46,18 +173,124 @@ pub fn kmeans_hsm(
         }
         hsm.push(min_depth);
     }
-    hsm.sort_by(|a, b| b.partial_cmp(a).unwrap());
-    log::debug!(
-        "Took {} to compute vertical distribution.",
-        format_duration(now.elapsed())
-    );
     Ok(hsm)
 }
 
+/// First value of each of `nclusters` bins of equal total weight of the sorted
+/// `samples`.
+fn quantile_minimums(
+    samples: &Vec<(f64, f64)>,
+    nclusters: &usize,
+) -> Result<Vec<f64>, KMeansHSMCreateError> {
+    validate_nclusters(samples.len(), nclusters)?;
+    let total: f64 = samples.iter().map(|(_, weight)| weight).sum();
+    let mut hsm = Vec::with_capacity(*nclusters);
+    let mut cumulative = 0.;
+    let mut class = 0;
+    for &(depth, weight) in samples.iter() {
+        cumulative += weight;
+        // the sample holding the start of the class opens it
+        while class < *nclusters && cumulative > class as f64 * total / *nclusters as f64 {
+            hsm.push(depth);
+            class += 1;
+        }
+    }
+    hsm.dedup();
+    Ok(hsm)
+}
+
+/// First value of each Jenks natural breaks class of the sorted `samples`,
+/// using Fisher's dynamic programming formulation with weighted variances.
+fn jenks_minimums(
+    samples: &Vec<(f64, f64)>,
+    nclusters: &usize,
+) -> Result<Vec<f64>, KMeansHSMCreateError> {
+    let samples: Vec<(f64, f64)> = if samples.len() > JENKS_MAX_VALUES {
+        (0..JENKS_MAX_VALUES)
+            .map(|i| samples[i * (samples.len() - 1) / (JENKS_MAX_VALUES - 1)])
+            .collect()
+    } else {
+        samples.clone()
+    };
+    validate_nclusters(samples.len(), nclusters)?;
+    let values: Vec<f64> = samples.iter().map(|(depth, _)| *depth).collect();
+    let n = values.len();
+    let k = *nclusters;
+    // lower_class_limits[i][j]: 1-based index of the first value of the last
+    // class when splitting the first i values in j classes.
+    let mut lower_class_limits = vec![vec![0usize; k + 1]; n + 1];
+    let mut variance_combinations = vec![vec![f64::INFINITY; k + 1]; n + 1];
+    for j in 1..=k {
+        lower_class_limits[1][j] = 1;
+        variance_combinations[1][j] = 0.;
+    }
+    for i in 2..=n {
+        let (mut sum, mut sum_squares, mut count) = (0., 0., 0.);
+        let mut variance = 0.;
+        for m in 1..=i {
+            let lower = i - m + 1;
+            let (value, weight) = samples[lower - 1];
+            count += weight;
+            sum += weight * value;
+            sum_squares += weight * value * value;
+            variance = sum_squares - sum * sum / count;
+            if lower > 1 {
+                for j in 2..=k {
+                    let candidate = variance + variance_combinations[lower - 1][j - 1];
+                    if variance_combinations[i][j] >= candidate {
+                        lower_class_limits[i][j] = lower;
+                        variance_combinations[i][j] = candidate;
+                    }
+                }
+            }
+        }
+        lower_class_limits[i][1] = 1;
+        variance_combinations[i][1] = variance;
+    }
+    let mut hsm = Vec::with_capacity(k);
+    let mut upper = n;
+    for j in (1..=k).rev() {
+        let lower = lower_class_limits[upper][j];
+        hsm.push(values[lower - 1]);
+        if lower <= 1 {
+            break;
+        }
+        upper = lower - 1;
+    }
+    hsm.dedup();
+    Ok(hsm)
+}
+
+fn validate_nclusters(nsamples: usize, nclusters: &usize) -> Result<(), KMeansHSMCreateError> {
+    if *nclusters < 1 || *nclusters > nsamples {
+        return Err(KMeansHSMCreateError::InvalidNClusters(*nclusters, nsamples));
+    }
+    Ok(())
+}
+
+fn validate_weights(depths: &[f64], weights: &[f64]) -> Result<(), KMeansHSMCreateError> {
+    if weights.len() != depths.len() {
+        return Err(KMeansHSMCreateError::WeightsSizeMismatch(
+            weights.len(),
+            depths.len(),
+        ));
+    }
+    if let Some(weight) = weights.iter().find(|w| !w.is_finite() || **w <= 0.) {
+        return Err(KMeansHSMCreateError::InvalidWeight(*weight));
+    }
+    Ok(())
+}
+
 #[derive(Error, Debug)]
 pub enum KMeansHSMCreateError {
     #[error(transparent)]
     NDArrayShapeError(#[from] ShapeError),
     #[error(transparent)]
     KMeansError(#[from] KMeansError),
+    #[error("nclusters must be >= 1 and <= the number of wet depths ({1}) but got {0}")]
+    InvalidNClusters(usize, usize),
+    #[error("weights has {0} values but depths has {1}")]
+    WeightsSizeMismatch(usize, usize),
+    #[error("weights must be finite and > 0 but got {0}")]
+    InvalidWeight(f64),
 }
@@ -0,0 +1,118 @@
+//! Adjacent layer thickness ratio limit of [`crate::vqs::VQSBuildOptions::max_layer_ratio`].
+
+use ndarray::{Array2, Axis};
+use std::fmt;
+
+/// Maximum number of violations listed when displaying a report.
+const MAX_LISTED_VIOLATIONS: usize = 20;
+
+/// Two adjacent layers of a node whose thicknesses differ by more than the
+/// allowed ratio.
+#[derive(Debug)]
+pub struct LayerRatioViolation {
+    node: usize,
+    level: usize,
+    ratio: f64,
+}
+
+impl LayerRatioViolation {
+    /// 1-based node id, as in hgrid.gr3.
+    pub fn node(&self) -> usize {
+        self.node
+    }
+
+    /// 1-based vgrid level between the two layers.
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// Thickness of the thicker layer over the thinner one, always >= 1.
+    pub fn ratio(&self) -> f64 {
+        self.ratio
+    }
+}
+
+/// Every pair of adjacent layers over the limit, in node order and from the
+/// bottom up within a node.
+#[derive(Debug)]
+pub struct LayerRatioReport {
+    max_layer_ratio: f64,
+    violations: Vec<LayerRatioViolation>,
+}
+
+impl LayerRatioReport {
+    pub fn max_layer_ratio(&self) -> f64 {
+        self.max_layer_ratio
+    }
+
+    pub fn violations(&self) -> &Vec<LayerRatioViolation> {
+        &self.violations
+    }
+
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl fmt::Display for LayerRatioReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut nodes: Vec<usize> = self.violations.iter().map(|v| v.node).collect();
+        nodes.dedup();
+        write!(
+            f,
+            "{} layer pairs on {} nodes exceed the layer ratio of {}\n",
+            self.violations.len(),
+            nodes.len(),
+            self.max_layer_ratio
+        )?;
+        write!(f, "{:>10}{:>8}{:>14}\n", "node", "level", "ratio")?;
+        for violation in self.violations.iter().take(MAX_LISTED_VIOLATIONS) {
+            write!(
+                f,
+                "{:>10}{:>8}{:>14.4}\n",
+                violation.node, violation.level, violation.ratio
+            )?;
+        }
+        if self.violations.len() > MAX_LISTED_VIOLATIONS {
+            write!(f, "...\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the layers of `sigma`, (nvrt, np) in [`crate::vqs::VQS::LEVEL_ORDER`]
+/// with NaN below the bottom, against `max_layer_ratio`.
+///
+/// Every layer of a column is the same fraction of the total depth at any
+/// elevation, so the ratios are taken on sigma directly.
+pub(crate) fn check_layer_ratio(sigma: &Array2<f64>, max_layer_ratio: f64) -> LayerRatioReport {
+    let mut violations = Vec::new();
+    for (i, column) in sigma.axis_iter(Axis(1)).enumerate() {
+        let first = match column.iter().position(|s| !s.is_nan()) {
+            Some(first) => first,
+            None => continue,
+        };
+        let dsigma: Vec<f64> = column
+            .iter()
+            .skip(first)
+            .collect::<Vec<_>>()
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect();
+        for (k, pair) in dsigma.windows(2).enumerate() {
+            let ratio = pair[0].max(pair[1]) / pair[0].min(pair[1]);
+            if ratio > max_layer_ratio {
+                violations.push(LayerRatioViolation {
+                    node: i + 1,
+                    // 1-based level shared by the two layers
+                    level: first + k + 2,
+                    ratio,
+                });
+            }
+        }
+    }
+    LayerRatioReport {
+        max_layer_ratio,
+        violations,
+    }
+}
@@ -0,0 +1,290 @@
+use crate::csv::CsvFormatter;
+use crate::mesh::element_connectivity;
+use crate::vqs::VQS;
+use ndarray::Array2;
+use schismrs_hgrid::hgrid::Hgrid;
+use std::f64::NAN;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+/// A node whose bottom level was raised by [`VQS::limit_level_jumps`].
+pub struct AdjustedNode {
+    node: usize,
+    levels_before: usize,
+    levels_after: usize,
+}
+
+impl AdjustedNode {
+    /// 0-based node index.
+    pub fn node(&self) -> usize {
+        self.node
+    }
+
+    pub fn levels_before(&self) -> usize {
+        self.levels_before
+    }
+
+    pub fn levels_after(&self) -> usize {
+        self.levels_after
+    }
+}
+
+/// Nodes adjusted by [`VQS::limit_level_jumps`], in node order.
+pub struct LevelJumpReport {
+    max_level_jump: usize,
+    adjusted: Vec<AdjustedNode>,
+    sweeps: usize,
+}
+
+impl LevelJumpReport {
+    pub fn max_level_jump(&self) -> usize {
+        self.max_level_jump
+    }
+
+    pub fn adjusted(&self) -> &Vec<AdjustedNode> {
+        &self.adjusted
+    }
+
+    /// Number of sweeps over the elements until no node changed.
+    pub fn sweeps(&self) -> usize {
+        self.sweeps
+    }
+
+    /// Writes the adjusted nodes as a CSV table with 1-based node ids, as in
+    /// hgrid.gr3.
+    pub fn write_to_csv(&self, path: &PathBuf, csv: &CsvFormatter) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        csv.write_header(&mut writer, &["node", "levels_before", "levels_after"])?;
+        for node in self.adjusted.iter() {
+            csv.write_record(
+                &mut writer,
+                &[
+                    (node.node + 1).to_string(),
+                    node.levels_before.to_string(),
+                    node.levels_after.to_string(),
+                ],
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+impl fmt::Display for LevelJumpReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let removed = self
+            .adjusted
+            .iter()
+            .map(|node| node.levels_before - node.levels_after)
+            .max()
+            .unwrap_or(0);
+        write!(f, "{:<28}{:>14}\n", "max level jump", self.max_level_jump)?;
+        write!(f, "{:<28}{:>14}\n", "adjusted nodes", self.adjusted.len())?;
+        write!(f, "{:<28}{:>14}\n", "max removed levels", removed)?;
+        write!(f, "{:<28}{:>14}\n", "sweeps", self.sweeps)
+    }
+}
+
+/// Maximum number of elements listed when displaying a [`SteepElementReport`].
+const MAX_LISTED_ELEMENTS: usize = 20;
+
+/// An element whose nodes' level counts differ by more than the limit, e.g.
+/// a node with 2 levels on a bank next to one with 40 in the channel.
+pub struct SteepElement {
+    nodes: Vec<usize>,
+    min_levels: usize,
+    max_levels: usize,
+}
+
+impl SteepElement {
+    /// 1-based node ids of the element, as in hgrid.gr3.
+    pub fn nodes(&self) -> &Vec<usize> {
+        &self.nodes
+    }
+
+    pub fn min_levels(&self) -> usize {
+        self.min_levels
+    }
+
+    pub fn max_levels(&self) -> usize {
+        self.max_levels
+    }
+
+    pub fn level_jump(&self) -> usize {
+        self.max_levels - self.min_levels
+    }
+}
+
+/// Elements over the level jump limit, steepest first. See
+/// [`VQS::steep_elements`].
+pub struct SteepElementReport {
+    max_level_jump: usize,
+    elements: Vec<SteepElement>,
+}
+
+impl SteepElementReport {
+    pub fn max_level_jump(&self) -> usize {
+        self.max_level_jump
+    }
+
+    pub fn elements(&self) -> &Vec<SteepElement> {
+        &self.elements
+    }
+
+    /// Writes the nodes and level counts of every steep element.
+    pub fn write_to_csv(&self, path: &PathBuf, csv: &CsvFormatter) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        csv.write_header(&mut writer, &["nodes", "min_levels", "max_levels"])?;
+        for element in self.elements.iter() {
+            let nodes: Vec<String> = element.nodes.iter().map(|node| node.to_string()).collect();
+            csv.write_record(
+                &mut writer,
+                &[
+                    csv.text(&nodes.join(" ")),
+                    element.min_levels.to_string(),
+                    element.max_levels.to_string(),
+                ],
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+impl fmt::Display for SteepElementReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let max_jump = self
+            .elements
+            .first()
+            .map_or(0, |element| element.level_jump());
+        write!(f, "{:<28}{:>14}\n", "max level jump", self.max_level_jump)?;
+        write!(f, "{:<28}{:>14}\n", "steep elements", self.elements.len())?;
+        write!(f, "{:<28}{:>14}\n", "steepest jump", max_jump)?;
+        if self.elements.is_empty() {
+            return Ok(());
+        }
+        write!(f, "\n{:>8}{:>8}  {}\n", "min", "max", "nodes")?;
+        for element in self.elements.iter().take(MAX_LISTED_ELEMENTS) {
+            let nodes: Vec<String> = element.nodes.iter().map(|node| node.to_string()).collect();
+            write!(
+                f,
+                "{:>8}{:>8}  {}\n",
+                element.min_levels,
+                element.max_levels,
+                nodes.join(" ")
+            )?;
+        }
+        if self.elements.len() > MAX_LISTED_ELEMENTS {
+            write!(f, "...\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Implements [`VQS::steep_elements`].
+pub(crate) fn steep_elements(
+    vqs: &VQS,
+    hgrid: &Hgrid,
+    max_level_jump: usize,
+) -> SteepElementReport {
+    let nvrt = vqs.nvrt();
+    let levels: Vec<usize> = vqs
+        .bottom_level_indices()
+        .iter()
+        .map(|kbp| nvrt + 1 - kbp)
+        .collect();
+    let mut elements: Vec<SteepElement> = element_connectivity(hgrid)
+        .into_iter()
+        .filter_map(|element| {
+            let min_levels = element.iter().map(|&node| levels[node]).min()?;
+            let max_levels = element.iter().map(|&node| levels[node]).max()?;
+            if max_levels - min_levels <= max_level_jump {
+                return None;
+            }
+            Some(SteepElement {
+                nodes: element.iter().map(|&node| node + 1).collect(),
+                min_levels,
+                max_levels,
+            })
+        })
+        .collect();
+    elements.sort_by(|a, b| b.level_jump().cmp(&a.level_jump()));
+    SteepElementReport {
+        max_level_jump,
+        elements,
+    }
+}
+
+/// Implements [`VQS::limit_level_jumps`] on `sigma_vqs` and `znd`, both in
+/// [`VQS::LEVEL_ORDER`].
+///
+/// Dropping the levels right above the bottom keeps the surface resolution.
+/// Lowering a node can push one of its neighbours over the limit, so the
+/// elements are swept until no node changes.
+pub(crate) fn limit_level_jumps(
+    sigma_vqs: &mut Array2<f64>,
+    mut znd: Option<&mut Array2<f64>>,
+    hgrid: &Hgrid,
+    max_level_jump: usize,
+) -> LevelJumpReport {
+    let nvrt = sigma_vqs.nrows();
+    let before: Vec<usize> = (0..sigma_vqs.ncols())
+        .map(|i| {
+            nvrt - sigma_vqs
+                .column(i)
+                .iter()
+                .take_while(|sigma| sigma.is_nan())
+                .count()
+        })
+        .collect();
+    let mut levels = before.clone();
+    let elements = element_connectivity(hgrid);
+    let mut sweeps = 0;
+    let mut changed = true;
+    while changed {
+        changed = false;
+        sweeps += 1;
+        for element in elements.iter() {
+            let allowed = element
+                .iter()
+                .map(|&node| levels[node])
+                .min()
+                .unwrap_or(nvrt)
+                + max_level_jump;
+            for &node in element.iter() {
+                if levels[node] > allowed {
+                    levels[node] = allowed;
+                    changed = true;
+                }
+            }
+        }
+    }
+    let mut adjusted = Vec::new();
+    for (node, (&levels_before, &levels_after)) in before.iter().zip(levels.iter()).enumerate() {
+        if levels_after == levels_before {
+            continue;
+        }
+        let old_bottom = nvrt - levels_before;
+        let new_bottom = nvrt - levels_after;
+        sigma_vqs[[new_bottom, node]] = -1.;
+        if let Some(znd) = znd.as_deref_mut() {
+            znd[[new_bottom, node]] = znd[[old_bottom, node]];
+        }
+        for row in old_bottom..new_bottom {
+            sigma_vqs[[row, node]] = NAN;
+            if let Some(znd) = znd.as_deref_mut() {
+                znd[[row, node]] = NAN;
+            }
+        }
+        adjusted.push(AdjustedNode {
+            node,
+            levels_before,
+            levels_after,
+        });
+    }
+    LevelJumpReport {
+        max_level_jump,
+        adjusted,
+        sweeps,
+    }
+}
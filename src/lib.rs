@@ -1,5 +1,54 @@
-pub use kmeans_hsm::{kmeans_hsm, KMeansHSMCreateError};
+pub use kmeans_hsm::{
+    cluster_hsm, cluster_hsm_from_depths, kmeans_hsm, kmeans_hsm_from_depths, ClusteringMethod,
+    KMeansHSMCreateError,
+};
+pub mod anchors;
+pub mod autotune;
+pub mod bands;
+pub mod blend;
+pub mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod cells;
+pub mod channels;
+pub mod checks;
+pub mod config;
+pub mod convert;
+pub mod csv;
+pub mod diff;
+pub mod fit;
+pub mod fvcom;
+mod hash;
+pub mod inspect;
+pub mod interpolation;
 pub mod kmeans_hsm;
+pub mod layer_ratio;
+pub mod level_jumps;
+#[doc(hidden)]
+pub mod lock;
+pub mod master_grid;
+mod mesh;
+pub mod metadata;
+pub mod optimize;
+#[cfg(feature = "plotters")]
+pub mod plot;
+pub mod prelude;
+pub mod pressure_gradient;
+pub mod probe;
+pub mod progress;
+pub mod quality;
+pub mod regions;
+pub mod sigma;
+pub mod smoothing;
+pub mod summary;
 pub mod sz;
+#[cfg(any(test, feature = "testing"))]
+#[doc(hidden)]
+pub mod testing;
 pub mod transforms;
+#[cfg(feature = "netcdf")]
+pub mod ugrid;
+pub mod validation;
+pub mod vgrid_file;
 pub mod vqs;
+pub mod writer;
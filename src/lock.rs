@@ -0,0 +1,176 @@
+use crate::hash::{fnv1a, FNV_OFFSET_BASIS};
+use crate::vqs::VQS;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Keys recorded for information only, which never make two locks differ.
+/// The version changes with every commit, so comparing it would fail locked
+/// builds that resolve identically.
+pub const INFO_KEYS: &[&str] = &["version"];
+
+/// Keys holding the outcome of the build rather than its parameters, see
+/// [`VgridLock::insert_master_grids`].
+pub const RESOLVED_KEYS: &[&str] = &["master_depths", "master_nlevels", "nvrt"];
+
+/// Resolved parameters of a grid build, written as a `vgrid.lock` file.
+///
+/// Entries are plain `key = value` lines kept in insertion order. Two builds
+/// resolve identically when their locks hold the same entries, except for
+/// [`INFO_KEYS`], so a locked build can refuse to proceed when defaults,
+/// automatic calculations or the input files change.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct VgridLock {
+    entries: Vec<(String, String)>,
+}
+
+impl VgridLock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `key`, replacing its previous value if any.
+    pub fn insert<T: ToString>(&mut self, key: &str, value: T) -> &mut Self {
+        let value = value.to_string();
+        match self.entries.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.entries.push((key.to_string(), value)),
+        }
+        self
+    }
+
+    /// Records the master grids the build resolved to, which captures the
+    /// outcome of automatic depth and level calculations.
+    pub fn insert_master_grids(&mut self, vqs: &VQS) -> &mut Self {
+        if let Some((depths, nlevels)) = vqs.master_grids() {
+            let depths: Vec<String> = depths.iter().map(|depth| depth.to_string()).collect();
+            let nlevels: Vec<String> = nlevels.iter().map(|n| n.to_string()).collect();
+            self.insert("master_depths", depths.join(" "));
+            self.insert("master_nlevels", nlevels.join(" "));
+        }
+        self.insert("nvrt", vqs.nvrt())
+    }
+
+    /// Records the FNV-1a hash of the file at `path` under `key`.
+    pub fn insert_file_hash(
+        &mut self,
+        key: &str,
+        path: &PathBuf,
+    ) -> Result<&mut Self, VgridLockError> {
+        let hash = fnv1a(FNV_OFFSET_BASIS, &fs::read(path)?);
+        Ok(self.insert(key, format!("{:016x}", hash)))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Keys whose values differ between both locks, including keys present in
+    /// only one of them. [`INFO_KEYS`] are ignored.
+    pub fn differences(&self, other: &VgridLock) -> Vec<String> {
+        self.differences_ignoring(other, INFO_KEYS)
+    }
+
+    /// Like [`VgridLock::differences`], but also ignores [`RESOLVED_KEYS`], so
+    /// the parameters can be checked before spending the time of a build.
+    pub fn parameter_differences(&self, other: &VgridLock) -> Vec<String> {
+        let ignored: Vec<&str> = INFO_KEYS.iter().chain(RESOLVED_KEYS).cloned().collect();
+        self.differences_ignoring(other, &ignored)
+    }
+
+    fn differences_ignoring(&self, other: &VgridLock, ignored: &[&str]) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(key, value)| other.get(key) != Some(value.as_str()))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for (key, _) in other.entries.iter() {
+            if self.get(key).is_none() {
+                keys.push(key.clone());
+            }
+        }
+        keys.retain(|key| !ignored.contains(&key.as_str()));
+        keys
+    }
+
+    pub fn try_from_file(path: &PathBuf) -> Result<Self, VgridLockError> {
+        let mut lock = Self::new();
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once(" = ")
+                .ok_or_else(|| VgridLockError::InvalidLine(number + 1, line.to_string()))?;
+            lock.insert(key.trim(), value.trim());
+        }
+        Ok(lock)
+    }
+
+    pub fn write_to_file(&self, path: &PathBuf) -> std::io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for VgridLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "# Resolved vgrid build parameters. Do not edit by hand.\n"
+        )?;
+        for (key, value) in self.entries.iter() {
+            write!(f, "{} = {}\n", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum VgridLockError {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error("Line {0} of the lock file is not a 'key = value' pair: {1:?}")]
+    InvalidLine(usize, String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock(version: &str, dz_bottom_min: f64, nvrt: usize) -> VgridLock {
+        let mut lock = VgridLock::new();
+        lock.insert("version", version);
+        lock.insert("dz_bottom_min", dz_bottom_min);
+        lock.insert("nvrt", nvrt);
+        lock
+    }
+
+    #[test]
+    fn version_is_not_compared() {
+        let locked = lock("v0.1.0-3-gabc1234", 0.5, 30);
+        let current = lock("v0.1.0-4-gdef5678", 0.5, 30);
+        assert!(current.differences(&locked).is_empty());
+        assert!(current.parameter_differences(&locked).is_empty());
+    }
+
+    #[test]
+    fn parameter_differences_ignore_resolved_keys() {
+        let locked = lock("v0.1.0", 0.5, 30);
+        let mut parameters = VgridLock::new();
+        parameters.insert("version", "v0.1.0");
+        parameters.insert("dz_bottom_min", 0.5);
+        assert!(parameters.parameter_differences(&locked).is_empty());
+        assert_eq!(parameters.differences(&locked), vec!["nvrt"]);
+        parameters.insert("dz_bottom_min", 1.);
+        assert_eq!(
+            parameters.parameter_differences(&locked),
+            vec!["dz_bottom_min"]
+        );
+    }
+}
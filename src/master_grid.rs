@@ -0,0 +1,97 @@
+//! The master grids of an LSC2 grid as plain data, for scripts that need the
+//! design levels, e.g. to interpolate boundary forcing onto them.
+
+use ndarray::{Array2, Axis};
+use serde::{Deserialize, Serialize};
+use std::f64::NAN;
+use std::fs;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Depth, level count and level z of each master grid, shallowest first.
+///
+/// Levels are stored per master grid from the surface down, without the NaN
+/// padding of the z_mas matrix, so the table serializes to plain JSON or YAML.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MasterGrid {
+    /// Free surface elevation the levels were computed at.
+    pub etal: f64,
+    /// Depth of each master grid (positive down).
+    pub depths: Vec<f64>,
+    pub nlevels: Vec<usize>,
+    /// z of the levels of each master grid, from the surface down.
+    pub z_mas: Vec<Vec<f64>>,
+}
+
+impl MasterGrid {
+    /// Table of a z_mas matrix with one column per master grid, levels from
+    /// the surface down and NaN below the bottom.
+    pub fn from_z_mas(z_mas: &Array2<f64>, etal: f64) -> Self {
+        let z_mas: Vec<Vec<f64>> = z_mas
+            .axis_iter(Axis(1))
+            .map(|column| column.iter().filter(|z| !z.is_nan()).cloned().collect())
+            .collect();
+        Self {
+            etal,
+            depths: z_mas
+                .iter()
+                .map(|levels| -levels.iter().cloned().fold(f64::INFINITY, f64::min))
+                .collect(),
+            nlevels: z_mas.iter().map(|levels| levels.len()).collect(),
+            z_mas,
+        }
+    }
+
+    /// The z_mas matrix: one column per master grid, levels from the surface
+    /// down, NaN below the bottom.
+    pub fn z_mas_array(&self) -> Array2<f64> {
+        let rows = self.nlevels.iter().cloned().max().unwrap_or(0);
+        let mut z_mas = Array2::from_elem((rows, self.z_mas.len()), NAN);
+        for (mut column, levels) in z_mas.axis_iter_mut(Axis(1)).zip(self.z_mas.iter()) {
+            for (k, &z) in levels.iter().enumerate() {
+                column[k] = z;
+            }
+        }
+        z_mas
+    }
+
+    /// Writes the table as YAML when `path` ends in .yaml or .yml, and as JSON
+    /// when it ends in .json.
+    pub fn write_to_file(&self, path: &PathBuf) -> Result<(), MasterGridError> {
+        let contents = match extension(path).as_deref() {
+            Some("json") => serde_json::to_string_pretty(self)?,
+            Some("yaml") | Some("yml") => serde_yaml::to_string(self)?,
+            _ => return Err(MasterGridError::UnknownFormat(path.clone())),
+        };
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Reads a table written by [`MasterGrid::write_to_file`].
+    pub fn try_from_file(path: &PathBuf) -> Result<Self, MasterGridError> {
+        let contents = fs::read_to_string(path)?;
+        Ok(match extension(path).as_deref() {
+            Some("json") => serde_json::from_str(&contents)?,
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&contents)?,
+            _ => return Err(MasterGridError::UnknownFormat(path.clone())),
+        })
+    }
+}
+
+fn extension(path: &PathBuf) -> Option<String> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase())
+}
+
+#[derive(Error, Debug)]
+pub enum MasterGridError {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error(transparent)]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("Master grid path must end in .json, .yaml or .yml but got {0:?}")]
+    UnknownFormat(PathBuf),
+}
@@ -0,0 +1,84 @@
+use schismrs_hgrid::hgrid::Hgrid;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+/// 0-based node indices of every element of `hgrid`, in file order.
+///
+/// gr3 node ids are 1-based and contiguous, so they map directly onto the
+/// columns of the VQS arrays.
+pub(crate) fn element_connectivity(hgrid: &Hgrid) -> Vec<Vec<usize>> {
+    hgrid
+        .elements()
+        .hash_map()
+        .values()
+        .map(|nodes| nodes.iter().map(|&id| id as usize - 1).collect())
+        .collect()
+}
+
+/// Median-dual area of every node of `hgrid`: each element gives an equal
+/// share of its area to each of its nodes.
+pub(crate) fn nodal_areas(hgrid: &Hgrid) -> Vec<f64> {
+    let (x, y) = (hgrid.x(), hgrid.y());
+    let mut areas = vec![0.; x.len()];
+    for element in element_connectivity(hgrid).iter() {
+        let mut twice_area = 0.;
+        for (a, &node_a) in element.iter().enumerate() {
+            let node_b = element[(a + 1) % element.len()];
+            twice_area += x[node_a] * y[node_b] - x[node_b] * y[node_a];
+        }
+        let share = twice_area.abs() / 2. / element.len() as f64;
+        for &node in element.iter() {
+            areas[node] += share;
+        }
+    }
+    areas
+}
+
+/// Writes a gr3 on the mesh of `hgrid` holding `values` at its nodes instead
+/// of the depths, for viewing node fields in the usual SCHISM tools.
+pub(crate) fn write_node_values_gr3<T: fmt::Display>(
+    hgrid: &Hgrid,
+    description: &str,
+    values: &[T],
+    path: &PathBuf,
+) -> std::io::Result<()> {
+    let (x, y) = (hgrid.x(), hgrid.y());
+    let mut elements: Vec<_> = hgrid.elements().hash_map().iter().collect();
+    elements.sort_by_key(|(id, _)| **id);
+    let mut writer = BufWriter::new(File::create(path)?);
+    write!(
+        writer,
+        "{}\n{} {}\n",
+        description,
+        elements.len(),
+        values.len()
+    )?;
+    for (node, value) in values.iter().enumerate() {
+        write!(writer, "{} {} {} {}\n", node + 1, x[node], y[node], value)?;
+    }
+    for (id, nodes) in elements {
+        write!(writer, "{} {}", id, nodes.len())?;
+        for node in nodes.iter() {
+            write!(writer, " {}", node)?;
+        }
+        write!(writer, "\n")?;
+    }
+    writer.flush()
+}
+
+/// Even-odd ray casting test of (x, y) against a closed ring of vertices.
+pub(crate) fn point_in_polygon(vertices: &Vec<(f64, f64)>, x: f64, y: f64) -> bool {
+    let mut inside = false;
+    let mut j = vertices.len() - 1;
+    for i in 0..vertices.len() {
+        let (xi, yi) = vertices[i];
+        let (xj, yj) = vertices[j];
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
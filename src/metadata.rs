@@ -0,0 +1,118 @@
+use crate::vgrid_file::VgridFile;
+use crate::vqs::{level_histogram, VQS};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Stretching the grid was built with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransformMetadata {
+    pub name: String,
+    pub parameters: BTreeMap<String, f64>,
+}
+
+/// Provenance of a vertical grid, meant to be archived next to its vgrid.in.
+///
+/// Fields that vgrid.in does not record (transform, master grids and
+/// dz_bottom_min) are only known for grids that were built rather than loaded.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VgridMetadata {
+    /// Version of schismrs-vgrid that produced the metadata.
+    pub version: String,
+    pub ivcor: usize,
+    pub nvrt: usize,
+    pub np: usize,
+    pub etal: f64,
+    /// Min and max of the per-node etal, for grids built with an etal field.
+    pub etal_field_range: Option<[f64; 2]>,
+    pub transform: Option<TransformMetadata>,
+    pub master_depths: Option<Vec<f64>>,
+    pub master_nlevels: Option<Vec<usize>>,
+    pub dz_bottom_min: Option<f64>,
+    /// Number of nodes for each wet level count.
+    pub level_histogram: BTreeMap<usize, usize>,
+}
+
+impl VgridMetadata {
+    pub(crate) fn new(vqs: &VQS) -> Self {
+        let nvrt = vqs.nvrt();
+        let transform = vqs.transform().map(|transform| TransformMetadata {
+            name: transform.name().to_string(),
+            parameters: transform
+                .parameters()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        });
+        let (master_depths, master_nlevels) = match vqs.master_grids() {
+            Some((depths, nlevels)) => (Some(depths), Some(nlevels)),
+            None => (None, None),
+        };
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            ivcor: vqs.ivcor(),
+            nvrt,
+            np: vqs.sigma().ncols(),
+            etal: *vqs.etal(),
+            etal_field_range: vqs.etal_field().map(|field| {
+                let min = field.iter().cloned().fold(f64::INFINITY, f64::min);
+                let max = field.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                [min, max]
+            }),
+            transform,
+            master_depths,
+            master_nlevels,
+            dz_bottom_min: vqs.dz_bottom_min().cloned(),
+            level_histogram: vqs.level_histogram(),
+        }
+    }
+
+    /// Metadata of a vgrid.in read without its hgrid, which like a loaded
+    /// [`VQS`] is assumed to be at etal=0.
+    pub(crate) fn from_vgrid_file(file: &VgridFile) -> Self {
+        let nvrt = file.nvrt();
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            ivcor: 1,
+            nvrt,
+            np: file.np(),
+            etal: 0.,
+            etal_field_range: None,
+            transform: None,
+            master_depths: None,
+            master_nlevels: None,
+            dz_bottom_min: None,
+            level_histogram: level_histogram(nvrt, file.kbp()),
+        }
+    }
+
+    /// Writes the metadata as YAML when `path` ends in .yaml or .yml, and as
+    /// JSON when it ends in .json.
+    pub fn write_to_file(&self, path: &PathBuf) -> Result<(), VgridMetadataError> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        let contents = match extension.as_deref() {
+            Some("json") => serde_json::to_string_pretty(self)?,
+            Some("yaml") | Some("yml") => serde_yaml::to_string(self)?,
+            _ => return Err(VgridMetadataError::UnknownFormat(path.clone())),
+        };
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum VgridMetadataError {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error(transparent)]
+    YamlError(#[from] serde_yaml::Error),
+    #[error("Metadata path must end in .json, .yaml or .yml but got {0:?}")]
+    UnknownFormat(PathBuf),
+}
@@ -0,0 +1,562 @@
+//! Nelder-Mead search of the stretching parameters over fixed master grids,
+//! behind `vgrid optimize`.
+//!
+//! Unstable: this module is not in [`crate::prelude`] and may change in any
+//! release.
+
+use crate::quality::quality_report;
+use crate::transforms::s::STransformOpts;
+use crate::transforms::song_haidvogel::{HcPolicy, SongHaidvogel1994TransformOpts};
+use crate::transforms::StretchingFunction;
+use crate::vqs::{VQSBuilder, VQS};
+use schismrs_hgrid::Hgrid;
+use std::fmt;
+use thiserror::Error;
+
+/// Cost added to candidates that violate a constraint, so that any feasible
+/// candidate ranks before them.
+const INFEASIBLE_PENALTY: f64 = 1e6;
+
+/// Edge of the initial simplex, as a fraction of each parameter range.
+const INITIAL_STEP: f64 = 0.25;
+
+/// Stretching family searched by [`VQSOptimizeBuilder`].
+#[derive(Clone, Debug)]
+pub enum OptimizeTransform {
+    /// Searches theta_f in (0, 20] and theta_b in [0, 1].
+    S,
+    /// Searches theta_s in (0, 20] and hc in (0, first master grid depth] with
+    /// a fixed theta_b.
+    SongHaidvogel1994 { theta_b: f64 },
+}
+
+impl OptimizeTransform {
+    fn bounds(&self, depths: &[f64]) -> [(f64, f64); 2] {
+        match self {
+            OptimizeTransform::S => [(0.01, 20.), (0., 1.)],
+            OptimizeTransform::SongHaidvogel1994 { .. } => [(0.01, 20.), (0.01, depths[0])],
+        }
+    }
+
+    fn parameter_names(&self) -> [&'static str; 2] {
+        match self {
+            OptimizeTransform::S => ["theta_f", "theta_b"],
+            OptimizeTransform::SongHaidvogel1994 { .. } => ["theta_s", "hc"],
+        }
+    }
+}
+
+/// Quantity minimized by [`VQSOptimizeBuilder`].
+#[derive(Clone, Debug, Default)]
+pub enum OptimizeCost {
+    /// Mean over the wet nodes of their largest adjacent layer ratio.
+    #[default]
+    MeanLayerRatio,
+    /// Largest adjacent layer ratio of the grid.
+    MaxLayerRatio,
+    /// Mean Haney (rx1) number of the elements.
+    MeanHaney,
+}
+
+/// Limits a candidate must honour to be accepted. Unset limits are not checked.
+#[derive(Clone, Debug, Default)]
+pub struct OptimizeConstraints {
+    pub max_layer_ratio: Option<f64>,
+    pub min_surface_dz: Option<f64>,
+    pub max_haney: Option<f64>,
+}
+
+/// Metrics of the best candidate of a [`VQSOptimizeBuilder`] search.
+pub struct OptimizeReport {
+    transform: OptimizeTransform,
+    parameters: [f64; 2],
+    cost: f64,
+    max_layer_ratio: f64,
+    min_surface_dz: f64,
+    max_haney: f64,
+    evaluations: usize,
+    iterations: usize,
+    converged: bool,
+    best: VQS,
+}
+
+impl OptimizeReport {
+    /// theta_f and theta_b, or theta_s and hc.
+    pub fn parameters(&self) -> &[f64; 2] {
+        &self.parameters
+    }
+
+    pub fn cost(&self) -> &f64 {
+        &self.cost
+    }
+
+    pub fn max_layer_ratio(&self) -> &f64 {
+        &self.max_layer_ratio
+    }
+
+    /// Thinnest surface layer over the wet nodes.
+    pub fn min_surface_dz(&self) -> &f64 {
+        &self.min_surface_dz
+    }
+
+    pub fn max_haney(&self) -> &f64 {
+        &self.max_haney
+    }
+
+    /// Number of grids built during the search.
+    pub fn evaluations(&self) -> usize {
+        self.evaluations
+    }
+
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Whether the simplex collapsed before the iteration limit.
+    pub fn converged(&self) -> bool {
+        self.converged
+    }
+
+    /// Vertical grid of the best candidate.
+    pub fn best(&self) -> &VQS {
+        &self.best
+    }
+}
+
+impl fmt::Display for OptimizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [first, second] = self.transform.parameter_names();
+        write!(f, "{:<20}{:>14.6}\n", first, self.parameters[0])?;
+        write!(f, "{:<20}{:>14.6}\n", second, self.parameters[1])?;
+        if let OptimizeTransform::SongHaidvogel1994 { theta_b } = self.transform {
+            write!(f, "{:<20}{:>14.6}\n", "theta_b", theta_b)?;
+        }
+        write!(f, "{:<20}{:>14.6}\n", "cost", self.cost)?;
+        write!(
+            f,
+            "{:<20}{:>14.6}\n",
+            "max layer ratio", self.max_layer_ratio
+        )?;
+        write!(f, "{:<20}{:>14.6}\n", "min surface dz", self.min_surface_dz)?;
+        write!(f, "{:<20}{:>14.6}\n", "max haney", self.max_haney)?;
+        write!(f, "{:<20}{:>14}\n", "evaluations", self.evaluations)?;
+        write!(f, "{:<20}{:>14}\n", "iterations", self.iterations)?;
+        write!(f, "{:<20}{:>14}\n", "converged", self.converged)
+    }
+}
+
+struct Evaluation {
+    cost: f64,
+    feasible: bool,
+    max_layer_ratio: f64,
+    min_surface_dz: f64,
+    max_haney: f64,
+    vqs: Option<VQS>,
+}
+
+/// Searches the stretching parameters of a grid with fixed master grids that
+/// minimize an [`OptimizeCost`] under [`OptimizeConstraints`].
+///
+/// The search is a Nelder-Mead simplex over both parameters, scaled to their
+/// ranges and clamped into them. Every evaluation builds the grid on the full
+/// mesh, so `max_iterations` bounds the run time.
+#[derive(Default)]
+pub struct VQSOptimizeBuilder<'a> {
+    hgrid: Option<&'a Hgrid>,
+    depths: Option<&'a Vec<f64>>,
+    nlevels: Option<&'a Vec<usize>>,
+    transform: Option<&'a OptimizeTransform>,
+    etal: Option<&'a f64>,
+    a_vqs0: Option<&'a f64>,
+    dz_bottom_min: Option<&'a f64>,
+    cost: Option<&'a OptimizeCost>,
+    constraints: Option<&'a OptimizeConstraints>,
+    max_iterations: Option<&'a usize>,
+    tolerance: Option<&'a f64>,
+}
+
+impl<'a> VQSOptimizeBuilder<'a> {
+    pub fn build(&self) -> Result<OptimizeReport, VQSOptimizeBuilderError> {
+        let hgrid = self
+            .hgrid
+            .ok_or_else(|| VQSOptimizeBuilderError::UninitializedFieldError("hgrid".to_string()))?;
+        let depths = self.depths.ok_or_else(|| {
+            VQSOptimizeBuilderError::UninitializedFieldError("depths".to_string())
+        })?;
+        let nlevels = self.nlevels.ok_or_else(|| {
+            VQSOptimizeBuilderError::UninitializedFieldError("nlevels".to_string())
+        })?;
+        let transform = self.transform.ok_or_else(|| {
+            VQSOptimizeBuilderError::UninitializedFieldError("transform".to_string())
+        })?;
+        let dz_bottom_min = self.dz_bottom_min.ok_or_else(|| {
+            VQSOptimizeBuilderError::UninitializedFieldError("dz_bottom_min".to_string())
+        })?;
+        if depths.is_empty() {
+            return Err(VQSOptimizeBuilderError::NoMasterGrids);
+        }
+        let max_iterations = self.max_iterations.cloned().unwrap_or(50);
+        Self::validate_max_iterations(&max_iterations)?;
+        let tolerance = self.tolerance.cloned().unwrap_or(1e-4);
+        let etal = self.etal.cloned().unwrap_or(0.);
+        let a_vqs0 = self.a_vqs0.cloned().unwrap_or(0.);
+        let default_cost = OptimizeCost::default();
+        let cost = self.cost.unwrap_or(&default_cost);
+        let default_constraints = OptimizeConstraints::default();
+        let constraints = self.constraints.unwrap_or(&default_constraints);
+        let problem = Problem {
+            hgrid,
+            depths,
+            nlevels,
+            transform,
+            etal: &etal,
+            a_vqs0: &a_vqs0,
+            dz_bottom_min,
+            cost,
+            constraints,
+        };
+        let bounds = transform.bounds(depths);
+        let to_parameters = |u: &[f64; 2]| {
+            let mut parameters = [0.; 2];
+            for (i, (low, high)) in bounds.iter().enumerate() {
+                parameters[i] = low + u[i].clamp(0., 1.) * (high - low);
+            }
+            parameters
+        };
+        let mut evaluations = 0;
+        let mut best: Option<([f64; 2], Evaluation)> = None;
+        let mut objective = |u: &[f64; 2]| {
+            let parameters = to_parameters(u);
+            let evaluation = problem.evaluate(&parameters);
+            evaluations += 1;
+            let total = evaluation.cost;
+            if best
+                .as_ref()
+                .map_or(true, |(_, best)| evaluation.cost < best.cost)
+            {
+                best = Some((parameters, evaluation));
+            }
+            total
+        };
+        let (iterations, converged) =
+            nelder_mead(&mut objective, [0.5, 0.5], max_iterations, tolerance);
+        let (parameters, best) = best.ok_or(VQSOptimizeBuilderError::NoFeasibleCandidate)?;
+        let vqs = match (best.feasible, best.vqs) {
+            (true, Some(vqs)) => vqs,
+            _ => return Err(VQSOptimizeBuilderError::NoFeasibleCandidate),
+        };
+        Ok(OptimizeReport {
+            transform: transform.clone(),
+            parameters,
+            cost: best.cost,
+            max_layer_ratio: best.max_layer_ratio,
+            min_surface_dz: best.min_surface_dz,
+            max_haney: best.max_haney,
+            evaluations,
+            iterations,
+            converged,
+            best: vqs,
+        })
+    }
+
+    fn validate_max_iterations(max_iterations: &usize) -> Result<(), VQSOptimizeBuilderError> {
+        if *max_iterations < 1 {
+            return Err(VQSOptimizeBuilderError::InvalidMaxIterations(
+                *max_iterations,
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn hgrid(&mut self, hgrid: &'a Hgrid) -> &mut Self {
+        self.hgrid = Some(hgrid);
+        self
+    }
+    pub fn depths(&mut self, depths: &'a Vec<f64>) -> &mut Self {
+        self.depths = Some(depths);
+        self
+    }
+    pub fn nlevels(&mut self, nlevels: &'a Vec<usize>) -> &mut Self {
+        self.nlevels = Some(nlevels);
+        self
+    }
+    pub fn transform(&mut self, transform: &'a OptimizeTransform) -> &mut Self {
+        self.transform = Some(transform);
+        self
+    }
+    pub fn etal(&mut self, etal: &'a f64) -> &mut Self {
+        self.etal = Some(etal);
+        self
+    }
+    pub fn a_vqs0(&mut self, a_vqs0: &'a f64) -> &mut Self {
+        self.a_vqs0 = Some(a_vqs0);
+        self
+    }
+    pub fn dz_bottom_min(&mut self, dz_bottom_min: &'a f64) -> &mut Self {
+        self.dz_bottom_min = Some(dz_bottom_min);
+        self
+    }
+    pub fn cost(&mut self, cost: &'a OptimizeCost) -> &mut Self {
+        self.cost = Some(cost);
+        self
+    }
+    pub fn constraints(&mut self, constraints: &'a OptimizeConstraints) -> &mut Self {
+        self.constraints = Some(constraints);
+        self
+    }
+    /// Simplex iterations before giving up, 50 by default.
+    pub fn max_iterations(&mut self, max_iterations: &'a usize) -> &mut Self {
+        self.max_iterations = Some(max_iterations);
+        self
+    }
+    /// Spread of the simplex costs under which the search stops, 1e-4 by
+    /// default.
+    pub fn tolerance(&mut self, tolerance: &'a f64) -> &mut Self {
+        self.tolerance = Some(tolerance);
+        self
+    }
+}
+
+/// Fixed inputs of a [`VQSOptimizeBuilder`] search.
+struct Problem<'b> {
+    hgrid: &'b Hgrid,
+    depths: &'b Vec<f64>,
+    nlevels: &'b Vec<usize>,
+    transform: &'b OptimizeTransform,
+    etal: &'b f64,
+    a_vqs0: &'b f64,
+    dz_bottom_min: &'b f64,
+    cost: &'b OptimizeCost,
+    constraints: &'b OptimizeConstraints,
+}
+
+impl<'b> Problem<'b> {
+    fn evaluate(&self, parameters: &[f64; 2]) -> Evaluation {
+        let stretching = match self.transform {
+            OptimizeTransform::S => StretchingFunction::S(STransformOpts {
+                etal: self.etal,
+                a_vqs0: self.a_vqs0,
+                theta_f: &parameters[0],
+                theta_b: &parameters[1],
+            }),
+            OptimizeTransform::SongHaidvogel1994 { theta_b } => {
+                StretchingFunction::SongHaidvogel1994(SongHaidvogel1994TransformOpts {
+                    etal: self.etal,
+                    a_vqs0: self.a_vqs0,
+                    theta_s: &parameters[0],
+                    theta_b,
+                    hc: &parameters[1],
+                    hc_policy: &HcPolicy::Error,
+                })
+            }
+        };
+        let vqs = match VQSBuilder::default()
+            .hgrid(self.hgrid)
+            .depths(self.depths)
+            .nlevels(self.nlevels)
+            .stretching(&stretching)
+            .dz_bottom_min(self.dz_bottom_min)
+            .build()
+        {
+            Ok(vqs) => vqs,
+            Err(err) => {
+                log::debug!("Skipping optimize candidate {:?}: {}", parameters, err);
+                return Evaluation {
+                    cost: f64::INFINITY,
+                    feasible: false,
+                    max_layer_ratio: f64::NAN,
+                    min_surface_dz: f64::NAN,
+                    max_haney: f64::NAN,
+                    vqs: None,
+                };
+            }
+        };
+        let report = quality_report(&vqs, self.hgrid);
+        let wet_nodes: Vec<_> = report
+            .nodes()
+            .iter()
+            .zip(vqs.depths().iter())
+            .filter(|&(_, &depth)| depth > 0.)
+            .map(|(node, _)| node)
+            .collect();
+        let max_layer_ratio = *report.max_dz_ratio();
+        let max_haney = *report.max_haney();
+        let min_surface_dz = min_surface_dz(&vqs);
+        let mut total = match self.cost {
+            OptimizeCost::MeanLayerRatio => {
+                wet_nodes
+                    .iter()
+                    .map(|node| *node.max_dz_ratio())
+                    .sum::<f64>()
+                    / wet_nodes.len().max(1) as f64
+            }
+            OptimizeCost::MaxLayerRatio => max_layer_ratio,
+            OptimizeCost::MeanHaney => {
+                report.haney().iter().sum::<f64>() / report.haney().len().max(1) as f64
+            }
+        };
+        // relative violations keep the simplex moving towards feasibility
+        let mut violation = 0.;
+        if let Some(limit) = self.constraints.max_layer_ratio {
+            violation += (max_layer_ratio / limit - 1.).max(0.);
+        }
+        if let Some(limit) = self.constraints.min_surface_dz {
+            violation += (1. - min_surface_dz / limit).max(0.);
+        }
+        if let Some(limit) = self.constraints.max_haney {
+            violation += (max_haney / limit - 1.).max(0.);
+        }
+        let feasible = violation == 0.;
+        if !feasible {
+            total = INFEASIBLE_PENALTY * (1. + violation);
+        }
+        Evaluation {
+            cost: total,
+            feasible,
+            max_layer_ratio,
+            min_surface_dz,
+            max_haney,
+            vqs: Some(vqs),
+        }
+    }
+}
+
+fn min_surface_dz(vqs: &VQS) -> f64 {
+    let z = vqs.z();
+    let nvrt = vqs.nvrt();
+    vqs.bottom_level_indices()
+        .iter()
+        .zip(vqs.depths().iter())
+        .enumerate()
+        .filter(|&(_, (&kbp, &depth))| depth > 0. && kbp < nvrt)
+        .map(|(i, _)| z[[nvrt - 1, i]] - z[[nvrt - 2, i]])
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// Minimizes `f` over two parameters from `start` with the standard
+/// reflection, expansion, contraction and shrink coefficients (1, 2, 1/2,
+/// 1/2). Returns the iterations run and whether the costs of the simplex fell
+/// within `tolerance` of each other.
+fn nelder_mead<F: FnMut(&[f64; 2]) -> f64>(
+    f: &mut F,
+    start: [f64; 2],
+    max_iterations: usize,
+    tolerance: f64,
+) -> (usize, bool) {
+    let mut simplex: Vec<([f64; 2], f64)> = vec![
+        start,
+        [start[0] + INITIAL_STEP, start[1]],
+        [start[0], start[1] + INITIAL_STEP],
+    ]
+    .into_iter()
+    .map(|x| (x, f(&x)))
+    .collect();
+    let along = |from: &[f64; 2], to: &[f64; 2], t: f64| {
+        [
+            from[0] + t * (to[0] - from[0]),
+            from[1] + t * (to[1] - from[1]),
+        ]
+    };
+    for iteration in 0..max_iterations {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        if (simplex[2].1 - simplex[0].1).abs() <= tolerance {
+            return (iteration, true);
+        }
+        let centroid = along(&simplex[0].0, &simplex[1].0, 0.5);
+        let worst = simplex[2];
+        let reflected = along(&centroid, &worst.0, -1.);
+        let reflected_cost = f(&reflected);
+        if reflected_cost < simplex[0].1 {
+            let expanded = along(&centroid, &worst.0, -2.);
+            let expanded_cost = f(&expanded);
+            simplex[2] = if expanded_cost < reflected_cost {
+                (expanded, expanded_cost)
+            } else {
+                (reflected, reflected_cost)
+            };
+        } else if reflected_cost < simplex[1].1 {
+            simplex[2] = (reflected, reflected_cost);
+        } else {
+            let contracted = if reflected_cost < worst.1 {
+                along(&centroid, &reflected, 0.5)
+            } else {
+                along(&centroid, &worst.0, 0.5)
+            };
+            let contracted_cost = f(&contracted);
+            if contracted_cost < worst.1.min(reflected_cost) {
+                simplex[2] = (contracted, contracted_cost);
+            } else {
+                let best = simplex[0].0;
+                for vertex in simplex.iter_mut().skip(1) {
+                    let x = along(&best, &vertex.0, 0.5);
+                    *vertex = (x, f(&x));
+                }
+            }
+        }
+    }
+    (max_iterations, false)
+}
+
+#[derive(Error, Debug)]
+pub enum VQSOptimizeBuilderError {
+    #[error("Unitialized field on VQSOptimizeBuilder: {0}")]
+    UninitializedFieldError(String),
+    #[error("At least one master grid is required")]
+    NoMasterGrids,
+    #[error("max_iterations must be >= 1 but got {0}")]
+    InvalidMaxIterations(usize),
+    #[error("No candidate met the constraints")]
+    NoFeasibleCandidate,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::channel_hgrid;
+
+    #[test]
+    fn improves_on_the_start() {
+        let hgrid = channel_hgrid(5, 9).unwrap();
+        let depths = vec![5., 15., 31.];
+        let nlevels = vec![4, 8, 12];
+        let transform = OptimizeTransform::S;
+        let cost = OptimizeCost::MaxLayerRatio;
+        let report = VQSOptimizeBuilder::default()
+            .hgrid(&hgrid)
+            .depths(&depths)
+            .nlevels(&nlevels)
+            .transform(&transform)
+            .dz_bottom_min(&0.1)
+            .cost(&cost)
+            .max_iterations(&10)
+            .build()
+            .unwrap();
+        // the master grids fix the level count
+        assert_eq!(report.best().nvrt(), 12);
+        // the search starts at the middle of both parameter ranges
+        let [(f_low, f_high), (b_low, b_high)] = transform.bounds(&depths);
+        let start = Problem {
+            hgrid: &hgrid,
+            depths: &depths,
+            nlevels: &nlevels,
+            transform: &transform,
+            etal: &0.,
+            a_vqs0: &0.,
+            dz_bottom_min: &0.1,
+            cost: &cost,
+            constraints: &OptimizeConstraints::default(),
+        }
+        .evaluate(&[
+            f_low + 0.5 * (f_high - f_low),
+            b_low + 0.5 * (b_high - b_low),
+        ]);
+        assert!(start.feasible);
+        assert!(
+            *report.cost() <= start.cost,
+            "{} > {}",
+            report.cost(),
+            start.cost
+        );
+        assert_eq!(report.max_layer_ratio(), report.cost());
+    }
+}
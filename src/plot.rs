@@ -0,0 +1,267 @@
+use ndarray::{Array2, Axis};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Image formats supported by the headless plotting backend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlotFormat {
+    Png,
+    Svg,
+}
+
+impl PlotFormat {
+    /// Guesses the format from the file extension of `path`.
+    pub fn from_path(path: &PathBuf) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "png" => Some(PlotFormat::Png),
+            "svg" => Some(PlotFormat::Svg),
+            _ => None,
+        }
+    }
+}
+
+const PLOT_SIZE: (u32, u32) = (1200, 800);
+
+/// Draws each master grid as a vertical transect located at its own depth,
+/// mirroring the plotly plot of [`crate::transforms::traits::Transform::make_zmas_plot`].
+pub(crate) fn plot_zmas_to_file(
+    z_mas: &Array2<f64>,
+    path: &PathBuf,
+    format: PlotFormat,
+) -> Result<(), PlotError> {
+    match format {
+        PlotFormat::Png => draw_zmas(
+            BitMapBackend::new(path, PLOT_SIZE).into_drawing_area(),
+            z_mas,
+        ),
+        PlotFormat::Svg => draw_zmas(SVGBackend::new(path, PLOT_SIZE).into_drawing_area(), z_mas),
+    }
+}
+
+fn draw_zmas<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    z_mas: &Array2<f64>,
+) -> Result<(), PlotError> {
+    let master_grids: Vec<Vec<f64>> = z_mas
+        .axis_iter(Axis(1))
+        .map(|master_grid| {
+            master_grid
+                .iter()
+                .filter(|z| !z.is_nan())
+                .cloned()
+                .collect::<Vec<f64>>()
+        })
+        .filter(|master_grid| !master_grid.is_empty())
+        .collect();
+    let bottoms: Vec<f64> = master_grids
+        .iter()
+        .map(|master_grid| master_grid.iter().cloned().fold(f64::INFINITY, f64::min))
+        .collect();
+    let z_min = bottoms.iter().cloned().fold(f64::INFINITY, f64::min);
+    let z_max = master_grids
+        .iter()
+        .flatten()
+        .cloned()
+        .fold(f64::NEG_INFINITY, f64::max);
+    if !z_min.is_finite() || !z_max.is_finite() {
+        return Err(PlotError::EmptyMasterGrids);
+    }
+    let to_plot_error =
+        |e: DrawingAreaErrorKind<DB::ErrorType>| PlotError::DrawingError(e.to_string());
+    root.fill(&WHITE).map_err(to_plot_error)?;
+    let mut chart = ChartBuilder::on(&root)
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(z_min..z_max.max(0.), z_min..z_max)
+        .map_err(to_plot_error)?;
+    chart
+        .configure_mesh()
+        .x_desc("master grid bottom (m)")
+        .y_desc("z (m)")
+        .draw()
+        .map_err(to_plot_error)?;
+    for (master_grid, &bottom) in master_grids.iter().zip(bottoms.iter()) {
+        chart
+            .draw_series(LineSeries::new(
+                master_grid.iter().map(|&z| (bottom, z)),
+                &BLUE,
+            ))
+            .map_err(to_plot_error)?;
+        chart
+            .draw_series(
+                master_grid
+                    .iter()
+                    .map(|&z| Circle::new((bottom, z), 2, BLACK.filled())),
+            )
+            .map_err(to_plot_error)?;
+    }
+    root.present().map_err(to_plot_error)?;
+    Ok(())
+}
+
+/// Number of bins of the layer thickness histogram.
+pub const HISTOGRAM_BINS: usize = 50;
+
+/// Draws each node at its hgrid position, colored from blue (fewest levels)
+/// to red (most levels).
+pub(crate) fn plot_levels_map_to_file(
+    x: &[f64],
+    y: &[f64],
+    nlevels: &[usize],
+    path: &PathBuf,
+    format: PlotFormat,
+) -> Result<(), PlotError> {
+    match format {
+        PlotFormat::Png => draw_levels_map(
+            BitMapBackend::new(path, PLOT_SIZE).into_drawing_area(),
+            x,
+            y,
+            nlevels,
+        ),
+        PlotFormat::Svg => draw_levels_map(
+            SVGBackend::new(path, PLOT_SIZE).into_drawing_area(),
+            x,
+            y,
+            nlevels,
+        ),
+    }
+}
+
+fn draw_levels_map<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    x: &[f64],
+    y: &[f64],
+    nlevels: &[usize],
+) -> Result<(), PlotError> {
+    if nlevels.is_empty() {
+        return Err(PlotError::NothingToPlot("nodes"));
+    }
+    let range = |values: &[f64]| {
+        values
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+                (min.min(v), max.max(v))
+            })
+    };
+    let (x_min, x_max) = range(x);
+    let (y_min, y_max) = range(y);
+    let min_levels = *nlevels.iter().min().unwrap_or(&0);
+    let max_levels = *nlevels.iter().max().unwrap_or(&0);
+    let to_plot_error =
+        |e: DrawingAreaErrorKind<DB::ErrorType>| PlotError::DrawingError(e.to_string());
+    root.fill(&WHITE).map_err(to_plot_error)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(
+            format!(
+                "levels per node, {} (blue) to {} (red)",
+                min_levels, max_levels
+            ),
+            ("sans-serif", 20),
+        )
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(80)
+        .build_cartesian_2d(x_min..x_max, y_min..y_max)
+        .map_err(to_plot_error)?;
+    chart
+        .configure_mesh()
+        .x_desc("x")
+        .y_desc("y")
+        .draw()
+        .map_err(to_plot_error)?;
+    let span = (max_levels - min_levels).max(1) as f64;
+    chart
+        .draw_series(
+            x.iter()
+                .zip(y.iter())
+                .zip(nlevels.iter())
+                .map(|((&x, &y), &nlevels)| {
+                    let t = (nlevels - min_levels) as f64 / span;
+                    Circle::new((x, y), 2, HSLColor(0.66 * (1. - t), 0.9, 0.45).filled())
+                }),
+        )
+        .map_err(to_plot_error)?;
+    root.present().map_err(to_plot_error)?;
+    Ok(())
+}
+
+/// Draws a histogram of `values` in [`HISTOGRAM_BINS`] bins.
+pub(crate) fn plot_histogram_to_file(
+    values: &[f64],
+    x_desc: &str,
+    path: &PathBuf,
+    format: PlotFormat,
+) -> Result<(), PlotError> {
+    match format {
+        PlotFormat::Png => draw_histogram(
+            BitMapBackend::new(path, PLOT_SIZE).into_drawing_area(),
+            values,
+            x_desc,
+        ),
+        PlotFormat::Svg => draw_histogram(
+            SVGBackend::new(path, PLOT_SIZE).into_drawing_area(),
+            values,
+            x_desc,
+        ),
+    }
+}
+
+fn draw_histogram<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    values: &[f64],
+    x_desc: &str,
+) -> Result<(), PlotError> {
+    let (min, max) = values
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+            (min.min(v), max.max(v))
+        });
+    if !min.is_finite() {
+        return Err(PlotError::NothingToPlot("values"));
+    }
+    // a single value still gets a bin of non-zero width
+    let width = ((max - min) / HISTOGRAM_BINS as f64).max(f64::EPSILON * max.abs().max(1.));
+    let mut counts = vec![0usize; HISTOGRAM_BINS];
+    for &value in values.iter().filter(|v| v.is_finite()) {
+        let bin = (((value - min) / width) as usize).min(HISTOGRAM_BINS - 1);
+        counts[bin] += 1;
+    }
+    let max_count = *counts.iter().max().unwrap_or(&1);
+    let to_plot_error =
+        |e: DrawingAreaErrorKind<DB::ErrorType>| PlotError::DrawingError(e.to_string());
+    root.fill(&WHITE).map_err(to_plot_error)?;
+    let mut chart = ChartBuilder::on(&root)
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(80)
+        .build_cartesian_2d(min..min + width * HISTOGRAM_BINS as f64, 0..max_count)
+        .map_err(to_plot_error)?;
+    chart
+        .configure_mesh()
+        .x_desc(x_desc)
+        .y_desc("count")
+        .draw()
+        .map_err(to_plot_error)?;
+    chart
+        .draw_series(counts.iter().enumerate().map(|(bin, &count)| {
+            let left = min + bin as f64 * width;
+            Rectangle::new([(left, 0), (left + width, count)], BLUE.filled())
+        }))
+        .map_err(to_plot_error)?;
+    root.present().map_err(to_plot_error)?;
+    Ok(())
+}
+
+#[derive(Error, Debug)]
+pub enum PlotError {
+    #[error("There are no master grid levels to plot")]
+    EmptyMasterGrids,
+    #[error("There are no {0} to plot")]
+    NothingToPlot(&'static str),
+    #[error("Failed to draw plot: {0}")]
+    DrawingError(String),
+}
@@ -0,0 +1,38 @@
+//! The supported library API.
+//!
+//! Everything re-exported here follows semver: it is only removed or changed
+//! incompatibly on a minor version bump while the crate is 0.x, and on a major
+//! one afterwards. Items reachable only through their module may change in any
+//! release, and the autotune, fit and optimize modules carry no guarantee.
+//!
+//! ```ignore
+//! use schismrs_vgrid::prelude::*;
+//! ```
+
+pub use crate::checks::{
+    assert_invariants, assert_kbp_bounds, assert_monotone_sigma,
+    assert_surface_zero_bottom_minus_one, CheckError,
+};
+pub use crate::kmeans_hsm::{ClusteringMethod, KMeansHSMCreateError};
+pub use crate::master_grid::{MasterGrid, MasterGridError};
+pub use crate::quality::{QualityReport, QualityScore, QualityScoreOptions};
+pub use crate::sigma::{LevelOrder, SigmaColumn};
+pub use crate::summary::VgridSummary;
+pub use crate::sz::{SZBuilder, SZBuilderError, SZ};
+pub use crate::transforms::geometric::GeometricTransformOpts;
+pub use crate::transforms::quadratic::QuadraticTransformOpts;
+pub use crate::transforms::s::STransformOpts;
+pub use crate::transforms::song_haidvogel::SongHaidvogel1994TransformOpts;
+pub use crate::transforms::transforms::StretchingFunctionError;
+pub use crate::transforms::StretchingFunction;
+pub use crate::validation::{
+    etal_robustness, validate, EtalRobustnessReport, ValidationCheck, ValidationReport,
+};
+pub use crate::vgrid_file::VgridFile;
+pub use crate::vqs::{
+    preview_node_levels, DzBottomMinProfile, LevelLimits, ShallowFormula, VQSAutoBuilder,
+    VQSAutoBuilderError, VQSBuildOptions, VQSBuilder, VQSBuilderError, VQSKMeansBuilder,
+    VQSKMeansBuilderError, VQSLoadError, VQSLogLinearBuilder, VQSLogLinearBuilderError, DEFAULT_H0,
+    DEFAULT_MIN_LEVELS, VQS,
+};
+pub use crate::writer::{VgridFormat, VgridWriterOptions};
@@ -0,0 +1,295 @@
+//! Sigma-coordinate pressure gradient error of a grid under an idealized,
+//! horizontally uniform stratification.
+//!
+//! With no horizontal density gradient the true horizontal pressure gradient
+//! is zero, so whatever the two-term sigma-coordinate discretization computes
+//! between two nodes is error. It is reported as the geostrophic current it
+//! would drive, which answers whether a grid is likely to spin up spurious
+//! flows in a baroclinic run.
+
+use crate::csv::CsvFormatter;
+use crate::mesh::element_connectivity;
+use crate::vqs::VQS;
+use schismrs_hgrid::hgrid::Hgrid;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+const GRAVITY: f64 = 9.81;
+const RHO0: f64 = 1025.;
+
+/// Exponential temperature profile, linear equation of state and Coriolis
+/// parameter of the estimate.
+pub struct PressureGradientOptions {
+    /// Temperature at z=0 in degrees C.
+    pub surface_temperature: f64,
+    /// Temperature approached at depth in degrees C.
+    pub bottom_temperature: f64,
+    /// e-folding depth of the thermocline in meters.
+    pub scale_depth: f64,
+    /// Thermal expansion coefficient in 1/K.
+    pub thermal_expansion: f64,
+    /// Coriolis parameter in 1/s converting the error to a current.
+    pub coriolis: f64,
+    /// Number of elements listed by the report.
+    pub worst_elements: usize,
+}
+
+impl Default for PressureGradientOptions {
+    fn default() -> Self {
+        Self {
+            surface_temperature: 25.,
+            bottom_temperature: 5.,
+            scale_depth: 500.,
+            thermal_expansion: 2e-4,
+            coriolis: 1e-4,
+            worst_elements: 10,
+        }
+    }
+}
+
+impl PressureGradientOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn surface_temperature(&mut self, surface_temperature: f64) -> &mut Self {
+        self.surface_temperature = surface_temperature;
+        self
+    }
+    pub fn bottom_temperature(&mut self, bottom_temperature: f64) -> &mut Self {
+        self.bottom_temperature = bottom_temperature;
+        self
+    }
+    pub fn scale_depth(&mut self, scale_depth: f64) -> &mut Self {
+        self.scale_depth = scale_depth;
+        self
+    }
+    pub fn thermal_expansion(&mut self, thermal_expansion: f64) -> &mut Self {
+        self.thermal_expansion = thermal_expansion;
+        self
+    }
+    pub fn coriolis(&mut self, coriolis: f64) -> &mut Self {
+        self.coriolis = coriolis;
+        self
+    }
+    pub fn worst_elements(&mut self, worst_elements: usize) -> &mut Self {
+        self.worst_elements = worst_elements;
+        self
+    }
+
+    /// Density anomaly at `z` in kg/m3. The constant part of the density
+    /// cancels exactly in the two-term gradient, so only the stratification
+    /// matters.
+    fn density_anomaly(&self, z: f64) -> f64 {
+        let dt = self.surface_temperature - self.bottom_temperature;
+        -RHO0 * self.thermal_expansion * dt * (z / self.scale_depth).exp()
+    }
+
+    /// Hydrostatic pressure of the density anomaly at `z`, integrated
+    /// analytically from z=0.
+    fn pressure_anomaly(&self, z: f64) -> f64 {
+        let dt = self.surface_temperature - self.bottom_temperature;
+        -GRAVITY
+            * RHO0
+            * self.thermal_expansion
+            * dt
+            * self.scale_depth
+            * (1. - (z / self.scale_depth).exp())
+    }
+}
+
+/// Pressure gradient error of a single element.
+pub struct ElementPressureGradient {
+    nodes: Vec<usize>,
+    x: f64,
+    y: f64,
+    velocity: f64,
+}
+
+impl ElementPressureGradient {
+    /// 1-based hgrid node ids of the element.
+    pub fn nodes(&self) -> &Vec<usize> {
+        &self.nodes
+    }
+
+    /// Centroid of the element.
+    pub fn centroid(&self) -> (f64, f64) {
+        (self.x, self.y)
+    }
+
+    /// Spurious geostrophic current of the worst level of the element, m/s.
+    pub fn velocity(&self) -> &f64 {
+        &self.velocity
+    }
+}
+
+/// Spurious currents of every element, see the module documentation.
+pub struct PressureGradientReport {
+    elements: Vec<ElementPressureGradient>,
+    worst_elements: usize,
+}
+
+impl PressureGradientReport {
+    /// Elements in decreasing order of error.
+    pub fn elements(&self) -> &Vec<ElementPressureGradient> {
+        &self.elements
+    }
+
+    /// Largest spurious current, 0 without elements.
+    pub fn max_velocity(&self) -> f64 {
+        self.elements.first().map_or(0., |element| element.velocity)
+    }
+
+    pub fn mean_velocity(&self) -> f64 {
+        if self.elements.is_empty() {
+            return 0.;
+        }
+        self.elements
+            .iter()
+            .map(|element| element.velocity)
+            .sum::<f64>()
+            / self.elements.len() as f64
+    }
+
+    /// Writes the centroid, nodes and spurious current of every element.
+    pub fn write_to_csv(&self, path: &PathBuf, csv: &CsvFormatter) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        csv.write_header(&mut writer, &["x", "y", "nodes", "velocity"])?;
+        for element in self.elements.iter() {
+            let nodes: Vec<String> = element.nodes.iter().map(|node| node.to_string()).collect();
+            csv.write_record(
+                &mut writer,
+                &[
+                    csv.float(element.x),
+                    csv.float(element.y),
+                    csv.text(&nodes.join(" ")),
+                    csv.float(element.velocity),
+                ],
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+impl fmt::Display for PressureGradientReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<28}{:>14}\n", "elements", self.elements.len())?;
+        write!(
+            f,
+            "{:<28}{:>14.6}\n",
+            "max spurious u (m/s)",
+            self.max_velocity()
+        )?;
+        write!(
+            f,
+            "{:<28}{:>14.6}\n",
+            "mean spurious u (m/s)",
+            self.mean_velocity()
+        )?;
+        if self.elements.is_empty() || self.worst_elements == 0 {
+            return Ok(());
+        }
+        write!(
+            f,
+            "\n{:>14}{:>14}{:>14}  {}\n",
+            "x", "y", "u (m/s)", "nodes"
+        )?;
+        for element in self.elements.iter().take(self.worst_elements) {
+            let nodes: Vec<String> = element.nodes.iter().map(|node| node.to_string()).collect();
+            write!(
+                f,
+                "{:>14.2}{:>14.2}{:>14.6}  {}\n",
+                element.x,
+                element.y,
+                element.velocity,
+                nodes.join(" ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Estimates the pressure gradient error of `vqs` on the mesh of `hgrid`.
+///
+/// Along each element edge and for each level both nodes reach, the two-term
+/// gradient `(p_b - p_a) / L + g rho(z_mid) (z_b - z_a) / L` of the analytic
+/// hydrostatic pressure is evaluated; its largest magnitude over the edges and
+/// levels of an element, divided by rho0 and f, is the element's spurious
+/// current.
+pub fn pressure_gradient_report(
+    vqs: &VQS,
+    hgrid: &Hgrid,
+    options: &PressureGradientOptions,
+) -> PressureGradientReport {
+    let z = vqs.z();
+    let nvrt = vqs.nvrt();
+    let bottom_level_indices = vqs.bottom_level_indices();
+    let (x, y) = (hgrid.x(), hgrid.y());
+    let mut elements: Vec<ElementPressureGradient> = element_connectivity(hgrid)
+        .into_iter()
+        .map(|element| {
+            let mut max_error: f64 = 0.;
+            for (a, &node_a) in element.iter().enumerate() {
+                let node_b = element[(a + 1) % element.len()];
+                let length = (x[node_b] - x[node_a]).hypot(y[node_b] - y[node_a]);
+                if length == 0. {
+                    continue;
+                }
+                let kbp = bottom_level_indices[node_a].max(bottom_level_indices[node_b]);
+                for row in kbp - 1..nvrt {
+                    let (z_a, z_b) = (z[[row, node_a]], z[[row, node_b]]);
+                    let error = options.pressure_anomaly(z_b) - options.pressure_anomaly(z_a)
+                        + GRAVITY * options.density_anomaly((z_a + z_b) / 2.) * (z_b - z_a);
+                    max_error = max_error.max((error / length).abs());
+                }
+            }
+            let n = element.len() as f64;
+            ElementPressureGradient {
+                x: element.iter().map(|&node| x[node]).sum::<f64>() / n,
+                y: element.iter().map(|&node| y[node]).sum::<f64>() / n,
+                nodes: element.iter().map(|&node| node + 1).collect(),
+                velocity: max_error / (RHO0 * options.coriolis.abs()),
+            }
+        })
+        .collect();
+    elements.sort_by(|a, b| b.velocity.total_cmp(&a.velocity));
+    PressureGradientReport {
+        elements,
+        worst_elements: options.worst_elements,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{structured_hgrid, SYNTHETIC_SPACING};
+    use ndarray::{Array1, Array2};
+
+    /// 5 evenly spaced sigma levels on a 4 x 3 mesh of the given depths.
+    fn report<F: Fn(f64, f64) -> f64>(depth: F) -> PressureGradientReport {
+        let hgrid = structured_hgrid(4, 3, SYNTHETIC_SPACING, &depth).unwrap();
+        let (x, y) = (hgrid.x(), hgrid.y());
+        let depths = Array1::from_shape_fn(x.len(), |node| depth(x[node], y[node]));
+        let sigma = Array2::from_shape_fn((5, x.len()), |(k, _)| k as f64 / 4. - 1.);
+        pressure_gradient_report(
+            &VQS::from_sigma(sigma, depths, 0.),
+            &hgrid,
+            &PressureGradientOptions::default(),
+        )
+    }
+
+    #[test]
+    fn flat_bottom_has_no_error() {
+        let report = report(|_, _| 800.);
+        assert!(!report.elements().is_empty());
+        assert_eq!(report.max_velocity(), 0.);
+    }
+
+    #[test]
+    fn sloping_bottom_has_error() {
+        let report = report(|x, _| 200. + x / 5.);
+        assert!(report.max_velocity() > 0., "{}", report);
+        assert!(report.mean_velocity() > 0.);
+    }
+}
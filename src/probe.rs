@@ -0,0 +1,334 @@
+//! Level interfaces of the grid at arbitrary (x, y) points, e.g. stations or
+//! moorings, interpolated the way SCHISM interpolates its station output.
+
+use crate::csv::CsvFormatter;
+use crate::mesh::element_connectivity;
+use crate::vqs::VQS;
+use schismrs_hgrid::hgrid::Hgrid;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Tolerance on the barycentric weights, so points on an element edge or
+/// node are not lost to round-off.
+const WEIGHT_TOLERANCE: f64 = 1e-9;
+
+/// Levels of the grid at a point inside the mesh.
+#[derive(Debug)]
+pub struct PointLevels {
+    x: f64,
+    y: f64,
+    nodes: Vec<usize>,
+    weights: Vec<f64>,
+    bottom_level: usize,
+    z: Vec<f64>,
+}
+
+impl PointLevels {
+    pub fn x(&self) -> f64 {
+        self.x
+    }
+
+    pub fn y(&self) -> f64 {
+        self.y
+    }
+
+    /// 1-based node ids of the element holding the point.
+    pub fn nodes(&self) -> &Vec<usize> {
+        &self.nodes
+    }
+
+    /// Interpolation weight of each node of [`PointLevels::nodes`], summing
+    /// to 1.
+    pub fn weights(&self) -> &Vec<f64> {
+        &self.weights
+    }
+
+    /// 1-based level of the lowest interface, the lowest bottom level of the
+    /// element's nodes.
+    pub fn bottom_level(&self) -> usize {
+        self.bottom_level
+    }
+
+    /// z of the level interfaces at the point, from the bed at
+    /// [`PointLevels::bottom_level`] to the surface at nvrt.
+    pub fn z(&self) -> &Vec<f64> {
+        &self.z
+    }
+}
+
+/// Levels at each probed point, in input order, `None` for the points
+/// outside the mesh.
+#[derive(Debug)]
+pub struct ProbeReport {
+    points: Vec<Option<PointLevels>>,
+}
+
+impl ProbeReport {
+    pub fn points(&self) -> &Vec<Option<PointLevels>> {
+        &self.points
+    }
+
+    /// Writes one row per level interface of each point inside the mesh.
+    pub fn write_to_csv(&self, path: &PathBuf, csv: &CsvFormatter) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        csv.write_header(&mut writer, &["point", "x", "y", "level", "z"])?;
+        for (i, point) in self.points.iter().enumerate() {
+            let point = match point {
+                Some(point) => point,
+                None => continue,
+            };
+            for (k, &z) in point.z.iter().enumerate() {
+                csv.write_record(
+                    &mut writer,
+                    &[
+                        (i + 1).to_string(),
+                        csv.float(point.x),
+                        csv.float(point.y),
+                        (point.bottom_level + k).to_string(),
+                        csv.float(z),
+                    ],
+                )?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+impl fmt::Display for ProbeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>8}{:>16}{:>16}{:>8}{:>14}\n",
+            "point", "x", "y", "levels", "bed z"
+        )?;
+        for (i, point) in self.points.iter().enumerate() {
+            match point {
+                Some(point) => write!(
+                    f,
+                    "{:>8}{:>16.4}{:>16.4}{:>8}{:>14.4}\n",
+                    i + 1,
+                    point.x,
+                    point.y,
+                    point.z.len(),
+                    point.z[0]
+                )?,
+                None => write!(f, "{:>8}  outside of the mesh\n", i + 1)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Barycentric weights of (x, y) in the triangle `nodes`, or `None` when the
+/// point is outside of it.
+fn triangle_weights(
+    x: &[f64],
+    y: &[f64],
+    nodes: [usize; 3],
+    point: (f64, f64),
+) -> Option<[f64; 3]> {
+    let [a, b, c] = nodes;
+    let det = (y[b] - y[c]) * (x[a] - x[c]) + (x[c] - x[b]) * (y[a] - y[c]);
+    if det == 0. {
+        return None;
+    }
+    let wa = ((y[b] - y[c]) * (point.0 - x[c]) + (x[c] - x[b]) * (point.1 - y[c])) / det;
+    let wb = ((y[c] - y[a]) * (point.0 - x[c]) + (x[a] - x[c]) * (point.1 - y[c])) / det;
+    let wc = 1. - wa - wb;
+    if wa < -WEIGHT_TOLERANCE || wb < -WEIGHT_TOLERANCE || wc < -WEIGHT_TOLERANCE {
+        return None;
+    }
+    Some([wa, wb, wc])
+}
+
+/// Weights of the nodes of `element` at `point`, quads being split along
+/// their first diagonal like SCHISM does for station output.
+fn element_weights(x: &[f64], y: &[f64], element: &[usize], point: (f64, f64)) -> Option<Vec<f64>> {
+    let splits: &[[usize; 3]] = match element.len() {
+        3 => &[[0, 1, 2]],
+        4 => &[[0, 1, 2], [0, 2, 3]],
+        _ => return None,
+    };
+    splits.iter().find_map(|split| {
+        let nodes = split.map(|corner| element[corner]);
+        triangle_weights(x, y, nodes, point).map(|triangle| {
+            let mut weights = vec![0.; element.len()];
+            for (&corner, weight) in split.iter().zip(triangle) {
+                weights[corner] = weight;
+            }
+            weights
+        })
+    })
+}
+
+/// Implements [`VQS::probe`].
+///
+/// Every element is scanned for every point, which is fine for station sets
+/// but not for dense point clouds.
+pub(crate) fn probe(vqs: &VQS, hgrid: &Hgrid, points: &[(f64, f64)]) -> ProbeReport {
+    let x = hgrid.x().to_vec();
+    let y = hgrid.y().to_vec();
+    let z = vqs.z();
+    let nvrt = vqs.nvrt();
+    let kbp = vqs.bottom_level_indices();
+    let elements = element_connectivity(hgrid);
+    let points = points
+        .iter()
+        .map(|&point| {
+            let (element, weights) = elements.iter().find_map(|element| {
+                element_weights(&x, &y, element, point).map(|weights| (element, weights))
+            })?;
+            let bottom_level = element.iter().map(|&node| kbp[node]).min()?;
+            // nodes whose bottom is above a level contribute their bed z to it
+            let levels: Vec<f64> = (bottom_level..=nvrt)
+                .map(|level| {
+                    element
+                        .iter()
+                        .zip(weights.iter())
+                        .map(|(&node, weight)| weight * z[[level.max(kbp[node]) - 1, node]])
+                        .sum()
+                })
+                .collect();
+            Some(PointLevels {
+                x: point.0,
+                y: point.1,
+                nodes: element.iter().map(|&node| node + 1).collect(),
+                weights,
+                bottom_level,
+                z: levels,
+            })
+        })
+        .collect();
+    ProbeReport { points }
+}
+
+/// Reads the points of a SCHISM build point (.bp) file: a description line,
+/// the number of points, then one `id x y [z]` line per point.
+pub fn read_build_points(path: &PathBuf) -> Result<Vec<(f64, f64)>, ProbeError> {
+    let content = fs::read_to_string(path)?;
+    let mut lines = content.lines().skip(1);
+    let count = lines
+        .next()
+        .and_then(|line| line.split_whitespace().next())
+        .ok_or(ProbeError::UnexpectedEndOfFile)?;
+    let count = count
+        .parse::<usize>()
+        .map_err(|_| ProbeError::ParseError(count.to_string()))?;
+    (0..count)
+        .map(|_| {
+            let line = lines.next().ok_or(ProbeError::UnexpectedEndOfFile)?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [_, x, y, ..] => match (x.parse::<f64>(), y.parse::<f64>()) {
+                    (Ok(x), Ok(y)) => Ok((x, y)),
+                    _ => Err(ProbeError::ParseError(line.to_string())),
+                },
+                _ => Err(ProbeError::ParseError(line.to_string())),
+            }
+        })
+        .collect()
+}
+
+#[derive(Error, Debug)]
+pub enum ProbeError {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error("Unexpected end of file")]
+    UnexpectedEndOfFile,
+    #[error("Could not parse {0:?}")]
+    ParseError(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::structured_hgrid;
+    use ndarray::{Array1, Array2};
+
+    const SPACING: f64 = 100.;
+
+    /// 3 levels at sigma -1, -0.5 and 0 on each node of a 3 x 2 mesh, with
+    /// node depths of 10 m along y=0 and 30 m along y=SPACING.
+    fn probed(points: &[(f64, f64)]) -> ProbeReport {
+        let hgrid = structured_hgrid(3, 2, SPACING, |_, y| 10. + 20. * y / SPACING).unwrap();
+        let sigma = Array2::from_shape_fn((3, 6), |(k, _)| [-1., -0.5, 0.][k]);
+        let depths = Array1::from_shape_fn(6, |node| if node < 3 { 10. } else { 30. });
+        probe(&VQS::from_sigma(sigma, depths, 0.), &hgrid, points)
+    }
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(
+            actual.len(),
+            expected.len(),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+        for (a, b) in actual.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-9, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn triangle_weights_at_vertices_and_centroid() {
+        let (x, y) = ([0., 1., 0.], [0., 0., 1.]);
+        assert_close(
+            &triangle_weights(&x, &y, [0, 1, 2], (1., 0.)).unwrap(),
+            &[0., 1., 0.],
+        );
+        let third = 1. / 3.;
+        assert_close(
+            &triangle_weights(&x, &y, [0, 1, 2], (third, third)).unwrap(),
+            &[third, third, third],
+        );
+        assert!(triangle_weights(&x, &y, [0, 1, 2], (1., 1.)).is_none());
+        assert!(triangle_weights(&[0., 1., 2.], &[0., 0., 0.], [0, 1, 2], (1., 0.)).is_none());
+    }
+
+    #[test]
+    fn quads_are_split_along_their_first_diagonal() {
+        let (x, y) = ([0., 1., 1., 0.], [0., 0., 1., 1.]);
+        let weights = element_weights(&x, &y, &[0, 1, 2, 3], (0.25, 0.75)).unwrap();
+        assert_close(&weights, &[0.25, 0., 0.25, 0.5]);
+        assert!(element_weights(&x, &y, &[0, 1, 2, 3], (2., 0.5)).is_none());
+    }
+
+    #[test]
+    fn probe_interpolates_levels_between_nodes() {
+        let report = probed(&[(0., 0.), (SPACING, SPACING / 2.), (-SPACING, 0.)]);
+        let points = report.points();
+        let at_node = points[0].as_ref().unwrap();
+        assert_eq!(at_node.bottom_level(), 1);
+        assert_close(at_node.z(), &[-10., -5., 0.]);
+        // halfway between a 10 m and a 30 m deep node
+        assert_close(points[1].as_ref().unwrap().z(), &[-20., -10., 0.]);
+        assert!(points[2].is_none());
+    }
+
+    #[test]
+    fn dry_nodes_collapse_onto_the_bed() {
+        // 2 m above the datum, with the surface at 0
+        let hgrid = structured_hgrid(3, 2, SPACING, |_, _| -2.).unwrap();
+        let sigma = Array2::from_shape_fn((3, 6), |(k, _)| [-1., -0.5, 0.][k]);
+        let vqs = VQS::from_sigma(sigma, Array1::from_elem(6, -2.), 0.);
+        assert!(vqs.z().iter().all(|&z| z == 2.));
+        let report = probe(&vqs, &hgrid, &[(SPACING / 2., SPACING / 4.)]);
+        assert_close(report.points()[0].as_ref().unwrap().z(), &[2., 2., 2.]);
+    }
+
+    #[test]
+    fn reads_build_points() {
+        let path =
+            std::env::temp_dir().join(format!("schismrs-vgrid-probe-{}.bp", std::process::id()));
+        fs::write(&path, "stations\n2\n1 10.5 -3 0\n2 20 40\n").unwrap();
+        let points = read_build_points(&path);
+        fs::write(&path, "stations\n3\n1 10.5 -3 0\n").unwrap();
+        let truncated = read_build_points(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(points.unwrap(), vec![(10.5, -3.), (20., 40.)]);
+        assert!(matches!(truncated, Err(ProbeError::UnexpectedEndOfFile)));
+    }
+}
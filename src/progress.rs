@@ -0,0 +1,68 @@
+//! Progress callbacks of the builders and the vgrid.in loader.
+//!
+//! Large meshes take a while to build or read, so front ends pass a
+//! [`ProgressReporter`] to show a progress bar instead of appearing frozen.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Receives the stages of a build or load and the items processed in each.
+///
+/// Every method has a no-op default. Builds fill node columns from several
+/// threads, so implementations must be thread safe and cheap: they are called
+/// about once per thousand nodes.
+pub trait ProgressReporter: Send + Sync {
+    /// A stage, e.g. "build columns", started.
+    fn stage_started(&self, _stage: &str) {}
+
+    /// `done` of `total` items of the current stage are processed. `total` is
+    /// 0 when it is not known up front.
+    fn progress(&self, _done: usize, _total: usize) {}
+
+    /// A stage finished after `elapsed`.
+    fn stage_finished(&self, _stage: &str, _elapsed: Duration) {}
+}
+
+/// Ignores all progress.
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {}
+
+/// Logs each stage and its wall time at info level, and the progress at debug
+/// level in steps of 10%.
+#[derive(Default)]
+pub struct LogProgress {
+    logged_decile: AtomicUsize,
+}
+
+impl LogProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ProgressReporter for LogProgress {
+    fn stage_started(&self, stage: &str) {
+        self.logged_decile.store(0, Ordering::Relaxed);
+        log::info!("{}...", stage);
+    }
+
+    fn progress(&self, done: usize, total: usize) {
+        if total == 0 {
+            return;
+        }
+        let decile = 10 * done.min(total) / total;
+        // only the first call reaching a new decile logs it
+        if self.logged_decile.fetch_max(decile, Ordering::Relaxed) < decile {
+            log::debug!("{}/{} ({}%)", done.min(total), total, 10 * decile);
+        }
+    }
+
+    fn stage_finished(&self, stage: &str, elapsed: Duration) {
+        log::info!("{} done in {:.3} s", stage, elapsed.as_secs_f64());
+    }
+}
+
+/// Number of nodes read between two calls to [`ProgressReporter::progress`]
+/// by loaders that do not know the node count up front.
+pub const PROGRESS_INTERVAL: usize = 1024;
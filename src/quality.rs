@@ -0,0 +1,436 @@
+use crate::csv::CsvFormatter;
+use crate::mesh::element_connectivity;
+use crate::vqs::VQS;
+use schismrs_hgrid::hgrid::Hgrid;
+use std::f64::NAN;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+/// Layer thickness metrics of a single node column at the grid etal.
+pub struct NodeQuality {
+    levels: usize,
+    min_dz: f64,
+    max_dz: f64,
+    mean_dz: f64,
+    max_dz_ratio: f64,
+    truncated: bool,
+}
+
+impl NodeQuality {
+    /// Number of wet levels of the column.
+    pub fn levels(&self) -> usize {
+        self.levels
+    }
+
+    pub fn min_dz(&self) -> &f64 {
+        &self.min_dz
+    }
+
+    pub fn max_dz(&self) -> &f64 {
+        &self.max_dz
+    }
+
+    pub fn mean_dz(&self) -> &f64 {
+        &self.mean_dz
+    }
+
+    /// Largest ratio between the thicknesses of two adjacent layers, always >= 1.
+    pub fn max_dz_ratio(&self) -> &f64 {
+        &self.max_dz_ratio
+    }
+
+    /// Whether the column has fewer levels than nvrt, i.e. the bottom cut it.
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+/// Per-node, per-element and global quality metrics of a vertical grid.
+pub struct QualityReport {
+    nodes: Vec<NodeQuality>,
+    haney: Vec<f64>,
+    level_jumps: Vec<usize>,
+    min_dz: f64,
+    max_dz: f64,
+    mean_dz: f64,
+    max_dz_ratio: f64,
+    truncated_nodes: usize,
+    max_haney: f64,
+    cell_count: usize,
+}
+
+impl QualityReport {
+    /// Metrics of each node, indexed like the hgrid nodes.
+    pub fn nodes(&self) -> &Vec<NodeQuality> {
+        &self.nodes
+    }
+
+    /// Haney (rx1) number of each element, in hgrid element order.
+    pub fn haney(&self) -> &Vec<f64> {
+        &self.haney
+    }
+
+    /// Largest difference in level count between the nodes of each element,
+    /// in hgrid element order.
+    pub fn level_jumps(&self) -> &Vec<usize> {
+        &self.level_jumps
+    }
+
+    pub fn min_dz(&self) -> &f64 {
+        &self.min_dz
+    }
+
+    pub fn max_dz(&self) -> &f64 {
+        &self.max_dz
+    }
+
+    /// Mean layer thickness over all layers of all nodes.
+    pub fn mean_dz(&self) -> &f64 {
+        &self.mean_dz
+    }
+
+    pub fn max_dz_ratio(&self) -> &f64 {
+        &self.max_dz_ratio
+    }
+
+    pub fn truncated_nodes(&self) -> usize {
+        self.truncated_nodes
+    }
+
+    pub fn max_haney(&self) -> &f64 {
+        &self.max_haney
+    }
+
+    /// Total number of 3D prisms, counting nvrt - kbe layers per element where
+    /// kbe is the deepest bottom level index among the element nodes.
+    pub fn cell_count(&self) -> usize {
+        self.cell_count
+    }
+
+    /// Writes the metrics of each node as a CSV table with 1-based node ids.
+    pub fn write_nodes_csv(&self, path: &PathBuf, csv: &CsvFormatter) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        csv.write_header(
+            &mut writer,
+            &[
+                "node",
+                "levels",
+                "min_dz",
+                "max_dz",
+                "mean_dz",
+                "max_dz_ratio",
+                "truncated",
+            ],
+        )?;
+        for (i, node) in self.nodes.iter().enumerate() {
+            csv.write_record(
+                &mut writer,
+                &[
+                    (i + 1).to_string(),
+                    node.levels.to_string(),
+                    csv.float(node.min_dz),
+                    csv.float(node.max_dz),
+                    csv.float(node.mean_dz),
+                    csv.float(node.max_dz_ratio),
+                    node.truncated.to_string(),
+                ],
+            )?;
+        }
+        writer.flush()
+    }
+
+    /// Composite score of the report, see [`QualityScore`].
+    pub fn score(&self, options: &QualityScoreOptions) -> QualityScore {
+        let fraction = |passed: usize, total: usize| {
+            if total == 0 {
+                1.
+            } else {
+                passed as f64 / total as f64
+            }
+        };
+        let haney = fraction(
+            self.haney
+                .iter()
+                .filter(|&&rx1| rx1 <= options.max_haney)
+                .count(),
+            self.haney.len(),
+        );
+        let dz_ratio = fraction(
+            self.nodes
+                .iter()
+                .filter(|node| node.max_dz_ratio <= options.max_dz_ratio)
+                .count(),
+            self.nodes.len(),
+        );
+        let transition = fraction(
+            self.level_jumps
+                .iter()
+                .filter(|&&jump| jump <= options.max_level_jump)
+                .count(),
+            self.level_jumps.len(),
+        );
+        let coverage = fraction(
+            self.nodes
+                .iter()
+                .filter(|node| node.levels >= options.min_levels)
+                .count(),
+            self.nodes.len(),
+        );
+        let weights = [
+            options.haney_weight,
+            options.dz_ratio_weight,
+            options.transition_weight,
+            options.coverage_weight,
+        ];
+        let components = [haney, dz_ratio, transition, coverage];
+        let weight_sum: f64 = weights.iter().sum();
+        let total = if weight_sum > 0. {
+            weights
+                .iter()
+                .zip(components.iter())
+                .map(|(weight, component)| weight * component)
+                .sum::<f64>()
+                / weight_sum
+        } else {
+            NAN
+        };
+        QualityScore {
+            haney,
+            dz_ratio,
+            transition,
+            coverage,
+            total,
+        }
+    }
+}
+
+/// Thresholds and weights of the composite [`QualityScore`].
+#[derive(Clone, Debug)]
+pub struct QualityScoreOptions {
+    /// Largest acceptable Haney number (rx1) of an element.
+    pub max_haney: f64,
+    /// Largest acceptable ratio between adjacent layer thicknesses of a node.
+    pub max_dz_ratio: f64,
+    /// Largest acceptable level count difference between nodes of an element.
+    pub max_level_jump: usize,
+    /// Smallest acceptable number of wet levels of a node.
+    pub min_levels: usize,
+    pub haney_weight: f64,
+    pub dz_ratio_weight: f64,
+    pub transition_weight: f64,
+    pub coverage_weight: f64,
+}
+
+impl Default for QualityScoreOptions {
+    fn default() -> Self {
+        Self {
+            max_haney: 7.,
+            max_dz_ratio: 1.5,
+            max_level_jump: 2,
+            min_levels: 3,
+            haney_weight: 1.,
+            dz_ratio_weight: 1.,
+            transition_weight: 1.,
+            coverage_weight: 1.,
+        }
+    }
+}
+
+impl QualityScoreOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn max_haney(&mut self, max_haney: f64) -> &mut Self {
+        self.max_haney = max_haney;
+        self
+    }
+    pub fn max_dz_ratio(&mut self, max_dz_ratio: f64) -> &mut Self {
+        self.max_dz_ratio = max_dz_ratio;
+        self
+    }
+    pub fn max_level_jump(&mut self, max_level_jump: usize) -> &mut Self {
+        self.max_level_jump = max_level_jump;
+        self
+    }
+    pub fn min_levels(&mut self, min_levels: usize) -> &mut Self {
+        self.min_levels = min_levels;
+        self
+    }
+    pub fn haney_weight(&mut self, haney_weight: f64) -> &mut Self {
+        self.haney_weight = haney_weight;
+        self
+    }
+    pub fn dz_ratio_weight(&mut self, dz_ratio_weight: f64) -> &mut Self {
+        self.dz_ratio_weight = dz_ratio_weight;
+        self
+    }
+    pub fn transition_weight(&mut self, transition_weight: f64) -> &mut Self {
+        self.transition_weight = transition_weight;
+        self
+    }
+    pub fn coverage_weight(&mut self, coverage_weight: f64) -> &mut Self {
+        self.coverage_weight = coverage_weight;
+        self
+    }
+}
+
+/// Composite quality score of a vertical grid, for comparing candidate designs.
+///
+/// Each component is the fraction of elements or nodes within the thresholds
+/// of [`QualityScoreOptions`], so 1 is best: elements under the Haney limit,
+/// nodes under the dz ratio limit, elements whose level count jump is
+/// acceptable and nodes with enough wet levels. The total is the weighted mean
+/// of the components.
+#[derive(Clone, Debug)]
+pub struct QualityScore {
+    haney: f64,
+    dz_ratio: f64,
+    transition: f64,
+    coverage: f64,
+    total: f64,
+}
+
+impl QualityScore {
+    pub fn haney(&self) -> &f64 {
+        &self.haney
+    }
+
+    pub fn dz_ratio(&self) -> &f64 {
+        &self.dz_ratio
+    }
+
+    pub fn transition(&self) -> &f64 {
+        &self.transition
+    }
+
+    pub fn coverage(&self) -> &f64 {
+        &self.coverage
+    }
+
+    pub fn total(&self) -> &f64 {
+        &self.total
+    }
+}
+
+impl fmt::Display for QualityScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<28}{:>14.4}\n", "Haney score", self.haney)?;
+        write!(f, "{:<28}{:>14.4}\n", "dz ratio score", self.dz_ratio)?;
+        write!(f, "{:<28}{:>14.4}\n", "transition score", self.transition)?;
+        write!(f, "{:<28}{:>14.4}\n", "coverage score", self.coverage)?;
+        write!(f, "{:<28}{:>14.4}\n", "quality score", self.total)
+    }
+}
+
+impl fmt::Display for QualityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<28}{:>14.6}\n", "min dz", self.min_dz)?;
+        write!(f, "{:<28}{:>14.6}\n", "max dz", self.max_dz)?;
+        write!(f, "{:<28}{:>14.6}\n", "mean dz", self.mean_dz)?;
+        write!(
+            f,
+            "{:<28}{:>14.6}\n",
+            "max adjacent dz ratio", self.max_dz_ratio
+        )?;
+        write!(f, "{:<28}{:>14}\n", "truncated nodes", self.truncated_nodes)?;
+        write!(
+            f,
+            "{:<28}{:>14.6}\n",
+            "max Haney number (rx1)", self.max_haney
+        )?;
+        write!(f, "{:<28}{:>14}\n", "3D cells", self.cell_count)
+    }
+}
+
+/// Computes the quality metrics of `vqs` on the mesh of `hgrid`.
+pub fn quality_report(vqs: &VQS, hgrid: &Hgrid) -> QualityReport {
+    let z = vqs.z();
+    let nvrt = vqs.nvrt();
+    let bottom_level_indices = vqs.bottom_level_indices();
+    let mut nodes = Vec::with_capacity(bottom_level_indices.len());
+    let (mut min_dz, mut max_dz, mut max_dz_ratio) = (f64::INFINITY, 0., 1.);
+    let (mut dz_sum, mut dz_count) = (0., 0);
+    for (i, &kbp) in bottom_level_indices.iter().enumerate() {
+        let mut node = NodeQuality {
+            levels: nvrt + 1 - kbp,
+            min_dz: f64::INFINITY,
+            max_dz: 0.,
+            mean_dz: NAN,
+            max_dz_ratio: 1.,
+            truncated: kbp > 1,
+        };
+        let dz: Vec<f64> = (kbp - 1..nvrt - 1)
+            .map(|row| z[[row + 1, i]] - z[[row, i]])
+            .collect();
+        for &layer in dz.iter() {
+            node.min_dz = node.min_dz.min(layer);
+            node.max_dz = node.max_dz.max(layer);
+        }
+        for pair in dz.windows(2) {
+            let ratio = (pair[0] / pair[1]).max(pair[1] / pair[0]);
+            node.max_dz_ratio = node.max_dz_ratio.max(ratio);
+        }
+        if !dz.is_empty() {
+            node.mean_dz = dz.iter().sum::<f64>() / dz.len() as f64;
+            dz_sum += dz.iter().sum::<f64>();
+            dz_count += dz.len();
+        }
+        min_dz = min_dz.min(node.min_dz);
+        max_dz = max_dz.max(node.max_dz);
+        max_dz_ratio = max_dz_ratio.max(node.max_dz_ratio);
+        nodes.push(node);
+    }
+    let truncated_nodes = nodes.iter().filter(|node| node.truncated).count();
+    let mut haney = Vec::new();
+    let mut level_jumps = Vec::new();
+    let mut cell_count = 0;
+    for element in element_connectivity(hgrid).iter() {
+        let kbe = element
+            .iter()
+            .map(|&node| bottom_level_indices[node])
+            .max()
+            .unwrap_or(nvrt);
+        cell_count += nvrt - kbe;
+        let kbe_min = element
+            .iter()
+            .map(|&node| bottom_level_indices[node])
+            .min()
+            .unwrap_or(nvrt);
+        level_jumps.push(kbe - kbe_min);
+        let mut rx1: f64 = 0.;
+        for (a, &node_a) in element.iter().enumerate() {
+            let node_b = element[(a + 1) % element.len()];
+            let kbp = bottom_level_indices[node_a].max(bottom_level_indices[node_b]);
+            for row in kbp..nvrt {
+                let numerator = z[[row, node_a]] - z[[row, node_b]] + z[[row - 1, node_a]]
+                    - z[[row - 1, node_b]];
+                let denominator = z[[row, node_a]] + z[[row, node_b]]
+                    - z[[row - 1, node_a]]
+                    - z[[row - 1, node_b]];
+                if denominator != 0. {
+                    rx1 = rx1.max((numerator / denominator).abs());
+                }
+            }
+        }
+        haney.push(rx1);
+    }
+    let max_haney = haney.iter().cloned().fold(0., f64::max);
+    QualityReport {
+        nodes,
+        haney,
+        level_jumps,
+        min_dz,
+        max_dz,
+        mean_dz: if dz_count > 0 {
+            dz_sum / dz_count as f64
+        } else {
+            NAN
+        },
+        max_dz_ratio,
+        truncated_nodes,
+        max_haney,
+        cell_count,
+    }
+}
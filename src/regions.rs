@@ -0,0 +1,107 @@
+use crate::mesh::point_in_polygon;
+use schismrs_hgrid::hgrid::Hgrid;
+use std::fs;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Forces a fixed number of levels on every node inside a polygon, regardless
+/// of its depth, e.g. 40 levels inside a navigation channel.
+#[derive(Clone, Debug)]
+pub struct RegionOverride {
+    vertices: Vec<(f64, f64)>,
+    nlevels: usize,
+}
+
+impl RegionOverride {
+    pub fn new(vertices: Vec<(f64, f64)>, nlevels: usize) -> Result<Self, RegionOverrideError> {
+        if vertices.len() < 3 {
+            return Err(RegionOverrideError::NotEnoughVertices(vertices.len()));
+        }
+        if nlevels < 2 {
+            return Err(RegionOverrideError::InvalidNLevels(nlevels));
+        }
+        Ok(Self { vertices, nlevels })
+    }
+
+    /// Parses a WKT `POLYGON ((x y, x y, ...))`. Only the outer ring is used.
+    pub fn from_wkt(wkt: &str, nlevels: usize) -> Result<Self, RegionOverrideError> {
+        let invalid = || RegionOverrideError::InvalidWkt(wkt.to_string());
+        let body = wkt.trim();
+        if !body.to_uppercase().starts_with("POLYGON") {
+            return Err(invalid());
+        }
+        let start = body.find("((").ok_or_else(invalid)?;
+        let end = body[start..].find(')').ok_or_else(invalid)? + start;
+        let mut vertices = Vec::new();
+        for pair in body[start + 2..end].split(',') {
+            let mut coordinates = pair.split_whitespace().map(|token| token.parse::<f64>());
+            match (coordinates.next(), coordinates.next()) {
+                (Some(Ok(x)), Some(Ok(y))) => vertices.push((x, y)),
+                _ => return Err(invalid()),
+            }
+        }
+        // WKT rings repeat the first vertex at the end
+        if vertices.len() > 1 && vertices.first() == vertices.last() {
+            vertices.pop();
+        }
+        Self::new(vertices, nlevels)
+    }
+
+    /// Reads one override per line as `nlevels POLYGON ((...))`. Empty lines and
+    /// lines starting with `#` are skipped.
+    pub fn try_from_file(path: &PathBuf) -> Result<Vec<Self>, RegionOverrideError> {
+        let mut regions = Vec::new();
+        for line in fs::read_to_string(path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (nlevels, wkt) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| RegionOverrideError::InvalidWkt(line.to_string()))?;
+            let nlevels = nlevels
+                .parse::<usize>()
+                .map_err(|_| RegionOverrideError::InvalidWkt(line.to_string()))?;
+            regions.push(Self::from_wkt(wkt, nlevels)?);
+        }
+        Ok(regions)
+    }
+
+    pub fn nlevels(&self) -> usize {
+        self.nlevels
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        point_in_polygon(&self.vertices, x, y)
+    }
+}
+
+/// Level count forced on each hgrid node by the first region containing it.
+pub(crate) fn node_level_overrides(
+    hgrid: &Hgrid,
+    regions: &Vec<RegionOverride>,
+) -> Vec<Option<usize>> {
+    hgrid
+        .x()
+        .iter()
+        .zip(hgrid.y().iter())
+        .map(|(&x, &y)| {
+            regions
+                .iter()
+                .find(|region| region.contains(x, y))
+                .map(|region| region.nlevels)
+        })
+        .collect()
+}
+
+#[derive(Error, Debug)]
+pub enum RegionOverrideError {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error("Expected a WKT POLYGON but got {0:?}")]
+    InvalidWkt(String),
+    #[error("A region polygon needs at least 3 vertices but got {0}")]
+    NotEnoughVertices(usize),
+    #[error("Region nlevels must be >= 2 but got {0}")]
+    InvalidNLevels(usize),
+}
@@ -0,0 +1,206 @@
+use ndarray::{ArrayBase, Axis, DataMut, Ix2};
+
+/// Order in which the levels of a vertical column are stored.
+///
+/// The builder computes columns from the surface down, while vgrid.in (and
+/// [`crate::vqs::VQS`]) store them from the bottom up, matching SCHISM's
+/// level indexing where level `nvrt` is the surface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LevelOrder {
+    SurfaceDown,
+    BottomUp,
+}
+
+impl LevelOrder {
+    pub fn reversed(&self) -> LevelOrder {
+        match self {
+            LevelOrder::SurfaceDown => LevelOrder::BottomUp,
+            LevelOrder::BottomUp => LevelOrder::SurfaceDown,
+        }
+    }
+
+    /// Reorders the level axis (axis 0) of a (levels, nodes) array stored in
+    /// `self` order so that it ends up in `order`. This is a no-op when both
+    /// orders match, so calling it can never flip an array twice.
+    pub fn convert<S: DataMut>(&self, levels: &mut ArrayBase<S, Ix2>, order: LevelOrder) {
+        if *self != order {
+            levels.invert_axis(Axis(0));
+        }
+    }
+}
+
+/// The sigma values of the wet levels of a single node, tagged with their order.
+#[derive(Clone, Debug)]
+pub struct SigmaColumn {
+    values: Vec<f64>,
+    order: LevelOrder,
+}
+
+impl SigmaColumn {
+    pub fn new(values: Vec<f64>, order: LevelOrder) -> Self {
+        Self { values, order }
+    }
+
+    pub fn from_surface_down(values: Vec<f64>) -> Self {
+        Self::new(values, LevelOrder::SurfaceDown)
+    }
+
+    pub fn from_bottom_up(values: Vec<f64>) -> Self {
+        Self::new(values, LevelOrder::BottomUp)
+    }
+
+    pub fn order(&self) -> LevelOrder {
+        self.order
+    }
+
+    /// Values in the order they are stored, see [`SigmaColumn::order`].
+    pub fn values(&self) -> &Vec<f64> {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the column in `order`, reversing the values only if needed.
+    pub fn into_order(mut self, order: LevelOrder) -> Self {
+        if self.order != order {
+            self.values.reverse();
+            self.order = order;
+        }
+        self
+    }
+
+    pub fn into_surface_down(self) -> Vec<f64> {
+        self.into_order(LevelOrder::SurfaceDown).values
+    }
+
+    pub fn into_bottom_up(self) -> Vec<f64> {
+        self.into_order(LevelOrder::BottomUp).values
+    }
+
+    /// Sigma of the surface level, which should be 0.
+    pub fn surface(&self) -> Option<f64> {
+        match self.order {
+            LevelOrder::SurfaceDown => self.values.first().cloned(),
+            LevelOrder::BottomUp => self.values.last().cloned(),
+        }
+    }
+
+    /// Sigma of the bottom level, which should be -1.
+    pub fn bottom(&self) -> Option<f64> {
+        match self.order {
+            LevelOrder::SurfaceDown => self.values.last().cloned(),
+            LevelOrder::BottomUp => self.values.first().cloned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::shelf_hgrid;
+    use crate::transforms::s::STransformOpts;
+    use crate::transforms::StretchingFunction;
+    use crate::vqs::{VQSAutoBuilder, VQS};
+    use ndarray::{array, Array2};
+
+    const ORDERS: [LevelOrder; 2] = [LevelOrder::SurfaceDown, LevelOrder::BottomUp];
+
+    fn built_vqs() -> VQS {
+        let hgrid = shelf_hgrid(12, 3).unwrap();
+        let stretching = StretchingFunction::S(STransformOpts {
+            etal: &0.,
+            a_vqs0: &-0.3,
+            theta_b: &0.7,
+            theta_f: &5.,
+        });
+        VQSAutoBuilder::default()
+            .hgrid(&hgrid)
+            .stretching(&stretching)
+            .ngrids(&6)
+            .dz_bottom_min(&0.5)
+            .initial_depth(&3.)
+            .shallow_levels(&3)
+            .max_levels(&30)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn convert_twice_is_identity() {
+        let levels = array![[0., 0., 0.], [-0.5, -0.4, -0.3], [-1., -1., f64::NAN]];
+        for from in ORDERS {
+            for to in ORDERS {
+                let mut converted = levels.clone();
+                from.convert(&mut converted, to);
+                to.convert(&mut converted, from);
+                assert!(converted
+                    .iter()
+                    .zip(levels.iter())
+                    .all(|(a, b)| a == b || (a.is_nan() && b.is_nan())));
+            }
+        }
+    }
+
+    #[test]
+    fn convert_to_the_same_order_is_a_no_op() {
+        let levels = array![[0., 0.], [-1., -1.]];
+        for order in ORDERS {
+            let mut converted = levels.clone();
+            order.convert(&mut converted, order);
+            assert_eq!(converted, levels);
+        }
+    }
+
+    #[test]
+    fn column_into_order_twice_is_identity() {
+        let column = SigmaColumn::from_surface_down(vec![0., -0.3, -0.7, -1.]);
+        for order in ORDERS {
+            let round_trip = column
+                .clone()
+                .into_order(order)
+                .into_order(LevelOrder::SurfaceDown);
+            assert_eq!(round_trip.values(), column.values());
+            assert_eq!(round_trip.order(), LevelOrder::SurfaceDown);
+        }
+    }
+
+    #[test]
+    fn column_surface_and_bottom_follow_the_order() {
+        let column = SigmaColumn::from_bottom_up(vec![-1., -0.5, 0.]);
+        for order in ORDERS {
+            let column = column.clone().into_order(order);
+            assert_eq!(column.surface(), Some(0.));
+            assert_eq!(column.bottom(), Some(-1.));
+        }
+    }
+
+    #[test]
+    fn built_vqs_keeps_surface_and_bottom_across_orders() {
+        let vqs = built_vqs();
+        let nvrt = vqs.nvrt();
+        let kbp = vqs.bottom_level_indices();
+        let mut sigma: Array2<f64> = vqs.sigma().clone();
+        VQS::LEVEL_ORDER.convert(&mut sigma, LevelOrder::SurfaceDown);
+        for (node, column) in sigma.columns().into_iter().enumerate() {
+            assert_eq!(column[0], 0., "surface of node {}", node);
+            assert_eq!(column[nvrt - kbp[node]], -1., "bottom of node {}", node);
+        }
+        LevelOrder::SurfaceDown.convert(&mut sigma, VQS::LEVEL_ORDER);
+        for node in 0..sigma.ncols() {
+            assert_eq!(sigma[[nvrt - 1, node]], 0., "surface of node {}", node);
+            assert_eq!(sigma[[kbp[node] - 1, node]], -1., "bottom of node {}", node);
+            let column = vqs.sigma_column(node);
+            for order in ORDERS {
+                let column = column.clone().into_order(order);
+                assert_eq!(column.surface(), Some(0.));
+                assert_eq!(column.bottom(), Some(-1.));
+            }
+        }
+    }
+}
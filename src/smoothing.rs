@@ -0,0 +1,162 @@
+use ndarray::Array2;
+use std::fmt;
+
+/// Level surface kinks of the master grids before and after [`smooth_zmas`].
+///
+/// A kink is the change in slope of a level surface, in m of z per m of depth,
+/// between two consecutive master grids.
+pub struct ZmasSmoothingReport {
+    max_kink_before: f64,
+    max_kink_after: f64,
+    max_shift: f64,
+    adjusted: usize,
+}
+
+impl ZmasSmoothingReport {
+    pub fn max_kink_before(&self) -> &f64 {
+        &self.max_kink_before
+    }
+
+    pub fn max_kink_after(&self) -> &f64 {
+        &self.max_kink_after
+    }
+
+    /// Largest vertical displacement of a master grid level, in m.
+    pub fn max_shift(&self) -> &f64 {
+        &self.max_shift
+    }
+
+    /// Number of master grid levels that moved.
+    pub fn adjusted(&self) -> usize {
+        self.adjusted
+    }
+}
+
+impl fmt::Display for ZmasSmoothingReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<28}{:>14.6}\n",
+            "max level kink before", self.max_kink_before
+        )?;
+        write!(
+            f,
+            "{:<28}{:>14.6}\n",
+            "max level kink after", self.max_kink_after
+        )?;
+        write!(f, "{:<28}{:>14.6}\n", "max level shift", self.max_shift)?;
+        write!(f, "{:<28}{:>14}\n", "adjusted levels", self.adjusted)
+    }
+}
+
+/// Smooths each level surface of `z_mas` across the master grids `hsm`.
+///
+/// Every interior level of a master grid is pulled, by `strength` in (0, 1],
+/// towards the monotone cubic (PCHIP) interpolant in depth of the same level
+/// on the other master grids. The surface and bottom levels, and the first and
+/// last master grid holding each level, are kept, as are changes that would
+/// fold a column.
+pub fn smooth_zmas(
+    z_mas: &Array2<f64>,
+    hsm: &[f64],
+    nlevels: &[usize],
+    strength: f64,
+) -> (Array2<f64>, ZmasSmoothingReport) {
+    let mut smoothed = z_mas.clone();
+    for k in 1..z_mas.nrows() {
+        // nlevels is non-decreasing, so the grids where k is an interior level
+        // are a suffix of the master grids
+        let run: Vec<usize> = (0..hsm.len()).filter(|&m| k + 1 < nlevels[m]).collect();
+        if run.len() < 3 {
+            continue;
+        }
+        for (i, &m) in run.iter().enumerate().skip(1).take(run.len() - 2) {
+            let others: Vec<usize> = run
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, &m)| m)
+                .collect();
+            let xs: Vec<f64> = others.iter().map(|&m| hsm[m]).collect();
+            let ys: Vec<f64> = others.iter().map(|&m| z_mas[[k, m]]).collect();
+            let predicted = pchip(&xs, &ys, hsm[m]);
+            smoothed[[k, m]] = (1. - strength) * z_mas[[k, m]] + strength * predicted;
+        }
+    }
+    // revert the levels that no longer lie strictly between their neighbours
+    let mut folded = true;
+    while folded {
+        folded = false;
+        for (m, &nlev) in nlevels.iter().enumerate() {
+            for k in 0..nlev - 1 {
+                if smoothed[[k, m]] <= smoothed[[k + 1, m]] {
+                    for row in [k, k + 1] {
+                        if smoothed[[row, m]] != z_mas[[row, m]] {
+                            smoothed[[row, m]] = z_mas[[row, m]];
+                            folded = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    let mut max_shift: f64 = 0.;
+    let mut adjusted = 0;
+    for (a, b) in z_mas.iter().zip(smoothed.iter()) {
+        if !a.is_nan() && a != b {
+            max_shift = max_shift.max((a - b).abs());
+            adjusted += 1;
+        }
+    }
+    let report = ZmasSmoothingReport {
+        max_kink_before: max_kink(z_mas, hsm, nlevels),
+        max_kink_after: max_kink(&smoothed, hsm, nlevels),
+        max_shift,
+        adjusted,
+    };
+    (smoothed, report)
+}
+
+fn max_kink(z_mas: &Array2<f64>, hsm: &[f64], nlevels: &[usize]) -> f64 {
+    let mut max_kink: f64 = 0.;
+    for k in 1..z_mas.nrows() {
+        let run: Vec<usize> = (0..hsm.len()).filter(|&m| k + 1 < nlevels[m]).collect();
+        let slopes: Vec<f64> = run
+            .windows(2)
+            .map(|pair| (z_mas[[k, pair[1]]] - z_mas[[k, pair[0]]]) / (hsm[pair[1]] - hsm[pair[0]]))
+            .collect();
+        for pair in slopes.windows(2) {
+            max_kink = max_kink.max((pair[1] - pair[0]).abs());
+        }
+    }
+    max_kink
+}
+
+/// Value at `x` of the Fritsch-Carlson monotone cubic through the points
+/// (`xs`, `ys`), with `xs` strictly increasing and `x` within their range.
+fn pchip(xs: &[f64], ys: &[f64], x: f64) -> f64 {
+    let n = xs.len();
+    let h: Vec<f64> = xs.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let delta: Vec<f64> = (0..n - 1).map(|i| (ys[i + 1] - ys[i]) / h[i]).collect();
+    let mut d = vec![0.; n];
+    d[0] = delta[0];
+    d[n - 1] = delta[n - 2];
+    for i in 1..n - 1 {
+        if delta[i - 1] * delta[i] > 0. {
+            let w1 = 2. * h[i] + h[i - 1];
+            let w2 = h[i] + 2. * h[i - 1];
+            d[i] = (w1 + w2) / (w1 / delta[i - 1] + w2 / delta[i]);
+        }
+    }
+    let i = xs
+        .partition_point(|&xi| xi <= x)
+        .saturating_sub(1)
+        .min(n - 2);
+    let t = (x - xs[i]) / h[i];
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (2. * t3 - 3. * t2 + 1.) * ys[i]
+        + (t3 - 2. * t2 + t) * h[i] * d[i]
+        + (-2. * t3 + 3. * t2) * ys[i + 1]
+        + (t3 - t2) * h[i] * d[i + 1]
+}
@@ -0,0 +1,79 @@
+use crate::hash::{fnv1a, FNV_OFFSET_BASIS};
+use crate::vqs::VQS;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Sigma values are rounded to this many decimals before hashing, so that
+/// round-off from reordering floating point operations does not change the
+/// snapshot.
+const SIGMA_DECIMALS: i32 = 6;
+
+/// Compact, deterministic text summary of a vertical grid.
+///
+/// Meant for snapshot tests (e.g. with `insta`) that lock in a grid design: any
+/// change to nvrt, to the distribution of level counts or to sigma beyond
+/// 6 decimals changes the rendered text.
+pub struct VgridSummary {
+    nvrt: usize,
+    np: usize,
+    level_histogram: BTreeMap<usize, usize>,
+    level_checksums: Vec<(usize, u64)>,
+}
+
+impl VgridSummary {
+    pub(crate) fn new(vqs: &VQS) -> Self {
+        let nvrt = vqs.nvrt();
+        let level_histogram = vqs.level_histogram();
+        let scale = 10f64.powi(SIGMA_DECIMALS);
+        let level_checksums = vqs
+            .level_stats()
+            .zip(vqs.sigma().rows())
+            .map(|(stats, row)| {
+                let checksum = row.iter().filter(|value| !value.is_nan()).fold(
+                    FNV_OFFSET_BASIS,
+                    |hash, value| {
+                        let quantized = (value * scale).round() as i64;
+                        fnv1a(hash, &quantized.to_le_bytes())
+                    },
+                );
+                (stats.count(), checksum)
+            })
+            .collect();
+        Self {
+            nvrt,
+            np: vqs.sigma().ncols(),
+            level_histogram,
+            level_checksums,
+        }
+    }
+
+    pub fn nvrt(&self) -> usize {
+        self.nvrt
+    }
+
+    /// Number of nodes for each wet level count.
+    pub fn level_histogram(&self) -> &BTreeMap<usize, usize> {
+        &self.level_histogram
+    }
+
+    /// Node count and quantized sigma checksum of each level, bottom to surface.
+    pub fn level_checksums(&self) -> &Vec<(usize, u64)> {
+        &self.level_checksums
+    }
+}
+
+impl fmt::Display for VgridSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "nvrt {}\n", self.nvrt)?;
+        write!(f, "np {}\n", self.np)?;
+        write!(f, "levels nodes\n")?;
+        for (levels, nodes) in self.level_histogram.iter() {
+            write!(f, "{} {}\n", levels, nodes)?;
+        }
+        write!(f, "level nodes checksum\n")?;
+        for (row, (nodes, checksum)) in self.level_checksums.iter().enumerate() {
+            write!(f, "{} {} {:016x}\n", row + 1, nodes, checksum)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+//! Synthetic hgrids for tests and experiments, built without mesh files.
+//!
+//! The meshes are structured and triangulated, with idealized bathymetries
+//! that exercise the shallow, master grid and deep paths of the builders.
+//! Depths are positive down, as in hgrid.gr3.
+
+use crate::vqs::VQS;
+use crate::writer::VgridWriterOptions;
+use schismrs_hgrid::hgrid::Hgrid;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Node spacing in meters of the idealized meshes.
+pub const SYNTHETIC_SPACING: f64 = 1000.;
+
+/// Distinguishes the temporary files of concurrent calls.
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn temp_path(extension: &str) -> PathBuf {
+    let id = TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!(
+        "schismrs-vgrid-testing-{}-{}.{}",
+        std::process::id(),
+        id,
+        extension
+    ))
+}
+
+/// hgrid.gr3 contents of a structured mesh of `nx` * `ny` nodes `spacing`
+/// apart, each square split into two triangles, with `depth(x, y)` at each
+/// node.
+pub fn structured_gr3<F: Fn(f64, f64) -> f64>(
+    nx: usize,
+    ny: usize,
+    spacing: f64,
+    depth: F,
+) -> String {
+    let mut gr3 = String::new();
+    let ne = 2 * nx.saturating_sub(1) * ny.saturating_sub(1);
+    write!(gr3, "synthetic\n{} {}\n", ne, nx * ny).unwrap();
+    for j in 0..ny {
+        for i in 0..nx {
+            let (x, y) = (i as f64 * spacing, j as f64 * spacing);
+            write!(gr3, "{} {} {} {}\n", j * nx + i + 1, x, y, depth(x, y)).unwrap();
+        }
+    }
+    let mut element = 0;
+    for j in 0..ny.saturating_sub(1) {
+        for i in 0..nx.saturating_sub(1) {
+            let n = j * nx + i + 1;
+            element += 1;
+            write!(gr3, "{} 3 {} {} {}\n", element, n, n + 1, n + nx + 1).unwrap();
+            element += 1;
+            write!(gr3, "{} 3 {} {} {}\n", element, n, n + nx + 1, n + nx).unwrap();
+        }
+    }
+    gr3
+}
+
+/// Loads the mesh of [`structured_gr3`] as an [`Hgrid`].
+pub fn structured_hgrid<F: Fn(f64, f64) -> f64>(
+    nx: usize,
+    ny: usize,
+    spacing: f64,
+    depth: F,
+) -> Result<Hgrid, Box<dyn Error>> {
+    let path = temp_path("gr3");
+    fs::write(&path, structured_gr3(nx, ny, spacing, depth))?;
+    let hgrid = Hgrid::try_from(&path);
+    fs::remove_file(&path)?;
+    Ok(hgrid?)
+}
+
+/// Continental shelf deepening from 2 m at x=0 to 100 m at the shelf break
+/// (60% of the width), a slope down to 3000 m (80%) and a flat abyss.
+pub fn shelf_hgrid(nx: usize, ny: usize) -> Result<Hgrid, Box<dyn Error>> {
+    let width = (nx.max(2) - 1) as f64 * SYNTHETIC_SPACING;
+    structured_hgrid(nx, ny, SYNTHETIC_SPACING, |x, _| {
+        let xi = x / width;
+        if xi <= 0.6 {
+            2. + 98. * xi / 0.6
+        } else if xi <= 0.8 {
+            100. + 2900. * (xi - 0.6) / 0.2
+        } else {
+            3000.
+        }
+    })
+}
+
+/// 30 m deep Gaussian channel along x between 1 m deep banks.
+pub fn channel_hgrid(nx: usize, ny: usize) -> Result<Hgrid, Box<dyn Error>> {
+    let height = (ny.max(2) - 1) as f64 * SYNTHETIC_SPACING;
+    structured_hgrid(nx, ny, SYNTHETIC_SPACING, |_, y| {
+        let offset = (y - height / 2.) / (height / 8.);
+        1. + 29. * (-offset * offset).exp()
+    })
+}
+
+/// 4000 m deep abyss with a Gaussian seamount rising to 200 m at its center.
+pub fn seamount_hgrid(nx: usize, ny: usize) -> Result<Hgrid, Box<dyn Error>> {
+    let (width, height) = (
+        (nx.max(2) - 1) as f64 * SYNTHETIC_SPACING,
+        (ny.max(2) - 1) as f64 * SYNTHETIC_SPACING,
+    );
+    let radius = width.min(height) / 6.;
+    structured_hgrid(nx, ny, SYNTHETIC_SPACING, |x, y| {
+        let r2 = ((x - width / 2.).powi(2) + (y - height / 2.).powi(2)) / (radius * radius);
+        4000. - 3800. * (-r2).exp()
+    })
+}
+
+/// Writes `vqs` with `options` to a temporary vgrid.in and loads it back on
+/// `hgrid`, for round-trip checks against [`crate::diff::VQSDiff`].
+pub fn round_trip(
+    vqs: &VQS,
+    hgrid: &Hgrid,
+    options: &VgridWriterOptions,
+) -> Result<VQS, Box<dyn Error>> {
+    let path = temp_path("vgrid.in");
+    vqs.write_to_file_with_options(&path, options)?;
+    let loaded = VQS::try_from_file(hgrid, &path);
+    fs::remove_file(&path)?;
+    Ok(loaded?)
+}
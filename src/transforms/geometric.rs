@@ -0,0 +1,366 @@
+use super::traits::Transform;
+use ndarray::Array2;
+use schismrs_hgrid::Hgrid;
+use std::f64::NAN;
+use thiserror::Error;
+
+/// Layers growing geometrically from the surface: the top layer is
+/// `surface_dz` thick and each layer below is `growth` times the one above.
+///
+/// Once the layers are thick enough for the remaining ones to fill the column
+/// uniformly, they stop growing. Columns too deep to be filled at `growth`
+/// grow faster, and columns shallower than `surface_dz` times their number of
+/// layers are uniform.
+pub struct GeometricTransform {
+    zmas: Array2<f64>,
+    etal: f64,
+    a_vqs0: f64,
+    surface_dz: f64,
+    growth: f64,
+}
+
+impl Transform for GeometricTransform {
+    fn zmas(&self) -> &Array2<f64> {
+        &self.zmas
+    }
+    fn etal(&self) -> &f64 {
+        &self.etal
+    }
+    fn a_vqs0(&self) -> &f64 {
+        &self.a_vqs0
+    }
+    fn name(&self) -> &'static str {
+        "geometric"
+    }
+    fn parameters(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("etal", self.etal),
+            ("a_vqs0", self.a_vqs0),
+            ("surface_dz", self.surface_dz),
+            ("growth", self.growth),
+        ]
+    }
+    fn sigma_at(&self, depth: f64, nlevels: usize) -> Vec<f64> {
+        geometric_sigma(self.etal + depth, nlevels, self.surface_dz, self.growth)
+    }
+}
+
+/// Sigma of the `nlevels` levels of a column `total_depth` thick, from the
+/// surface (0) to the bottom (-1).
+fn geometric_sigma(total_depth: f64, nlevels: usize, surface_dz: f64, growth: f64) -> Vec<f64> {
+    let nlayers = nlevels - 1;
+    let thicknesses = layer_thicknesses(total_depth, nlayers, surface_dz, growth);
+    let mut sigma = Vec::with_capacity(nlevels);
+    let mut z = 0.;
+    sigma.push(0.);
+    for dz in thicknesses.iter().take(nlayers - 1) {
+        z += dz;
+        sigma.push(-z / total_depth);
+    }
+    sigma.push(-1.);
+    sigma
+}
+
+fn layer_thicknesses(total_depth: f64, nlayers: usize, surface_dz: f64, growth: f64) -> Vec<f64> {
+    if surface_dz * nlayers as f64 >= total_depth {
+        return vec![total_depth / nlayers as f64; nlayers];
+    }
+    if geometric_sum(surface_dz, growth, nlayers) <= total_depth {
+        let growth = fill_growth(total_depth, nlayers, surface_dz, growth);
+        return (0..nlayers)
+            .map(|k| surface_dz * growth.powi(k as i32))
+            .collect();
+    }
+    let mut thicknesses = Vec::with_capacity(nlayers);
+    let mut remaining = total_depth;
+    let mut dz = surface_dz;
+    for k in 0..nlayers {
+        let uniform = remaining / (nlayers - k) as f64;
+        if dz >= uniform {
+            thicknesses.extend(std::iter::repeat(uniform).take(nlayers - k));
+            break;
+        }
+        thicknesses.push(dz);
+        remaining -= dz;
+        dz *= growth;
+    }
+    thicknesses
+}
+
+fn geometric_sum(surface_dz: f64, growth: f64, nlayers: usize) -> f64 {
+    if growth == 1. {
+        return surface_dz * nlayers as f64;
+    }
+    surface_dz * (growth.powi(nlayers as i32) - 1.) / (growth - 1.)
+}
+
+/// Smallest growth factor, no smaller than `growth`, whose `nlayers` layers
+/// starting at `surface_dz` fill `total_depth`, found by bisection.
+fn fill_growth(total_depth: f64, nlayers: usize, surface_dz: f64, growth: f64) -> f64 {
+    let mut lower = growth;
+    let mut upper = growth.max(1.) * 2.;
+    while geometric_sum(surface_dz, upper, nlayers) < total_depth {
+        lower = upper;
+        upper *= 2.;
+    }
+    for _ in 0..100 {
+        let middle = 0.5 * (lower + upper);
+        if geometric_sum(surface_dz, middle, nlayers) < total_depth {
+            lower = middle;
+        } else {
+            upper = middle;
+        }
+    }
+    upper
+}
+
+#[derive(Default)]
+pub struct GeometricTransformBuilder<'a> {
+    hgrid: Option<&'a Hgrid>,
+    etal: Option<&'a f64>,
+    depths: Option<&'a Vec<f64>>,
+    nlevels: Option<&'a Vec<usize>>,
+    a_vqs0: Option<&'a f64>,
+    surface_dz: Option<&'a f64>,
+    growth: Option<&'a f64>,
+}
+
+impl<'a> GeometricTransformBuilder<'a> {
+    pub fn build(&self) -> Result<GeometricTransform, GeometricTransformBuilderError> {
+        let hgrid = self.hgrid.ok_or_else(|| {
+            GeometricTransformBuilderError::UninitializedFieldError("hgrid".to_string())
+        })?;
+        let depths = self.depths.ok_or_else(|| {
+            GeometricTransformBuilderError::UninitializedFieldError("depths".to_string())
+        })?;
+        Self::validate_depths(hgrid, depths)?;
+        let nlevels = self.nlevels.ok_or_else(|| {
+            GeometricTransformBuilderError::UninitializedFieldError("nlevels".to_string())
+        })?;
+        Self::validate_nlevels(nlevels)?;
+        Self::validate_depths_and_nlevels(depths, nlevels)?;
+        let etal = self.etal.ok_or_else(|| {
+            GeometricTransformBuilderError::UninitializedFieldError("etal".to_string())
+        })?;
+        Self::validate_etal(etal, &depths[0])?;
+        let a_vqs0 = self.a_vqs0.ok_or_else(|| {
+            GeometricTransformBuilderError::UninitializedFieldError("a_vqs0".to_string())
+        })?;
+        Self::validate_a_vqs0(a_vqs0)?;
+        let surface_dz = self.surface_dz.ok_or_else(|| {
+            GeometricTransformBuilderError::UninitializedFieldError("surface_dz".to_string())
+        })?;
+        Self::validate_surface_dz(surface_dz)?;
+        let growth = self.growth.ok_or_else(|| {
+            GeometricTransformBuilderError::UninitializedFieldError("growth".to_string())
+        })?;
+        Self::validate_growth(growth)?;
+        let zmas = Self::build_zmas(depths, nlevels, etal, surface_dz, growth);
+        Ok(GeometricTransform {
+            zmas,
+            etal: *etal,
+            a_vqs0: *a_vqs0,
+            surface_dz: *surface_dz,
+            growth: *growth,
+        })
+    }
+
+    pub fn build_zmas(
+        depths: &Vec<f64>,
+        nlevels: &Vec<usize>,
+        etal: &f64,
+        surface_dz: &f64,
+        growth: &f64,
+    ) -> Array2<f64> {
+        let num_grids = depths.len();
+        let max_levels = nlevels.iter().max().unwrap();
+        let mut z_mas = Array2::from_elem((*max_levels, num_grids), NAN);
+        for (m, &depth) in depths.iter().enumerate() {
+            let total_depth = *etal + depth;
+            let sigma = geometric_sigma(total_depth, nlevels[m], *surface_dz, *growth);
+            for (k, sigma) in sigma.iter().enumerate() {
+                z_mas[[k, m]] = *etal + total_depth * sigma;
+            }
+        }
+        z_mas
+    }
+
+    fn validate_depths_and_nlevels(
+        depths: &Vec<f64>,
+        nlevels: &Vec<usize>,
+    ) -> Result<(), GeometricTransformBuilderError> {
+        let depth_len = depths.len();
+        let nlevels_len = nlevels.len();
+        if depth_len != nlevels_len {
+            return Err(GeometricTransformBuilderError::DepthsAndLevelsSizeMismatch(
+                depth_len,
+                nlevels_len,
+            ));
+        }
+        Ok(())
+    }
+
+    fn validate_a_vqs0(a_vqs0: &f64) -> Result<(), GeometricTransformBuilderError> {
+        if *a_vqs0 < -1.0 || *a_vqs0 > 1.0 {
+            return Err(GeometricTransformBuilderError::InvalidAVqs0(*a_vqs0));
+        }
+        Ok(())
+    }
+
+    pub fn validate_etal(etal: &f64, depths0: &f64) -> Result<(), GeometricTransformBuilderError> {
+        if *etal >= *depths0 {
+            return Err(GeometricTransformBuilderError::InvalidEtalValue(
+                *depths0, *etal,
+            ));
+        }
+        Ok(())
+    }
+
+    fn validate_depths(
+        hgrid: &Hgrid,
+        depths: &Vec<f64>,
+    ) -> Result<(), GeometricTransformBuilderError> {
+        let mut prev_depth = depths[0];
+        for &depth in &depths[1..] {
+            if depth <= prev_depth {
+                return Err(GeometricTransformBuilderError::InvalidDepths);
+            }
+            prev_depth = depth;
+        }
+
+        let hgrid_depths = hgrid.depths();
+        let mut min_hgrid_depth = f64::MAX;
+        for &depth in &hgrid_depths {
+            min_hgrid_depth = min_hgrid_depth.min(depth);
+        }
+        let last_depth = depths[depths.len() - 1];
+        if last_depth < -min_hgrid_depth {
+            return Err(GeometricTransformBuilderError::InvalidLastDepth(
+                last_depth,
+                -min_hgrid_depth,
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_surface_dz(surface_dz: &f64) -> Result<(), GeometricTransformBuilderError> {
+        if !(*surface_dz > 0.) || !surface_dz.is_finite() {
+            return Err(GeometricTransformBuilderError::InvalidSurfaceDz(
+                *surface_dz,
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn validate_growth(growth: &f64) -> Result<(), GeometricTransformBuilderError> {
+        if !(*growth >= 1.) || !growth.is_finite() {
+            return Err(GeometricTransformBuilderError::InvalidGrowth(*growth));
+        }
+        Ok(())
+    }
+
+    fn validate_nlevels(nlevels: &Vec<usize>) -> Result<(), GeometricTransformBuilderError> {
+        let mut prev_nlevel = nlevels[0];
+        if prev_nlevel < 2 {
+            return Err(GeometricTransformBuilderError::InvalidFirstLevel);
+        }
+        for &nlevel in &nlevels[1..] {
+            if nlevel < prev_nlevel {
+                return Err(GeometricTransformBuilderError::InvalidNLevels);
+            }
+            prev_nlevel = nlevel;
+        }
+        Ok(())
+    }
+
+    pub fn hgrid(&mut self, hgrid: &'a Hgrid) -> &mut Self {
+        self.hgrid = Some(hgrid);
+        self
+    }
+    pub fn depths(&mut self, depths: &'a Vec<f64>) -> &mut Self {
+        self.depths = Some(depths);
+        self
+    }
+    pub fn nlevels(&mut self, nlevels: &'a Vec<usize>) -> &mut Self {
+        self.nlevels = Some(nlevels);
+        self
+    }
+    pub fn etal(&mut self, etal: &'a f64) -> &mut Self {
+        self.etal = Some(etal);
+        self
+    }
+    pub fn a_vqs0(&mut self, a_vqs0: &'a f64) -> &mut Self {
+        self.a_vqs0 = Some(a_vqs0);
+        self
+    }
+    pub fn surface_dz(&mut self, surface_dz: &'a f64) -> &mut Self {
+        self.surface_dz = Some(surface_dz);
+        self
+    }
+    pub fn growth(&mut self, growth: &'a f64) -> &mut Self {
+        self.growth = Some(growth);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GeometricTransformOpts<'a> {
+    pub etal: &'a f64,
+    pub a_vqs0: &'a f64,
+    pub surface_dz: &'a f64,
+    pub growth: &'a f64,
+}
+
+impl<'a> GeometricTransformOpts<'a> {
+    pub fn new() -> Self {
+        Self {
+            etal: &0.,
+            a_vqs0: &0.,
+            surface_dz: &0.5,
+            growth: &1.15,
+        }
+    }
+    pub fn etal(&mut self, etal: &'a f64) -> &mut Self {
+        self.etal = etal;
+        self
+    }
+    pub fn a_vqs0(&mut self, a_vqs0: &'a f64) -> &mut Self {
+        self.a_vqs0 = a_vqs0;
+        self
+    }
+    pub fn surface_dz(&mut self, surface_dz: &'a f64) -> &mut Self {
+        self.surface_dz = surface_dz;
+        self
+    }
+    pub fn growth(&mut self, growth: &'a f64) -> &mut Self {
+        self.growth = growth;
+        self
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum GeometricTransformBuilderError {
+    #[error("Unitialized field on GeometricTransformBuilder: {0}")]
+    UninitializedFieldError(String),
+    #[error(
+        "depths and nlevels array must be of the same length. Got lengths {0} and {1} respectively"
+    )]
+    DepthsAndLevelsSizeMismatch(usize, usize),
+    #[error("depths vector must be strictly increasing")]
+    InvalidDepths,
+    #[error("First level in nlevels must be >= 2")]
+    InvalidFirstLevel,
+    #[error("nlevels vector must be strictly increasing")]
+    InvalidNLevels,
+    #[error("Last depth provided was {0} but it must be greater or equal than {1} which is the deepest point in hgrid.")]
+    InvalidLastDepth(f64, f64),
+    #[error("a_vqs0 must be < 0 and >= -1, but got {0}")]
+    InvalidAVqs0(f64),
+    #[error("surface_dz must be > 0, but got {0}")]
+    InvalidSurfaceDz(f64),
+    #[error("growth must be >= 1, but got {0}")]
+    InvalidGrowth(f64),
+    #[error("etal must be smaller than the first depth, (which is {0}) but got {1}")]
+    InvalidEtalValue(f64, f64),
+}
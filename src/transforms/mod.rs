@@ -1,6 +1,9 @@
+pub use geometric::GeometricTransform;
 pub use quadratic::QuadraticTransform;
 pub use transforms::StretchingFunction;
+pub mod geometric;
 pub mod quadratic;
 pub mod s;
+pub mod song_haidvogel;
 pub mod traits;
 pub mod transforms;
@@ -8,6 +8,9 @@ pub struct QuadraticTransform {
     zmas: Array2<f64>,
     etal: f64,
     a_vqs0: f64,
+    skew_decay_rate: f64,
+    depths: Vec<f64>,
+    a_vqs: Vec<f64>,
 }
 
 impl Transform for QuadraticTransform {
@@ -20,6 +23,32 @@ impl Transform for QuadraticTransform {
     fn a_vqs0(&self) -> &f64 {
         &self.a_vqs0
     }
+    fn name(&self) -> &'static str {
+        "quadratic"
+    }
+    fn parameters(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("etal", self.etal),
+            ("a_vqs0", self.a_vqs0),
+            ("skew_decay_rate", self.skew_decay_rate),
+        ]
+    }
+    /// Uses the stretching factor of the shallowest master grid at least
+    /// `depth` deep, or of the deepest one below the last master depth.
+    fn sigma_at(&self, depth: f64, nlevels: usize) -> Vec<f64> {
+        let m = self
+            .depths
+            .iter()
+            .position(|&master_depth| depth <= master_depth)
+            .unwrap_or(self.depths.len() - 1);
+        let a = self.a_vqs[m];
+        (0..nlevels)
+            .map(|k| {
+                let sigma = (k as f64) / (1. - nlevels as f64);
+                a * sigma * sigma + (1.0 + a) * sigma
+            })
+            .collect()
+    }
 }
 
 #[derive(Default)]
@@ -59,10 +88,14 @@ impl<'a> QuadraticTransformBuilder<'a> {
         })?;
         // Self::validate_skew_decay_rate
         let zmas = Self::build_zmas(depths, nlevels, etal, a_vqs0, skew_decay_rate);
+        let a_vqs = Self::build_vertical_stretching_factors(depths.len(), a_vqs0, skew_decay_rate);
         Ok(QuadraticTransform {
             zmas,
             etal: *etal,
             a_vqs0: *a_vqs0,
+            skew_decay_rate: *skew_decay_rate,
+            depths: depths.clone(),
+            a_vqs,
         })
     }
 
@@ -94,7 +127,7 @@ impl<'a> QuadraticTransformBuilder<'a> {
                 for col in 0..z_mas.shape()[1] {
                     row_string += &format!("{:12.4} ", z_mas[[row, col]]);
                 }
-                println!("{}", row_string);
+                log::debug!("{}", row_string);
             }
         }
 
@@ -273,6 +306,10 @@ pub enum QuadraticTransformBuilderError {
     InvalidAVqs0(f64),
     #[error("etal must be smaller than the first depth, (which is {0}) but got {1}")]
     InvalidEtalValue(f64, f64),
+    #[error("A surface dz of {0} on a {1} deep water column with {2} levels needs a_vqs0 = {3}, outside of [-1, 1]")]
+    UnreachableSurfaceDz(f64, f64, usize, f64),
+    #[error("The surface dz of a master grid with {0} levels is fixed by its depth, at least 3 levels are needed")]
+    SurfaceDzNeedsThreeLevels(usize),
 }
 
 #[derive(Clone, Debug)]
@@ -302,4 +339,32 @@ impl<'a> QuadraticTransformOpts<'a> {
         self.skew_decay_rate = skew_decay_rate;
         self
     }
+
+    /// Solves for the a_vqs0 giving a surface layer of `dz_surf` on the first
+    /// master grid, where `depth` is its water column (first master depth plus
+    /// etal) and `nlevels` its levels.
+    ///
+    /// The options only borrow their values, so this returns the a_vqs0 for
+    /// [`QuadraticTransformOpts::a_vqs0`] to borrow rather than the options.
+    pub fn from_surface_dz(
+        dz_surf: f64,
+        depth: f64,
+        nlevels: usize,
+    ) -> Result<f64, QuadraticTransformBuilderError> {
+        if nlevels < 3 {
+            return Err(QuadraticTransformBuilderError::SurfaceDzNeedsThreeLevels(
+                nlevels,
+            ));
+        }
+        // the top layer spans sigma in [-u, 0] with u = 1 / (nlevels - 1), so
+        // dz_surf / depth = u + a_vqs0 * u * (1 - u)
+        let u = 1. / (nlevels as f64 - 1.);
+        let a_vqs0 = (dz_surf / depth - u) / (u * (1. - u));
+        if !(-1.0..=1.0).contains(&a_vqs0) {
+            return Err(QuadraticTransformBuilderError::UnreachableSurfaceDz(
+                dz_surf, depth, nlevels, a_vqs0,
+            ));
+        }
+        Ok(a_vqs0)
+    }
 }
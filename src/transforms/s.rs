@@ -10,8 +10,10 @@ pub struct STransform {
     zmas: Array2<f64>,
     etal: f64,
     a_vqs0: f64,
-    _theta_f: f64,
-    _theta_b: f64,
+    theta_f: f64,
+    theta_b: f64,
+    /// The s transform uses the first master depth as its critical depth.
+    hc: f64,
 }
 
 impl Transform for STransform {
@@ -24,6 +26,33 @@ impl Transform for STransform {
     fn a_vqs0(&self) -> &f64 {
         &self.a_vqs0
     }
+    fn name(&self) -> &'static str {
+        "s"
+    }
+    fn parameters(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("etal", self.etal),
+            ("a_vqs0", self.a_vqs0),
+            ("theta_f", self.theta_f),
+            ("theta_b", self.theta_b),
+        ]
+    }
+    fn sigma_at(&self, depth: f64, nlevels: usize) -> Vec<f64> {
+        let total_depth = self.etal + depth;
+        (0..nlevels)
+            .map(|k| {
+                let sigma = (k as f64) / (1. - nlevels as f64);
+                let cs = stretching_curve(sigma, self.theta_f, self.theta_b);
+                (self.etal * sigma + self.hc * sigma + (depth - self.hc) * cs) / total_depth
+            })
+            .collect()
+    }
+}
+
+fn stretching_curve(sigma: f64, theta_f: f64, theta_b: f64) -> f64 {
+    (1. - theta_b) * sinh(theta_f * sigma) / sinh(theta_f)
+        + theta_b * (tanh(theta_f * (sigma + 0.5)) - tanh(theta_f * 0.5))
+            / (2. * tanh(theta_f * 0.5))
 }
 
 #[derive(Default)]
@@ -88,8 +117,9 @@ impl<'a> STransformBuilder<'a> {
             zmas,
             etal: *etal,
             a_vqs0: *a_vqs0,
-            _theta_f: *theta_f,
-            _theta_b: *theta_b,
+            theta_f: *theta_f,
+            theta_b: *theta_b,
+            hc: depths[0],
         })
     }
 
@@ -107,9 +137,7 @@ impl<'a> STransformBuilder<'a> {
             let nlev = nlevels[m];
             for k in 0..nlev {
                 let sigma = (k as f64) / (1. - nlev as f64);
-                let cs = (1. - *theta_b) * sinh(*theta_f * sigma) / sinh(*theta_f)
-                    + *theta_b * (tanh(*theta_f * (sigma + 0.5)) - tanh(*theta_f * 0.5))
-                        / (2. * tanh(*theta_f * 0.5));
+                let cs = stretching_curve(sigma, *theta_f, *theta_b);
                 z_mas[[k, m]] = *etal * (1. + sigma) + depths[0] * sigma + (depth - depths[0]) * cs;
             }
         }
@@ -0,0 +1,405 @@
+use super::traits::Transform;
+use libm::sinh;
+use libm::tanh;
+use ndarray::Array2;
+use schismrs_hgrid::Hgrid;
+use serde::Deserialize;
+use std::f64::NAN;
+use thiserror::Error;
+
+/// What [`SongHaidvogel1994TransformBuilder`] does when hc is deeper than the
+/// first master grid.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HcPolicy {
+    /// Fail with [`SongHaidvogel1994TransformBuilderError::InvalidHc`].
+    #[default]
+    Error,
+    /// Lower hc to the first master grid depth and log a warning.
+    Clamp,
+}
+
+/// Song & Haidvogel (1994) stretching, i.e. ROMS Vtransform=1, Vstretching=1.
+///
+/// z = etal * (1 + s) + hc * s + (h - hc) * C(s), with
+/// C(s) = (1 - theta_b) * sinh(theta_s * s) / sinh(theta_s)
+///      + theta_b * (tanh(theta_s * (s + 0.5)) - tanh(0.5 * theta_s)) / (2 * tanh(0.5 * theta_s))
+pub struct SongHaidvogel1994Transform {
+    zmas: Array2<f64>,
+    etal: f64,
+    a_vqs0: f64,
+    theta_s: f64,
+    theta_b: f64,
+    hc: f64,
+}
+
+impl Transform for SongHaidvogel1994Transform {
+    fn zmas(&self) -> &Array2<f64> {
+        &self.zmas
+    }
+    fn etal(&self) -> &f64 {
+        &self.etal
+    }
+    fn a_vqs0(&self) -> &f64 {
+        &self.a_vqs0
+    }
+    fn name(&self) -> &'static str {
+        "song-haidvogel1994"
+    }
+    fn parameters(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("etal", self.etal),
+            ("a_vqs0", self.a_vqs0),
+            ("theta_s", self.theta_s),
+            ("theta_b", self.theta_b),
+            ("hc", self.hc),
+        ]
+    }
+    fn sigma_at(&self, depth: f64, nlevels: usize) -> Vec<f64> {
+        let total_depth = self.etal + depth;
+        (0..nlevels)
+            .map(|k| {
+                let sigma = (k as f64) / (1. - nlevels as f64);
+                let cs = stretching_curve(sigma, self.theta_s, self.theta_b);
+                (self.etal * sigma + self.hc * sigma + (depth - self.hc) * cs) / total_depth
+            })
+            .collect()
+    }
+}
+
+fn stretching_curve(sigma: f64, theta_s: f64, theta_b: f64) -> f64 {
+    (1. - theta_b) * sinh(theta_s * sigma) / sinh(theta_s)
+        + theta_b * (tanh(theta_s * (sigma + 0.5)) - tanh(theta_s * 0.5))
+            / (2. * tanh(theta_s * 0.5))
+}
+
+#[derive(Default)]
+pub struct SongHaidvogel1994TransformBuilder<'a> {
+    hgrid: Option<&'a Hgrid>,
+    etal: Option<&'a f64>,
+    depths: Option<&'a Vec<f64>>,
+    nlevels: Option<&'a Vec<usize>>,
+    a_vqs0: Option<&'a f64>,
+    theta_s: Option<&'a f64>,
+    theta_b: Option<&'a f64>,
+    hc: Option<&'a f64>,
+    hc_policy: Option<&'a HcPolicy>,
+}
+
+impl<'a> SongHaidvogel1994TransformBuilder<'a> {
+    pub fn build(
+        &self,
+    ) -> Result<SongHaidvogel1994Transform, SongHaidvogel1994TransformBuilderError> {
+        let hgrid = self.hgrid.ok_or_else(|| {
+            SongHaidvogel1994TransformBuilderError::UninitializedFieldError("hgrid".to_string())
+        })?;
+        let depths = self.depths.ok_or_else(|| {
+            SongHaidvogel1994TransformBuilderError::UninitializedFieldError("depths".to_string())
+        })?;
+        Self::validate_depths(hgrid, depths)?;
+        let nlevels = self.nlevels.ok_or_else(|| {
+            SongHaidvogel1994TransformBuilderError::UninitializedFieldError("nlevels".to_string())
+        })?;
+        Self::validate_nlevels(nlevels)?;
+        Self::validate_depths_and_nlevels(depths, nlevels)?;
+        let etal = self.etal.ok_or_else(|| {
+            SongHaidvogel1994TransformBuilderError::UninitializedFieldError("etal".to_string())
+        })?;
+        Self::validate_etal(etal, &depths[0])?;
+        let a_vqs0 = self.a_vqs0.ok_or_else(|| {
+            SongHaidvogel1994TransformBuilderError::UninitializedFieldError("a_vqs0".to_string())
+        })?;
+        Self::validate_a_vqs0(a_vqs0)?;
+        let theta_s = self.theta_s.ok_or_else(|| {
+            SongHaidvogel1994TransformBuilderError::UninitializedFieldError("theta_s".to_string())
+        })?;
+        Self::validate_theta_s(theta_s)?;
+        let theta_b = self.theta_b.ok_or_else(|| {
+            SongHaidvogel1994TransformBuilderError::UninitializedFieldError("theta_b".to_string())
+        })?;
+        Self::validate_theta_b(theta_b)?;
+        let hc = self.hc.ok_or_else(|| {
+            SongHaidvogel1994TransformBuilderError::UninitializedFieldError("hc".to_string())
+        })?;
+        let hc = &Self::resolve_hc(hc, &depths[0], self.hc_policy.cloned().unwrap_or_default())?;
+        let zmas = Self::build_zmas(depths, nlevels, etal, theta_s, theta_b, hc);
+        Ok(SongHaidvogel1994Transform {
+            zmas,
+            etal: *etal,
+            a_vqs0: *a_vqs0,
+            theta_s: *theta_s,
+            theta_b: *theta_b,
+            hc: *hc,
+        })
+    }
+
+    pub fn build_zmas(
+        depths: &Vec<f64>,
+        nlevels: &Vec<usize>,
+        etal: &f64,
+        theta_s: &f64,
+        theta_b: &f64,
+        hc: &f64,
+    ) -> Array2<f64> {
+        let num_grids = depths.len();
+        let max_levels = nlevels.iter().max().unwrap();
+        let mut z_mas = Array2::from_elem((*max_levels, num_grids), NAN);
+        for (m, &depth) in depths.iter().enumerate() {
+            let nlev = nlevels[m];
+            for k in 0..nlev {
+                let sigma = (k as f64) / (1. - nlev as f64);
+                let cs = stretching_curve(sigma, *theta_s, *theta_b);
+                z_mas[[k, m]] = *etal * (1. + sigma) + *hc * sigma + (depth - *hc) * cs;
+            }
+        }
+        z_mas
+    }
+
+    fn validate_depths_and_nlevels(
+        depths: &Vec<f64>,
+        nlevels: &Vec<usize>,
+    ) -> Result<(), SongHaidvogel1994TransformBuilderError> {
+        let depth_len = depths.len();
+        let nlevels_len = nlevels.len();
+        if depth_len != nlevels_len {
+            return Err(
+                SongHaidvogel1994TransformBuilderError::DepthsAndLevelsSizeMismatch(
+                    depth_len,
+                    nlevels_len,
+                ),
+            );
+        }
+        Ok(())
+    }
+
+    fn validate_a_vqs0(a_vqs0: &f64) -> Result<(), SongHaidvogel1994TransformBuilderError> {
+        if *a_vqs0 < -1.0 || *a_vqs0 > 1.0 {
+            return Err(SongHaidvogel1994TransformBuilderError::InvalidAVqs0(
+                *a_vqs0,
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn validate_etal(
+        etal: &f64,
+        depths0: &f64,
+    ) -> Result<(), SongHaidvogel1994TransformBuilderError> {
+        if *etal >= *depths0 {
+            return Err(SongHaidvogel1994TransformBuilderError::InvalidEtalValue(
+                *depths0, *etal,
+            ));
+        }
+        Ok(())
+    }
+
+    fn validate_depths(
+        hgrid: &Hgrid,
+        depths: &Vec<f64>,
+    ) -> Result<(), SongHaidvogel1994TransformBuilderError> {
+        let mut prev_depth = depths[0];
+        for &depth in &depths[1..] {
+            if depth <= prev_depth {
+                return Err(SongHaidvogel1994TransformBuilderError::InvalidDepths);
+            }
+            prev_depth = depth;
+        }
+
+        let hgrid_depths = hgrid.depths();
+        let mut min_hgrid_depth = f64::MAX;
+        for &depth in &hgrid_depths {
+            min_hgrid_depth = min_hgrid_depth.min(depth);
+        }
+        let last_depth = depths[depths.len() - 1];
+        if last_depth < -min_hgrid_depth {
+            return Err(SongHaidvogel1994TransformBuilderError::InvalidLastDepth(
+                last_depth,
+                -min_hgrid_depth,
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_theta_s(theta_s: &f64) -> Result<(), SongHaidvogel1994TransformBuilderError> {
+        if *theta_s <= 0. || *theta_s > 20. {
+            return Err(SongHaidvogel1994TransformBuilderError::InvalidThetaS(
+                *theta_s,
+            ));
+        };
+        Ok(())
+    }
+
+    pub fn validate_theta_b(theta_b: &f64) -> Result<(), SongHaidvogel1994TransformBuilderError> {
+        if !(0.0 <= *theta_b && *theta_b <= 1.0) {
+            return Err(SongHaidvogel1994TransformBuilderError::InvalidThetaB(
+                *theta_b,
+            ));
+        };
+        Ok(())
+    }
+
+    /// ROMS Vtransform=1 requires hc <= the shallowest depth, otherwise (h - hc)
+    /// turns negative and the stretched columns fold over.
+    pub fn validate_hc(
+        hc: &f64,
+        depths0: &f64,
+    ) -> Result<(), SongHaidvogel1994TransformBuilderError> {
+        if *hc <= 0. || *hc > *depths0 {
+            return Err(SongHaidvogel1994TransformBuilderError::InvalidHc(
+                *depths0, *hc,
+            ));
+        }
+        Ok(())
+    }
+
+    /// hc to build with under `policy`: hc itself when valid, the first depth
+    /// when a deeper hc is clamped.
+    pub fn resolve_hc(
+        hc: &f64,
+        depths0: &f64,
+        policy: HcPolicy,
+    ) -> Result<f64, SongHaidvogel1994TransformBuilderError> {
+        if policy == HcPolicy::Clamp && *hc > *depths0 {
+            log::warn!(
+                "hc={} is deeper than the first master grid, clamping it to {}",
+                hc,
+                depths0
+            );
+            Self::validate_hc(depths0, depths0)?;
+            return Ok(*depths0);
+        }
+        Self::validate_hc(hc, depths0)?;
+        Ok(*hc)
+    }
+
+    fn validate_nlevels(
+        nlevels: &Vec<usize>,
+    ) -> Result<(), SongHaidvogel1994TransformBuilderError> {
+        let mut prev_nlevel = nlevels[0];
+        if prev_nlevel < 2 {
+            return Err(SongHaidvogel1994TransformBuilderError::InvalidFirstLevel);
+        }
+        for &nlevel in &nlevels[1..] {
+            if nlevel < prev_nlevel {
+                return Err(SongHaidvogel1994TransformBuilderError::InvalidNLevels);
+            }
+            prev_nlevel = nlevel;
+        }
+        Ok(())
+    }
+
+    pub fn hgrid(&mut self, hgrid: &'a Hgrid) -> &mut Self {
+        self.hgrid = Some(hgrid);
+        self
+    }
+    pub fn depths(&mut self, depths: &'a Vec<f64>) -> &mut Self {
+        self.depths = Some(depths);
+        self
+    }
+    pub fn nlevels(&mut self, nlevels: &'a Vec<usize>) -> &mut Self {
+        self.nlevels = Some(nlevels);
+        self
+    }
+    pub fn etal(&mut self, etal: &'a f64) -> &mut Self {
+        self.etal = Some(etal);
+        self
+    }
+    pub fn a_vqs0(&mut self, a_vqs0: &'a f64) -> &mut Self {
+        self.a_vqs0 = Some(a_vqs0);
+        self
+    }
+    pub fn theta_s(&mut self, theta_s: &'a f64) -> &mut Self {
+        self.theta_s = Some(theta_s);
+        self
+    }
+    pub fn theta_b(&mut self, theta_b: &'a f64) -> &mut Self {
+        self.theta_b = Some(theta_b);
+        self
+    }
+    pub fn hc(&mut self, hc: &'a f64) -> &mut Self {
+        self.hc = Some(hc);
+        self
+    }
+    /// Whether an hc deeper than the first master grid fails the build (the
+    /// default) or is clamped to it.
+    pub fn hc_policy(&mut self, hc_policy: &'a HcPolicy) -> &mut Self {
+        self.hc_policy = Some(hc_policy);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SongHaidvogel1994TransformOpts<'a> {
+    pub etal: &'a f64,
+    pub a_vqs0: &'a f64,
+    pub theta_s: &'a f64,
+    pub theta_b: &'a f64,
+    pub hc: &'a f64,
+    pub hc_policy: &'a HcPolicy,
+}
+
+impl<'a> SongHaidvogel1994TransformOpts<'a> {
+    pub fn new() -> Self {
+        Self {
+            etal: &0.,
+            a_vqs0: &0.,
+            theta_s: &5.,
+            theta_b: &0.4,
+            hc: &5.,
+            hc_policy: &HcPolicy::Error,
+        }
+    }
+    pub fn etal(&mut self, etal: &'a f64) -> &mut Self {
+        self.etal = etal;
+        self
+    }
+    pub fn a_vqs0(&mut self, a_vqs0: &'a f64) -> &mut Self {
+        self.a_vqs0 = a_vqs0;
+        self
+    }
+    pub fn theta_s(&mut self, theta_s: &'a f64) -> &mut Self {
+        self.theta_s = theta_s;
+        self
+    }
+    pub fn theta_b(&mut self, theta_b: &'a f64) -> &mut Self {
+        self.theta_b = theta_b;
+        self
+    }
+    pub fn hc(&mut self, hc: &'a f64) -> &mut Self {
+        self.hc = hc;
+        self
+    }
+    pub fn hc_policy(&mut self, hc_policy: &'a HcPolicy) -> &mut Self {
+        self.hc_policy = hc_policy;
+        self
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SongHaidvogel1994TransformBuilderError {
+    #[error("Unitialized field on SongHaidvogel1994TransformBuilder: {0}")]
+    UninitializedFieldError(String),
+    #[error(
+        "depths and nlevels array must be of the same length. Got lengths {0} and {1} respectively"
+    )]
+    DepthsAndLevelsSizeMismatch(usize, usize),
+    #[error("depths vector must be strictly increasing")]
+    InvalidDepths,
+    #[error("First level in nlevels must be >= 2")]
+    InvalidFirstLevel,
+    #[error("nlevels vector must be strictly increasing")]
+    InvalidNLevels,
+    #[error("Last depth provided was {0} but it must be greater or equal than {1} which is the deepest point in hgrid.")]
+    InvalidLastDepth(f64, f64),
+    #[error("a_vqs0 must be < 0 and >= -1, but got {0}")]
+    InvalidAVqs0(f64),
+    #[error("theta_s must be larger than 0, and smaller or equal to 20., but got {0}")]
+    InvalidThetaS(f64),
+    #[error("theta_b must be in [0., 1.], but got {0}")]
+    InvalidThetaB(f64),
+    #[error("hc must be > 0 and <= the first depth (which is {0}) but got {1}")]
+    InvalidHc(f64, f64),
+    #[error("etal must be smaller than the first depth, (which is {0}) but got {1}")]
+    InvalidEtalValue(f64, f64),
+}
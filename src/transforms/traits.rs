@@ -12,24 +12,39 @@ pub trait Transform {
     fn etal(&self) -> &f64;
     fn a_vqs0(&self) -> &f64;
 
+    /// Name of the stretching, as accepted by gen_vqs --transform.
+    fn name(&self) -> &'static str;
+
+    /// Parameters of the stretching, by name.
+    fn parameters(&self) -> Vec<(&'static str, f64)>;
+
+    /// Sigma of the `nlevels` levels of a column `depth` deep (positive down)
+    /// under this stretching, from the surface (0) to the bottom (-1).
+    fn sigma_at(&self, depth: f64, nlevels: usize) -> Vec<f64>;
+
     fn make_zmas_plot(&self) -> Result<Plot, TransformPlotterError> {
-        let z_mas = self.zmas();
-        let mut plot = Plot::new();
-        for master_grid in z_mas.axis_iter(Axis(1)) {
-            let master_grid = master_grid
-                .iter()
-                .filter(|&&x| !x.is_nan())
-                .cloned()
-                .collect::<Array1<f64>>();
-            let min_value = *master_grid.min()?;
-            let trace = Scatter::new(vec![min_value; master_grid.len()], master_grid.to_vec())
-                .mode(Mode::LinesMarkers)
-                .line(Line::new().color(NamedColor::Blue))
-                .marker(Marker::new().color(NamedColor::Black));
-            plot.add_trace(trace);
-        }
-        Ok(plot)
+        zmas_plot(self.zmas())
+    }
+}
+
+/// Plots each master grid of `z_mas` (levels from the surface down, one column
+/// per master grid) as a vertical line of levels located at its bottom.
+pub fn zmas_plot(z_mas: &Array2<f64>) -> Result<Plot, TransformPlotterError> {
+    let mut plot = Plot::new();
+    for master_grid in z_mas.axis_iter(Axis(1)) {
+        let master_grid = master_grid
+            .iter()
+            .filter(|&&x| !x.is_nan())
+            .cloned()
+            .collect::<Array1<f64>>();
+        let min_value = *master_grid.min()?;
+        let trace = Scatter::new(vec![min_value; master_grid.len()], master_grid.to_vec())
+            .mode(Mode::LinesMarkers)
+            .line(Line::new().color(NamedColor::Blue))
+            .marker(Marker::new().color(NamedColor::Black));
+        plot.add_trace(trace);
     }
+    Ok(plot)
 }
 
 #[derive(Error, Debug)]
@@ -1,9 +1,15 @@
+use super::geometric::GeometricTransformBuilder;
+use super::geometric::GeometricTransformBuilderError;
+use super::geometric::GeometricTransformOpts;
 use super::quadratic::QuadraticTransformBuilder;
 use super::quadratic::QuadraticTransformBuilderError;
 use super::quadratic::QuadraticTransformOpts;
 use super::s::STransformBuilder;
 use super::s::STransformBuilderError;
 use super::s::STransformOpts;
+use super::song_haidvogel::SongHaidvogel1994TransformBuilder;
+use super::song_haidvogel::SongHaidvogel1994TransformBuilderError;
+use super::song_haidvogel::SongHaidvogel1994TransformOpts;
 use super::traits::Transform;
 use schismrs_hgrid::Hgrid;
 use std::rc::Rc;
@@ -13,6 +19,8 @@ use thiserror::Error;
 pub enum StretchingFunction<'a> {
     Quadratic(QuadraticTransformOpts<'a>),
     S(STransformOpts<'a>),
+    SongHaidvogel1994(SongHaidvogel1994TransformOpts<'a>),
+    Geometric(GeometricTransformOpts<'a>),
 }
 
 impl<'a> StretchingFunction<'a> {
@@ -20,6 +28,8 @@ impl<'a> StretchingFunction<'a> {
         match self {
             StretchingFunction::Quadratic(opts) => opts.etal,
             StretchingFunction::S(opts) => opts.etal,
+            StretchingFunction::SongHaidvogel1994(opts) => opts.etal,
+            StretchingFunction::Geometric(opts) => opts.etal,
         }
     }
     pub fn transform(
@@ -50,6 +60,30 @@ impl<'a> StretchingFunction<'a> {
                     .theta_b(opts.theta_b)
                     .build()?,
             )),
+            StretchingFunction::SongHaidvogel1994(opts) => Ok(Rc::new(
+                SongHaidvogel1994TransformBuilder::default()
+                    .hgrid(hgrid)
+                    .depths(depths)
+                    .nlevels(nlevels)
+                    .etal(opts.etal)
+                    .a_vqs0(opts.a_vqs0)
+                    .theta_s(opts.theta_s)
+                    .theta_b(opts.theta_b)
+                    .hc(opts.hc)
+                    .hc_policy(opts.hc_policy)
+                    .build()?,
+            )),
+            StretchingFunction::Geometric(opts) => Ok(Rc::new(
+                GeometricTransformBuilder::default()
+                    .hgrid(hgrid)
+                    .depths(depths)
+                    .nlevels(nlevels)
+                    .etal(opts.etal)
+                    .a_vqs0(opts.a_vqs0)
+                    .surface_dz(opts.surface_dz)
+                    .growth(opts.growth)
+                    .build()?,
+            )),
         }
     }
 }
@@ -60,4 +94,8 @@ pub enum StretchingFunctionError {
     STransformBuilderError(#[from] STransformBuilderError),
     #[error(transparent)]
     QuadraticTransformBuilderError(#[from] QuadraticTransformBuilderError),
+    #[error(transparent)]
+    SongHaidvogel1994TransformBuilderError(#[from] SongHaidvogel1994TransformBuilderError),
+    #[error(transparent)]
+    GeometricTransformBuilderError(#[from] GeometricTransformBuilderError),
 }
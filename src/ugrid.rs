@@ -0,0 +1,139 @@
+use crate::mesh::element_connectivity;
+use crate::vqs::VQS;
+use ndarray::{Array1, Array2};
+use schismrs_hgrid::hgrid::Hgrid;
+use std::f64::NAN;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Name of the UGRID mesh topology variable.
+const MESH: &str = "mesh";
+
+/// Writes `vqs` on the mesh of `hgrid` following the UGRID 1.0 conventions for
+/// layered 2D meshes, so other unstructured model tooling can read it.
+///
+/// The layer interfaces are the vgrid.in levels, bottom to surface, and their
+/// sigma varies per node as an `ocean_sigma_coordinate` with NaN below the
+/// bottom of each node.
+pub fn write_ugrid_vertical(
+    vqs: &VQS,
+    hgrid: &Hgrid,
+    filename: &PathBuf,
+) -> Result<(), UgridError> {
+    let np = vqs.sigma().ncols();
+    let elements = element_connectivity(hgrid);
+    let max_face_nodes = elements.iter().map(|nodes| nodes.len()).max().unwrap_or(3);
+    let mut file = netcdf::create(filename)?;
+    file.add_dimension("nmesh_node", np)?;
+    file.add_dimension("nmesh_face", elements.len())?;
+    file.add_dimension("max_nmesh_face_nodes", max_face_nodes)?;
+    file.add_dimension("nmesh_interfaces", vqs.nvrt())?;
+    file.add_dimension("nmesh_layers", vqs.nvrt() - 1)?;
+    file.add_attribute("Conventions", "CF-1.8 UGRID-1.0")?;
+    file.add_attribute("title", "SCHISM LSC2 vertical grid")?;
+    let mut mesh = file.add_variable::<i32>(MESH, &[])?;
+    mesh.put_attribute("cf_role", "mesh_topology")?;
+    mesh.put_attribute("topology_dimension", 2)?;
+    mesh.put_attribute("node_coordinates", "mesh_node_x mesh_node_y")?;
+    mesh.put_attribute("face_node_connectivity", "mesh_face_nodes")?;
+    mesh.put_attribute("face_dimension", "nmesh_face")?;
+    mesh.put_attribute("layer_dimension", "nmesh_layers")?;
+    mesh.put_attribute("interface_dimension", "nmesh_interfaces")?;
+    mesh.put_attribute("vertical_coordinate", "mesh_interface_sigma")?;
+    let mut x = file.add_variable::<f64>("mesh_node_x", &["nmesh_node"])?;
+    x.put_attribute("standard_name", "projection_x_coordinate")?;
+    x.put_values(hgrid.x().to_vec().as_slice(), ..)?;
+    let mut y = file.add_variable::<f64>("mesh_node_y", &["nmesh_node"])?;
+    y.put_attribute("standard_name", "projection_y_coordinate")?;
+    y.put_values(hgrid.y().to_vec().as_slice(), ..)?;
+    let mut faces =
+        file.add_variable::<i32>("mesh_face_nodes", &["nmesh_face", "max_nmesh_face_nodes"])?;
+    faces.put_attribute("cf_role", "face_node_connectivity")?;
+    faces.put_attribute("start_index", 0)?;
+    faces.set_fill_value(-1)?;
+    let mut values = Vec::with_capacity(elements.len() * max_face_nodes);
+    for nodes in elements.iter() {
+        values.extend(nodes.iter().map(|&node| node as i32));
+        values.extend(std::iter::repeat(-1).take(max_face_nodes - nodes.len()));
+    }
+    faces.put_values(&values, ..)?;
+    let mut etal = file.add_variable::<f64>("mesh_etal", &["nmesh_node"])?;
+    etal.put_attribute("long_name", "elevation the vertical grid was designed at")?;
+    etal.put_attribute("units", "m")?;
+    etal.put_attribute("mesh", MESH)?;
+    etal.put_attribute("location", "node")?;
+    let values: Vec<f64> = (0..np).map(|node| vqs.etal_at(node)).collect();
+    etal.put_values(&values, ..)?;
+    let mut depth = file.add_variable::<f64>("mesh_depth", &["nmesh_node"])?;
+    depth.put_attribute("standard_name", "sea_floor_depth_below_geoid")?;
+    depth.put_attribute("units", "m")?;
+    depth.put_attribute("positive", "down")?;
+    depth.put_attribute("mesh", MESH)?;
+    depth.put_attribute("location", "node")?;
+    depth.put_values(vqs.depths().to_vec().as_slice(), ..)?;
+    let mut sigma =
+        file.add_variable::<f64>("mesh_interface_sigma", &["nmesh_interfaces", "nmesh_node"])?;
+    sigma.put_attribute("standard_name", "ocean_sigma_coordinate")?;
+    sigma.put_attribute("positive", "up")?;
+    sigma.put_attribute(
+        "formula_terms",
+        "sigma: mesh_interface_sigma eta: mesh_etal depth: mesh_depth",
+    )?;
+    sigma.put_attribute("mesh", MESH)?;
+    sigma.put_attribute("location", "node")?;
+    sigma.set_fill_value(NAN)?;
+    let values: Vec<f64> = vqs.sigma().iter().cloned().collect();
+    sigma.put_values(&values, ..)?;
+    Ok(())
+}
+
+/// Reads a vertical grid written by [`write_ugrid_vertical`], or by any tool
+/// following the same layout, for the nodes of `hgrid`.
+pub fn read_ugrid_vertical(hgrid: &Hgrid, filename: &PathBuf) -> Result<VQS, UgridError> {
+    let file = netcdf::open(filename)?;
+    let variable = |name: &str| {
+        file.variable(name)
+            .ok_or_else(|| UgridError::MissingVariable(name.to_string()))
+    };
+    let sigma = variable("mesh_interface_sigma")?;
+    let shape: Vec<usize> = sigma.dimensions().iter().map(|dim| dim.len()).collect();
+    let (nvrt, np) = match shape.as_slice() {
+        [nvrt, np] => (*nvrt, *np),
+        _ => return Err(UgridError::InvalidShape(shape)),
+    };
+    let hgrid_np = hgrid.depths().len();
+    if np != hgrid_np {
+        return Err(UgridError::NodeCountMismatch(np, hgrid_np));
+    }
+    let sigma = Array2::from_shape_vec((nvrt, np), sigma.get_values::<f64, _>(..)?)
+        .map_err(|_| UgridError::InvalidShape(shape))?;
+    let depths = Array1::from_vec(variable("mesh_depth")?.get_values::<f64, _>(..)?);
+    // mesh_etal is per node, but older files and other writers store a scalar
+    let etal: Vec<f64> = match variable("mesh_etal") {
+        Ok(etal) => etal.get_values::<f64, _>(..)?,
+        Err(_) => vec![0.],
+    };
+    let etal_field = match etal.as_slice() {
+        [_] => None,
+        values if values.len() == np => Some(Array1::from_vec(values.to_vec()))
+            .filter(|field| field.iter().any(|&value| value != field[0])),
+        _ => return Err(UgridError::InvalidEtalSize(etal.len(), np)),
+    };
+    // other writers mark the levels below the bottom with a large fill value
+    let sigma = sigma.mapv(|value| if value.abs() > 1. { NAN } else { value });
+    Ok(VQS::from_sigma(sigma, depths, etal[0]).with_etal_field(etal_field))
+}
+
+#[derive(Error, Debug)]
+pub enum UgridError {
+    #[error(transparent)]
+    NetcdfError(#[from] netcdf::Error),
+    #[error("Variable {0} not found")]
+    MissingVariable(String),
+    #[error("mesh_interface_sigma must be (nmesh_interfaces, nmesh_node) but got shape {0:?}")]
+    InvalidShape(Vec<usize>),
+    #[error("The file has {0} nodes but the hgrid has {1}")]
+    NodeCountMismatch(usize, usize),
+    #[error("mesh_etal must hold 1 or {1} values but holds {0}")]
+    InvalidEtalSize(usize, usize),
+}
@@ -0,0 +1,388 @@
+use crate::checks::CheckError;
+use crate::vgrid_file::VgridFile;
+use crate::vqs::VQS;
+use ndarray::Array2;
+use std::fmt;
+
+/// Maximum number of offending node ids listed per check when displaying a report.
+const MAX_LISTED_NODES: usize = 20;
+
+/// Tolerance on sigma values read from fixed-width files.
+pub const SIGMA_TOLERANCE: f64 = 1e-5;
+
+#[derive(Debug)]
+pub struct ValidationCheck {
+    name: &'static str,
+    offending_nodes: Vec<usize>,
+}
+
+impl ValidationCheck {
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    /// 1-based ids of the nodes failing this check.
+    pub fn offending_nodes(&self) -> &Vec<usize> {
+        &self.offending_nodes
+    }
+
+    pub fn passed(&self) -> bool {
+        self.offending_nodes.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct ValidationReport {
+    checks: Vec<ValidationCheck>,
+}
+
+impl ValidationReport {
+    pub fn checks(&self) -> &Vec<ValidationCheck> {
+        &self.checks
+    }
+
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed())
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in self.checks.iter() {
+            if check.passed() {
+                write!(f, "[PASS] {}\n", check.name)?;
+                continue;
+            }
+            let listed = check
+                .offending_nodes
+                .iter()
+                .take(MAX_LISTED_NODES)
+                .map(|node| node.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            write!(
+                f,
+                "[FAIL] {}: {} nodes: {}",
+                check.name,
+                check.offending_nodes.len(),
+                listed
+            )?;
+            if check.offending_nodes.len() > MAX_LISTED_NODES {
+                write!(f, ", ...")?;
+            }
+            write!(f, "\n")?;
+        }
+        if self.passed() {
+            write!(f, "PASS\n")
+        } else {
+            write!(f, "FAIL\n")
+        }
+    }
+}
+
+/// Runs the battery of consistency checks SCHISM relies on over a vertical grid.
+pub fn validate(vqs: &VQS, dz_bottom_min: &f64) -> ValidationReport {
+    let depths = vqs.depths();
+    let nvrt = vqs.nvrt();
+    let mut monotonic = Vec::new();
+    let mut bounds = Vec::new();
+    let mut min_levels = Vec::new();
+    let mut inverted_z = Vec::new();
+    let mut bottom_layer = Vec::new();
+    let sigma = vqs.sigma();
+    for (i, &kbp) in vqs.bottom_level_indices().iter().enumerate() {
+        let node_id = i + 1;
+        if check_node_levels(nvrt, i, kbp).is_err() {
+            min_levels.push(node_id);
+            continue;
+        }
+        if check_node_sigma_bounds(sigma, i, kbp).is_err() {
+            bounds.push(node_id);
+        }
+        if check_node_monotone_sigma(sigma, i, kbp).is_err() {
+            monotonic.push(node_id);
+        }
+        let column = vqs.sigma_column(i).into_bottom_up();
+        if column.len() < 2 {
+            min_levels.push(node_id);
+            continue;
+        }
+        let etal = vqs.etal_at(i);
+        let total_depth = etal + depths[i];
+        let z: Vec<f64> = column
+            .iter()
+            .map(|value| value * total_depth + etal)
+            .collect();
+        if total_depth <= 0. || !z.windows(2).all(|pair| pair[0] < pair[1]) {
+            inverted_z.push(node_id);
+        }
+        if z[1] - z[0] < *dz_bottom_min {
+            bottom_layer.push(node_id);
+        }
+    }
+    ValidationReport {
+        checks: vec![
+            ValidationCheck {
+                name: "sigma is monotonic from bottom to surface",
+                offending_nodes: monotonic,
+            },
+            ValidationCheck {
+                name: "sigma spans [-1, 0] from kbp to nvrt",
+                offending_nodes: bounds,
+            },
+            ValidationCheck {
+                name: "every node has at least 2 levels",
+                offending_nodes: min_levels,
+            },
+            ValidationCheck {
+                name: "z is not inverted",
+                offending_nodes: inverted_z,
+            },
+            ValidationCheck {
+                name: "bottom layer is at least dz_bottom_min thick",
+                offending_nodes: bottom_layer,
+            },
+        ],
+    }
+}
+
+/// Node `i` (0-based) has its bottom level `kbp` in [1, nvrt - 1], i.e. at
+/// least two levels.
+pub(crate) fn check_node_levels(nvrt: usize, i: usize, kbp: usize) -> Result<(), CheckError> {
+    if kbp < 1 || kbp >= nvrt {
+        return Err(CheckError::KbpOutOfBounds(i + 1, kbp, nvrt));
+    }
+    Ok(())
+}
+
+/// Sigma of node `i` is -1 at its bottom level `kbp` and 0 at the surface.
+pub(crate) fn check_node_sigma_bounds(
+    sigma: &Array2<f64>,
+    i: usize,
+    kbp: usize,
+) -> Result<(), CheckError> {
+    let nvrt = sigma.nrows();
+    if kbp < 1 || kbp > nvrt {
+        return Err(CheckError::KbpOutOfBounds(i + 1, kbp, nvrt));
+    }
+    // written so that NaN fails
+    let bottom = sigma[[kbp - 1, i]];
+    if !((bottom + 1.).abs() <= SIGMA_TOLERANCE) {
+        return Err(CheckError::BottomNotMinusOne(i + 1, bottom));
+    }
+    let surface = sigma[[nvrt - 1, i]];
+    if !(surface.abs() <= SIGMA_TOLERANCE) {
+        return Err(CheckError::SurfaceNotZero(i + 1, surface));
+    }
+    Ok(())
+}
+
+/// Sigma of node `i` strictly increases from its bottom level `kbp` to the
+/// surface, with no NaN in between.
+pub(crate) fn check_node_monotone_sigma(
+    sigma: &Array2<f64>,
+    i: usize,
+    kbp: usize,
+) -> Result<(), CheckError> {
+    for level in kbp.max(1) + 1..=sigma.nrows() {
+        let (below, above) = (sigma[[level - 2, i]], sigma[[level - 1, i]]);
+        if !(above > below) {
+            return Err(CheckError::NonMonotoneSigma(i + 1, level, below, above));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the bottom level index of each node in `file` agrees with its
+/// sigma, i.e. that sigma runs from -1 at kbp to 0 at nvrt without repeated
+/// or decreasing levels.
+///
+/// A kbp off by one against the sigma records parses fine but shows up here:
+/// too high and the column does not reach -1, too low and it picks up the
+/// fill value of the level below the bottom (-9, or a repeated -1 in files
+/// from some older tools).
+pub fn check_bottom_levels(file: &VgridFile) -> ValidationReport {
+    let sigma = file.sigma();
+    let nvrt = file.nvrt();
+    let mut bottom = Vec::new();
+    let mut surface = Vec::new();
+    let mut bounds = Vec::new();
+    let mut duplicates = Vec::new();
+    let mut monotonic = Vec::new();
+    for (i, &kbp) in file.kbp().iter().enumerate() {
+        let node_id = i + 1;
+        let column: Vec<f64> = (kbp - 1..nvrt).map(|row| sigma[[row, i]]).collect();
+        // written so that NaN fails
+        if !((column[0] + 1.).abs() <= SIGMA_TOLERANCE) {
+            bottom.push(node_id);
+        }
+        if !(column[column.len() - 1].abs() <= SIGMA_TOLERANCE) {
+            surface.push(node_id);
+        }
+        if !column
+            .iter()
+            .all(|&value| value >= -1. - SIGMA_TOLERANCE && value <= SIGMA_TOLERANCE)
+        {
+            bounds.push(node_id);
+        }
+        if column
+            .windows(2)
+            .any(|pair| (pair[1] - pair[0]).abs() <= SIGMA_TOLERANCE)
+        {
+            duplicates.push(node_id);
+        }
+        if column
+            .windows(2)
+            .any(|pair| pair[1] - pair[0] < -SIGMA_TOLERANCE)
+        {
+            monotonic.push(node_id);
+        }
+    }
+    ValidationReport {
+        checks: vec![
+            ValidationCheck {
+                name: "sigma is -1 at kbp",
+                offending_nodes: bottom,
+            },
+            ValidationCheck {
+                name: "sigma is 0 at nvrt",
+                offending_nodes: surface,
+            },
+            ValidationCheck {
+                name: "sigma lies in [-1, 0] from kbp to nvrt",
+                offending_nodes: bounds,
+            },
+            ValidationCheck {
+                name: "no two levels share a sigma",
+                offending_nodes: duplicates,
+            },
+            ValidationCheck {
+                name: "sigma does not decrease from kbp to nvrt",
+                offending_nodes: monotonic,
+            },
+        ],
+    }
+}
+
+/// Layer thinning and inversion statistics of a grid at a single surface elevation.
+pub struct EtalRobustness {
+    etal: f64,
+    min_dz: f64,
+    min_dz_node: Option<usize>,
+    thin_nodes: Vec<usize>,
+    dry_nodes: Vec<usize>,
+}
+
+impl EtalRobustness {
+    pub fn etal(&self) -> &f64 {
+        &self.etal
+    }
+
+    /// Thinnest layer found over all wet nodes.
+    pub fn min_dz(&self) -> &f64 {
+        &self.min_dz
+    }
+
+    /// 1-based id of the node holding the thinnest layer.
+    pub fn min_dz_node(&self) -> Option<usize> {
+        self.min_dz_node
+    }
+
+    /// 1-based ids of wet nodes with at least one layer thinner than the threshold.
+    pub fn thin_nodes(&self) -> &Vec<usize> {
+        &self.thin_nodes
+    }
+
+    /// 1-based ids of nodes whose column inverts (etal + h <= 0).
+    pub fn dry_nodes(&self) -> &Vec<usize> {
+        &self.dry_nodes
+    }
+}
+
+/// Evaluation of a grid, designed at a single etal, over a range of surface elevations.
+pub struct EtalRobustnessReport {
+    min_dz_threshold: f64,
+    results: Vec<EtalRobustness>,
+}
+
+impl EtalRobustnessReport {
+    pub fn results(&self) -> &Vec<EtalRobustness> {
+        &self.results
+    }
+}
+
+impl fmt::Display for EtalRobustnessReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>10} {:>14} {:>10} {:>12} {:>12}\n",
+            "etal", "min_dz", "node", "thin nodes", "dry nodes"
+        )?;
+        for result in self.results.iter() {
+            let node = match result.min_dz_node {
+                Some(node) => node.to_string(),
+                None => "-".to_string(),
+            };
+            write!(
+                f,
+                "{:>10.3} {:>14.6} {:>10} {:>12} {:>12}\n",
+                result.etal,
+                result.min_dz,
+                node,
+                result.thin_nodes.len(),
+                result.dry_nodes.len()
+            )?;
+        }
+        write!(
+            f,
+            "thin nodes have at least one layer thinner than {}\n",
+            self.min_dz_threshold
+        )
+    }
+}
+
+/// Evaluates the layer thicknesses of `vqs` at each surface elevation in `etals`.
+///
+/// Grids are usually designed at etal=0 but run across the full tidal and
+/// surge range, where layers thin out (falling water) or columns invert once
+/// the node dries.
+pub fn etal_robustness(vqs: &VQS, etals: &Vec<f64>, min_dz: &f64) -> EtalRobustnessReport {
+    let sigma = vqs.sigma();
+    let depths = vqs.depths();
+    let nvrt = vqs.nvrt();
+    let bottom_level_indices = vqs.bottom_level_indices();
+    let mut results = Vec::with_capacity(etals.len());
+    for &etal in etals.iter() {
+        let mut result = EtalRobustness {
+            etal,
+            min_dz: f64::INFINITY,
+            min_dz_node: None,
+            thin_nodes: Vec::new(),
+            dry_nodes: Vec::new(),
+        };
+        for (i, &kbp) in bottom_level_indices.iter().enumerate() {
+            let total_depth = etal + depths[i];
+            if total_depth <= 0. {
+                result.dry_nodes.push(i + 1);
+                continue;
+            }
+            let mut node_min_dz = f64::INFINITY;
+            for row in kbp - 1..nvrt - 1 {
+                let dz = (sigma[[row + 1, i]] - sigma[[row, i]]) * total_depth;
+                node_min_dz = node_min_dz.min(dz);
+            }
+            if node_min_dz < *min_dz {
+                result.thin_nodes.push(i + 1);
+            }
+            if node_min_dz < result.min_dz {
+                result.min_dz = node_min_dz;
+                result.min_dz_node = Some(i + 1);
+            }
+        }
+        results.push(result);
+    }
+    EtalRobustnessReport {
+        min_dz_threshold: *min_dz,
+        results,
+    }
+}
@@ -0,0 +1,217 @@
+use crate::metadata::VgridMetadata;
+use crate::progress::{NoProgress, ProgressReporter, PROGRESS_INTERVAL};
+use crate::vqs::VQSLoadError;
+use crate::writer::{is_gzip_path, VgridFormat};
+use flate2::read::GzDecoder;
+use ndarray::Array2;
+use std::f64::NAN;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Raw contents of an ivcor=1 vgrid.in, read without the matching hgrid.
+///
+/// The node count is inferred from the file itself, so a vgrid.in can be
+/// inspected or converted when its hgrid is not at hand. Use
+/// [`crate::vqs::VQS::try_from_file`] to also check it against an hgrid and
+/// get the node depths.
+pub struct VgridFile {
+    nvrt: usize,
+    kbp: Vec<usize>,
+    sigma: Array2<f64>,
+    format: VgridFormat,
+}
+
+impl VgridFile {
+    /// Parses `filename` in either [`VgridFormat`], decompressing it when it
+    /// ends in .gz.
+    pub fn parse(filename: &PathBuf) -> Result<Self, VQSLoadError> {
+        Self::parse_with_progress(filename, &NoProgress)
+    }
+
+    /// Parses `filename` like [`VgridFile::parse`], reporting the levels (legacy
+    /// layout) or nodes (node-major layout) read to `progress`.
+    pub fn parse_with_progress(
+        filename: &PathBuf,
+        progress: &dyn ProgressReporter,
+    ) -> Result<Self, VQSLoadError> {
+        progress.stage_started("read vgrid.in");
+        let started = Instant::now();
+        let contents = read_contents(filename)?;
+        let format = detect_format(&contents);
+        let mut tokens = contents.split_whitespace();
+        let ivcor: usize = next_token(&mut tokens, "ivcor")?;
+        if ivcor != 1 {
+            return Err(VQSLoadError::UnsupportedIvcor(ivcor));
+        }
+        let nvrt: usize = next_token(&mut tokens, "nvrt")?;
+        let (kbp, sigma) = match format {
+            VgridFormat::Legacy => {
+                // np bottom level indices, then nvrt records of a level index
+                // and np sigma values
+                let remaining = tokens.clone().count();
+                if remaining < nvrt || (remaining - nvrt) % (nvrt + 1) != 0 {
+                    return Err(VQSLoadError::InvalidTokenCount(remaining, nvrt));
+                }
+                let np = (remaining - nvrt) / (nvrt + 1);
+                let mut kbp = Vec::with_capacity(np);
+                for _ in 0..np {
+                    let bottom_level: usize = next_token(&mut tokens, "kbp")?;
+                    validate_bottom_level(kbp.len() + 1, bottom_level, nvrt)?;
+                    kbp.push(bottom_level);
+                }
+                let mut sigma = Array2::from_elem((nvrt, np), NAN);
+                for level in 1..=nvrt {
+                    let index: usize = next_token(&mut tokens, "level index")?;
+                    if index != level {
+                        return Err(VQSLoadError::UnexpectedLevelIndex(level, index));
+                    }
+                    for i in 0..np {
+                        let value: f64 = next_token(&mut tokens, "sigma")?;
+                        if level >= kbp[i] {
+                            sigma[[level - 1, i]] = value;
+                        }
+                    }
+                    progress.progress(level, nvrt);
+                }
+                (kbp, sigma)
+            }
+            VgridFormat::NodeMajor => {
+                let mut kbp = Vec::new();
+                let mut columns = Vec::new();
+                while let Some(token) = tokens.next() {
+                    let node = kbp.len() + 1;
+                    let index: usize = token.parse().map_err(|_| {
+                        VQSLoadError::ParseError("node index".to_string(), token.to_string())
+                    })?;
+                    if index != node {
+                        return Err(VQSLoadError::UnexpectedNodeIndex(node, index));
+                    }
+                    let bottom_level: usize = next_token(&mut tokens, "kbp")?;
+                    validate_bottom_level(node, bottom_level, nvrt)?;
+                    let mut column = vec![NAN; nvrt];
+                    for level in bottom_level..=nvrt {
+                        column[level - 1] = next_token(&mut tokens, "sigma")?;
+                    }
+                    kbp.push(bottom_level);
+                    columns.push(column);
+                    if node % PROGRESS_INTERVAL == 0 {
+                        // the node count is only known at the end of the file
+                        progress.progress(node, 0);
+                    }
+                }
+                let mut sigma = Array2::from_elem((nvrt, kbp.len()), NAN);
+                for (i, column) in columns.into_iter().enumerate() {
+                    for (level, value) in column.into_iter().enumerate() {
+                        sigma[[level, i]] = value;
+                    }
+                }
+                (kbp, sigma)
+            }
+        };
+        progress.stage_finished("read vgrid.in", started.elapsed());
+        Ok(Self {
+            nvrt,
+            kbp,
+            sigma,
+            format,
+        })
+    }
+
+    pub fn nvrt(&self) -> usize {
+        self.nvrt
+    }
+
+    /// Number of nodes found in the file.
+    pub fn np(&self) -> usize {
+        self.kbp.len()
+    }
+
+    /// 1-based bottom level index of each node.
+    pub fn kbp(&self) -> &Vec<usize> {
+        &self.kbp
+    }
+
+    /// (nvrt, np) sigma in [`crate::vqs::VQS::LEVEL_ORDER`], NaN below each
+    /// node's bottom.
+    pub fn sigma(&self) -> &Array2<f64> {
+        &self.sigma
+    }
+
+    /// Layout the file was written in, legacy for grids read from other
+    /// formats by [`crate::convert::read_vgrid`].
+    pub fn format(&self) -> VgridFormat {
+        self.format
+    }
+
+    /// Provenance of the grid, without the fields only a built grid knows.
+    pub fn metadata(&self) -> VgridMetadata {
+        VgridMetadata::from_vgrid_file(self)
+    }
+
+    pub(crate) fn from_parts(kbp: Vec<usize>, sigma: Array2<f64>, format: VgridFormat) -> Self {
+        Self {
+            nvrt: sigma.nrows(),
+            kbp,
+            sigma,
+            format,
+        }
+    }
+
+    pub(crate) fn into_sigma(self) -> Array2<f64> {
+        self.sigma
+    }
+}
+
+/// Contents of `filename`, decompressed when it ends in .gz.
+fn read_contents(filename: &PathBuf) -> std::io::Result<String> {
+    if !is_gzip_path(filename) {
+        return std::fs::read_to_string(filename);
+    }
+    let mut contents = String::new();
+    GzDecoder::new(File::open(filename)?).read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/// The record after nvrt holds only integers in the legacy layout, and the
+/// sigma of the first node in the node-major one.
+fn detect_format(contents: &str) -> VgridFormat {
+    let third_record = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .nth(2);
+    match third_record {
+        Some(record)
+            if record
+                .split_whitespace()
+                .any(|token| token.parse::<usize>().is_err()) =>
+        {
+            VgridFormat::NodeMajor
+        }
+        _ => VgridFormat::Legacy,
+    }
+}
+
+fn validate_bottom_level(
+    node: usize,
+    bottom_level: usize,
+    nvrt: usize,
+) -> Result<(), VQSLoadError> {
+    if bottom_level < 1 || bottom_level > nvrt {
+        return Err(VQSLoadError::InvalidBottomLevel(node, bottom_level, nvrt));
+    }
+    Ok(())
+}
+
+fn next_token<T: std::str::FromStr>(
+    tokens: &mut std::str::SplitWhitespace,
+    name: &str,
+) -> Result<T, VQSLoadError> {
+    let token = tokens
+        .next()
+        .ok_or_else(|| VQSLoadError::UnexpectedEndOfFile(name.to_string()))?;
+    token
+        .parse::<T>()
+        .map_err(|_| VQSLoadError::ParseError(name.to_string(), token.to_string()))
+}
@@ -1,38 +1,335 @@
+use crate::blend::{blend, BlendError};
+use crate::cache::{read_cache, write_cache, CacheError};
+use crate::cells::CellCountSummary;
+use crate::diff::{VQSDiff, VQSDiffError};
+use crate::inspect::VgridInspection;
+use crate::layer_ratio::{check_layer_ratio, LayerRatioReport};
+use crate::level_jumps::{limit_level_jumps, steep_elements, LevelJumpReport, SteepElementReport};
+use crate::master_grid::MasterGrid;
+use crate::mesh::{nodal_areas, write_node_values_gr3};
+use crate::metadata::VgridMetadata;
+#[cfg(feature = "plotters")]
+use crate::plot::{
+    plot_histogram_to_file, plot_levels_map_to_file, plot_zmas_to_file, PlotError, PlotFormat,
+};
+use crate::pressure_gradient::{
+    pressure_gradient_report, PressureGradientOptions, PressureGradientReport,
+};
+use crate::probe::{probe, ProbeReport};
+use crate::progress::{NoProgress, ProgressReporter};
+use crate::quality::{quality_report, QualityReport};
+use crate::regions::{node_level_overrides, RegionOverride};
+use crate::sigma::{LevelOrder, SigmaColumn};
+use crate::smoothing::smooth_zmas;
+use crate::summary::VgridSummary;
 use crate::transforms::quadratic::QuadraticTransformBuilderError;
 use crate::transforms::s::STransformBuilderError;
-use crate::transforms::traits::{Transform, TransformPlotterError};
+use crate::transforms::traits::{zmas_plot, Transform, TransformPlotterError};
 use crate::transforms::transforms::StretchingFunctionError;
 use crate::transforms::StretchingFunction;
-use crate::{kmeans_hsm, KMeansHSMCreateError};
+use crate::validation::{check_bottom_levels, ValidationReport};
+use crate::vgrid_file::VgridFile;
+use crate::writer::{
+    is_gzip_path, push_right_aligned, VgridFormat, VgridWriterOptions, INDEX_WIDTH,
+    STREAMING_CHUNK_SIZE,
+};
+use crate::{cluster_hsm, cluster_hsm_from_depths, ClusteringMethod, KMeansHSMCreateError};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use ndarray::s;
 use ndarray::Array2;
 use ndarray::Axis;
 use ndarray::{Array, Array1};
+use ndarray::{ArrayView1, ArrayViewMut1};
 use ndarray_stats::errors::MinMaxError;
 use ndarray_stats::QuantileExt;
-use plotly::Plot;
+use plotly::common::{ColorScale, ColorScalePalette, Marker, Mode, Title};
+use plotly::layout::{Axis as LayoutAxis, Layout};
+use plotly::{Histogram, Plot, Scatter};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use schismrs_hgrid::hgrid::Hgrid;
 use std::cmp::min;
+use std::collections::BTreeMap;
 use std::f64::NAN;
 use std::fmt;
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufWriter, Write};
 use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 use thiserror::Error;
 
+/// LSC2 vertical grid.
+///
+/// Sigma is stored as a (nvrt, np) array in [`VQS::LEVEL_ORDER`], i.e. row 0
+/// is vgrid.in level 1, with NaN on the levels below each node's bottom.
 pub struct VQS {
     sigma_vqs: Array2<f64>,
-    // _depths: Array1<f64>,
-    // _etal: f64,
-    _znd: Array2<f64>,
+    depths: Array1<f64>,
+    etal: f64,
+    etal_field: Option<Array1<f64>>,
+    dz_bottom_min: Option<f64>,
+    znd: Option<Array2<f64>>,
+    floored_nodes: Option<usize>,
     // z_mas: Array2<f64>,
-    transform: Rc<dyn Transform>,
+    transform: Option<Rc<dyn Transform>>,
 }
 
 impl VQS {
+    pub const LEVEL_ORDER: LevelOrder = LevelOrder::BottomUp;
+
+    /// Loads an ivcor=1 vgrid.in written for `hgrid`, in either
+    /// [`VgridFormat`], gzip-compressed when the path ends in .gz.
+    ///
+    /// vgrid.in does not record the elevation it was designed at, so the loaded
+    /// grid uses etal=0 and carries no transform.
+    pub fn try_from_file(hgrid: &Hgrid, filename: &PathBuf) -> Result<VQS, VQSLoadError> {
+        let file = VgridFile::parse(filename)?;
+        let depths = -hgrid.depths();
+        if file.np() != depths.len() {
+            return Err(VQSLoadError::NodeCountMismatch(file.np(), depths.len()));
+        }
+        Ok(VQS::from_sigma(file.into_sigma(), depths, 0.))
+    }
+
+    /// Loads a vgrid.in like [`VQS::try_from_file`], but first checks that the
+    /// bottom level index of every node agrees with its sigma, see
+    /// [`crate::validation::check_bottom_levels`].
+    ///
+    /// The full report of the failed checks is returned in
+    /// [`VQSLoadError::InconsistentBottomLevels`].
+    pub fn try_from_file_strict(hgrid: &Hgrid, filename: &PathBuf) -> Result<VQS, VQSLoadError> {
+        let file = VgridFile::parse(filename)?;
+        let depths = -hgrid.depths();
+        if file.np() != depths.len() {
+            return Err(VQSLoadError::NodeCountMismatch(file.np(), depths.len()));
+        }
+        let report = check_bottom_levels(&file);
+        if !report.passed() {
+            return Err(VQSLoadError::InconsistentBottomLevels(report));
+        }
+        Ok(VQS::from_sigma(file.into_sigma(), depths, 0.))
+    }
+
+    /// Loads a grid written by [`VQS::write_cache`] for the same `hgrid`,
+    /// which is much faster than parsing vgrid.in for large meshes.
+    ///
+    /// Fails with [`CacheError::HgridMismatch`] when the node coordinates or
+    /// depths of `hgrid` differ from those the cache was written with.
+    pub fn read_cache(hgrid: &Hgrid, filename: &PathBuf) -> Result<VQS, CacheError> {
+        read_cache(hgrid, filename)
+    }
+
+    /// Grid without build provenance, from sigma in [`VQS::LEVEL_ORDER`] and
+    /// node depths (positive down).
+    pub(crate) fn from_sigma(sigma_vqs: Array2<f64>, depths: Array1<f64>, etal: f64) -> VQS {
+        VQS {
+            sigma_vqs,
+            depths,
+            etal,
+            etal_field: None,
+            dz_bottom_min: None,
+            znd: None,
+            floored_nodes: None,
+            transform: None,
+        }
+    }
+
+    /// Sets the per-node etal of a grid made by [`VQS::from_sigma`].
+    pub(crate) fn with_etal_field(mut self, etal_field: Option<Array1<f64>>) -> VQS {
+        self.etal_field = etal_field;
+        self
+    }
+
+    /// Writes the grid to `filename`, gzip-compressed when it ends in .gz.
     pub fn write_to_file(&self, filename: &PathBuf) -> std::io::Result<()> {
-        let mut file = File::create(filename)?;
-        write!(file, "{}", self)?;
+        if is_gzip_path(filename) {
+            let mut encoder = GzEncoder::new(File::create(filename)?, Compression::default());
+            self.write_streaming(&mut encoder)?;
+            return encoder.finish().map(|_| ());
+        }
+        self.write_streaming(File::create(filename)?)
+    }
+
+    /// Writes the grid to `filename` with the layout given by `options`,
+    /// gzip-compressed when it ends in .gz.
+    pub fn write_to_file_with_options(
+        &self,
+        filename: &PathBuf,
+        options: &VgridWriterOptions,
+    ) -> std::io::Result<()> {
+        let file = File::create(filename)?;
+        if is_gzip_path(filename) {
+            let mut writer = BufWriter::new(GzEncoder::new(file, Compression::default()));
+            self.write_with_options(&mut writer, options)?;
+            let encoder = writer.into_inner().map_err(|e| e.into_error())?;
+            return encoder.finish().map(|_| ());
+        }
+        let mut writer = BufWriter::new(file);
+        self.write_with_options(&mut writer, options)?;
+        writer.flush()
+    }
+
+    /// Writes the grid in vgrid.in format through a [`BufWriter`].
+    ///
+    /// Memory use is bounded regardless of the mesh size: lines are flushed in
+    /// chunks of about [`crate::writer::STREAMING_CHUNK_SIZE`] bytes instead of being
+    /// assembled whole, which matters for 10M+ node grids.
+    pub fn write_streaming<W: Write>(&self, writer: W) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Writes the grid in vgrid.in format.
+    ///
+    /// Produces the same output as [`fmt::Display`], but each line is built in a
+    /// single byte buffer that is reused across levels, so the cost of writing
+    /// does not grow with per-value allocations on large meshes.
+    pub fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.write_with_options(writer, &VgridWriterOptions::default())
+    }
+
+    /// Writes the grid in vgrid.in format with the number formatting and
+    /// layout given by `options`.
+    pub fn write_with_options<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &VgridWriterOptions,
+    ) -> std::io::Result<()> {
+        let mut integers = itoa::Buffer::new();
+        let mut line: Vec<u8> = Vec::with_capacity(
+            ((INDEX_WIDTH + 1).max(options.field_width) * self.sigma_vqs.ncols() + INDEX_WIDTH + 1)
+                .min(STREAMING_CHUNK_SIZE + (INDEX_WIDTH + 2).max(options.field_width)),
+        );
+        write!(writer, "{:>12}\n{:>12}\n", self.ivcor(), self.nvrt())?;
+        if options.format == VgridFormat::NodeMajor {
+            return self.write_node_major(writer, options, &mut line);
+        }
+        line.push(b' ');
+        for (i, &index) in self.bottom_level_indices().iter().enumerate() {
+            if i > 0 {
+                match options.indices_per_line {
+                    Some(per_line) if i % per_line == 0 => line.extend_from_slice(b"\n "),
+                    _ => line.push(b' '),
+                }
+            }
+            push_right_aligned(&mut line, integers.format(index), INDEX_WIDTH);
+            if line.len() >= STREAMING_CHUNK_SIZE {
+                writer.write_all(&line)?;
+                line.clear();
+            }
+        }
+        line.push(b'\n');
+        writer.write_all(&line)?;
+        for (level, values) in self.iter_level_values() {
+            line.clear();
+            push_right_aligned(&mut line, integers.format(level), INDEX_WIDTH);
+            for value in values.iter() {
+                // ryu only emits shortest round-trip representations, so fixed
+                // width fields go through core::fmt, which formats on the
+                // stack and writes straight into the line buffer.
+                options.push_sigma(&mut line, if value.is_nan() { -9.0 } else { *value })?;
+                if line.len() >= STREAMING_CHUNK_SIZE {
+                    writer.write_all(&line)?;
+                    line.clear();
+                }
+            }
+            line.push(b'\n');
+            writer.write_all(&line)?;
+        }
+        Ok(())
+    }
+
+    fn write_node_major<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &VgridWriterOptions,
+        line: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        let mut integers = itoa::Buffer::new();
+        for (i, (column, &index)) in self
+            .sigma_vqs
+            .columns()
+            .into_iter()
+            .zip(self.bottom_level_indices().iter())
+            .enumerate()
+        {
+            line.clear();
+            push_right_aligned(line, integers.format(i + 1), INDEX_WIDTH);
+            line.push(b' ');
+            push_right_aligned(line, integers.format(index), INDEX_WIDTH);
+            for value in column.iter().skip(index - 1) {
+                options.push_sigma(line, *value)?;
+                if line.len() >= STREAMING_CHUNK_SIZE {
+                    writer.write_all(line)?;
+                    line.clear();
+                }
+            }
+            line.push(b'\n');
+            writer.write_all(line)?;
+        }
+        Ok(())
+    }
+
+    /// Writes sigma, bottom level indices and z at nodes as a CF-style netCDF
+    /// file, plus the interpolated znd when retained. Levels are ordered bottom
+    /// to surface as in vgrid.in.
+    #[cfg(feature = "netcdf")]
+    pub fn write_to_netcdf(&self, filename: &PathBuf) -> Result<(), netcdf::Error> {
+        let mut file = netcdf::create(filename)?;
+        file.add_dimension("nSCHISM_vgrid_layers", self.nvrt())?;
+        file.add_dimension("nSCHISM_hgrid_node", self.sigma_vqs.ncols())?;
+        file.add_attribute("Conventions", "CF-1.8")?;
+        file.add_attribute("title", "SCHISM LSC2 vertical grid")?;
+        file.add_attribute("ivcor", self.ivcor() as i32)?;
+        file.add_attribute("etal", self.etal)?;
+        let mut depth = file.add_variable::<f64>("depth", &["nSCHISM_hgrid_node"])?;
+        depth.put_attribute("standard_name", "sea_floor_depth_below_geoid")?;
+        depth.put_attribute("units", "m")?;
+        depth.put_attribute("positive", "down")?;
+        depth.put_values(self.depths.to_vec().as_slice(), ..)?;
+        let mut kbp = file.add_variable::<i32>("kbp", &["nSCHISM_hgrid_node"])?;
+        kbp.put_attribute("long_name", "1-based index of the bottom level")?;
+        let values: Vec<i32> = self
+            .bottom_level_indices()
+            .iter()
+            .map(|&index| index as i32)
+            .collect();
+        kbp.put_values(&values, ..)?;
+        let mut sigma =
+            file.add_variable::<f64>("sigma", &["nSCHISM_vgrid_layers", "nSCHISM_hgrid_node"])?;
+        sigma.put_attribute("standard_name", "ocean_sigma_coordinate")?;
+        sigma.put_attribute("positive", "up")?;
+        sigma.put_attribute("formula_terms", "sigma: sigma eta: etal depth: depth")?;
+        sigma.set_fill_value(NAN)?;
+        let values: Vec<f64> = self.sigma_vqs.iter().cloned().collect();
+        sigma.put_values(&values, ..)?;
+        let mut z =
+            file.add_variable::<f64>("z", &["nSCHISM_vgrid_layers", "nSCHISM_hgrid_node"])?;
+        z.put_attribute("standard_name", "height_above_reference_ellipsoid")?;
+        z.put_attribute("long_name", "z of each level at etal")?;
+        z.put_attribute("units", "m")?;
+        z.put_attribute("positive", "up")?;
+        z.set_fill_value(NAN)?;
+        let values: Vec<f64> = self.z().iter().cloned().collect();
+        z.put_values(&values, ..)?;
+        if let Some(znd) = &self.znd {
+            let mut variable =
+                file.add_variable::<f64>("znd", &["nSCHISM_vgrid_layers", "nSCHISM_hgrid_node"])?;
+            variable.put_attribute(
+                "long_name",
+                "z of each level interpolated from the master grids",
+            )?;
+            variable.put_attribute("units", "m")?;
+            variable.put_attribute("positive", "up")?;
+            variable.set_fill_value(NAN)?;
+            let values: Vec<f64> = znd.iter().cloned().collect();
+            variable.put_values(&values, ..)?;
+        }
         Ok(())
     }
 
@@ -44,13 +341,278 @@ impl VQS {
         self.sigma_vqs.nrows()
     }
 
+    /// z of each level as interpolated from the master grids during the build,
+    /// in [`VQS::LEVEL_ORDER`]. Only kept when the builder was asked to
+    /// [`VQSBuildOptions::retain_znd`], which helps tracking down
+    /// [`VQSBuilderError::InvertedZ`] and interpolation issues.
+    pub fn znd(&self) -> Option<&Array2<f64>> {
+        self.znd.as_ref()
+    }
+
     pub fn sigma(&self) -> &Array2<f64> {
         &self.sigma_vqs
     }
 
-    pub fn transform(&self) -> Rc<dyn Transform> {
+    /// Sigma of the wet levels of node `node` (0-based), bottom to surface.
+    pub fn sigma_column(&self, node: usize) -> SigmaColumn {
+        let values = self
+            .sigma_vqs
+            .column(node)
+            .iter()
+            .filter(|value| !value.is_nan())
+            .cloned()
+            .collect();
+        SigmaColumn::new(values, Self::LEVEL_ORDER)
+    }
+
+    /// Iterates over the nodes in order, yielding the 0-based node index, a
+    /// view of the sigma of its wet levels from the bottom up and its 1-based
+    /// bottom level index. Unlike [`VQS::sigma_column`] nothing is copied; use
+    /// [`VQS::z_coordinates`] for the z of a column.
+    pub fn iter_node_columns(&self) -> IterNodeColumns {
+        IterNodeColumns { vqs: self, node: 0 }
+    }
+
+    /// Node depths used to build the grid (positive down).
+    pub fn depths(&self) -> &Array1<f64> {
+        &self.depths
+    }
+
+    pub fn etal(&self) -> &f64 {
+        &self.etal
+    }
+
+    /// Mean free surface elevation of each node when the grid was built with
+    /// [`VQSBuildOptions::etal_field`].
+    pub fn etal_field(&self) -> Option<&Array1<f64>> {
+        self.etal_field.as_ref()
+    }
+
+    /// Mean free surface elevation of node `node` (0-based): its value in
+    /// [`VQS::etal_field`] when the grid has one, and [`VQS::etal`] otherwise.
+    pub fn etal_at(&self, node: usize) -> f64 {
+        self.etal_field
+            .as_ref()
+            .map_or(self.etal, |field| field[node])
+    }
+
+    /// z of the wet levels of node `node` (0-based), bottom to surface, for a
+    /// free surface elevation `eta` rather than the etal the grid was built
+    /// at. The column collapses onto the bed when `eta` is at or below it.
+    pub fn z_coordinates(&self, node: usize, eta: f64) -> Vec<f64> {
+        let depth = self.depths[node];
+        self.sigma_vqs
+            .column(node)
+            .iter()
+            .filter(|sigma| !sigma.is_nan())
+            .map(|sigma| z_of_sigma(*sigma, depth, eta))
+            .collect()
+    }
+
+    /// z of every level at etal, or at the etal field the grid was built
+    /// with, bottom to surface, NaN below the bottom. Dry nodes collapse onto
+    /// the bed like in [`VQS::z_coordinates`].
+    pub fn z(&self) -> Array2<f64> {
+        let mut z = self.sigma_vqs.clone();
+        for (i, (mut column, depth)) in z.axis_iter_mut(Axis(1)).zip(self.depths.iter()).enumerate()
+        {
+            let etal = self.etal_at(i);
+            column.mapv_inplace(|sigma| z_of_sigma(sigma, *depth, etal));
+        }
+        z
+    }
+
+    /// Layer thickness, truncation, Haney number and cell count metrics of the
+    /// grid on the mesh of `hgrid`. See [`crate::quality::QualityReport`].
+    pub fn quality_report(&self, hgrid: &Hgrid) -> QualityReport {
+        quality_report(self, hgrid)
+    }
+
+    /// Spurious currents the sigma-coordinate pressure gradient error would
+    /// drive on the mesh of `hgrid` under an idealized stratification. See
+    /// [`crate::pressure_gradient`].
+    pub fn pressure_gradient_report(
+        &self,
+        hgrid: &Hgrid,
+        options: &PressureGradientOptions,
+    ) -> PressureGradientReport {
+        pressure_gradient_report(self, hgrid, options)
+    }
+
+    /// Node levels, prisms and estimated SCHISM cost of the grid on the mesh
+    /// of `hgrid`. See [`crate::cells::CellCountSummary`].
+    pub fn cell_count_summary(&self, hgrid: &Hgrid) -> CellCountSummary {
+        CellCountSummary::new(self, hgrid)
+    }
+
+    /// Writes a gr3 on the mesh of `hgrid` whose node values are the 1-based
+    /// bottom level indices (kbp), to view the vertical resolution next to
+    /// the bathymetry in xmgredit or VisIt.
+    pub fn write_nlevels_gr3(&self, hgrid: &Hgrid, path: &PathBuf) -> std::io::Result<()> {
+        write_node_values_gr3(hgrid, "kbp", &self.bottom_level_indices(), path)
+    }
+
+    /// Writes a gr3 on the mesh of `hgrid` whose node values are the thickness
+    /// at etal of the layer below vgrid level `level`, or of each node's
+    /// bottom layer when `level` is None, to map where layers get too thin.
+    ///
+    /// Nodes whose bottom is at or above `level` get 0, as SCHISM collapses the
+    /// levels below kbp onto the bottom.
+    pub fn write_layer_thickness_gr3(
+        &self,
+        hgrid: &Hgrid,
+        level: Option<usize>,
+        path: &PathBuf,
+    ) -> std::io::Result<()> {
+        let nvrt = self.nvrt();
+        if let Some(level) = level {
+            if level < 2 || level > nvrt {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("level must be in [2, nvrt={}] but got {}", nvrt, level),
+                ));
+            }
+        }
+        let z = self.z();
+        let thicknesses: Vec<f64> = self
+            .bottom_level_indices()
+            .iter()
+            .enumerate()
+            .map(|(i, &kbp)| {
+                // 0-based row of the top of the layer
+                let top = level.unwrap_or(kbp + 1) - 1;
+                if top < kbp || top >= nvrt {
+                    return 0.;
+                }
+                z[[top, i]] - z[[top - 1, i]]
+            })
+            .collect();
+        let description = match level {
+            Some(level) => format!("dz below level {}", level),
+            None => "bottom layer dz".to_string(),
+        };
+        write_node_values_gr3(hgrid, &description, &thicknesses, path)
+    }
+
+    /// Elements of `hgrid` whose nodes' level counts differ by more than
+    /// `max_level_jump`, the steep LSC2 transitions at channel edges that
+    /// [`VQS::limit_level_jumps`] fixes.
+    pub fn steep_elements(&self, hgrid: &Hgrid, max_level_jump: usize) -> SteepElementReport {
+        steep_elements(self, hgrid, max_level_jump)
+    }
+
+    /// Level interfaces at each of `points`, in the coordinates of `hgrid`.
+    ///
+    /// z at etal is interpolated from the nodes of the element holding the
+    /// point, with quads split in two triangles. Levels below the bottom of a
+    /// node take its bed z, so the interfaces run from the lowest bottom
+    /// level of the element up to the surface. Points outside of the mesh
+    /// get `None`. See [`ProbeReport`].
+    pub fn probe(&self, hgrid: &Hgrid, points: &[(f64, f64)]) -> ProbeReport {
+        probe(self, hgrid, points)
+    }
+
+    /// Raises the bottom level of the nodes with more than `max_level_jump`
+    /// levels over a node sharing an element of `hgrid`, like the neighbour
+    /// check of SCHISM's gen_vqs. The levels right above the bottom of each
+    /// adjusted node are dropped, so its bottom layer absorbs them.
+    pub fn limit_level_jumps(&mut self, hgrid: &Hgrid, max_level_jump: usize) -> LevelJumpReport {
+        limit_level_jumps(
+            &mut self.sigma_vqs,
+            self.znd.as_mut(),
+            hgrid,
+            max_level_jump,
+        )
+    }
+
+    /// The grid restricted to the nodes `node_indices` (0-based, in the order
+    /// given), e.g. those a mesh clipper kept for a nested child model.
+    ///
+    /// Bottom levels no kept node reaches are dropped, so nvrt becomes the
+    /// largest level count of the subset and kbp is renumbered accordingly.
+    /// Build provenance (etal, transform, retained znd) is kept, except the
+    /// count of [`VQS::floored_nodes`].
+    ///
+    /// # Panics
+    ///
+    /// If an index is out of bounds.
+    pub fn subset(&self, node_indices: &[usize]) -> VQS {
+        let sigma_vqs = self.sigma_vqs.select(Axis(1), node_indices);
+        let bottom = sigma_vqs
+            .axis_iter(Axis(1))
+            .map(|column| column.iter().take_while(|sigma| sigma.is_nan()).count())
+            .min()
+            .unwrap_or(0);
+        VQS {
+            sigma_vqs: sigma_vqs.slice(s![bottom.., ..]).to_owned(),
+            depths: self.depths.select(Axis(0), node_indices),
+            etal: self.etal,
+            etal_field: self
+                .etal_field
+                .as_ref()
+                .map(|field| field.select(Axis(0), node_indices)),
+            dz_bottom_min: self.dz_bottom_min,
+            znd: self.znd.as_ref().map(|znd| {
+                znd.select(Axis(1), node_indices)
+                    .slice(s![bottom.., ..])
+                    .to_owned()
+            }),
+            floored_nodes: None,
+            transform: self.transform.clone(),
+        }
+    }
+
+    /// The transform the grid was built with, if it was built rather than loaded.
+    pub fn transform(&self) -> Option<Rc<dyn Transform>> {
         self.transform.clone()
     }
+
+    /// Depth (positive down) and number of levels of each master grid, if the
+    /// grid was built rather than loaded.
+    pub fn master_grids(&self) -> Option<(Vec<f64>, Vec<usize>)> {
+        let transform = self.transform.as_ref()?;
+        Some(
+            transform
+                .zmas()
+                .columns()
+                .into_iter()
+                .map(|column| {
+                    let wet = column.iter().filter(|z| !z.is_nan());
+                    let bottom = wet.clone().cloned().fold(f64::INFINITY, f64::min);
+                    (-bottom, wet.count())
+                })
+                .unzip(),
+        )
+    }
+
+    /// Minimum bottom layer thickness the grid was built with, if it was built
+    /// rather than loaded.
+    pub fn dz_bottom_min(&self) -> Option<&f64> {
+        self.dz_bottom_min.as_ref()
+    }
+
+    /// Number of nodes whose column was raised to the minimum level count of
+    /// [`VQSBuildOptions::min_levels`], if the grid was built rather than loaded.
+    pub fn floored_nodes(&self) -> Option<usize> {
+        self.floored_nodes
+    }
+
+    /// Serializable description of the grid, see [`VgridMetadata`].
+    pub fn metadata(&self) -> VgridMetadata {
+        VgridMetadata::new(self)
+    }
+    /// Number of nodes for each wet level count.
+    pub fn level_histogram(&self) -> BTreeMap<usize, usize> {
+        level_histogram(self.nvrt(), &self.bottom_level_indices())
+    }
+    /// Total number of 3D nodes, i.e. the wet levels summed over all nodes.
+    pub fn wet_node_count(&self) -> usize {
+        let nvrt = self.nvrt();
+        self.bottom_level_indices()
+            .iter()
+            .map(|kbp| nvrt + 1 - kbp)
+            .sum()
+    }
     pub fn bottom_level_indices(&self) -> Vec<usize> {
         let num_columns = self.sigma_vqs.shape()[1];
         let num_rows = self.sigma_vqs.shape()[0];
@@ -68,6 +630,71 @@ impl VQS {
         indices
     }
 
+    /// Level histogram, bottom layer thickness, thinnest layer and master
+    /// grids of the grid. See [`VgridInspection`].
+    pub fn inspect(&self) -> VgridInspection {
+        VgridInspection::new(self)
+    }
+
+    /// Deterministic text snapshot of the grid for regression tests.
+    /// See [`VgridSummary`].
+    pub fn summary_snapshot(&self) -> VgridSummary {
+        VgridSummary::new(self)
+    }
+
+    /// Level count and sigma differences against `other`, built on the same hgrid.
+    pub fn diff(&self, other: &VQS) -> Result<VQSDiff, VQSDiffError> {
+        VQSDiff::new(self, other)
+    }
+
+    /// Grid whose columns move from this grid's (weight 0) to `other`'s
+    /// (weight 1) with the per-node `weights`, both grids being built on the
+    /// same hgrid.
+    ///
+    /// A node gets the weighted mean of the two level counts, rounded, and
+    /// the weighted mean of both sigma profiles resampled to it, so sigma
+    /// stays monotonic from -1 to 0. The result keeps this grid's depths, etal
+    /// and etal field but no other build provenance.
+    pub fn blend(&self, other: &VQS, weights: &Array1<f64>) -> Result<VQS, BlendError> {
+        blend(self, other, weights)
+    }
+
+    /// Writes the bottom level indices, sigma and etal to a compact binary
+    /// file tied to `hgrid`, see [`crate::cache`]. Build provenance is not
+    /// kept.
+    pub fn write_cache(&self, hgrid: &Hgrid, filename: &PathBuf) -> Result<(), CacheError> {
+        write_cache(self, hgrid, filename)
+    }
+
+    /// Per-level node count and sigma range, from level 1 (bottom) to nvrt.
+    ///
+    /// Statistics are computed lazily from the sigma rows, without copying them.
+    pub fn level_stats(&self) -> impl Iterator<Item = LevelStats> + '_ {
+        self.sigma_vqs
+            .axis_iter(Axis(0))
+            .enumerate()
+            .map(|(row, values)| {
+                let mut stats = LevelStats {
+                    level: row + 1,
+                    count: 0,
+                    min: NAN,
+                    mean: NAN,
+                    max: NAN,
+                };
+                let mut sum = 0.;
+                for &value in values.iter().filter(|value| !value.is_nan()) {
+                    stats.min = stats.min.min(value);
+                    stats.max = stats.max.max(value);
+                    sum += value;
+                    stats.count += 1;
+                }
+                if stats.count > 0 {
+                    stats.mean = sum / stats.count as f64;
+                }
+                stats
+            })
+    }
+
     fn iter_level_values(&self) -> IterLevelValues {
         IterLevelValues {
             vqs: self,
@@ -75,12 +702,182 @@ impl VQS {
         }
     }
 
-    fn values_at_level(&self, level: usize) -> Vec<f64> {
-        self.sigma_vqs.row(level - 1).to_vec()
+    fn values_at_level(&self, level: usize) -> ArrayView1<f64> {
+        self.sigma_vqs.row(level - 1)
+    }
+
+    /// z of the master grids, one column each from the surface down, NaN
+    /// below the bottom. Grids loaded from file have no master grids, so the
+    /// column of the deepest node of each level count stands in for them.
+    pub fn master_grid_z(&self) -> Array2<f64> {
+        if let Some(transform) = self.transform.as_ref() {
+            return transform.zmas().clone();
+        }
+        let nvrt = self.nvrt();
+        let mut deepest: BTreeMap<usize, usize> = BTreeMap::new();
+        for (node, (&kbp, &depth)) in self
+            .bottom_level_indices()
+            .iter()
+            .zip(self.depths.iter())
+            .enumerate()
+        {
+            if depth <= 0. {
+                continue;
+            }
+            let entry = deepest.entry(nvrt + 1 - kbp).or_insert(node);
+            if depth > self.depths[*entry] {
+                *entry = node;
+            }
+        }
+        let z = self.z();
+        let mut z_mas = Array2::from_elem((nvrt, deepest.len()), NAN);
+        for (mut master_grid, &node) in z_mas.axis_iter_mut(Axis(1)).zip(deepest.values()) {
+            for (level, z) in z.column(node).iter().rev().enumerate() {
+                master_grid[level] = *z;
+            }
+        }
+        z_mas
+    }
+
+    /// The master grids as a serializable table, extracted as in
+    /// [`VQS::master_grid_z`] for grids loaded from file.
+    pub fn master_grid(&self) -> MasterGrid {
+        MasterGrid::from_z_mas(&self.master_grid_z(), self.etal)
+    }
+
+    /// Thickness of every wet layer of every node at etal, in node order and
+    /// from the bottom up within a node.
+    pub fn layer_thicknesses(&self) -> Vec<f64> {
+        let z = self.z();
+        let mut thicknesses = Vec::new();
+        for column in z.axis_iter(Axis(1)) {
+            let wet: Vec<f64> = column.iter().filter(|z| !z.is_nan()).cloned().collect();
+            thicknesses.extend(wet.windows(2).map(|pair| pair[1] - pair[0]));
+        }
+        thicknesses
+    }
+
+    pub fn make_z_mas_plot(&self) -> Result<Plot, VQSPlotError> {
+        Ok(zmas_plot(&self.master_grid_z())?)
+    }
+
+    /// Map of the number of levels of each node of `hgrid`.
+    pub fn make_levels_map_plot(&self, hgrid: &Hgrid) -> Plot {
+        let nvrt = self.nvrt();
+        let nlevels: Vec<f64> = self
+            .bottom_level_indices()
+            .iter()
+            .map(|kbp| (nvrt + 1 - kbp) as f64)
+            .collect();
+        let trace = Scatter::new(hgrid.x().to_vec(), hgrid.y().to_vec())
+            .mode(Mode::Markers)
+            .text_array(nlevels.iter().map(|n| format!("{} levels", n)).collect())
+            .marker(
+                Marker::new()
+                    .size(4)
+                    .color_array(nlevels)
+                    .color_scale(ColorScale::Palette(ColorScalePalette::Viridis))
+                    .show_scale(true),
+            );
+        let mut plot = Plot::new();
+        plot.add_trace(trace);
+        plot.set_layout(Layout::new().title(Title::new("levels per node")));
+        plot
+    }
+
+    /// Histogram of [`VQS::layer_thicknesses`].
+    pub fn make_thickness_histogram_plot(&self) -> Plot {
+        let mut plot = Plot::new();
+        plot.add_trace(Histogram::new(self.layer_thicknesses()).name("dz"));
+        plot.set_layout(
+            Layout::new()
+                .x_axis(LayoutAxis::new().title(Title::new("layer thickness (m)")))
+                .y_axis(LayoutAxis::new().title(Title::new("count"))),
+        );
+        plot
+    }
+
+    /// Renders the master grids to a PNG or SVG file without plotly or a
+    /// browser, for use on headless compute nodes.
+    #[cfg(feature = "plotters")]
+    pub fn plot_zmas_to_file(
+        &self,
+        path: &PathBuf,
+        format: PlotFormat,
+    ) -> Result<(), VQSPlotError> {
+        Ok(plot_zmas_to_file(&self.master_grid_z(), path, format)?)
+    }
+
+    /// Headless counterpart of [`VQS::make_levels_map_plot`].
+    #[cfg(feature = "plotters")]
+    pub fn plot_levels_map_to_file(
+        &self,
+        hgrid: &Hgrid,
+        path: &PathBuf,
+        format: PlotFormat,
+    ) -> Result<(), VQSPlotError> {
+        let nvrt = self.nvrt();
+        let nlevels: Vec<usize> = self
+            .bottom_level_indices()
+            .iter()
+            .map(|kbp| nvrt + 1 - kbp)
+            .collect();
+        Ok(plot_levels_map_to_file(
+            hgrid.x().to_vec().as_slice(),
+            hgrid.y().to_vec().as_slice(),
+            &nlevels,
+            path,
+            format,
+        )?)
+    }
+
+    /// Headless counterpart of [`VQS::make_thickness_histogram_plot`].
+    #[cfg(feature = "plotters")]
+    pub fn plot_thickness_histogram_to_file(
+        &self,
+        path: &PathBuf,
+        format: PlotFormat,
+    ) -> Result<(), VQSPlotError> {
+        Ok(plot_histogram_to_file(
+            &self.layer_thicknesses(),
+            "layer thickness (m)",
+            path,
+            format,
+        )?)
+    }
+}
+
+/// Sigma statistics of a single level over the nodes where it exists.
+pub struct LevelStats {
+    level: usize,
+    count: usize,
+    min: f64,
+    mean: f64,
+    max: f64,
+}
+
+impl LevelStats {
+    /// 1-based vgrid.in level.
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// Number of nodes where the level is above the bottom.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Minimum sigma, NaN if the level exists at no node.
+    pub fn min(&self) -> &f64 {
+        &self.min
+    }
+
+    pub fn mean(&self) -> &f64 {
+        &self.mean
     }
 
-    pub fn make_z_mas_plot(&self) -> Result<Plot, TransformPlotterError> {
-        Ok(self.transform.make_zmas_plot()?)
+    pub fn max(&self) -> &f64 {
+        &self.max
     }
 }
 
@@ -90,7 +887,7 @@ pub struct IterLevelValues<'a> {
 }
 
 impl<'a> Iterator for IterLevelValues<'a> {
-    type Item = (usize, Vec<f64>);
+    type Item = (usize, ArrayView1<'a, f64>);
 
     fn next(&mut self) -> Option<Self::Item> {
         self.level += 1;
@@ -102,39 +899,222 @@ impl<'a> Iterator for IterLevelValues<'a> {
     }
 }
 
+/// Iterator returned by [`VQS::iter_node_columns`].
+pub struct IterNodeColumns<'a> {
+    vqs: &'a VQS,
+    node: usize,
+}
+
+impl<'a> Iterator for IterNodeColumns<'a> {
+    type Item = (usize, ArrayView1<'a, f64>, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.node >= self.vqs.sigma_vqs.ncols() {
+            return None;
+        }
+        let node = self.node;
+        self.node += 1;
+        let column = self.vqs.sigma_vqs.column(node);
+        let kbp = column.iter().take_while(|value| value.is_nan()).count() + 1;
+        Some((node, column.slice_move(s![kbp - 1..]), kbp))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.vqs.sigma_vqs.ncols() - self.node;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for IterNodeColumns<'a> {}
+
+/// Number of nodes for each wet level count nvrt + 1 - kbp of the 1-based
+/// bottom level indices `kbp`.
+pub(crate) fn level_histogram(nvrt: usize, kbp: &[usize]) -> BTreeMap<usize, usize> {
+    let mut histogram = BTreeMap::new();
+    for kbp in kbp {
+        *histogram.entry(nvrt + 1 - kbp).or_insert(0) += 1;
+    }
+    histogram
+}
+
+/// z of the level at `sigma` of a node `depth` deep (positive down) under a
+/// free surface at `eta`, on the bed when `eta` is at or below it.
+fn z_of_sigma(sigma: f64, depth: f64, eta: f64) -> f64 {
+    sigma * (eta + depth).max(0.) + eta.max(-depth)
+}
+
 impl fmt::Display for VQS {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{:>12}\n", self.ivcor())?;
         write!(f, "{:>12}\n", self.nvrt())?;
-        write!(
-            f,
-            " {}\n",
-            self.bottom_level_indices()
-                .iter()
-                .map(|&index| format!("{:>10}", index))
-                .collect::<Vec<_>>()
-                .join(" ")
-        )?;
+        write!(f, " ")?;
+        for (i, index) in self.bottom_level_indices().iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{:>10}", index)?;
+        }
+        write!(f, "\n")?;
         for (level, values) in self.iter_level_values() {
-            let formatted_values: Vec<String> = values
-                .iter()
-                .map(|value| {
-                    if value.is_nan() {
-                        format!("{:15.6}", -9.0)
-                    } else {
-                        format!("{:15.6}", value)
-                    }
-                })
-                .collect();
-
-            write!(f, "{:>10}{}\n", level, formatted_values.join(""))
-                .expect("Error writing to output");
+            write!(f, "{:>10}", level)?;
+            for value in values.iter() {
+                write!(f, "{:15.6}", if value.is_nan() { -9.0 } else { *value })?;
+            }
+            write!(f, "\n")?;
         }
-
         Ok(())
     }
 }
 
+#[derive(Error, Debug)]
+pub enum VQSLoadError {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error("Only ivcor=1 (LSC2) vgrid files are supported, but got ivcor={0}")]
+    UnsupportedIvcor(usize),
+    #[error("Unexpected end of file while reading {0}")]
+    UnexpectedEndOfFile(String),
+    #[error("Could not parse {0} from token {1:?}")]
+    ParseError(String, String),
+    #[error("Node {0} has bottom level {1} which is outside of [1, nvrt={2}]")]
+    InvalidBottomLevel(usize, usize, usize),
+    #[error("Expected level index {0} but got {1}")]
+    UnexpectedLevelIndex(usize, usize),
+    #[error("Expected node index {0} but got {1}")]
+    UnexpectedNodeIndex(usize, usize),
+    #[error("{0} values after nvrt={1} do not make whole legacy records")]
+    InvalidTokenCount(usize, usize),
+    #[error("The vgrid file has {0} nodes but the hgrid has {1}")]
+    NodeCountMismatch(usize, usize),
+    #[error("Bottom level indices disagree with sigma:\n{0}")]
+    InconsistentBottomLevels(ValidationReport),
+}
+
+#[derive(Error, Debug)]
+pub enum VQSPlotError {
+    #[error(transparent)]
+    TransformPlotterError(#[from] TransformPlotterError),
+    #[cfg(feature = "plotters")]
+    #[error(transparent)]
+    PlotError(#[from] PlotError),
+}
+
+/// Options shared by [`VQSBuilder`], [`VQSKMeansBuilder`], [`VQSAutoBuilder`]
+/// and [`VQSLogLinearBuilder`], set on a builder with its `options` method.
+/// The last three hand them over unchanged to the [`VQSBuilder`] they build
+/// the grid with.
+#[derive(Clone, Copy, Default)]
+pub struct VQSBuildOptions<'a> {
+    dz_bottom_min_profile: Option<&'a DzBottomMinProfile>,
+    intertidal_depth: Option<&'a f64>,
+    intertidal_levels: Option<&'a usize>,
+    h0: Option<&'a f64>,
+    cancellation_token: Option<&'a Arc<AtomicBool>>,
+    progress: Option<&'a dyn ProgressReporter>,
+    shallow_formula: Option<&'a ShallowFormula>,
+    region_overrides: Option<&'a Vec<RegionOverride>>,
+    retain_znd: Option<&'a bool>,
+    level_limits: Option<&'a LevelLimits>,
+    bathymetry: Option<&'a Array1<f64>>,
+    etal_field: Option<&'a Array1<f64>>,
+    smooth_zmas: Option<&'a f64>,
+    min_levels: Option<&'a usize>,
+    max_layer_ratio: Option<&'a f64>,
+}
+
+impl<'a> VQSBuildOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Truncates each column at the dz_bottom_min the profile gives for the
+    /// node depth instead of the constant [`VQSBuilder::dz_bottom_min`], which
+    /// is still required and recorded as the grid's nominal value.
+    pub fn dz_bottom_min_profile(
+        &mut self,
+        dz_bottom_min_profile: &'a DzBottomMinProfile,
+    ) -> &mut Self {
+        self.dz_bottom_min_profile = Some(dz_bottom_min_profile);
+        self
+    }
+    pub fn intertidal_depth(&mut self, intertidal_depth: &'a f64) -> &mut Self {
+        self.intertidal_depth = Some(intertidal_depth);
+        self
+    }
+    pub fn intertidal_levels(&mut self, intertidal_levels: &'a usize) -> &mut Self {
+        self.intertidal_levels = Some(intertidal_levels);
+        self
+    }
+    /// Floor of the total depth etal + h of shallow columns, [`DEFAULT_H0`] by
+    /// default. Shallow sigma does not depend on the total depth, so this
+    /// only affects the z levels kept with [`Self::retain_znd`].
+    pub fn h0(&mut self, h0: &'a f64) -> &mut Self {
+        self.h0 = Some(h0);
+        self
+    }
+    /// Aborts the build with a `Cancelled` error once the token is set.
+    pub fn cancellation_token(&mut self, cancellation_token: &'a Arc<AtomicBool>) -> &mut Self {
+        self.cancellation_token = Some(cancellation_token);
+        self
+    }
+    /// Reports the stages of the build and the nodes processed.
+    pub fn progress(&mut self, progress: &'a dyn ProgressReporter) -> &mut Self {
+        self.progress = Some(progress);
+        self
+    }
+    pub fn shallow_formula(&mut self, shallow_formula: &'a ShallowFormula) -> &mut Self {
+        self.shallow_formula = Some(shallow_formula);
+        self
+    }
+    pub fn region_overrides(&mut self, region_overrides: &'a Vec<RegionOverride>) -> &mut Self {
+        self.region_overrides = Some(region_overrides);
+        self
+    }
+    pub fn retain_znd(&mut self, retain_znd: &'a bool) -> &mut Self {
+        self.retain_znd = Some(retain_znd);
+        self
+    }
+    pub fn level_limits(&mut self, level_limits: &'a LevelLimits) -> &mut Self {
+        self.level_limits = Some(level_limits);
+        self
+    }
+    /// Builds the columns on these node depths instead of the hgrid ones, e.g.
+    /// a smoothed or dredged bathymetry. Same sign convention as
+    /// [`Hgrid::depths`] (negative down) and one value per hgrid node. The
+    /// kmeans and auto builders also design their master grids on them.
+    pub fn bathymetry(&mut self, bathymetry: &'a Array1<f64>) -> &mut Self {
+        self.bathymetry = Some(bathymetry);
+        self
+    }
+    /// Mean free surface elevation of each node (positive up), for domains
+    /// spanning a significant steric gradient. Each column is built as the
+    /// column of the same total depth at etal, shifted to the local surface.
+    pub fn etal_field(&mut self, etal_field: &'a Array1<f64>) -> &mut Self {
+        self.etal_field = Some(etal_field);
+        self
+    }
+    /// Pulls the interior levels of each master grid towards the level
+    /// surfaces of the other master grids by this strength in (0, 1] before
+    /// the node columns are interpolated, see [`smooth_zmas`].
+    pub fn smooth_zmas(&mut self, smooth_zmas: &'a f64) -> &mut Self {
+        self.smooth_zmas = Some(smooth_zmas);
+        self
+    }
+    /// Minimum number of levels of every column, [`DEFAULT_MIN_LEVELS`] by
+    /// default. Columns with fewer levels are rebuilt with this many and
+    /// counted in [`VQS::floored_nodes`].
+    pub fn min_levels(&mut self, min_levels: &'a usize) -> &mut Self {
+        self.min_levels = Some(min_levels);
+        self
+    }
+    /// Fails the build with [`VQSBuilderError::LayerRatioExceeded`], listing
+    /// every node and level, when two adjacent layers of a column differ in
+    /// thickness by more than this ratio (>= 1).
+    pub fn max_layer_ratio(&mut self, max_layer_ratio: &'a f64) -> &mut Self {
+        self.max_layer_ratio = Some(max_layer_ratio);
+        self
+    }
+}
+
 #[derive(Default)]
 pub struct VQSBuilder<'a> {
     hgrid: Option<&'a Hgrid>,
@@ -142,6 +1122,312 @@ pub struct VQSBuilder<'a> {
     nlevels: Option<&'a Vec<usize>>,
     stretching: Option<&'a StretchingFunction<'a>>,
     dz_bottom_min: Option<&'a f64>,
+    options: VQSBuildOptions<'a>,
+}
+
+/// Per-build options that shape each node column.
+struct ColumnOptions {
+    a_vqs0: f64,
+    dz_bottom_min: f64,
+    /// dz_bottom_min of each node from a [`DzBottomMinProfile`], if any.
+    dz_bottom_min_field: Option<Array1<f64>>,
+    intertidal: Option<(f64, usize)>,
+    h0: f64,
+    shallow_formula: ShallowFormula,
+    /// Level count forced on each node by a [`RegionOverride`], if any.
+    level_overrides: Option<Vec<Option<usize>>>,
+    min_levels: usize,
+}
+
+/// How columns shallower than the first master grid are distributed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShallowFormula {
+    /// Quadratic sigma skewed by the transform's a_vqs0.
+    #[default]
+    Quadratic,
+    /// Equal layer thicknesses down to the bottom, which behaves better for
+    /// wetting and drying in some SCHISM configurations.
+    UniformZ,
+}
+
+/// Bottom layer floor that varies with the node depth, see
+/// [`VQSBuildOptions::dz_bottom_min_profile`].
+pub enum DzBottomMinProfile {
+    /// Linear interpolation of (depth, dz_bottom_min) pairs with strictly
+    /// increasing depths (positive down), constant beyond the first and last.
+    Table(Vec<(f64, f64)>),
+    /// `max(min, fraction * depth)`.
+    Proportional { min: f64, fraction: f64 },
+    /// Any function of the depth (positive down).
+    Function(Box<dyn Fn(f64) -> f64 + Send + Sync>),
+}
+
+impl DzBottomMinProfile {
+    /// dz_bottom_min of a node `depth` deep (positive down).
+    pub fn at(&self, depth: f64) -> f64 {
+        match self {
+            DzBottomMinProfile::Table(table) => {
+                let upper = table.partition_point(|&(d, _)| d < depth);
+                if upper == 0 {
+                    return table[0].1;
+                }
+                if upper == table.len() {
+                    return table[table.len() - 1].1;
+                }
+                let ((d0, v0), (d1, v1)) = (table[upper - 1], table[upper]);
+                v0 + (v1 - v0) * (depth - d0) / (d1 - d0)
+            }
+            DzBottomMinProfile::Proportional { min, fraction } => min.max(fraction * depth),
+            DzBottomMinProfile::Function(function) => function(depth),
+        }
+    }
+}
+
+/// Default minimum total water depth used for shallow columns, as in SCHISM's h0.
+pub const DEFAULT_H0: f64 = 0.01;
+
+/// Default of [`VQSBuildOptions::min_levels`]: SCHISM needs a surface and a bottom
+/// level in every column.
+pub const DEFAULT_MIN_LEVELS: usize = 2;
+
+/// Bounds on the number of levels of any column of a build.
+///
+/// Builds whose master grids or region overrides ask for more than `soft`
+/// levels log a warning, and more than `hard` levels are refused, since such
+/// values are almost always a typo and allocate (nvrt, np) arrays.
+#[derive(Clone, Debug)]
+pub struct LevelLimits {
+    pub soft: usize,
+    pub hard: usize,
+}
+
+impl Default for LevelLimits {
+    fn default() -> Self {
+        Self {
+            soft: 200,
+            hard: 1000,
+        }
+    }
+}
+
+impl LevelLimits {
+    pub fn new(soft: usize, hard: usize) -> Self {
+        Self { soft, hard }
+    }
+}
+
+/// Minimum number of nodes handed to each rayon task, and number of nodes built
+/// between two checks of the cancellation token.
+const NODE_CHUNK_SIZE: usize = 1024;
+
+/// Cancellation and progress reporting of the column construction, checked
+/// once per [`NODE_CHUNK_SIZE`] nodes.
+struct BuildControl<'c> {
+    cancellation_token: Option<&'c AtomicBool>,
+    progress: &'c dyn ProgressReporter,
+    done: AtomicUsize,
+    total: usize,
+}
+
+impl<'c> BuildControl<'c> {
+    fn checkpoint(&self) -> Result<(), VQSBuilderError> {
+        VQSBuilder::check_cancelled(self.cancellation_token)?;
+        let done = self.done.fetch_add(NODE_CHUNK_SIZE, Ordering::Relaxed) + NODE_CHUNK_SIZE;
+        self.progress.progress(done.min(self.total), self.total);
+        Ok(())
+    }
+}
+
+/// Builds the sigma and z column of a single node from the master grids.
+///
+/// Nodes are independent of each other, so columns can be filled in any order
+/// (and in parallel). Columns are written in [`ColumnBuilder::LEVEL_ORDER`].
+struct ColumnBuilder<'b> {
+    z_mas: &'b Array2<f64>,
+    hsm: &'b Vec<f64>,
+    nv_vqs: &'b Vec<usize>,
+    etal: f64,
+    opts: &'b ColumnOptions,
+    /// Nodes whose column was raised to `opts.min_levels`.
+    floored: AtomicUsize,
+}
+
+impl<'b> ColumnBuilder<'b> {
+    const LEVEL_ORDER: LevelOrder = LevelOrder::SurfaceDown;
+
+    fn build(
+        &self,
+        i: usize,
+        dp: f64,
+        mut sigma_vqs: ArrayViewMut1<f64>,
+        mut znd: ArrayViewMut1<f64>,
+    ) -> Result<(), VQSBuilderError> {
+        let (z_mas, hsm, nv_vqs, eta2, opts) =
+            (self.z_mas, self.hsm, self.nv_vqs, self.etal, self.opts);
+        if let Some(nlev) = opts.level_overrides.as_ref().and_then(|levels| levels[i]) {
+            // region overrides get a column of their own level count at any depth
+            let total_depth = (eta2 + dp).max(opts.h0);
+            let nlev = self.floor_levels(nlev);
+            Self::quadratic_column(nlev, opts.a_vqs0, total_depth, eta2, sigma_vqs, znd);
+            return Ok(());
+        }
+        if dp <= hsm[0] {
+            // intertidal nodes get a flat, uniform column
+            let (nlev, a_vqs) = match opts.intertidal {
+                Some((intertidal_depth, intertidal_levels)) if dp <= intertidal_depth => {
+                    (intertidal_levels, 0.)
+                }
+                _ => match opts.shallow_formula {
+                    ShallowFormula::Quadratic => (nv_vqs[0], opts.a_vqs0),
+                    // a linear sigma gives equal dz over the column
+                    ShallowFormula::UniformZ => (nv_vqs[0], 0.),
+                },
+            };
+            // the shallow sigma profile does not depend on the total depth, so
+            // the h0 floor only keeps the z of nodes dry at the design etal
+            // (retained with retain_znd) from collapsing or inverting
+            let total_depth = (eta2 + dp).max(opts.h0);
+            let nlev = self.floor_levels(nlev);
+            Self::quadratic_column(nlev, a_vqs, total_depth, eta2, sigma_vqs, znd);
+            return Ok(());
+        }
+        let mut m0 = 0;
+        let mut zrat = 0.;
+        for m in 1..hsm.len() {
+            if dp > hsm[m - 1] && dp <= hsm[m] {
+                m0 = m;
+                zrat = (dp - hsm[m - 1]) / (hsm[m] - hsm[m - 1]);
+                break;
+            }
+        }
+        if m0 == 0 {
+            return Err(VQSBuilderError::FailedToFindAMasterVgrid(i + 1, dp));
+        }
+
+        // interpolate vertical levels
+        let dz_bottom_min = opts
+            .dz_bottom_min_field
+            .as_ref()
+            .map_or(opts.dz_bottom_min, |field| field[i]);
+        let mut kbp = 0;
+        let mut z3 = NAN;
+        // below its last level, the shallower master grid is clamped at its
+        // bottom, as `min(k, nv_vqs(m0-1))` in the 1-based Fortran
+        let last_shallower_level = nv_vqs[m0 - 1] - 1;
+        for k in 0..nv_vqs[m0] {
+            let z1 = z_mas[[min(k, last_shallower_level), m0 - 1]];
+            let z2 = z_mas[[k, m0]];
+            z3 = z1 + (z2 - z1) * zrat;
+
+            if z3 >= -dp + dz_bottom_min {
+                znd[k] = z3;
+            } else {
+                kbp = k;
+                break;
+            }
+        }
+        if kbp == 0 {
+            return Err(VQSBuilderError::FailedToFindABottom(
+                i + 1,
+                dp,
+                z3,
+                z_mas.index_axis(Axis(1), m0).to_owned(),
+            ));
+        }
+        if kbp + 1 < opts.min_levels {
+            // dz_bottom_min cut the column short, so it falls back to the
+            // shallow profile with the minimum level count
+            let nlev = self.floor_levels(kbp + 1);
+            sigma_vqs.fill(NAN);
+            znd.fill(NAN);
+            Self::quadratic_column(nlev, opts.a_vqs0, eta2 + dp, eta2, sigma_vqs, znd);
+            return Ok(());
+        }
+        znd[kbp] = -dp;
+        for k in 0..=kbp {
+            sigma_vqs[k] = (znd[k] - eta2) / (eta2 + dp);
+        }
+        for k in 1..kbp {
+            if znd[k - 1] <= znd[k] {
+                return Err(VQSBuilderError::InvertedZ(
+                    i + 1,
+                    dp,
+                    m0,
+                    k,
+                    znd[k - 1],
+                    znd[k],
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// `nlev` raised to the minimum level count, counting the nodes raised.
+    fn floor_levels(&self, nlev: usize) -> usize {
+        if nlev < self.opts.min_levels {
+            self.floored.fetch_add(1, Ordering::Relaxed);
+            return self.opts.min_levels;
+        }
+        nlev
+    }
+
+    /// Fills the top `nlev` levels with the quadratic sigma profile of
+    /// coefficient `a_vqs` (uniform for 0) over `total_depth`.
+    fn quadratic_column(
+        nlev: usize,
+        a_vqs: f64,
+        total_depth: f64,
+        eta2: f64,
+        mut sigma_vqs: ArrayViewMut1<f64>,
+        mut znd: ArrayViewMut1<f64>,
+    ) {
+        for k in 0..nlev {
+            let sigma = (k as f64) / (1.0 - nlev as f64);
+            sigma_vqs[k] = a_vqs * sigma * sigma + (1.0 + a_vqs) * sigma;
+            znd[k] = sigma_vqs[k] * total_depth + eta2;
+        }
+    }
+}
+
+/// z of the levels, bottom to surface, that a node `depth` deep (positive
+/// down) gets from the master grids `master_depths` and `master_nlevels` of
+/// `transform`, after the `dz_bottom_min` truncation.
+///
+/// The column is computed by the same code as [`VQSBuilder::build`] with its
+/// default options, so a preview matches what the build writes for the node.
+pub fn preview_node_levels(
+    depth: f64,
+    master_depths: &Vec<f64>,
+    master_nlevels: &Vec<usize>,
+    transform: &dyn Transform,
+    dz_bottom_min: f64,
+) -> Result<Vec<f64>, VQSBuilderError> {
+    VQSBuilder::validate_dz_bottom_min(&dz_bottom_min)?;
+    let z_mas = transform.zmas();
+    let opts = ColumnOptions {
+        a_vqs0: *transform.a_vqs0(),
+        dz_bottom_min,
+        dz_bottom_min_field: None,
+        intertidal: None,
+        h0: DEFAULT_H0,
+        shallow_formula: ShallowFormula::default(),
+        level_overrides: None,
+        min_levels: DEFAULT_MIN_LEVELS,
+    };
+    let columns = ColumnBuilder {
+        z_mas,
+        hsm: master_depths,
+        nv_vqs: master_nlevels,
+        etal: *transform.etal(),
+        opts: &opts,
+        floored: AtomicUsize::new(0),
+    };
+    let nvrt = z_mas.nrows().max(opts.min_levels);
+    let mut sigma = Array1::from_elem(nvrt, NAN);
+    let mut znd = Array1::from_elem(nvrt, NAN);
+    columns.build(0, depth, sigma.view_mut(), znd.view_mut())?;
+    // the column is built from the surface down
+    Ok(znd.iter().rev().filter(|z| !z.is_nan()).cloned().collect())
 }
 
 impl<'a> VQSBuilder<'a> {
@@ -166,126 +1452,214 @@ impl<'a> VQSBuilder<'a> {
             .clone()
             .ok_or_else(|| VQSBuilderError::UninitializedFieldError("dz_bottom_min".to_string()))?;
         Self::validate_dz_bottom_min(dz_bottom_min)?;
-        let transform = stretching.transform(hgrid, depths, nlevels)?;
-        let z_mas = transform.zmas();
-        let etal = transform.etal();
-        let (sigma_vqs, znd) = Self::build_sigma_vqs(
-            z_mas,
-            hgrid,
-            depths,
+        let intertidal = match (
+            self.options.intertidal_depth,
+            self.options.intertidal_levels,
+        ) {
+            (None, None) => None,
+            (Some(intertidal_depth), intertidal_levels) => {
+                let intertidal_levels = intertidal_levels.cloned().unwrap_or(2);
+                Self::validate_intertidal(intertidal_depth, &intertidal_levels, depths, nlevels)?;
+                Some((*intertidal_depth, intertidal_levels))
+            }
+            (None, Some(_)) => {
+                return Err(VQSBuilderError::UninitializedFieldError(
+                    "intertidal_depth".to_string(),
+                ))
+            }
+        };
+        let h0 = match self.options.h0 {
+            Some(h0) => *h0,
+            None => DEFAULT_H0,
+        };
+        Self::validate_h0(&h0)?;
+        let min_levels = self
+            .options
+            .min_levels
+            .cloned()
+            .unwrap_or(DEFAULT_MIN_LEVELS);
+        Self::validate_min_levels(min_levels)?;
+        if let Some(max_layer_ratio) = self.options.max_layer_ratio {
+            Self::validate_max_layer_ratio(max_layer_ratio)?;
+        }
+        Self::check_level_limits(
             nlevels,
-            etal,
-            transform.a_vqs0(),
-            dz_bottom_min,
+            self.options.region_overrides,
+            self.options.level_limits.cloned().unwrap_or_default(),
         )?;
-        // let depths = hgrid.depths();
+        let bed = match self.options.bathymetry {
+            Some(bathymetry) => {
+                Self::validate_bathymetry(hgrid, bathymetry)?;
+                bathymetry.clone()
+            }
+            None => hgrid.depths(),
+        };
+        let progress = self.options.progress.unwrap_or(&NoProgress);
+        progress.stage_started("master grids");
+        let started = Instant::now();
+        let transform = stretching.transform(hgrid, depths, nlevels)?;
+        let smoothed;
+        let z_mas = match self.options.smooth_zmas {
+            Some(strength) => {
+                Self::validate_smooth_zmas(strength)?;
+                let (z_mas, report) = smooth_zmas(transform.zmas(), depths, nlevels, *strength);
+                log::info!("Smoothed the master grid level surfaces:\n{}", report);
+                smoothed = z_mas;
+                &smoothed
+            }
+            None => transform.zmas(),
+        };
+        progress.stage_finished("master grids", started.elapsed());
+        let etal = transform.etal();
+        // a node at a local elevation gets the column of the node with the same
+        // total depth at etal, so it is built on that equivalent bed
+        let shift = match self.options.etal_field {
+            Some(etal_field) => {
+                Self::validate_etal_field(hgrid, etal_field)?;
+                Some(etal_field - *etal)
+            }
+            None => None,
+        };
+        let column_bed = match &shift {
+            Some(shift) => &bed - shift,
+            None => bed.clone(),
+        };
+        Self::check_surface_collapse(&column_bed, etal, &h0);
+        let dz_bottom_min_field = match self.options.dz_bottom_min_profile {
+            Some(profile) => Some(Self::dz_bottom_min_field(profile, &column_bed)?),
+            None => None,
+        };
+        let opts = ColumnOptions {
+            a_vqs0: *transform.a_vqs0(),
+            dz_bottom_min: *dz_bottom_min,
+            dz_bottom_min_field,
+            intertidal,
+            h0,
+            shallow_formula: self.options.shallow_formula.cloned().unwrap_or_default(),
+            level_overrides: self
+                .region_overrides
+                .map(|regions| node_level_overrides(hgrid, regions)),
+            min_levels,
+        };
+        let control = BuildControl {
+            cancellation_token: self.options.cancellation_token.map(|token| token.as_ref()),
+            progress,
+            done: AtomicUsize::new(0),
+            total: column_bed.len(),
+        };
+        progress.stage_started("build columns");
+        let started = Instant::now();
+        let (sigma_vqs, mut znd, floored_nodes) =
+            Self::build_sigma_vqs(z_mas, &column_bed, depths, nlevels, etal, &opts, &control)?;
+        progress.stage_finished("build columns", started.elapsed());
+        if floored_nodes > 0 {
+            log::warn!(
+                "{} nodes got fewer than {} levels and were raised to it",
+                floored_nodes,
+                min_levels
+            );
+        }
+        if let Some(max_layer_ratio) = self.options.max_layer_ratio {
+            let report = check_layer_ratio(&sigma_vqs, *max_layer_ratio);
+            if !report.passed() {
+                return Err(VQSBuilderError::LayerRatioExceeded(report));
+            }
+        }
+        let znd = match self.options.retain_znd {
+            Some(true) => {
+                if let Some(shift) = &shift {
+                    znd += shift;
+                }
+                ColumnBuilder::LEVEL_ORDER.convert(&mut znd, VQS::LEVEL_ORDER);
+                Some(znd)
+            }
+            _ => None,
+        };
         Ok(VQS {
             sigma_vqs,
-            // _depths: depths,
-            // _etal: *etal,
-            _znd: znd,
+            depths: -bed,
+            etal: *etal,
+            etal_field: self.options.etal_field.cloned(),
+            dz_bottom_min: Some(*dz_bottom_min),
+            znd,
+            floored_nodes: Some(floored_nodes),
             // z_mas: z_mas.clone(),
-            transform,
+            transform: Some(transform),
         })
     }
 
     fn build_sigma_vqs(
         z_mas: &Array2<f64>,
-        hgrid: &Hgrid,
+        bed: &Array1<f64>,
         hsm: &Vec<f64>,
         nv_vqs: &Vec<usize>,
         etal: &f64,
-        a_vqs0: &f64,
-        dz_bottom_min: &f64,
-    ) -> Result<(Array2<f64>, Array2<f64>), VQSBuilderError> {
-        let nvrt = z_mas.nrows();
-        let dp = -hgrid.depths();
+        opts: &ColumnOptions,
+        control: &BuildControl,
+    ) -> Result<(Array2<f64>, Array2<f64>, usize), VQSBuilderError> {
+        let nvrt = opts
+            .level_overrides
+            .iter()
+            .flatten()
+            .flatten()
+            .fold(z_mas.nrows().max(opts.min_levels), |nvrt, &nlev| {
+                nvrt.max(nlev)
+            });
+        let dp = -bed;
         let np = dp.len();
         let mut sigma_vqs = Array2::from_elem((nvrt, np), NAN);
-        let mut kbp = Array1::zeros(np);
-        let eta2 = Array1::from_elem(np, etal);
         let mut znd = Array2::from_elem((nvrt, np), NAN);
-        let uninitialized_m0_value = hsm.len() + 1;
-        let mut m0 = Array1::from_elem(np, uninitialized_m0_value);
-        for i in 0..np {
-            if dp[i] <= hsm[0] {
-                kbp[i] = nv_vqs[0];
-                for k in 0..nv_vqs[0] {
-                    let sigma = (k as f64) / (1.0 - nv_vqs[0] as f64);
-                    sigma_vqs[[k, i]] = a_vqs0 * sigma * sigma + (1.0 + a_vqs0) * sigma;
-                    znd[[k, i]] = sigma_vqs[[k, i]] * (eta2[i] + dp[i]) + eta2[i];
-                }
-            } else {
-                m0[i] = 0;
-                let mut zrat = 0.;
-                for m in 1..hsm.len() {
-                    if dp[i] > hsm[m - 1] && dp[i] <= hsm[m] {
-                        m0[i] = m;
-                        zrat = (dp[i] - hsm[m - 1]) / (hsm[m] - hsm[m - 1]);
-                        break;
-                    }
-                }
-                if m0[i] == 0 {
-                    return Err(VQSBuilderError::FailedToFindAMasterVgrid(i + 1, dp[i]));
-                }
-
-                // interpolate vertical levels
-                kbp[i] = 0;
-                let mut z3 = NAN;
-                for k in 0..nv_vqs[m0[i]] {
-                    let z1 = z_mas[[min(k, nv_vqs[m0[i] - 1]), m0[i] - 1]];
-                    let z2 = z_mas[[k, m0[i]]];
-                    z3 = z1 + (z2 - z1) * zrat;
-
-                    if z3 >= -dp[i] + dz_bottom_min {
-                        znd[[k, i]] = z3;
-                    } else {
-                        kbp[i] = k;
-                        break;
-                    }
-                }
-                if kbp[i] == 0 {
-                    return Err(VQSBuilderError::FailedToFindABottom(
-                        i + 1,
-                        dp[i],
-                        z3,
-                        z_mas.index_axis(Axis(1), m0[i]).to_owned(),
-                    ));
-                }
-                znd[[kbp[i], i]] = -dp[i];
-                for k in 1..kbp[i] {
-                    if znd[[k - 1, i]] <= znd[[k, i]] {
-                        return Err(VQSBuilderError::InvertedZ(
-                            i + 1,
-                            dp[i],
-                            m0[i],
-                            k,
-                            znd[[k - 1, i]],
-                            znd[[k, i]],
-                        ));
-                    }
+        let columns = ColumnBuilder {
+            z_mas,
+            hsm,
+            nv_vqs,
+            etal: *etal,
+            opts,
+            floored: AtomicUsize::new(0),
+        };
+        #[cfg(feature = "parallel")]
+        sigma_vqs
+            .axis_iter_mut(Axis(1))
+            .into_par_iter()
+            .zip(znd.axis_iter_mut(Axis(1)).into_par_iter())
+            .enumerate()
+            .with_min_len(NODE_CHUNK_SIZE)
+            .try_for_each(|(i, (sigma_column, znd_column))| {
+                if i % NODE_CHUNK_SIZE == 0 {
+                    control.checkpoint()?;
                 }
+                columns.build(i, dp[i], sigma_column, znd_column)
+            })?;
+        #[cfg(not(feature = "parallel"))]
+        for (i, (sigma_column, znd_column)) in sigma_vqs
+            .axis_iter_mut(Axis(1))
+            .zip(znd.axis_iter_mut(Axis(1)))
+            .enumerate()
+        {
+            if i % NODE_CHUNK_SIZE == 0 {
+                control.checkpoint()?;
             }
+            columns.build(i, dp[i], sigma_column, znd_column)?;
+        }
+        ColumnBuilder::LEVEL_ORDER.convert(&mut sigma_vqs, VQS::LEVEL_ORDER);
+        Ok((sigma_vqs, znd, columns.floored.into_inner()))
+    }
+
+    fn check_cancelled(cancellation_token: Option<&AtomicBool>) -> Result<(), VQSBuilderError> {
+        match cancellation_token {
+            Some(token) if token.load(Ordering::Relaxed) => Err(VQSBuilderError::Cancelled),
+            _ => Ok(()),
         }
-        // let mut file = File::create("znd.out").expect("Unable to create file");
-        // for j in 0..znd.ncols() {
-        //     let line = (0..znd.nrows())
-        //         .map(|i| format!("{:16.6}", znd[[i, j]])) // Format each number with 16 decimal places and align
-        //         .collect::<Vec<_>>()
-        //         .join(" ");
-        //     writeln!(file, "{:10} {:16.6} {}", j + 1, dp[j], line)
-        //         .expect("Unable to write to file");
-        // }
-        // file.flush().expect("Unable to flush file");
-        // unimplemented!("wrote znd.out");
-        sigma_vqs.invert_axis(Axis(0));
-        Ok((sigma_vqs, znd))
     }
 
     pub fn hgrid(&mut self, hgrid: &'a Hgrid) -> &mut Self {
         self.hgrid = Some(hgrid);
         self
     }
+    pub fn options(&mut self, options: VQSBuildOptions<'a>) -> &mut Self {
+        self.options = options;
+        self
+    }
 
     pub fn depths(&mut self, depths: &'a Vec<f64>) -> &mut Self {
         self.depths = Some(depths);
@@ -303,6 +1677,122 @@ impl<'a> VQSBuilder<'a> {
         self.dz_bottom_min = Some(dz_bottom_min);
         self
     }
+    fn validate_min_levels(min_levels: usize) -> Result<(), VQSBuilderError> {
+        if min_levels < DEFAULT_MIN_LEVELS {
+            return Err(VQSBuilderError::InvalidMinLevels(min_levels));
+        }
+        Ok(())
+    }
+    /// dz_bottom_min of each node of `bed` (negative down) from `profile`.
+    fn dz_bottom_min_field(
+        profile: &DzBottomMinProfile,
+        bed: &Array1<f64>,
+    ) -> Result<Array1<f64>, VQSBuilderError> {
+        if let DzBottomMinProfile::Table(table) = profile {
+            if table.is_empty() || !table.windows(2).all(|pair| pair[0].0 < pair[1].0) {
+                return Err(VQSBuilderError::InvalidDzBottomMinTable);
+            }
+        }
+        let field = bed.mapv(|z| profile.at(-z));
+        if field.iter().any(|dz_bottom_min| !(*dz_bottom_min >= 0.)) {
+            return Err(VQSBuilderError::InvalidDzBottomMin);
+        }
+        Ok(field)
+    }
+    fn validate_max_layer_ratio(max_layer_ratio: &f64) -> Result<(), VQSBuilderError> {
+        if !(*max_layer_ratio >= 1.) {
+            return Err(VQSBuilderError::InvalidMaxLayerRatio(*max_layer_ratio));
+        }
+        Ok(())
+    }
+    fn validate_h0(h0: &f64) -> Result<(), VQSBuilderError> {
+        if *h0 <= 0. {
+            return Err(VQSBuilderError::InvalidH0(*h0));
+        }
+        Ok(())
+    }
+    fn check_level_limits(
+        nlevels: &Vec<usize>,
+        region_overrides: Option<&Vec<RegionOverride>>,
+        limits: LevelLimits,
+    ) -> Result<(), VQSBuilderError> {
+        let max_levels = region_overrides
+            .iter()
+            .flat_map(|regions| regions.iter())
+            .map(|region| region.nlevels())
+            .chain(nlevels.iter().cloned())
+            .max()
+            .unwrap_or(0);
+        if max_levels > limits.hard {
+            return Err(VQSBuilderError::TooManyLevels(max_levels, limits.hard));
+        }
+        if max_levels > limits.soft {
+            log::warn!(
+                "The build asks for {} levels, above the soft limit of {}",
+                max_levels,
+                limits.soft
+            );
+        }
+        Ok(())
+    }
+    fn validate_smooth_zmas(strength: &f64) -> Result<(), VQSBuilderError> {
+        if !(*strength > 0. && *strength <= 1.) {
+            return Err(VQSBuilderError::InvalidZmasSmoothing(*strength));
+        }
+        Ok(())
+    }
+    fn validate_etal_field(hgrid: &Hgrid, etal_field: &Array1<f64>) -> Result<(), VQSBuilderError> {
+        let np = hgrid.depths().len();
+        if etal_field.len() != np {
+            return Err(VQSBuilderError::EtalFieldSizeMismatch(etal_field.len(), np));
+        }
+        Ok(())
+    }
+    fn validate_bathymetry(hgrid: &Hgrid, bathymetry: &Array1<f64>) -> Result<(), VQSBuilderError> {
+        let np = hgrid.depths().len();
+        if bathymetry.len() != np {
+            return Err(VQSBuilderError::BathymetrySizeMismatch(
+                bathymetry.len(),
+                np,
+            ));
+        }
+        Ok(())
+    }
+    /// Warns about nodes whose water column would be thinner than h0 at the
+    /// design etal. Their sigma is the usual shallow profile, but their z
+    /// levels are computed with h0 as total depth.
+    fn check_surface_collapse(bed: &Array1<f64>, etal: &f64, h0: &f64) {
+        let collapsed = bed.iter().filter(|&&depth| etal - depth < *h0).count();
+        if collapsed > 0 {
+            log::warn!(
+                "{} nodes have a total depth etal + h below h0={} at etal={}; \
+                 their z levels are computed using h0 as effective depth.",
+                collapsed,
+                h0,
+                etal
+            );
+        }
+    }
+    fn validate_intertidal(
+        intertidal_depth: &f64,
+        intertidal_levels: &usize,
+        depths: &Vec<f64>,
+        nlevels: &Vec<usize>,
+    ) -> Result<(), VQSBuilderError> {
+        if *intertidal_depth >= depths[0] {
+            return Err(VQSBuilderError::InvalidIntertidalDepth(
+                *intertidal_depth,
+                depths[0],
+            ));
+        }
+        if *intertidal_levels < 2 || *intertidal_levels > nlevels[0] {
+            return Err(VQSBuilderError::InvalidIntertidalLevels(
+                *intertidal_levels,
+                nlevels[0],
+            ));
+        }
+        Ok(())
+    }
     fn validate_dz_bottom_min(dz_bottom_min: &f64) -> Result<(), VQSBuilderError> {
         if *dz_bottom_min < 0. {
             return Err(VQSBuilderError::InvalidDzBottomMin);
@@ -321,6 +1811,12 @@ pub enum VQSBuilderError {
     STransformBuilderError(#[from] STransformBuilderError),
     #[error("dz_bottom_min must be >= 0")]
     InvalidDzBottomMin,
+    #[error("h0 must be > 0 but got {0}")]
+    InvalidH0(f64),
+    #[error("intertidal_depth must be smaller than the first master grid depth ({1}) but got {0}")]
+    InvalidIntertidalDepth(f64, f64),
+    #[error("intertidal_levels must be >= 2 and <= the levels of the first master grid ({1}) but got {0}")]
+    InvalidIntertidalLevels(usize, usize),
     #[error("Failed to find a master vgrid for node id: {0} and depth {1}")]
     FailedToFindAMasterVgrid(usize, f64),
     #[error("Failed to find a bottom for node id: {0}, depth {1}, z3={2}, z_mas={3}")]
@@ -329,6 +1825,24 @@ pub enum VQSBuilderError {
     InvertedZ(usize, f64, usize, usize, f64, f64),
     #[error(transparent)]
     StretchingFunctionError(#[from] StretchingFunctionError),
+    #[error("VQS build was cancelled")]
+    Cancelled,
+    #[error("The build asks for {0} levels, above the hard limit of {1}")]
+    TooManyLevels(usize, usize),
+    #[error("bathymetry has {0} values but the hgrid has {1} nodes")]
+    BathymetrySizeMismatch(usize, usize),
+    #[error("etal field has {0} values but the hgrid has {1} nodes")]
+    EtalFieldSizeMismatch(usize, usize),
+    #[error("z_mas smoothing strength must be in (0, 1] but got {0}")]
+    InvalidZmasSmoothing(f64),
+    #[error("min_levels must be >= 2 but got {0}")]
+    InvalidMinLevels(usize),
+    #[error("dz_bottom_min table must be non-empty with strictly increasing depths")]
+    InvalidDzBottomMinTable,
+    #[error("max_layer_ratio must be >= 1 but got {0}")]
+    InvalidMaxLayerRatio(f64),
+    #[error("{0}")]
+    LayerRatioExceeded(LayerRatioReport),
 }
 
 #[derive(Default)]
@@ -340,6 +1854,10 @@ pub struct VQSKMeansBuilder<'a> {
     shallow_levels: Option<&'a usize>,
     dz_bottom_min: Option<&'a f64>,
     max_levels: Option<&'a usize>,
+    clustering_method: Option<&'a ClusteringMethod>,
+    area_weighted: Option<&'a bool>,
+    max_depth_override: Option<&'a f64>,
+    options: VQSBuildOptions<'a>,
 }
 
 impl<'a> VQSKMeansBuilder<'a> {
@@ -372,10 +1890,55 @@ impl<'a> VQSKMeansBuilder<'a> {
         let dz_bottom_min = self.dz_bottom_min.ok_or_else(|| {
             VQSKMeansBuilderError::UninitializedFieldError("dz_bottom_min".to_string())
         })?;
-        let mut hsm = kmeans_hsm(hgrid, nclusters, etal)?;
+        let clustering_method = self
+            .clustering_method
+            .cloned()
+            .unwrap_or(ClusteringMethod::KMeans);
+        // weighting by nodal area makes the clusters follow spatial coverage
+        // rather than mesh density
+        let weights = match self.area_weighted {
+            Some(true) => Some(nodal_areas(hgrid)),
+            _ => None,
+        };
+        let mut hsm = match (self.options.bathymetry, &weights) {
+            (Some(bathymetry), weights) => {
+                let mut hsm = cluster_hsm_from_depths(
+                    &bathymetry.to_vec(),
+                    weights.as_deref(),
+                    nclusters,
+                    etal,
+                    &clustering_method,
+                )?;
+                // the transforms validate the last master grid against the hgrid
+                let hgrid_min = hgrid.depths().iter().cloned().fold(f64::INFINITY, f64::min);
+                if let Some(last) = hsm.last_mut() {
+                    *last = last.min(hgrid_min);
+                }
+                hsm
+            }
+            (None, Some(weights)) => cluster_hsm_from_depths(
+                &hgrid.depths().to_vec(),
+                Some(weights),
+                nclusters,
+                etal,
+                &clustering_method,
+            )?,
+            (None, None) => cluster_hsm(hgrid, nclusters, etal, &clustering_method)?,
+        };
         hsm.iter_mut().for_each(|depth| *depth = depth.abs());
-        let mut nlevels = Vec::<usize>::with_capacity(*nclusters);
-        let levels = Array::linspace(*shallow_levels as f64, max_levels as f64, *nclusters);
+        let deepest_node = *hsm.last().unwrap();
+        if let Some(max_depth_override) = self.max_depth_override {
+            if *max_depth_override < deepest_node {
+                return Err(VQSKMeansBuilderError::InvalidMaxDepthOverride(
+                    *max_depth_override,
+                    deepest_node,
+                ));
+            }
+            *hsm.last_mut().unwrap() = *max_depth_override;
+        }
+        let mut nlevels = Vec::<usize>::with_capacity(hsm.len());
+        // Quantile and Jenks classes can collapse on meshes with few distinct depths.
+        let levels = Array::linspace(*shallow_levels as f64, max_levels as f64, hsm.len());
         for level in levels.iter() {
             let mut level = level.round() as usize;
             if level < *shallow_levels {
@@ -383,23 +1946,49 @@ impl<'a> VQSKMeansBuilder<'a> {
             }
             nlevels.push(level);
         }
-        Ok(VQSBuilder::default()
+        if self.max_depth_override.is_some() {
+            warn_max_depth_headroom(&hsm, &nlevels, deepest_node);
+        }
+        let mut builder = VQSBuilder::default();
+        builder
             .hgrid(&hgrid)
             .depths(&hsm)
             .nlevels(&nlevels)
             .stretching(&stretching)
             .dz_bottom_min(&dz_bottom_min)
-            .build()?)
+            .options(self.options);
+        Ok(builder.build()?)
     }
 
+    /// Designs the deepest master grid for this depth (positive down) instead
+    /// of the deepest node, so the grid stays valid after the mesh is
+    /// deepened. Must be no shallower than the deepest node.
+    pub fn max_depth_override(&mut self, max_depth_override: &'a f64) -> &mut Self {
+        self.max_depth_override = Some(max_depth_override);
+        self
+    }
     pub fn hgrid(&mut self, hgrid: &'a Hgrid) -> &mut Self {
         self.hgrid = Some(hgrid);
         self
     }
+    pub fn options(&mut self, options: VQSBuildOptions<'a>) -> &mut Self {
+        self.options = options;
+        self
+    }
     pub fn nclusters(&mut self, nclusters: &'a usize) -> &mut Self {
         self.nclusters = Some(nclusters);
         self
     }
+    pub fn clustering_method(&mut self, clustering_method: &'a ClusteringMethod) -> &mut Self {
+        self.clustering_method = Some(clustering_method);
+        self
+    }
+    /// Weight each node by its median-dual area when clustering, so that
+    /// densely refined regions do not dominate the master grids.
+    pub fn area_weighted(&mut self, area_weighted: &'a bool) -> &mut Self {
+        self.area_weighted = Some(area_weighted);
+        self
+    }
     pub fn stretching(&mut self, stretching: &'a StretchingFunction) -> &mut Self {
         self.stretching = Some(stretching);
         self
@@ -444,6 +2033,24 @@ impl<'a> VQSKMeansBuilder<'a> {
     }
 }
 
+/// Warns about the master grids and levels that no node uses yet when the
+/// grids were designed deeper than the deepest node.
+fn warn_max_depth_headroom(depths: &[f64], nlevels: &[usize], deepest_node: f64) {
+    let max_depth = *depths.last().unwrap();
+    let zone = depths.partition_point(|&depth| depth < deepest_node);
+    log::warn!(
+        "The master grids reach {} m but the deepest node is at {} m: {:.1} m of headroom, \
+         {} of {} master grids below the deepest node, which gets {} of the {} levels.",
+        max_depth,
+        deepest_node,
+        max_depth - deepest_node,
+        depths.len() - zone - 1,
+        depths.len(),
+        nlevels[zone],
+        nlevels.last().unwrap()
+    );
+}
+
 #[derive(Error, Debug)]
 pub enum VQSKMeansBuilderError {
     #[error("Unitialized field on VQSKMeansBuilder: {0}")]
@@ -456,6 +2063,8 @@ pub enum VQSKMeansBuilderError {
     InvalidShallowLevels,
     #[error("max_levels must be > shallow_levels but got max_levels={1}, shallow_levels={0}")]
     InvalidMaxLevels(usize, usize),
+    #[error("max_depth_override must be >= the deepest node ({1}) but got {0}")]
+    InvalidMaxDepthOverride(f64, f64),
 }
 
 #[derive(Default)]
@@ -467,8 +2076,15 @@ pub struct VQSAutoBuilder<'a> {
     initial_depth: Option<&'a f64>,
     shallow_levels: Option<&'a usize>,
     max_levels: Option<&'a usize>,
+    max_depth_override: Option<&'a f64>,
+    target_cells: Option<&'a usize>,
+    target_cells_tolerance: Option<&'a f64>,
+    options: VQSBuildOptions<'a>,
 }
 
+/// Relative tolerance of [`VQSAutoBuilder::target_cells`] when none is given.
+pub const DEFAULT_TARGET_CELLS_TOLERANCE: f64 = 0.02;
+
 impl<'a> VQSAutoBuilder<'a> {
     pub fn build(&self) -> Result<VQS, VQSAutoBuilderError> {
         let hgrid = self
@@ -493,6 +2109,20 @@ impl<'a> VQSAutoBuilder<'a> {
             VQSAutoBuilderError::UninitializedFieldError("shallow_levels".to_string())
         })?;
         Self::validate_shallow_levels(shallow_levels)?;
+        if let Some(target_cells) = self.target_cells {
+            if self.max_levels.is_some() {
+                return Err(VQSAutoBuilderError::TargetCellsWithMaxLevels);
+            }
+            return self.build_for_target_cells(
+                hgrid,
+                stretching,
+                ngrids,
+                dz_bottom_min,
+                initial_depth,
+                shallow_levels,
+                target_cells,
+            );
+        }
         let max_levels = match self.max_levels {
             Some(max_levels) => *max_levels,
             None => Self::calculate_max_levels(shallow_levels, ngrids),
@@ -502,15 +2132,182 @@ impl<'a> VQSAutoBuilder<'a> {
         //     VQSAutoBuilderError::UninitializedFieldError("max_levels".to_string())
         // })?;
         // Self::validate_max_levels(shallow_levels, max_levels)?;
-        let (hsm, nlevels) =
-            Self::build_hsm_and_nlevels(hgrid, ngrids, initial_depth, shallow_levels, &max_levels)?;
-        Ok(VQSBuilder::default()
+        self.build_with_max_levels(
+            hgrid,
+            stretching,
+            ngrids,
+            dz_bottom_min,
+            initial_depth,
+            shallow_levels,
+            &max_levels,
+        )
+    }
+
+    fn build_with_max_levels(
+        &self,
+        hgrid: &Hgrid,
+        stretching: &StretchingFunction,
+        ngrids: &'a usize,
+        dz_bottom_min: &f64,
+        initial_depth: &'a f64,
+        shallow_levels: &usize,
+        max_levels: &usize,
+    ) -> Result<VQS, VQSAutoBuilderError> {
+        let (hsm, nlevels) = Self::build_hsm_and_nlevels(
+            hgrid,
+            self.options.bathymetry,
+            self.max_depth_override,
+            ngrids,
+            initial_depth,
+            shallow_levels,
+            max_levels,
+        )?;
+        let mut builder = VQSBuilder::default();
+        builder
             .hgrid(&hgrid)
             .depths(&hsm)
             .nlevels(&nlevels)
             .stretching(&stretching)
             .dz_bottom_min(&dz_bottom_min)
-            .build()?)
+            .options(self.options);
+        Ok(builder.build()?)
+    }
+
+    /// Bisects max_levels for the master grids whose estimated wet 3D node
+    /// count is closest to `target_cells`, builds the grid once at that
+    /// max_levels and accepts it if its count is within the relative tolerance.
+    fn build_for_target_cells(
+        &self,
+        hgrid: &Hgrid,
+        stretching: &StretchingFunction,
+        ngrids: &'a usize,
+        dz_bottom_min: &f64,
+        initial_depth: &'a f64,
+        shallow_levels: &usize,
+        target_cells: &usize,
+    ) -> Result<VQS, VQSAutoBuilderError> {
+        let tolerance = self
+            .target_cells_tolerance
+            .cloned()
+            .unwrap_or(DEFAULT_TARGET_CELLS_TOLERANCE);
+        let hard = self.options.level_limits.cloned().unwrap_or_default().hard;
+        let depths = self.column_depths(hgrid, stretching.etal());
+        // only the master grids are designed for each candidate, sigma is not
+        // allocated until the search is over
+        let estimate = |max_levels: usize| -> Result<usize, VQSAutoBuilderError> {
+            let (hsm, nlevels) = Self::build_hsm_and_nlevels(
+                hgrid,
+                self.options.bathymetry,
+                self.max_depth_override,
+                ngrids,
+                initial_depth,
+                shallow_levels,
+                &max_levels,
+            )?;
+            Ok(self.estimated_wet_node_count(&depths, &hsm, &nlevels))
+        };
+        // the node count grows with max_levels, so find the smallest max_levels
+        // reaching the target and compare it with the one below
+        let (mut lower, mut upper) = (*shallow_levels, hard.max(*shallow_levels));
+        let mut upper_count = estimate(upper)?;
+        if upper_count >= *target_cells {
+            while upper - lower > 1 {
+                let middle = (lower + upper) / 2;
+                let count = estimate(middle)?;
+                if count >= *target_cells {
+                    upper = middle;
+                    upper_count = count;
+                } else {
+                    lower = middle;
+                }
+            }
+        }
+        let mut max_levels = upper;
+        if upper > *shallow_levels {
+            let below = estimate(upper - 1)?;
+            if below.abs_diff(*target_cells) < upper_count.abs_diff(*target_cells) {
+                max_levels = upper - 1;
+            }
+        }
+        let vqs = self.build_with_max_levels(
+            hgrid,
+            stretching,
+            ngrids,
+            dz_bottom_min,
+            initial_depth,
+            shallow_levels,
+            &max_levels,
+        )?;
+        let count = vqs.wet_node_count();
+        log::info!(
+            "Target of {} wet 3D nodes resolved to nvrt={} with {} nodes.",
+            target_cells,
+            vqs.nvrt(),
+            count
+        );
+        if count.abs_diff(*target_cells) as f64 > tolerance * *target_cells as f64 {
+            return Err(VQSAutoBuilderError::TargetCellsNotReached(
+                *target_cells,
+                count,
+                tolerance,
+            ));
+        }
+        Ok(vqs)
+    }
+
+    /// Depth (positive down) each node's column is built for, as in
+    /// [`VQSBuilder::build`].
+    fn column_depths(&self, hgrid: &Hgrid, etal: &f64) -> Array1<f64> {
+        let mut depths = match self.options.bathymetry {
+            Some(bathymetry) => -bathymetry,
+            None => -hgrid.depths(),
+        };
+        if let Some(etal_field) = self.options.etal_field {
+            depths += &(etal_field - *etal);
+        }
+        depths
+    }
+
+    /// Wet 3D node count of a grid on the master grids `hsm` and `nlevels`,
+    /// interpolating the level count of each node linearly in depth between
+    /// the master grids around it. The dz_bottom_min truncation and region
+    /// overrides are ignored, so this is an upper estimate of
+    /// [`VQS::wet_node_count`].
+    fn estimated_wet_node_count(
+        &self,
+        depths: &Array1<f64>,
+        hsm: &[f64],
+        nlevels: &[usize],
+    ) -> usize {
+        let min_levels = self
+            .options
+            .min_levels
+            .cloned()
+            .unwrap_or(DEFAULT_MIN_LEVELS);
+        let intertidal = self
+            .intertidal_depth
+            .map(|depth| (*depth, self.options.intertidal_levels.cloned().unwrap_or(2)));
+        depths
+            .iter()
+            .map(|&dp| {
+                let nlev = match intertidal {
+                    Some((intertidal_depth, intertidal_levels)) if dp <= intertidal_depth => {
+                        intertidal_levels
+                    }
+                    _ if dp <= hsm[0] => nlevels[0],
+                    _ => {
+                        let m = hsm
+                            .iter()
+                            .position(|&depth| dp <= depth)
+                            .unwrap_or(hsm.len() - 1);
+                        let zrat = ((dp - hsm[m - 1]) / (hsm[m] - hsm[m - 1])).min(1.);
+                        let (shallower, deeper) = (nlevels[m - 1] as f64, nlevels[m] as f64);
+                        (shallower + (deeper - shallower) * zrat).round() as usize
+                    }
+                };
+                nlev.max(min_levels)
+            })
+            .sum()
     }
 
     fn validate_ngrids(ngrids: &usize) -> Result<(), VQSAutoBuilderError> {
@@ -556,12 +2353,31 @@ impl<'a> VQSAutoBuilder<'a> {
 
     fn build_hsm_and_nlevels(
         hgrid: &Hgrid,
+        bathymetry: Option<&Array1<f64>>,
+        max_depth_override: Option<&f64>,
         ngrids: &'a usize,
         initial_depth: &'a f64,
         shallow_levels: &usize,
         max_levels: &usize,
     ) -> Result<(Vec<f64>, Vec<usize>), VQSAutoBuilderError> {
-        let max_depth = -hgrid.depths().min()?;
+        // the last master grid must reach the deepest node of both the hgrid,
+        // which the transforms validate against, and the bathymetry in use
+        let mut deepest_node = -hgrid.depths().min()?;
+        if let Some(bathymetry) = bathymetry {
+            deepest_node = deepest_node.max(-bathymetry.min()?);
+        }
+        let max_depth = match max_depth_override {
+            Some(max_depth_override) => {
+                if *max_depth_override < deepest_node {
+                    return Err(VQSAutoBuilderError::InvalidMaxDepthOverride(
+                        *max_depth_override,
+                        deepest_node,
+                    ));
+                }
+                *max_depth_override
+            }
+            None => deepest_node,
+        };
         let x1 = *shallow_levels as f64;
         let y1 = *initial_depth;
         let x2 = *max_levels as f64;
@@ -582,6 +2398,9 @@ impl<'a> VQSAutoBuilder<'a> {
             hsm.push(*this_depth);
             levels.push(level);
         }
+        if max_depth_override.is_some() {
+            warn_max_depth_headroom(&hsm, &levels, deepest_node);
+        }
         Ok((hsm, levels))
     }
 
@@ -598,10 +2417,35 @@ impl<'a> VQSAutoBuilder<'a> {
         Ok(())
     }
 
+    /// Designs the deepest master grid for this depth (positive down) instead
+    /// of the deepest node, so the grid stays valid after the mesh is
+    /// deepened. Must be no shallower than the deepest node.
+    pub fn max_depth_override(&mut self, max_depth_override: &'a f64) -> &mut Self {
+        self.max_depth_override = Some(max_depth_override);
+        self
+    }
+    /// Searches max_levels for the grid whose total number of wet 3D nodes,
+    /// see [`VQS::wet_node_count`], is closest to this budget. Excludes
+    /// max_levels. The search compares node counts estimated from the master
+    /// grids, so only the chosen grid is built.
+    pub fn target_cells(&mut self, target_cells: &'a usize) -> &mut Self {
+        self.target_cells = Some(target_cells);
+        self
+    }
+    /// Largest accepted relative difference between the wet 3D node count and
+    /// target_cells. Defaults to [`DEFAULT_TARGET_CELLS_TOLERANCE`].
+    pub fn target_cells_tolerance(&mut self, target_cells_tolerance: &'a f64) -> &mut Self {
+        self.target_cells_tolerance = Some(target_cells_tolerance);
+        self
+    }
     pub fn hgrid(&mut self, hgrid: &'a Hgrid) -> &mut Self {
         self.hgrid = Some(hgrid);
         self
     }
+    pub fn options(&mut self, options: VQSBuildOptions<'a>) -> &mut Self {
+        self.options = options;
+        self
+    }
     pub fn ngrids(&mut self, ngrids: &'a usize) -> &mut Self {
         self.ngrids = Some(ngrids);
         self
@@ -641,6 +2485,12 @@ pub enum VQSAutoBuilderError {
     InvalidInitialDepth(f64, f64),
     #[error("ngrids must be >= 2 but got {0}")]
     InvalidNgridsValue(usize),
+    #[error("max_depth_override must be >= the deepest node ({1}) but got {0}")]
+    InvalidMaxDepthOverride(f64, f64),
+    #[error("target_cells searches max_levels, so max_levels must not be set")]
+    TargetCellsWithMaxLevels,
+    #[error("No max_levels gives {0} wet 3D nodes within a relative tolerance of {2}, the closest gives {1}")]
+    TargetCellsNotReached(usize, usize, f64),
     #[error(transparent)]
     MinMaxError(#[from] MinMaxError),
     #[error(transparent)]
@@ -648,3 +2498,226 @@ pub enum VQSAutoBuilderError {
     #[error(transparent)]
     QuadraticTransformBuilderError(#[from] QuadraticTransformBuilderError),
 }
+
+/// Allocates master grid levels following `nlevels = slope * ln(depth) + intercept`.
+///
+/// This is the same log-linear law observed in legacy vgrid.in files, so grids
+/// redesigned from them keep their original level-allocation philosophy. The
+/// law is either given explicitly through `slope` and `intercept` or fitted by
+/// least squares to anchor points.
+#[derive(Default)]
+pub struct VQSLogLinearBuilder<'a> {
+    hgrid: Option<&'a Hgrid>,
+    depths: Option<&'a Vec<f64>>,
+    stretching: Option<&'a StretchingFunction<'a>>,
+    dz_bottom_min: Option<&'a f64>,
+    slope: Option<&'a f64>,
+    intercept: Option<&'a f64>,
+    anchor_depths: Option<&'a Vec<f64>>,
+    anchor_levels: Option<&'a Vec<usize>>,
+    shallow_levels: Option<&'a usize>,
+    options: VQSBuildOptions<'a>,
+}
+
+impl<'a> VQSLogLinearBuilder<'a> {
+    pub fn build(&self) -> Result<VQS, VQSLogLinearBuilderError> {
+        let hgrid = self.hgrid.ok_or_else(|| {
+            VQSLogLinearBuilderError::UninitializedFieldError("hgrid".to_string())
+        })?;
+        let depths = self.depths.ok_or_else(|| {
+            VQSLogLinearBuilderError::UninitializedFieldError("depths".to_string())
+        })?;
+        Self::validate_depths(depths)?;
+        let stretching = self.stretching.ok_or_else(|| {
+            VQSLogLinearBuilderError::UninitializedFieldError("stretching".to_string())
+        })?;
+        let dz_bottom_min = self.dz_bottom_min.ok_or_else(|| {
+            VQSLogLinearBuilderError::UninitializedFieldError("dz_bottom_min".to_string())
+        })?;
+        let shallow_levels = match self.shallow_levels {
+            Some(shallow_levels) => *shallow_levels,
+            None => 2,
+        };
+        Self::validate_shallow_levels(&shallow_levels)?;
+        let (slope, intercept) = match (self.slope, self.intercept) {
+            (Some(slope), Some(intercept)) => (*slope, *intercept),
+            (None, None) => {
+                let anchor_depths = self.anchor_depths.ok_or_else(|| {
+                    VQSLogLinearBuilderError::UninitializedFieldError(
+                        "slope and intercept, or anchor_depths".to_string(),
+                    )
+                })?;
+                let anchor_levels = self.anchor_levels.ok_or_else(|| {
+                    VQSLogLinearBuilderError::UninitializedFieldError("anchor_levels".to_string())
+                })?;
+                Self::fit(anchor_depths, anchor_levels)?
+            }
+            (Some(_), None) => {
+                return Err(VQSLogLinearBuilderError::UninitializedFieldError(
+                    "intercept".to_string(),
+                ))
+            }
+            (None, Some(_)) => {
+                return Err(VQSLogLinearBuilderError::UninitializedFieldError(
+                    "slope".to_string(),
+                ))
+            }
+        };
+        Self::validate_slope(&slope)?;
+        let nlevels = Self::nlevels_from_law(depths, &slope, &intercept, &shallow_levels);
+        log::info!(
+            "Log-linear level allocation: nlevels = {:.4} * ln(depth) + {:.4}",
+            slope,
+            intercept
+        );
+        let mut builder = VQSBuilder::default();
+        builder
+            .hgrid(&hgrid)
+            .depths(&depths)
+            .nlevels(&nlevels)
+            .stretching(&stretching)
+            .dz_bottom_min(&dz_bottom_min)
+            .options(self.options);
+        Ok(builder.build()?)
+    }
+
+    /// Least squares fit of `levels = slope * ln(depth) + intercept`.
+    pub fn fit(
+        depths: &Vec<f64>,
+        levels: &Vec<usize>,
+    ) -> Result<(f64, f64), VQSLogLinearBuilderError> {
+        if depths.len() != levels.len() {
+            return Err(VQSLogLinearBuilderError::AnchorsSizeMismatch(
+                depths.len(),
+                levels.len(),
+            ));
+        }
+        if depths.len() < 2 {
+            return Err(VQSLogLinearBuilderError::NotEnoughAnchors(depths.len()));
+        }
+        Self::validate_depths(depths)?;
+        let n = depths.len() as f64;
+        let x: Vec<f64> = depths.iter().map(|depth| depth.ln()).collect();
+        let y: Vec<f64> = levels.iter().map(|&level| level as f64).collect();
+        let x_mean = x.iter().sum::<f64>() / n;
+        let y_mean = y.iter().sum::<f64>() / n;
+        let mut sxx = 0.;
+        let mut sxy = 0.;
+        for (xi, yi) in x.iter().zip(y.iter()) {
+            sxx += (xi - x_mean) * (xi - x_mean);
+            sxy += (xi - x_mean) * (yi - y_mean);
+        }
+        if sxx == 0. {
+            return Err(VQSLogLinearBuilderError::DegenerateAnchors);
+        }
+        let slope = sxy / sxx;
+        let intercept = y_mean - slope * x_mean;
+        Ok((slope, intercept))
+    }
+
+    /// Evaluates the law at each master depth, flooring at `shallow_levels` and
+    /// keeping the result non-decreasing with depth.
+    pub fn nlevels_from_law(
+        depths: &Vec<f64>,
+        slope: &f64,
+        intercept: &f64,
+        shallow_levels: &usize,
+    ) -> Vec<usize> {
+        let mut nlevels = Vec::with_capacity(depths.len());
+        let mut prev_level = *shallow_levels;
+        for depth in depths.iter() {
+            let level = (slope * depth.ln() + intercept).round();
+            let mut level = if level < 0. { 0 } else { level as usize };
+            if level < prev_level {
+                level = prev_level;
+            }
+            nlevels.push(level);
+            prev_level = level;
+        }
+        nlevels
+    }
+
+    fn validate_depths(depths: &Vec<f64>) -> Result<(), VQSLogLinearBuilderError> {
+        if depths.iter().any(|&depth| depth <= 0.) {
+            return Err(VQSLogLinearBuilderError::InvalidDepths);
+        }
+        Ok(())
+    }
+
+    fn validate_slope(slope: &f64) -> Result<(), VQSLogLinearBuilderError> {
+        if *slope <= 0. {
+            return Err(VQSLogLinearBuilderError::InvalidSlope(*slope));
+        }
+        Ok(())
+    }
+
+    fn validate_shallow_levels(shallow_levels: &usize) -> Result<(), VQSLogLinearBuilderError> {
+        if *shallow_levels < 2 {
+            return Err(VQSLogLinearBuilderError::InvalidShallowLevels(
+                *shallow_levels,
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn hgrid(&mut self, hgrid: &'a Hgrid) -> &mut Self {
+        self.hgrid = Some(hgrid);
+        self
+    }
+    pub fn options(&mut self, options: VQSBuildOptions<'a>) -> &mut Self {
+        self.options = options;
+        self
+    }
+    pub fn depths(&mut self, depths: &'a Vec<f64>) -> &mut Self {
+        self.depths = Some(depths);
+        self
+    }
+    pub fn stretching(&mut self, stretching: &'a StretchingFunction) -> &mut Self {
+        self.stretching = Some(stretching);
+        self
+    }
+    pub fn dz_bottom_min(&mut self, dz_bottom_min: &'a f64) -> &mut Self {
+        self.dz_bottom_min = Some(dz_bottom_min);
+        self
+    }
+    pub fn slope(&mut self, slope: &'a f64) -> &mut Self {
+        self.slope = Some(slope);
+        self
+    }
+    pub fn intercept(&mut self, intercept: &'a f64) -> &mut Self {
+        self.intercept = Some(intercept);
+        self
+    }
+    pub fn anchor_depths(&mut self, anchor_depths: &'a Vec<f64>) -> &mut Self {
+        self.anchor_depths = Some(anchor_depths);
+        self
+    }
+    pub fn anchor_levels(&mut self, anchor_levels: &'a Vec<usize>) -> &mut Self {
+        self.anchor_levels = Some(anchor_levels);
+        self
+    }
+    pub fn shallow_levels(&mut self, shallow_levels: &'a usize) -> &mut Self {
+        self.shallow_levels = Some(shallow_levels);
+        self
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum VQSLogLinearBuilderError {
+    #[error("Unitialized field on VQSLogLinearBuilder: {0}")]
+    UninitializedFieldError(String),
+    #[error(transparent)]
+    VQSBuilderError(#[from] VQSBuilderError),
+    #[error("depths must be all positive to evaluate ln(depth)")]
+    InvalidDepths,
+    #[error("slope must be > 0 but got {0}")]
+    InvalidSlope(f64),
+    #[error("shallow_levels must be >= 2 but got {0}")]
+    InvalidShallowLevels(usize),
+    #[error("anchor_depths and anchor_levels must be of the same length. Got lengths {0} and {1} respectively")]
+    AnchorsSizeMismatch(usize, usize),
+    #[error("At least 2 anchors are required to fit the log-linear law, but got {0}")]
+    NotEnoughAnchors(usize),
+    #[error("anchor_depths must contain at least two distinct depths")]
+    DegenerateAnchors,
+}
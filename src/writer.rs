@@ -0,0 +1,135 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Width of the bottom level index and level number fields in vgrid.in.
+pub(crate) const INDEX_WIDTH: usize = 10;
+
+/// Size in bytes above which a partially built vgrid.in line is handed to the
+/// writer, bounding the line buffer on very large meshes.
+pub const STREAMING_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Whether `path` ends in .gz, in which case vgrid.in is written and read
+/// gzip-compressed.
+pub fn is_gzip_path(path: &PathBuf) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+/// Layout of the sigma values in vgrid.in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VgridFormat {
+    /// All bottom level indices on one record, then one record per level
+    /// holding the sigma of every node, -9 below the bottom.
+    #[default]
+    Legacy,
+    /// One record per node with its id, its bottom level index and the sigma
+    /// of its wet levels from the bottom up, as read by newer SCHISM versions.
+    NodeMajor,
+}
+
+/// Number formatting and layout of a written vgrid.in.
+///
+/// The defaults reproduce the output of [`crate::vqs::VQS`]'s Display. The
+/// other settings exist to byte-match files written by the Fortran tools for
+/// regression comparisons.
+#[derive(Clone, Debug)]
+pub struct VgridWriterOptions {
+    /// Digits after the decimal point of each sigma value.
+    pub precision: usize,
+    /// Width of each sigma field.
+    pub field_width: usize,
+    /// Wrap the bottom level indices after this many values. None writes them
+    /// all on a single line. Only used by [`VgridFormat::Legacy`].
+    pub indices_per_line: Option<usize>,
+    /// Write sigma in Fortran ES notation, e.g. -5.000000E-01.
+    pub scientific: bool,
+    /// Level-major or node-major records.
+    pub format: VgridFormat,
+}
+
+impl Default for VgridWriterOptions {
+    fn default() -> Self {
+        Self {
+            precision: 6,
+            field_width: 15,
+            indices_per_line: None,
+            scientific: false,
+            format: VgridFormat::Legacy,
+        }
+    }
+}
+
+impl VgridWriterOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn precision(&mut self, precision: usize) -> &mut Self {
+        self.precision = precision;
+        self
+    }
+    pub fn field_width(&mut self, field_width: usize) -> &mut Self {
+        self.field_width = field_width;
+        self
+    }
+    pub fn indices_per_line(&mut self, indices_per_line: usize) -> &mut Self {
+        self.indices_per_line = Some(indices_per_line);
+        self
+    }
+    pub fn scientific(&mut self, scientific: bool) -> &mut Self {
+        self.scientific = scientific;
+        self
+    }
+    pub fn format(&mut self, format: VgridFormat) -> &mut Self {
+        self.format = format;
+        self
+    }
+
+    pub(crate) fn push_sigma(&self, line: &mut Vec<u8>, value: f64) -> std::io::Result<()> {
+        let (width, precision) = (self.field_width, self.precision);
+        if !self.scientific {
+            return write!(line, "{:width$.precision$}", value);
+        }
+        if value == 0. {
+            return push_scientific(line, 0., 0, width, precision);
+        }
+        let mut exponent = value.abs().log10().floor() as i32;
+        let mut mantissa = value / 10f64.powi(exponent);
+        // Rounding to the requested precision can carry into a second digit.
+        let scale = 10f64.powi(precision as i32);
+        if (mantissa.abs() * scale).round() / scale >= 10. {
+            exponent += 1;
+            mantissa /= 10.;
+        }
+        push_scientific(line, mantissa, exponent, width, precision)
+    }
+}
+
+fn push_scientific(
+    line: &mut Vec<u8>,
+    mantissa: f64,
+    exponent: i32,
+    width: usize,
+    precision: usize,
+) -> std::io::Result<()> {
+    let exponent_digits = if exponent.abs() >= 100 { 3 } else { 2 };
+    let fraction = if precision > 0 { precision + 1 } else { 0 };
+    let len = (mantissa < 0.) as usize + 1 + fraction + 2 + exponent_digits;
+    for _ in len..width {
+        line.push(b' ');
+    }
+    write!(
+        line,
+        "{:.precision$}E{}{:0>exponent_digits$}",
+        mantissa,
+        if exponent < 0 { '-' } else { '+' },
+        exponent.abs()
+    )
+}
+
+pub(crate) fn push_right_aligned(line: &mut Vec<u8>, digits: &str, width: usize) {
+    for _ in digits.len()..width {
+        line.push(b' ');
+    }
+    line.extend_from_slice(digits.as_bytes());
+}
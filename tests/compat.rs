@@ -0,0 +1,66 @@
+use schismrs_hgrid::hgrid::Hgrid;
+use schismrs_vgrid::transforms::quadratic::QuadraticTransformOpts;
+use schismrs_vgrid::transforms::StretchingFunction;
+use schismrs_vgrid::vgrid_file::VgridFile;
+use schismrs_vgrid::vqs::{VQSBuilder, VQS};
+use std::error::Error;
+use std::path::PathBuf;
+
+/// vgrid.in stores sigma with 6 decimals.
+const TOLERANCE: f64 = 1e-6;
+
+/// Golden files written by tests/fixtures/compat/reference_gen_vqs.py, a
+/// transcription of the loops of SCHISM's Fortran gen_vqs.
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/compat")
+        .join(name)
+}
+
+fn build(hgrid: &Hgrid) -> Result<VQS, Box<dyn Error>> {
+    // inputs of reference_gen_vqs.py
+    let stretching = StretchingFunction::Quadratic(QuadraticTransformOpts {
+        etal: &0.,
+        a_vqs0: &-0.3,
+        skew_decay_rate: &0.03,
+    });
+    let depths = vec![5., 15., 30., 60.];
+    let nlevels = vec![6, 10, 15, 22];
+    Ok(VQSBuilder::default()
+        .hgrid(hgrid)
+        .depths(&depths)
+        .nlevels(&nlevels)
+        .stretching(&stretching)
+        .dz_bottom_min(&0.5)
+        .build()?)
+}
+
+#[test]
+fn matches_gen_vqs() -> Result<(), Box<dyn Error>> {
+    let hgrid = Hgrid::try_from(&fixture("hgrid.gr3"))?;
+    let vqs = build(&hgrid)?;
+    let golden = VgridFile::parse(&fixture("vgrid.in"))?;
+    assert_eq!(vqs.sigma().ncols(), golden.np());
+    for i in 0..golden.np() {
+        let expected: Vec<f64> = golden
+            .sigma()
+            .column(i)
+            .iter()
+            .filter(|sigma| !sigma.is_nan())
+            .cloned()
+            .collect();
+        let actual = vqs.sigma_column(i).into_bottom_up();
+        assert_eq!(actual.len(), expected.len(), "node {} level count", i + 1);
+        for (k, (a, e)) in actual.iter().zip(expected.iter()).enumerate() {
+            assert!(
+                (a - e).abs() <= TOLERANCE,
+                "node {} level {} has sigma={} instead of {}",
+                i + 1,
+                k + 1,
+                a,
+                e
+            );
+        }
+    }
+    Ok(())
+}
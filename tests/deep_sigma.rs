@@ -0,0 +1,61 @@
+use schismrs_vgrid::testing::{structured_hgrid, SYNTHETIC_SPACING};
+use schismrs_vgrid::transforms::s::STransformOpts;
+use schismrs_vgrid::transforms::StretchingFunction;
+use schismrs_vgrid::vqs::{VQSBuildOptions, VQSBuilder};
+use std::error::Error;
+
+const ETAL: f64 = 1.5;
+const MASTER_DEPTHS: [f64; 3] = [10., 40., 110.];
+const MASTER_NLEVELS: [usize; 3] = [6, 12, 20];
+
+/// Nodes deeper than the first master grid take their z from the master grids,
+/// and their sigma used to be left as NaN. It must be (z - etal) / (etal + h).
+#[test]
+fn deep_nodes_get_sigma_from_their_z() -> Result<(), Box<dyn Error>> {
+    let hgrid = structured_hgrid(12, 3, SYNTHETIC_SPACING, |x, _| {
+        2. + 98. * x / (11. * SYNTHETIC_SPACING)
+    })?;
+    let stretching = StretchingFunction::S(STransformOpts {
+        etal: &ETAL,
+        a_vqs0: &-0.3,
+        theta_b: &0.7,
+        theta_f: &5.,
+    });
+    let (depths, nlevels) = (MASTER_DEPTHS.to_vec(), MASTER_NLEVELS.to_vec());
+    let mut options = VQSBuildOptions::new();
+    options.retain_znd(&true);
+    let vqs = VQSBuilder::default()
+        .hgrid(&hgrid)
+        .depths(&depths)
+        .nlevels(&nlevels)
+        .stretching(&stretching)
+        .dz_bottom_min(&0.5)
+        .options(options)
+        .build()?;
+    let znd = vqs.znd().expect("znd is retained");
+    let kbp = vqs.bottom_level_indices();
+    let mut deep_nodes = 0;
+    for (node, &depth) in vqs.depths().iter().enumerate() {
+        if depth <= MASTER_DEPTHS[0] {
+            continue;
+        }
+        deep_nodes += 1;
+        for k in kbp[node] - 1..vqs.nvrt() {
+            let (sigma, z) = (vqs.sigma()[[k, node]], znd[[k, node]]);
+            let expected = (z - ETAL) / (ETAL + depth);
+            assert!(!sigma.is_nan(), "node {} level {} is NaN", node, k + 1);
+            assert!(
+                (sigma - expected).abs() < 1e-12,
+                "node {} level {}: sigma={} expected={}",
+                node,
+                k + 1,
+                sigma,
+                expected
+            );
+        }
+        assert_eq!(vqs.sigma()[[vqs.nvrt() - 1, node]], 0.);
+        assert!((vqs.sigma()[[kbp[node] - 1, node]] + 1.).abs() < 1e-12);
+    }
+    assert!(deep_nodes > 0);
+    Ok(())
+}
@@ -0,0 +1,92 @@
+"""Writes the golden files of tests/compat.rs.
+
+A line by line transcription of the master grid and column loops of SCHISM's
+Fortran gen_vqs (1-based indices kept as in the Fortran source), run on a
+small structured mesh. Rerun from this directory after changing the inputs.
+"""
+
+ETAL = 0.0
+A_VQS0 = -0.3
+SKEW_DECAY_RATE = 0.03
+DZ_BOT_MIN = 0.5
+HSM = [5.0, 15.0, 30.0, 60.0]
+NV_VQS = [6, 10, 15, 22]
+COLUMN_DEPTHS = [0.5, 3.0, 12.0, 27.0, 55.0]
+NX, NY, SPACING = len(COLUMN_DEPTHS), 2, 1000.0
+
+
+def write_hgrid():
+    with open("hgrid.gr3", "w") as f:
+        f.write("compat\n{} {}\n".format(2 * (NX - 1) * (NY - 1), NX * NY))
+        for j in range(NY):
+            for i in range(NX):
+                f.write("{} {} {} {}\n".format(
+                    j * NX + i + 1, i * SPACING, j * SPACING, COLUMN_DEPTHS[i]))
+        element = 0
+        for j in range(NY - 1):
+            for i in range(NX - 1):
+                n = j * NX + i + 1
+                element += 1
+                f.write("{} 3 {} {} {}\n".format(element, n, n + 1, n + NX + 1))
+                element += 1
+                f.write("{} 3 {} {} {}\n".format(element, n, n + NX + 1, n + NX))
+
+
+def gen_vqs(dp):
+    m_vqs = len(HSM)
+    a_vqs = [max(-1.0, A_VQS0 - (m - 1) * SKEW_DECAY_RATE) for m in range(1, m_vqs + 1)]
+    nvrt = max(NV_VQS)
+    z_mas = {}
+    for m in range(1, m_vqs + 1):
+        for k in range(1, NV_VQS[m - 1] + 1):
+            sigma = (k - 1.0) / (1.0 - NV_VQS[m - 1])
+            tmp = a_vqs[m - 1] * sigma * sigma + (1 + a_vqs[m - 1]) * sigma
+            z_mas[k, m] = tmp * (ETAL + HSM[m - 1]) + ETAL
+    columns = []
+    for d in dp:
+        eta2 = ETAL
+        if d <= HSM[0]:
+            nlev = NV_VQS[0]
+            sigma_vqs = []
+            for k in range(1, nlev + 1):
+                sigma = (k - 1.0) / (1.0 - nlev)
+                sigma_vqs.append(A_VQS0 * sigma * sigma + (1 + A_VQS0) * sigma)
+        else:
+            m0 = next(m for m in range(2, m_vqs + 1) if HSM[m - 2] < d <= HSM[m - 1])
+            zrat = (d - HSM[m0 - 2]) / (HSM[m0 - 1] - HSM[m0 - 2])
+            znd = {}
+            kbp = 0
+            for k in range(1, NV_VQS[m0 - 1] + 1):
+                z1 = z_mas[min(k, NV_VQS[m0 - 2]), m0 - 1]
+                z2 = z_mas[k, m0]
+                z3 = z1 + (z2 - z1) * zrat
+                if z3 >= -d + DZ_BOT_MIN:
+                    znd[k] = z3
+                else:
+                    kbp = k
+                    break
+            if kbp == 0:
+                raise ValueError("no bottom found at depth {}".format(d))
+            znd[kbp] = -d
+            sigma_vqs = [(znd[k] - eta2) / (eta2 + d) for k in range(1, kbp + 1)]
+        # surface first, as sigma_vqs in gen_vqs
+        columns.append(sigma_vqs)
+    return nvrt, columns
+
+
+def write_vgrid(nvrt, columns):
+    bottom = [nvrt + 1 - len(column) for column in columns]
+    with open("vgrid.in", "w") as f:
+        f.write("{:>12}\n{:>12}\n".format(1, nvrt))
+        f.write("".join(" {:>10}".format(k) for k in bottom) + "\n")
+        for level in range(1, nvrt + 1):
+            f.write("{:>10}".format(level))
+            for kbp, column in zip(bottom, columns):
+                value = column[nvrt - level] if level >= kbp else -9.0
+                f.write("{:15.6f}".format(value))
+            f.write("\n")
+
+
+if __name__ == "__main__":
+    write_hgrid()
+    write_vgrid(*gen_vqs([d for _ in range(NY) for d in COLUMN_DEPTHS]))
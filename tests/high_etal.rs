@@ -0,0 +1,58 @@
+use schismrs_hgrid::hgrid::Hgrid;
+use schismrs_vgrid::checks::assert_invariants;
+use schismrs_vgrid::testing::{structured_hgrid, SYNTHETIC_SPACING};
+use schismrs_vgrid::transforms::s::STransformOpts;
+use schismrs_vgrid::transforms::StretchingFunction;
+use schismrs_vgrid::vqs::{VQSAutoBuilder, VQS};
+use std::error::Error;
+
+/// Design elevations of a storm surge study, from MSL to 3 m.
+const ETALS: [f64; 7] = [0., 0.5, 1., 1.5, 2., 2.5, 3.];
+
+fn build(hgrid: &Hgrid, etal: f64) -> Result<VQS, Box<dyn Error>> {
+    let stretching = StretchingFunction::S(STransformOpts {
+        etal: &etal,
+        a_vqs0: &-0.3,
+        theta_b: &0.7,
+        theta_f: &5.,
+    });
+    Ok(VQSAutoBuilder::default()
+        .hgrid(hgrid)
+        .stretching(&stretching)
+        .ngrids(&6)
+        .dz_bottom_min(&0.1)
+        .initial_depth(&(etal + 1.))
+        .shallow_levels(&3)
+        .max_levels(&20)
+        .build()?)
+}
+
+fn assert_sweep(hgrid: &Hgrid) -> Result<(), Box<dyn Error>> {
+    for etal in ETALS {
+        let vqs = build(hgrid, etal)?;
+        for value in vqs.sigma().iter().filter(|value| !value.is_nan()) {
+            assert!(value.is_finite(), "sigma={} at etal={}", value, etal);
+        }
+        assert_eq!(assert_invariants(&vqs), Ok(()), "at etal={}", etal);
+    }
+    Ok(())
+}
+
+#[test]
+fn shallow_mesh_over_etal_range() -> Result<(), Box<dyn Error>> {
+    // 0.2 to 8 m deep, mostly in the shallow branch at high etal
+    let hgrid = structured_hgrid(20, 3, SYNTHETIC_SPACING, |x, _| {
+        0.2 + 7.8 * x / (19. * SYNTHETIC_SPACING)
+    })?;
+    assert_sweep(&hgrid)
+}
+
+#[test]
+fn dry_mesh_over_etal_range() -> Result<(), Box<dyn Error>> {
+    // from 1.5 m above MSL to 20 m deep, so the first nodes are dry at low
+    // etal and get the h0 floor
+    let hgrid = structured_hgrid(24, 3, SYNTHETIC_SPACING, |x, _| {
+        -1.5 + 21.5 * x / (23. * SYNTHETIC_SPACING)
+    })?;
+    assert_sweep(&hgrid)
+}
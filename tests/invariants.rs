@@ -0,0 +1,42 @@
+use proptest::prelude::*;
+use schismrs_vgrid::checks::assert_invariants;
+use schismrs_vgrid::testing::{structured_hgrid, SYNTHETIC_SPACING};
+use schismrs_vgrid::transforms::s::STransformOpts;
+use schismrs_vgrid::transforms::StretchingFunction;
+use schismrs_vgrid::vqs::VQSAutoBuilder;
+
+proptest! {
+    // every case writes and parses a mesh, so keep the count small
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn built_grids_satisfy_invariants(
+        shallow in 0.5f64..10.,
+        deep in 20f64..5000.,
+        theta_f in 0.5f64..10.,
+        theta_b in 0f64..1.,
+        dz_bottom_min in 0.1f64..1.,
+    ) {
+        let hgrid = structured_hgrid(12, 3, SYNTHETIC_SPACING, |x, _| {
+            shallow + (deep - shallow) * x / (11. * SYNTHETIC_SPACING)
+        })
+        .unwrap();
+        let stretching = StretchingFunction::S(STransformOpts {
+            etal: &0.,
+            a_vqs0: &-0.3,
+            theta_b: &theta_b,
+            theta_f: &theta_f,
+        });
+        let vqs = VQSAutoBuilder::default()
+            .hgrid(&hgrid)
+            .stretching(&stretching)
+            .ngrids(&6)
+            .dz_bottom_min(&dz_bottom_min)
+            .initial_depth(&(shallow + 1.))
+            .shallow_levels(&3)
+            .max_levels(&30)
+            .build()
+            .map_err(|err| TestCaseError::fail(format!("build failed: {}", err)))?;
+        prop_assert_eq!(assert_invariants(&vqs), Ok(()));
+    }
+}
@@ -0,0 +1,80 @@
+use schismrs_hgrid::hgrid::Hgrid;
+use schismrs_vgrid::diff::VQSDiff;
+use schismrs_vgrid::testing::{channel_hgrid, round_trip, seamount_hgrid, shelf_hgrid};
+use schismrs_vgrid::transforms::s::STransformOpts;
+use schismrs_vgrid::transforms::StretchingFunction;
+use schismrs_vgrid::vgrid_file::VgridFile;
+use schismrs_vgrid::vqs::{VQSAutoBuilder, VQS};
+use schismrs_vgrid::writer::{VgridFormat, VgridWriterOptions};
+use std::error::Error;
+
+/// vgrid.in stores sigma with 6 decimals.
+const TOLERANCE: f64 = 1e-6;
+
+fn build(hgrid: &Hgrid) -> Result<VQS, Box<dyn Error>> {
+    let stretching = StretchingFunction::S(STransformOpts {
+        etal: &0.,
+        a_vqs0: &-0.3,
+        theta_b: &0.7,
+        theta_f: &5.,
+    });
+    Ok(VQSAutoBuilder::default()
+        .hgrid(hgrid)
+        .stretching(&stretching)
+        .ngrids(&10)
+        .dz_bottom_min(&0.5)
+        .initial_depth(&5.)
+        .shallow_levels(&3)
+        .max_levels(&30)
+        .build()?)
+}
+
+fn assert_round_trip(hgrid: &Hgrid) -> Result<(), Box<dyn Error>> {
+    let vqs = build(hgrid)?;
+    for format in [VgridFormat::Legacy, VgridFormat::NodeMajor] {
+        let mut options = VgridWriterOptions::new();
+        options.format(format);
+        let loaded = round_trip(&vqs, hgrid, &options)?;
+        let diff = VQSDiff::new(&vqs, &loaded)?;
+        assert!(
+            diff.is_identical(&TOLERANCE),
+            "{:?} round trip changed the grid",
+            format
+        );
+        assert_eq!(vqs.bottom_level_indices(), loaded.bottom_level_indices());
+    }
+    Ok(())
+}
+
+#[test]
+fn shelf_round_trip() -> Result<(), Box<dyn Error>> {
+    assert_round_trip(&shelf_hgrid(40, 10)?)
+}
+
+#[test]
+fn channel_round_trip() -> Result<(), Box<dyn Error>> {
+    assert_round_trip(&channel_hgrid(10, 40)?)
+}
+
+#[test]
+fn seamount_round_trip() -> Result<(), Box<dyn Error>> {
+    assert_round_trip(&seamount_hgrid(30, 30)?)
+}
+
+#[test]
+fn vgrid_file_infers_node_count() -> Result<(), Box<dyn Error>> {
+    let hgrid = shelf_hgrid(20, 5)?;
+    let vqs = build(&hgrid)?;
+    let path = std::env::temp_dir().join(format!(
+        "schismrs-vgrid-round-trip-{}.vgrid.in",
+        std::process::id()
+    ));
+    vqs.write_to_file(&path)?;
+    let file = VgridFile::parse(&path);
+    std::fs::remove_file(&path)?;
+    let file = file?;
+    assert_eq!(file.np(), 100);
+    assert_eq!(file.nvrt(), vqs.nvrt());
+    assert_eq!(file.kbp(), &vqs.bottom_level_indices());
+    Ok(())
+}
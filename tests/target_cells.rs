@@ -0,0 +1,31 @@
+use schismrs_vgrid::testing::shelf_hgrid;
+use schismrs_vgrid::transforms::s::STransformOpts;
+use schismrs_vgrid::transforms::StretchingFunction;
+use schismrs_vgrid::vqs::VQSAutoBuilder;
+use std::error::Error;
+
+#[test]
+fn target_cells_builds_close_to_the_budget() -> Result<(), Box<dyn Error>> {
+    let hgrid = shelf_hgrid(30, 4)?;
+    let stretching = StretchingFunction::S(STransformOpts {
+        etal: &0.,
+        a_vqs0: &-0.3,
+        theta_b: &0.7,
+        theta_f: &5.,
+    });
+    let target_cells = 120 * 20;
+    let vqs = VQSAutoBuilder::default()
+        .hgrid(&hgrid)
+        .stretching(&stretching)
+        .ngrids(&10)
+        .dz_bottom_min(&0.5)
+        .initial_depth(&3.)
+        .shallow_levels(&3)
+        .target_cells(&target_cells)
+        .target_cells_tolerance(&0.25)
+        .build()?;
+    let count = vqs.wet_node_count();
+    assert!(count.abs_diff(target_cells) as f64 <= 0.25 * target_cells as f64);
+    assert!(vqs.nvrt() < 100, "nvrt={}", vqs.nvrt());
+    Ok(())
+}